@@ -0,0 +1,140 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveIden)]
+enum User {
+  Table, // 表名
+  Id,    // 主键 ID
+  Email, // 邮箱
+}
+
+/// 去掉首尾空格并转为小写的邮箱，与注册时的规范化一致
+fn normalized_email() -> SimpleExpr {
+  Func::lower(Expr::expr(
+    Func::cust(Alias::new("TRIM")).arg(Expr::col(User::Email)),
+  ))
+  .into()
+}
+
+/// 邮箱唯一，注册和修改邮箱同时发生时由数据库拒绝重复的邮箱。
+/// 已有邮箱先规范化；规范化后重复的邮箱无法自动合并，迁移失败并列出这些邮箱，需先人工处理
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let duplicates = Query::select()
+      .expr_as(normalized_email(), Alias::new("email"))
+      .from(User::Table)
+      .add_group_by([normalized_email()])
+      .and_having(Expr::expr(Func::count(Expr::col(User::Id))).gt(1))
+      .to_owned();
+    let duplicates = db
+      .query_all(manager.get_database_backend().build(&duplicates))
+      .await?
+      .iter()
+      .map(|row| row.try_get::<String>("", "email"))
+      .collect::<Result<Vec<_>, _>>()?;
+    if !duplicates.is_empty() {
+      return Err(DbErr::Migration(format!(
+        "Cannot add the unique index on user.email, these addresses belong to several \
+         accounts once trimmed and lowercased: {}. Merge or rename those accounts, then run \
+         the migration again",
+        duplicates.join(", ")
+      )));
+    }
+    manager
+      .exec_stmt(
+        Query::update()
+          .table(User::Table)
+          .value(User::Email, normalized_email())
+          .and_where(Expr::col(User::Email).ne(normalized_email()))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_user_email")
+          .table(User::Table)
+          .col(User::Email)
+          .unique()
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_index(
+        Index::drop()
+          .name("idx_user_email")
+          .table(User::Table)
+          .to_owned(),
+      )
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm_migration::sea_orm::{Database, DatabaseConnection, Statement};
+
+  use crate::{Migrator, MigratorTrait};
+
+  use super::*;
+
+  /// 迁移到本迁移之前的数据库
+  async fn before_index() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let steps = Migrator::migrations()
+      .iter()
+      .position(|m| m.name() == Migration.name())
+      .unwrap();
+    Migrator::up(&db, Some(steps as u32)).await.unwrap();
+    db
+  }
+
+  async fn insert_user(db: &DatabaseConnection, user_id: &str, email: &str) {
+    let sql = r#"INSERT INTO "user" ("user_id", "nickname", "password", "email", "avatar",
+      "is_email_verified", "is_phone_verified", "created_at")
+      VALUES (?, 'nickname', 'hash', ?, '', 0, 0, '2024-01-01 00:00:00')"#;
+    let values = [user_id.into(), email.into()];
+    let stmt = Statement::from_sql_and_values(db.get_database_backend(), sql, values);
+    db.execute(stmt).await.unwrap();
+  }
+
+  async fn emails(db: &DatabaseConnection) -> Vec<String> {
+    let sql = r#"SELECT "email" FROM "user" ORDER BY "id""#;
+    let stmt = Statement::from_string(db.get_database_backend(), sql);
+    db.query_all(stmt)
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| row.try_get("", "email").unwrap())
+      .collect()
+  }
+
+  #[async_std::test]
+  async fn normalizes_existing_emails() {
+    let db = before_index().await;
+    insert_user(&db, "a", " Alice@Example.com").await;
+    insert_user(&db, "b", "bob@example.com").await;
+    Migrator::up(&db, Some(1)).await.unwrap();
+    assert_eq!(emails(&db).await, ["alice@example.com", "bob@example.com"]);
+  }
+
+  #[async_std::test]
+  async fn refuses_duplicates_without_touching_them() {
+    let db = before_index().await;
+    insert_user(&db, "a", "alice@example.com").await;
+    insert_user(&db, "b", "Alice@Example.com ").await;
+    let err = Migrator::up(&db, Some(1)).await.unwrap_err().to_string();
+    assert!(err.contains("alice@example.com"), "{err}");
+    assert_eq!(
+      emails(&db).await,
+      ["alice@example.com", "Alice@Example.com "]
+    );
+  }
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum ApiKey {
+  Table,      // 表名
+  Id,         // 密钥 ID，即前缀中的部分
+  UserId,     // 用户 UUID
+  Name,       // 名称
+  Prefix,     // 展示用前缀
+  KeyHash,    // 密钥哈希
+  Scopes,     // 权限范围，逗号分隔
+  CreatedAt,  // 创建时间
+  ExpiresAt,  // 过期时间
+  LastUsedAt, // 最后使用时间
+  RevokedAt,  // 吊销时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(ApiKey::Table)
+          .if_not_exists()
+          .col(
+            string(ApiKey::Id)
+              .primary_key()
+              .comment("密钥 ID，即前缀中的部分"),
+          )
+          .col(string(ApiKey::UserId).comment("用户 UUID"))
+          .col(string(ApiKey::Name).comment("名称"))
+          .col(string(ApiKey::Prefix).comment("展示用前缀"))
+          .col(string(ApiKey::KeyHash).comment("密钥哈希"))
+          .col(string(ApiKey::Scopes).comment("read, write, admin，逗号分隔"))
+          .col(timestamp(ApiKey::CreatedAt).comment("创建时间"))
+          .col(timestamp_null(ApiKey::ExpiresAt).comment("过期时间"))
+          .col(timestamp_null(ApiKey::LastUsedAt).comment("最后使用时间"))
+          .col(timestamp_null(ApiKey::RevokedAt).comment("吊销时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_api_key_user_id")
+          .table(ApiKey::Table)
+          .col(ApiKey::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+      .await
+  }
+}
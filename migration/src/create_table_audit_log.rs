@@ -0,0 +1,67 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum AuditLog {
+  Table,     // 表名
+  Id,        // 主键 ID
+  ActorId,   // 操作者用户 UUID
+  TargetId,  // 被操作用户 UUID
+  Action,    // 操作类型
+  Diff,      // 变更前后
+  Ip,        // 操作者 IP
+  UserAgent, // 操作者 User-Agent
+  RequestId, // 请求 ID
+  CreatedAt, // 创建时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(AuditLog::Table)
+          .if_not_exists()
+          .col(pk_auto(AuditLog::Id).unsigned())
+          .col(string_null(AuditLog::ActorId).comment("操作者用户 UUID"))
+          .col(string_null(AuditLog::TargetId).comment("被操作用户 UUID"))
+          .col(
+            string(AuditLog::Action)
+              .comment("login, login_failed, profile_update, role_change, user_delete"),
+          )
+          .col(json_null(AuditLog::Diff).comment("变更前后"))
+          .col(string_null(AuditLog::Ip).comment("操作者 IP"))
+          .col(string_null(AuditLog::UserAgent).comment("操作者 User-Agent"))
+          .col(string_null(AuditLog::RequestId).comment("请求 ID"))
+          .col(timestamp(AuditLog::CreatedAt).comment("创建时间"))
+          .to_owned(),
+      )
+      .await?;
+    for (name, col) in [
+      ("idx_audit_log_actor_id", AuditLog::ActorId),
+      ("idx_audit_log_target_id", AuditLog::TargetId),
+      ("idx_audit_log_action", AuditLog::Action),
+      ("idx_audit_log_created_at", AuditLog::CreatedAt),
+    ] {
+      manager
+        .create_index(
+          Index::create()
+            .name(name)
+            .table(AuditLog::Table)
+            .col(col)
+            .to_owned(),
+        )
+        .await?;
+    }
+    Ok(())
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,52 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum EmailChange {
+  Table,     // 表名
+  TokenHash, // 确认令牌的哈希
+  UserId,    // 用户 UUID
+  NewEmail,  // 新邮箱
+  CreatedAt, // 创建时间
+  ExpiresAt, // 过期时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(EmailChange::Table)
+          .if_not_exists()
+          .col(
+            string(EmailChange::TokenHash)
+              .primary_key()
+              .comment("确认令牌的哈希"),
+          )
+          .col(string(EmailChange::UserId).comment("用户 UUID"))
+          .col(string(EmailChange::NewEmail).comment("新邮箱"))
+          .col(timestamp(EmailChange::CreatedAt).comment("创建时间"))
+          .col(timestamp(EmailChange::ExpiresAt).comment("过期时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_email_change_user_id")
+          .table(EmailChange::Table)
+          .col(EmailChange::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(EmailChange::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum MagicLink {
+  Table,     // 表名
+  TokenHash, // 链接令牌的哈希
+  UserId,    // 用户 UUID
+  CreatedAt, // 创建时间
+  ExpiresAt, // 过期时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(MagicLink::Table)
+          .if_not_exists()
+          .col(
+            string(MagicLink::TokenHash)
+              .primary_key()
+              .comment("链接令牌的哈希"),
+          )
+          .col(string(MagicLink::UserId).comment("用户 UUID"))
+          .col(timestamp(MagicLink::CreatedAt).comment("创建时间"))
+          .col(timestamp(MagicLink::ExpiresAt).comment("过期时间"))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(MagicLink::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum OauthClient {
+  Table,            // 表名
+  ClientId,         // 客户端 ID
+  ClientSecretHash, // 客户端密钥哈希，公开客户端为空
+  Name,             // 名称
+  RedirectUris,     // 回调地址，空格分隔
+  GrantTypes,       // 授权类型，空格分隔
+  Scopes,           // 可申请的权限范围，空格分隔
+  CreatedBy,        // 创建者用户 UUID
+  CreatedAt,        // 创建时间
+  RevokedAt,        // 吊销时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(OauthClient::Table)
+          .if_not_exists()
+          .col(
+            string(OauthClient::ClientId)
+              .primary_key()
+              .comment("客户端 ID"),
+          )
+          .col(string_null(OauthClient::ClientSecretHash).comment("客户端密钥哈希，公开客户端为空"))
+          .col(string(OauthClient::Name).comment("名称"))
+          .col(text(OauthClient::RedirectUris).comment("回调地址，空格分隔"))
+          .col(
+            string(OauthClient::GrantTypes)
+              .comment("authorization_code, client_credentials，空格分隔"),
+          )
+          .col(string(OauthClient::Scopes).comment("可申请的权限范围，空格分隔"))
+          .col(string(OauthClient::CreatedBy).comment("创建者用户 UUID"))
+          .col(timestamp(OauthClient::CreatedAt).comment("创建时间"))
+          .col(timestamp_null(OauthClient::RevokedAt).comment("吊销时间"))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(OauthClient::Table).to_owned())
+      .await
+  }
+}
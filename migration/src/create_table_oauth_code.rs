@@ -0,0 +1,49 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum OauthCode {
+  Table,         // 表名
+  CodeHash,      // 授权码哈希
+  ClientId,      // 客户端 ID
+  UserId,        // 用户 UUID
+  RedirectUri,   // 回调地址
+  Scopes,        // 权限范围，空格分隔
+  CodeChallenge, // PKCE code_challenge
+  CreatedAt,     // 创建时间
+  ExpiresAt,     // 过期时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(OauthCode::Table)
+          .if_not_exists()
+          .col(
+            string(OauthCode::CodeHash)
+              .primary_key()
+              .comment("授权码哈希"),
+          )
+          .col(string(OauthCode::ClientId).comment("客户端 ID"))
+          .col(string(OauthCode::UserId).comment("用户 UUID"))
+          .col(text(OauthCode::RedirectUri).comment("回调地址"))
+          .col(string(OauthCode::Scopes).comment("权限范围，空格分隔"))
+          .col(string(OauthCode::CodeChallenge).comment("PKCE code_challenge"))
+          .col(timestamp(OauthCode::CreatedAt).comment("创建时间"))
+          .col(timestamp(OauthCode::ExpiresAt).comment("过期时间"))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(OauthCode::Table).to_owned())
+      .await
+  }
+}
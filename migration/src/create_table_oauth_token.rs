@@ -0,0 +1,54 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum OauthToken {
+  Table,     // 表名
+  Id,        // 主键 ID
+  TokenHash, // 访问令牌哈希
+  ClientId,  // 客户端 ID
+  UserId,    // 用户 UUID，client_credentials 为空
+  Scopes,    // 权限范围，空格分隔
+  CreatedAt, // 签发时间
+  ExpiresAt, // 过期时间
+  RevokedAt, // 吊销时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(OauthToken::Table)
+          .if_not_exists()
+          .col(pk_auto(OauthToken::Id).unsigned())
+          .col(string_uniq(OauthToken::TokenHash).comment("访问令牌哈希"))
+          .col(string(OauthToken::ClientId).comment("客户端 ID"))
+          .col(string_null(OauthToken::UserId).comment("用户 UUID，client_credentials 为空"))
+          .col(string(OauthToken::Scopes).comment("权限范围，空格分隔"))
+          .col(timestamp(OauthToken::CreatedAt).comment("签发时间"))
+          .col(timestamp(OauthToken::ExpiresAt).comment("过期时间"))
+          .col(timestamp_null(OauthToken::RevokedAt).comment("吊销时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_oauth_token_client_id")
+          .table(OauthToken::Table)
+          .col(OauthToken::ClientId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(OauthToken::Table).to_owned())
+      .await
+  }
+}
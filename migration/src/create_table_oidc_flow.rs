@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum OidcFlow {
+  Table,        // 表名
+  State,        // state 参数
+  Provider,     // 第三方名称
+  Nonce,        // nonce 参数
+  CodeVerifier, // PKCE code_verifier
+  UserId,       // 绑定账号时的用户 UUID
+  CreatedAt,    // 创建时间
+  ExpiresAt,    // 过期时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(OidcFlow::Table)
+          .if_not_exists()
+          .col(string(OidcFlow::State).primary_key().comment("state 参数"))
+          .col(string(OidcFlow::Provider).comment("第三方名称"))
+          .col(string(OidcFlow::Nonce).comment("nonce 参数"))
+          .col(string(OidcFlow::CodeVerifier).comment("PKCE code_verifier"))
+          .col(string_null(OidcFlow::UserId).comment("绑定账号时的用户 UUID"))
+          .col(timestamp(OidcFlow::CreatedAt).comment("创建时间"))
+          .col(timestamp(OidcFlow::ExpiresAt).comment("过期时间"))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(OidcFlow::Table).to_owned())
+      .await
+  }
+}
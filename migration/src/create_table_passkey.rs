@@ -0,0 +1,54 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum Passkey {
+  Table,        // 表名
+  Id,           // 主键 ID
+  UserId,       // 用户 UUID
+  CredentialId, // 凭据 ID
+  PublicKey,    // COSE 公钥
+  SignCount,    // 签名计数器
+  Name,         // 名称
+  CreatedAt,    // 注册时间
+  LastUsedAt,   // 最后使用时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Passkey::Table)
+          .if_not_exists()
+          .col(pk_auto(Passkey::Id).unsigned())
+          .col(string(Passkey::UserId).comment("用户 UUID"))
+          .col(string_uniq(Passkey::CredentialId).comment("凭据 ID"))
+          .col(text(Passkey::PublicKey).comment("COSE 公钥"))
+          .col(unsigned(Passkey::SignCount).comment("签名计数器"))
+          .col(string(Passkey::Name).comment("名称"))
+          .col(timestamp(Passkey::CreatedAt).comment("注册时间"))
+          .col(timestamp_null(Passkey::LastUsedAt).comment("最后使用时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_passkey_user_id")
+          .table(Passkey::Table)
+          .col(Passkey::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Passkey::Table).to_owned())
+      .await
+  }
+}
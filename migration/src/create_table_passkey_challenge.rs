@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum PasskeyChallenge {
+  Table,     // 表名
+  Challenge, // 挑战值
+  Ceremony,  // 注册或登录
+  UserId,    // 注册时的用户 UUID
+  CreatedAt, // 创建时间
+  ExpiresAt, // 过期时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(PasskeyChallenge::Table)
+          .if_not_exists()
+          .col(
+            string(PasskeyChallenge::Challenge)
+              .primary_key()
+              .comment("挑战值"),
+          )
+          .col(string(PasskeyChallenge::Ceremony).comment("注册或登录"))
+          .col(string_null(PasskeyChallenge::UserId).comment("注册时的用户 UUID"))
+          .col(timestamp(PasskeyChallenge::CreatedAt).comment("创建时间"))
+          .col(timestamp(PasskeyChallenge::ExpiresAt).comment("过期时间"))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(PasskeyChallenge::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum RateLimit {
+  Table,     // 表名
+  Id,        // 限流键
+  State,     // 限流状态
+  ExpiresAt, // 过期时间（毫秒时间戳）
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(RateLimit::Table)
+          .if_not_exists()
+          .col(string(RateLimit::Id).primary_key().comment("限流键"))
+          .col(text(RateLimit::State).comment("限流状态"))
+          .col(big_integer(RateLimit::ExpiresAt).comment("过期时间（毫秒时间戳）"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_rate_limit_expires_at")
+          .table(RateLimit::Table)
+          .col(RateLimit::ExpiresAt)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(RateLimit::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum Session {
+  Table,      // 表名
+  Id,         // 会话 ID，写入 JWT
+  UserId,     // 用户 UUID
+  Ip,         // 登录 IP
+  UserAgent,  // 登录设备 User-Agent
+  CreatedAt,  // 登录时间
+  LastSeenAt, // 最后活跃时间
+  ExpiresAt,  // 过期时间
+  RevokedAt,  // 注销时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Session::Table)
+          .if_not_exists()
+          .col(
+            string(Session::Id)
+              .primary_key()
+              .comment("会话 ID，写入 JWT"),
+          )
+          .col(string(Session::UserId).comment("用户 UUID"))
+          .col(string_null(Session::Ip).comment("登录 IP"))
+          .col(string_null(Session::UserAgent).comment("登录设备 User-Agent"))
+          .col(timestamp(Session::CreatedAt).comment("登录时间"))
+          .col(timestamp(Session::LastSeenAt).comment("最后活跃时间"))
+          .col(timestamp(Session::ExpiresAt).comment("过期时间"))
+          .col(timestamp_null(Session::RevokedAt).comment("注销时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_session_user_id")
+          .table(Session::Table)
+          .col(Session::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Session::Table).to_owned())
+      .await
+  }
+}
@@ -0,0 +1,63 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveIden)]
+enum UserIdentity {
+  Table,       // 表名
+  Id,          // 主键 ID
+  UserId,      // 用户 UUID
+  Provider,    // 第三方名称
+  Subject,     // 第三方账号 ID
+  Email,       // 第三方账号邮箱
+  CreatedAt,   // 绑定时间
+  LastLoginAt, // 最后登录时间
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(UserIdentity::Table)
+          .if_not_exists()
+          .col(pk_auto(UserIdentity::Id).unsigned())
+          .col(string(UserIdentity::UserId).comment("用户 UUID"))
+          .col(string(UserIdentity::Provider).comment("第三方名称"))
+          .col(string(UserIdentity::Subject).comment("第三方账号 ID"))
+          .col(string_null(UserIdentity::Email).comment("第三方账号邮箱"))
+          .col(timestamp(UserIdentity::CreatedAt).comment("绑定时间"))
+          .col(timestamp_null(UserIdentity::LastLoginAt).comment("最后登录时间"))
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_user_identity_provider_subject")
+          .table(UserIdentity::Table)
+          .col(UserIdentity::Provider)
+          .col(UserIdentity::Subject)
+          .unique()
+          .to_owned(),
+      )
+      .await?;
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_user_identity_user_id")
+          .table(UserIdentity::Table)
+          .col(UserIdentity::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(UserIdentity::Table).to_owned())
+      .await
+  }
+}
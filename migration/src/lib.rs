@@ -1,12 +1,46 @@
 pub use sea_orm_migration::prelude::*;
 
+mod create_index_user_email;
+mod create_table_api_key;
+mod create_table_audit_log;
+mod create_table_email_change;
+mod create_table_magic_link;
+mod create_table_oauth_client;
+mod create_table_oauth_code;
+mod create_table_oauth_token;
+mod create_table_oidc_flow;
+mod create_table_passkey;
+mod create_table_passkey_challenge;
+mod create_table_rate_limit;
+mod create_table_session;
+// Shipped migration, kept byte for byte; the lints are silenced here instead of editing it
+#[allow(dead_code, clippy::enum_variant_names)]
 mod create_table_user;
+mod create_table_user_identity;
+mod update_user_default_avatar;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
   fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-    vec![Box::new(create_table_user::Migration)]
+    vec![
+      Box::new(create_table_user::Migration),
+      Box::new(create_table_rate_limit::Migration),
+      Box::new(create_table_audit_log::Migration),
+      Box::new(create_table_session::Migration),
+      Box::new(create_table_api_key::Migration),
+      Box::new(create_table_user_identity::Migration),
+      Box::new(create_table_oidc_flow::Migration),
+      Box::new(create_table_oauth_client::Migration),
+      Box::new(create_table_oauth_code::Migration),
+      Box::new(create_table_oauth_token::Migration),
+      Box::new(create_table_magic_link::Migration),
+      Box::new(create_table_passkey::Migration),
+      Box::new(create_table_passkey_challenge::Migration),
+      Box::new(create_table_email_change::Migration),
+      Box::new(create_index_user_email::Migration),
+      Box::new(update_user_default_avatar::Migration),
+    ]
   }
 }
@@ -0,0 +1,49 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+#[derive(DeriveIden)]
+enum User {
+  Table,  // 表名
+  Avatar, // 头像 URL
+}
+
+/// 旧的默认头像
+const DEFAULT_AVATAR: &str = "v2/avatars/default.png";
+
+/// 生成头像的路径前缀
+const IDENTICON_PATH: &str = "/api/v1/avatars/";
+
+/// 使用默认头像的用户改为按用户生成的头像
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    // MySQL 默认把 || 当作 OR
+    let avatar = match manager.get_database_backend() {
+      DatabaseBackend::MySql => Expr::cust_with_values("CONCAT(?, `user_id`)", [IDENTICON_PATH]),
+      _ => Expr::cust_with_values(r#"? || "user_id""#, [IDENTICON_PATH]),
+    };
+    manager
+      .exec_stmt(
+        Query::update()
+          .table(User::Table)
+          .value(User::Avatar, avatar)
+          .and_where(Expr::col(User::Avatar).eq(DEFAULT_AVATAR))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .exec_stmt(
+        Query::update()
+          .table(User::Table)
+          .value(User::Avatar, DEFAULT_AVATAR)
+          .and_where(Expr::col(User::Avatar).like(format!("{IDENTICON_PATH}%")))
+          .to_owned(),
+      )
+      .await
+  }
+}
@@ -0,0 +1,150 @@
+//! `cargo bench -p rate-limiter`: the cost of a check for one hot key, for many distinct
+//! client IPs, from several threads at once, and of the background eviction
+use std::{
+  hint::black_box,
+  thread,
+  time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rate_limiter::{now_millis, MemoryStore, Quota, RateLimitAlgorithm};
+
+const ALGORITHMS: [(&str, RateLimitAlgorithm); 2] = [
+  ("gcra", RateLimitAlgorithm::Gcra),
+  ("sliding_window", RateLimitAlgorithm::SlidingWindow),
+];
+
+fn quota() -> Quota {
+  "60/min".parse().unwrap()
+}
+
+/// Keys as the middleware builds them for client IPs
+fn ip_keys(count: u32) -> Vec<String> {
+  (0..count)
+    .map(|i| {
+      let [_, b, c, d] = i.to_be_bytes();
+      format!("default:ip:10.{b}.{c}.{d}")
+    })
+    .collect()
+}
+
+/// A clock moving 1ms per check, so buckets refill and windows slide as in production
+struct Clock(u64);
+
+impl Clock {
+  fn new() -> Self {
+    Clock(now_millis())
+  }
+
+  fn tick(&mut self) -> u64 {
+    self.0 += 1;
+    self.0
+  }
+}
+
+fn single_key(c: &mut Criterion) {
+  let mut group = c.benchmark_group("single_key");
+  group.throughput(Throughput::Elements(1));
+  for (name, algorithm) in ALGORITHMS {
+    let store = MemoryStore::default();
+    let mut clock = Clock::new();
+    group.bench_function(name, |b| {
+      b.iter(|| {
+        store.check_at(
+          black_box("default:ip:10.0.0.1"),
+          quota(),
+          algorithm,
+          clock.tick(),
+        )
+      })
+    });
+  }
+  group.finish();
+}
+
+fn distinct_ips(c: &mut Criterion) {
+  let mut group = c.benchmark_group("distinct_ips");
+  group.throughput(Throughput::Elements(1));
+  for count in [1_000, 100_000, 1_000_000] {
+    let keys = ip_keys(count);
+    for (name, algorithm) in ALGORITHMS {
+      let store = MemoryStore::default();
+      let mut clock = Clock::new();
+      for key in &keys {
+        store.check_at(key, quota(), algorithm, clock.tick());
+      }
+      let mut next = keys.iter().cycle();
+      group.bench_with_input(BenchmarkId::new(name, count), &store, |b, store| {
+        b.iter(|| store.check_at(next.next().unwrap(), quota(), algorithm, clock.tick()))
+      });
+    }
+  }
+  group.finish();
+}
+
+/// Threads checking their own share of 100k IPs on one store, as the workers of the server.
+/// Only shows the scaling of the sharded map on a machine with as many cores
+fn concurrent(c: &mut Criterion) {
+  let mut group = c.benchmark_group("concurrent_distinct_ips");
+  group.throughput(Throughput::Elements(1));
+  let keys = ip_keys(100_000);
+  for threads in [1, 4, 8] {
+    for (name, algorithm) in ALGORITHMS {
+      let store = MemoryStore::default();
+      group.bench_function(BenchmarkId::new(name, threads), |b| {
+        b.iter_custom(|iters| {
+          let per_thread = iters.div_ceil(threads);
+          let start = Instant::now();
+          thread::scope(|scope| {
+            for shard in keys.chunks(keys.len() / threads as usize) {
+              let store = &store;
+              scope.spawn(move || {
+                let mut clock = Clock::new();
+                for key in shard.iter().cycle().take(per_thread as usize) {
+                  black_box(store.check_at(key, quota(), algorithm, clock.tick()));
+                }
+              });
+            }
+          });
+          start.elapsed()
+        })
+      });
+    }
+  }
+  group.finish();
+}
+
+/// One pass of the background eviction over 100k entries, half of them expired
+fn eviction(c: &mut Criterion) {
+  let mut group = c.benchmark_group("evict_expired");
+  let keys = ip_keys(100_000);
+  let now = now_millis();
+  let window = quota().window.as_millis() as u64;
+  for (name, algorithm) in ALGORITHMS {
+    group.bench_function(name, |b| {
+      b.iter_batched(
+        || {
+          let store = MemoryStore::default();
+          for (i, key) in keys.iter().enumerate() {
+            let at = if i % 2 == 0 { now } else { now + window };
+            store.check_at(key, quota(), algorithm, at);
+          }
+          store
+        },
+        |store| {
+          store.evict_expired_at(now + window);
+          store
+        },
+        BatchSize::LargeInput,
+      )
+    });
+  }
+  group.finish();
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default().measurement_time(Duration::from_secs(3));
+  targets = single_key, distinct_ips, concurrent, eviction
+}
+criterion_main!(benches);
@@ -0,0 +1,242 @@
+//! Rate limiting algorithms and an in-process store, apart from the server so they can be
+//! benchmarked on their own
+mod memory;
+
+use std::{
+  collections::VecDeque,
+  fmt,
+  str::FromStr,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+pub use memory::MemoryStore;
+
+/// Number of requests allowed per window, written as `5/min`, `100/hour` or `10/30s`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+  pub limit: u32,
+  pub window: Duration,
+}
+
+/// A quota that is not `<requests>/<window>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidQuota(pub String);
+
+impl fmt::Display for InvalidQuota {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Invalid rate limit quota {}, expected e.g. 5/min or 10/30s",
+      self.0
+    )
+  }
+}
+
+impl std::error::Error for InvalidQuota {}
+
+impl FromStr for Quota {
+  type Err = InvalidQuota;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || InvalidQuota(s.to_string());
+    let (limit, period) = s.trim().split_once('/').ok_or_else(invalid)?;
+    let limit = limit.trim().parse().map_err(|_| invalid())?;
+    let period = period.trim();
+    let unit_at = period
+      .find(|c: char| !c.is_ascii_digit())
+      .ok_or_else(invalid)?;
+    let (count, unit) = period.split_at(unit_at);
+    let count: u64 = match count {
+      "" => 1,
+      count => count.parse().map_err(|_| invalid())?,
+    };
+    let unit = match unit {
+      "s" | "sec" | "second" => 1,
+      "m" | "min" | "minute" => 60,
+      "h" | "hour" => 3600,
+      "d" | "day" => 86400,
+      _ => return Err(invalid()),
+    };
+    // A limit of 0 would refuse everything and divide by zero in the algorithms
+    if count == 0 || limit == 0 {
+      return Err(invalid());
+    }
+    Ok(Quota {
+      limit,
+      window: Duration::from_secs(count * unit),
+    })
+  }
+}
+
+/// Outcome of a rate limit check, used for the `RateLimit-*` response headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+  pub allowed: bool,
+  pub limit: u32,
+  pub remaining: u32,
+  /// Until the full quota is available again
+  pub reset_after: Duration,
+  /// Until the next request would be allowed, zero when allowed
+  pub retry_after: Duration,
+}
+
+impl RateLimitDecision {
+  /// Whether `self` should be reported rather than `other`
+  pub fn is_tighter_than(&self, other: &Self) -> bool {
+    (!self.allowed && other.allowed)
+      || (self.allowed == other.allowed && self.remaining < other.remaining)
+  }
+
+  pub fn denied(quota: Quota) -> Self {
+    Self {
+      allowed: false,
+      limit: quota.limit,
+      remaining: 0,
+      reset_after: quota.window,
+      retry_after: quota.window,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+  /// Generic cell rate algorithm, a token bucket refilled continuously that allows
+  /// bursts up to the limit
+  #[default]
+  Gcra,
+  /// Exact count of the requests within the last window, keeps one timestamp per request
+  SlidingWindow,
+}
+
+/// Milliseconds since the unix epoch. Unlike `Instant` it is comparable across processes
+pub fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Window and emission interval of `quota` in milliseconds
+pub fn gcra_params(quota: Quota) -> (u64, u64) {
+  let window = quota.window.as_millis() as u64;
+  (window, (window / u64::from(quota.limit)).max(1))
+}
+
+/// `tat` is the theoretical arrival time, when the bucket is full again
+pub fn gcra(tat: &mut u64, quota: Quota, now: u64) -> RateLimitDecision {
+  let (window, interval) = gcra_params(quota);
+  let new_tat = (*tat).max(now) + interval;
+  let allowed = new_tat <= now + window;
+  if allowed {
+    *tat = new_tat;
+  }
+  let reset_after = (*tat).max(now) - now;
+  RateLimitDecision {
+    allowed,
+    limit: quota.limit,
+    remaining: (window.saturating_sub(reset_after) / interval) as u32,
+    reset_after: Duration::from_millis(reset_after),
+    retry_after: Duration::from_millis((new_tat - now).saturating_sub(window)),
+  }
+}
+
+/// `hits` are the times of the requests within the window, oldest first
+pub fn sliding_window(hits: &mut VecDeque<u64>, quota: Quota, now: u64) -> RateLimitDecision {
+  let window = quota.window.as_millis() as u64;
+  while hits.front().is_some_and(|&hit| hit + window <= now) {
+    hits.pop_front();
+  }
+  let allowed = hits.len() < quota.limit as usize;
+  if allowed {
+    hits.push_back(now);
+  }
+  let expiry = |hit: Option<&u64>| Duration::from_millis(hit.map_or(0, |&hit| hit + window - now));
+  RateLimitDecision {
+    allowed,
+    limit: quota.limit,
+    remaining: quota.limit.saturating_sub(hits.len() as u32),
+    reset_after: expiry(hits.back()),
+    retry_after: if allowed {
+      Duration::ZERO
+    } else {
+      expiry(hits.front())
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_quotas() {
+    let quota = |s: &str| s.parse::<Quota>().map(|q| (q.limit, q.window.as_secs()));
+    assert_eq!(quota("5/min"), Ok((5, 60)));
+    assert_eq!(quota(" 10 / 30s "), Ok((10, 30)));
+    assert_eq!(quota("3/10min"), Ok((3, 600)));
+    assert_eq!(quota("100/hour"), Ok((100, 3600)));
+    for invalid in [
+      "",
+      "5",
+      "5/",
+      "5/0s",
+      "0/min",
+      "5/fortnight",
+      "x/min",
+      "-1/min",
+    ] {
+      assert!(quota(invalid).is_err(), "{invalid}");
+    }
+  }
+
+  #[test]
+  fn gcra_allows_a_burst_then_refills_one_by_one() {
+    let quota = "3/3s".parse().unwrap();
+    let mut tat = 0;
+    let now = 10_000;
+    for remaining in [2, 1, 0] {
+      let decision = gcra(&mut tat, quota, now);
+      assert!(decision.allowed);
+      assert_eq!(decision.remaining, remaining);
+    }
+    let denied = gcra(&mut tat, quota, now);
+    assert!(!denied.allowed);
+    assert_eq!(denied.retry_after, Duration::from_secs(1));
+    assert_eq!(denied.reset_after, Duration::from_secs(3));
+    assert!(gcra(&mut tat, quota, now + 1000).allowed);
+    assert!(!gcra(&mut tat, quota, now + 1000).allowed);
+  }
+
+  #[test]
+  fn sliding_window_counts_the_requests_of_the_last_window() {
+    let quota = "2/10s".parse().unwrap();
+    let mut hits = VecDeque::new();
+    assert!(sliding_window(&mut hits, quota, 0).allowed);
+    assert!(sliding_window(&mut hits, quota, 4_000).allowed);
+    let denied = sliding_window(&mut hits, quota, 5_000);
+    assert!(!denied.allowed);
+    assert_eq!(denied.retry_after, Duration::from_secs(5));
+    assert_eq!(hits.len(), 2);
+    assert!(sliding_window(&mut hits, quota, 10_000).allowed);
+    assert!(!sliding_window(&mut hits, quota, 13_999).allowed);
+  }
+
+  #[test]
+  fn memory_store_evicts_refilled_buckets() {
+    let store = MemoryStore::default();
+    let quota = "2/10s".parse().unwrap();
+    for algorithm in [RateLimitAlgorithm::Gcra, RateLimitAlgorithm::SlidingWindow] {
+      store.check_at(&format!("{algorithm:?}:a"), quota, algorithm, 0);
+      store.check_at(&format!("{algorithm:?}:b"), quota, algorithm, 5_000);
+    }
+    assert_eq!(store.len(), 4);
+    // A single request is refilled after one emission interval by GCRA, after the whole
+    // window by the sliding window
+    store.evict_expired_at(9_000);
+    assert_eq!(store.len(), 3);
+    store.evict_expired_at(15_000);
+    assert!(store.is_empty());
+  }
+}
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+
+use dashmap::DashMap;
+
+use crate::{gcra, sliding_window, Quota, RateLimitAlgorithm, RateLimitDecision};
+
+#[derive(Debug)]
+enum Bucket {
+  Gcra(u64),
+  Log(VecDeque<u64>),
+}
+
+#[derive(Debug)]
+struct Entry {
+  bucket: Bucket,
+  expires_at: u64,
+}
+
+/// In-process store. Entries live in a sharded map so concurrent requests for different
+/// keys rarely contend
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+  entries: DashMap<String, Entry>,
+}
+
+impl MemoryStore {
+  /// Counts a request for `key` at `now`, in milliseconds since the unix epoch
+  pub fn check_at(
+    &self,
+    key: &str,
+    quota: Quota,
+    algorithm: RateLimitAlgorithm,
+    now: u64,
+  ) -> RateLimitDecision {
+    let mut entry = match self.entries.get_mut(key) {
+      Some(entry) => entry,
+      None => self
+        .entries
+        .entry(key.to_string())
+        .or_insert_with(|| Entry {
+          bucket: match algorithm {
+            RateLimitAlgorithm::Gcra => Bucket::Gcra(now),
+            RateLimitAlgorithm::SlidingWindow => Bucket::Log(VecDeque::new()),
+          },
+          expires_at: now,
+        }),
+    };
+    let decision = match &mut entry.bucket {
+      Bucket::Gcra(tat) => gcra(tat, quota, now),
+      Bucket::Log(hits) => sliding_window(hits, quota, now),
+    };
+    entry.expires_at = now + decision.reset_after.as_millis() as u64;
+    decision
+  }
+
+  /// Drops the entries whose quota is fully available again at `now`
+  pub fn evict_expired_at(&self, now: u64) {
+    self.entries.retain(|_, entry| entry.expires_at > now);
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
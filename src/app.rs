@@ -1,109 +1,336 @@
 //! app
-use std::{
-  collections::HashMap,
-  sync::{Arc, Mutex},
-  time::{Duration, Instant},
-};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::{
   api::modify_api,
   components::{
-    basis,
+    audit, avatar, basis, oauth, oidc, passkey, session,
     user::{self},
   },
   config::EnvConfig,
   error::AppError,
+  helpers::{
+    email::Mailer,
+    header::{extract_host, parse_trusted_proxy},
+    identicon::IdenticonStyle,
+    oidc::{providers, OidcProvider},
+    password::Passwords,
+    password_policy::PasswordPolicy,
+    webauthn::RelyingParty,
+  },
+  listener::{self, Listener},
+  metrics,
+  middlewares::{
+    self,
+    metrics::Metrics,
+    rate_limit::{self, RateLimit, RateLimiter},
+    request_id::{RequestId, SetRequestId},
+    trace::{Trace, TraceId},
+  },
   repository::RepositoryManager,
+  storage::{self, Storage},
+  tls,
 };
 
-use actix_cors::Cors;
 use actix_web::{
+  http::header,
   middleware,
   web::{self},
-  App, HttpServer,
+  App, HttpMessage, HttpRequest, HttpResponse, HttpServer,
 };
-use sea_orm::Database;
+use futures_util::future::try_join_all;
+use ipnet::IpNet;
+use sea_orm::{Database, DatabaseConnection};
 use utoipa_actix_web::{service_config::ServiceConfig, AppExt};
 use utoipa_swagger_ui::SwaggerUi;
 
-#[derive(Debug)]
-pub struct RateLimiter {
-  qps: u64,
-  counter: Mutex<HashMap<String, (usize, Instant)>>,
-}
-
-impl RateLimiter {
-  fn new(qps: u64) -> Self {
-    RateLimiter {
-      qps,
-      counter: Mutex::new(HashMap::new()),
-    }
-  }
-  pub fn check_and_update(&self, client_ip: &str, count: usize) -> bool {
-    let mut counter = self.counter.lock().unwrap();
-    counter.retain(|_, &mut (_, timestamp)| timestamp.elapsed() < Duration::from_secs(self.qps));
-    match counter.get_mut(client_ip) {
-      Some((cnt, timestamp)) => {
-        if *cnt >= count {
-          false
-        } else {
-          *cnt += 1;
-          *timestamp = Instant::now();
-          true
-        }
-      }
-      None => {
-        counter.insert(client_ip.to_string(), (1, Instant::now()));
-        true
-      }
-    }
-  }
-}
-
 #[derive(Debug, Clone)]
 pub struct AppState {
   pub repo: RepositoryManager,
   pub rate_limiter: Arc<RateLimiter>,
+  pub trusted_proxies: Arc<Vec<IpNet>>,
   pub jwt_token: String,
+  pub metrics_token: Option<String>,
+  pub oidc_providers: Arc<HashMap<String, OidcProvider>>,
+  pub magic_link_url: Option<String>,
+  pub email_change_url: Option<String>,
+  /// `None` when mail is not configured
+  pub mailer: Option<Mailer>,
+  pub relying_party: Option<RelyingParty>,
+  pub passwords: Passwords,
+  pub password_policy: Arc<PasswordPolicy>,
+  pub storage: Arc<dyn Storage>,
+  /// URL prefix of the stored files
+  pub storage_url: String,
+  pub avatar_max_size: usize,
+  pub avatar_sizes: Arc<Vec<u32>>,
+  pub avatar_style: IdenticonStyle,
 }
 
 pub fn config_app(cfg: &mut ServiceConfig) {
   cfg.configure(basis::config);
+  cfg.configure(session::config);
+  cfg.configure(oidc::config);
+  cfg.configure(passkey::config);
+  cfg.configure(oauth::config);
+  cfg.configure(avatar::config);
   cfg.configure(user::config);
+  cfg.configure(audit::config);
+}
+
+/// The default access log format followed by the trace and request ids, the line is written
+/// after the request span closed
+fn access_log() -> middleware::Logger {
+  middleware::Logger::new(
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T trace_id=%{trace_id}xi request_id=%{request_id}xi"#,
+  )
+  .custom_request_replace("trace_id", |req| {
+    req
+      .extensions()
+      .get::<TraceId>()
+      .map_or_else(|| "-".to_string(), |id| id.0.clone())
+  })
+  .custom_request_replace("request_id", |req| {
+    req
+      .extensions()
+      .get::<RequestId>()
+      .map_or_else(|| "-".to_string(), |id| id.0.clone())
+  })
+}
+
+/// Redirects plain HTTP requests to the same host and path on the HTTPS listener
+async fn redirect_to_https(req: HttpRequest, tls_port: u16) -> HttpResponse {
+  let host = extract_host(&req);
+  let host = match host.rsplit_once(':') {
+    Some((name, _)) if !host.ends_with(']') => name,
+    _ => host.as_str(),
+  };
+  let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+  let location = if tls_port == 443 {
+    format!("https://{host}{path}")
+  } else {
+    format!("https://{host}:{tls_port}{path}")
+  };
+  HttpResponse::PermanentRedirect()
+    .insert_header((header::LOCATION, location))
+    .finish()
+}
+
+/// `avatar_sizes`, at least one, each between 1 pixel and the largest decoded image
+fn avatar_sizes(config: &EnvConfig) -> Result<Vec<u32>, AppError> {
+  let (sizes, max) = (&config.avatar_sizes, avatar::service::MAX_DIMENSION);
+  if sizes.is_empty() || sizes.iter().any(|size| !(1..=max).contains(size)) {
+    tracing::error!("AVATAR_SIZES must list sizes between 1 and {max}, got {sizes:?}");
+    return Err(AppError::Error);
+  }
+  Ok(sizes.clone())
+}
+
+/// State shared by the workers, on the database of `conn`
+pub async fn app_state(config: &EnvConfig, conn: DatabaseConnection) -> Result<AppState, AppError> {
+  let repo = RepositoryManager::new(conn);
+  let rate_limiter = Arc::new(RateLimiter::new(
+    config.rate_limit_algorithm,
+    HashMap::from([
+      ("default".to_string(), config.rate_limit.parse()?),
+      ("login".to_string(), config.rate_limit_login.parse()?),
+      ("register".to_string(), config.rate_limit_register.parse()?),
+      (
+        "magic_link".to_string(),
+        config.rate_limit_magic_link.parse()?,
+      ),
+      ("upload".to_string(), config.rate_limit_upload.parse()?),
+    ]),
+    config.rate_limit_fail_closed.iter().cloned().collect(),
+    rate_limit::store(config, &repo).await?,
+  ));
+  Ok(AppState {
+    repo,
+    jwt_token: config.jwt_token.clone(),
+    metrics_token: config.metrics_token.clone(),
+    rate_limiter,
+    oidc_providers: Arc::new(providers(config)?),
+    magic_link_url: config.magic_link_url.clone(),
+    email_change_url: config.email_change_url.clone(),
+    mailer: Mailer::from_config(config)?,
+    relying_party: RelyingParty::from_config(config),
+    passwords: Passwords::from_config(config),
+    password_policy: Arc::new(PasswordPolicy::from_config(config)?),
+    storage: storage::storage(config)?,
+    storage_url: config.storage_url.trim_end_matches('/').to_string(),
+    avatar_max_size: config.avatar_max_size,
+    avatar_sizes: Arc::new(avatar_sizes(config)?),
+    avatar_style: config.avatar_style,
+    trusted_proxies: Arc::new(
+      config
+        .trusted_proxies
+        .iter()
+        .map(|proxy| parse_trusted_proxy(proxy))
+        .collect::<Result<_, _>>()?,
+    ),
+  })
 }
 
-pub async fn start() -> Result<(), AppError> {
+pub async fn start(config: EnvConfig) -> Result<(), AppError> {
+  metrics::init();
+  let conn = Database::connect(&config.database_url).await?;
+  conn.ping().await?;
+  let state = app_state(&config, conn).await?;
+  state.rate_limiter.spawn_eviction(Duration::from_secs(60));
+  middlewares::cors::check(&config)?;
+  let tls_config = tls::from_config(&config)?;
+  let http_listeners = listener::http_listeners(&config)?;
+  let https_listeners = match tls_config {
+    Some(_) => listener::https_listeners(&config)?,
+    None => vec![],
+  };
   let EnvConfig {
     workers,
-    host,
-    port,
-    database_url,
-    jwt_token,
-    ipqps,
+    tls_port,
+    tls_redirect_http,
+    metrics_host,
+    metrics_port,
     ..
-  } = EnvConfig::load_env()?;
-  let conn = Database::connect(database_url).await?;
-  conn.ping().await?;
-  let state = AppState {
-    repo: RepositoryManager::new(conn),
-    jwt_token,
-    rate_limiter: Arc::new(RateLimiter::new(ipqps)),
-  };
-  HttpServer::new(move || {
-    let (app, mut api) = App::new()
+  } = config;
+  let public_metrics = metrics_port.is_none() && config.metrics_token.is_some();
+  let mut servers = vec![];
+  if let Some(metrics_port) = metrics_port {
+    let state = state.clone();
+    let metrics_server = HttpServer::new(move || {
+      App::new()
+        .app_data(web::Data::new(state.clone()))
+        .route("/metrics", web::get().to(metrics::handler))
+    })
+    .workers(1)
+    .bind((metrics_host, metrics_port))?;
+    servers.push(metrics_server.run());
+  }
+  let mut server = HttpServer::new(move || {
+    let (mut app, mut api) = App::new()
       .into_utoipa_app()
       .app_data(web::Data::new(state.clone()))
       .service(utoipa_actix_web::scope("/api/v1").configure(config_app))
       .split_for_parts();
     modify_api(&mut api);
+    if public_metrics {
+      app = app.route("/metrics", web::get().to(metrics::handler));
+    }
     app
-      .wrap(Cors::permissive())
-      .wrap(middleware::Logger::default())
+      .wrap(RateLimit::global())
+      .wrap(middlewares::cors::cors(&config))
+      .wrap(Metrics)
+      .wrap(access_log())
+      .wrap(SetRequestId)
+      .wrap(Trace)
       .service(SwaggerUi::new("/swagger/{_:.*}").url("/api-docs/openapi.json", api))
   })
-  .bind((host, port))?
-  .workers(workers)
-  .run()
-  .await
-  .map_err(AppError::from)
+  .workers(workers);
+  let redirect_only = tls_redirect_http && tls_config.is_some();
+  if let Some(tls_config) = tls_config {
+    for lst in https_listeners {
+      server = server.listen_rustls(lst, tls_config.clone())?;
+    }
+  }
+  if redirect_only {
+    let mut redirect = HttpServer::new(move || {
+      App::new()
+        .wrap(middleware::Logger::default())
+        .default_service(web::to(move |req| redirect_to_https(req, tls_port)))
+    })
+    .workers(1);
+    for lst in http_listeners {
+      redirect = match lst {
+        Listener::Tcp(lst) => redirect.listen(lst)?,
+        #[cfg(unix)]
+        Listener::Unix(lst) => redirect.listen_uds(lst)?,
+      };
+    }
+    servers.push(redirect.run());
+  } else {
+    for lst in http_listeners {
+      server = match lst {
+        Listener::Tcp(lst) => server.listen(lst)?,
+        #[cfg(unix)]
+        Listener::Unix(lst) => server.listen_uds(lst)?,
+      };
+    }
+  }
+  servers.push(server.run());
+  try_join_all(servers)
+    .await
+    .map(|_| ())
+    .map_err(AppError::from)
+}
+
+#[cfg(test)]
+pub mod testing {
+  use migration::{Migrator, MigratorTrait};
+
+  use super::*;
+  use crate::{
+    components::{audit::model::AuditContext, user},
+    entity::prelude::UserModel,
+  };
+
+  /// Password of the users of [`user`]
+  pub const PASSWORD: &str = "violet rocket harbor";
+
+  /// State on a new, migrated SQLite database in memory, configured by `vars` on top of the
+  /// required variables. Each call gets its own database, gone with its pool, so nothing is
+  /// left on disk
+  pub async fn app_state(vars: &[(&str, &str)]) -> AppState {
+    let mut env = vec![
+      ("DATABASE_URL".to_string(), "sqlite::memory:".to_string()),
+      ("JWT_TOKEN".to_string(), "test".to_string()),
+    ];
+    env.extend(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let config: EnvConfig = envy::from_iter(env).unwrap();
+    let conn = Database::connect(&config.database_url).await.unwrap();
+    Migrator::up(&conn, None).await.unwrap();
+    super::app_state(&config, conn).await.unwrap()
+  }
+
+  /// Context of a request without any client details
+  pub fn ctx() -> AuditContext {
+    AuditContext {
+      ip: None,
+      user_agent: None,
+      request_id: None,
+    }
+  }
+
+  /// A verified user with [`PASSWORD`], the first one of a state is root
+  pub async fn user(state: &AppState, nickname: &str, email: &str) -> UserModel {
+    let hashed = state.passwords.hash(PASSWORD).await.unwrap();
+    let (nickname, email) = (nickname.to_string(), email.to_string());
+    user::service::create_user(state, nickname, email, hashed, true)
+      .await
+      .unwrap()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(avatar_sizes: &str) -> EnvConfig {
+    let vars = [
+      ("DATABASE_URL", "sqlite::memory:"),
+      ("JWT_TOKEN", "test"),
+      ("AVATAR_SIZES", avatar_sizes),
+    ];
+    envy::from_iter(vars.map(|(k, v)| (k.to_string(), v.to_string()))).unwrap()
+  }
+
+  #[test]
+  fn avatar_sizes_are_listed_and_decodable() {
+    assert_eq!(avatar_sizes(&config("256,64")).unwrap(), [256, 64]);
+    assert_eq!(avatar_sizes(&config("1,8192")).unwrap(), [1, 8192]);
+    for sizes in ["0", "256,0", "8193"] {
+      assert!(avatar_sizes(&config(sizes)).is_err(), "{sizes}");
+    }
+    let mut empty = config("256");
+    empty.avatar_sizes.clear();
+    assert!(avatar_sizes(&empty).is_err());
+  }
 }
@@ -0,0 +1,34 @@
+use actix_web::{
+  get,
+  web::{Data, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::audit::{model::*, service},
+  helpers::header::extract_token,
+  response::Response,
+};
+
+/// Audit log, newest first. Only for admin and root users
+#[utoipa::path(
+  tag = "Audit",
+  params(AuditLogQuery),
+  responses((status = OK, body = Response<AuditLogResponseData>)),
+)]
+#[get("/audit-logs")]
+#[tracing::instrument(skip_all)]
+pub async fn get_audit_logs(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<AuditLogQuery>,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_audit_logs(&state, token, query.into_inner()).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
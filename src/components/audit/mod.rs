@@ -0,0 +1,9 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_audit_logs);
+}
@@ -0,0 +1,142 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+  dev::Payload, http::header::USER_AGENT, web::Data, FromRequest, HttpMessage, HttpRequest,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+  app::AppState, entity::prelude::AuditLogModel, helpers::header::extract_ip,
+  middlewares::request_id::RequestId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+  Login,
+  LoginFailed,
+  ProfileUpdate,
+  RoleChange,
+  UserDelete,
+  EmailChange,
+  SessionRevoke,
+  ApiKeyCreate,
+  ApiKeyRevoke,
+  IdentityLink,
+  IdentityUnlink,
+  OauthClientCreate,
+  OauthClientRevoke,
+  OauthAuthorize,
+  PasskeyRegister,
+  PasskeyDelete,
+  Reauth,
+}
+
+impl AuditAction {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Login => "login",
+      Self::LoginFailed => "login_failed",
+      Self::ProfileUpdate => "profile_update",
+      Self::RoleChange => "role_change",
+      Self::UserDelete => "user_delete",
+      Self::EmailChange => "email_change",
+      Self::SessionRevoke => "session_revoke",
+      Self::ApiKeyCreate => "api_key_create",
+      Self::ApiKeyRevoke => "api_key_revoke",
+      Self::IdentityLink => "identity_link",
+      Self::IdentityUnlink => "identity_unlink",
+      Self::OauthClientCreate => "oauth_client_create",
+      Self::OauthClientRevoke => "oauth_client_revoke",
+      Self::OauthAuthorize => "oauth_authorize",
+      Self::PasskeyRegister => "passkey_register",
+      Self::PasskeyDelete => "passkey_delete",
+      Self::Reauth => "reauth",
+    }
+  }
+}
+
+/// Where an audited request came from
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  pub request_id: Option<String>,
+}
+
+impl FromRequest for AuditContext {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<Self, Self::Error>>;
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    let trusted_proxies = req
+      .app_data::<Data<AppState>>()
+      .map(|state| state.trusted_proxies.as_slice())
+      .unwrap_or_default();
+    ready(Ok(AuditContext {
+      ip: extract_ip(req, trusted_proxies).map(|ip| ip.to_string()),
+      user_agent: req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from),
+      request_id: req.extensions().get::<RequestId>().map(|id| id.0.clone()),
+    }))
+  }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+  /// `user_id` of the user who acted
+  pub actor: Option<String>,
+  /// `user_id` of the user acted upon
+  pub target: Option<String>,
+  pub action: Option<AuditAction>,
+  /// Inclusive, RFC 3339
+  pub from: Option<DateTime<Utc>>,
+  /// Exclusive, RFC 3339
+  pub to: Option<DateTime<Utc>>,
+  /// Starts at 1
+  pub page: Option<u64>,
+  /// At most 100, 20 by default
+  pub per_page: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditLogEntry {
+  pub id: u32,
+  pub actor_id: Option<String>,
+  pub target_id: Option<String>,
+  pub action: String,
+  /// `{ field: { before, after } }`
+  pub diff: Option<Value>,
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  pub request_id: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<AuditLogModel> for AuditLogEntry {
+  fn from(model: AuditLogModel) -> Self {
+    Self {
+      id: model.id,
+      actor_id: model.actor_id,
+      target_id: model.target_id,
+      action: model.action,
+      diff: model.diff,
+      ip: model.ip,
+      user_agent: model.user_agent,
+      request_id: model.request_id,
+      created_at: model.created_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditLogResponseData {
+  pub total: u64,
+  pub entries: Vec<AuditLogEntry>,
+}
@@ -0,0 +1,148 @@
+use helpers::time::utc_now;
+use sea_orm::Set;
+use serde_json::Value;
+
+use crate::{
+  app::AppState,
+  components::{session, user::model::ApiKeyScope},
+  entity::prelude::*,
+  error::AppError,
+  repository::AuditLogFilter,
+};
+
+use super::model::{AuditAction, AuditContext, AuditLogQuery, AuditLogResponseData};
+
+/// Appends an entry to the audit log. Failures are logged and do not fail the audited action,
+/// which has already happened
+#[tracing::instrument(skip_all)]
+pub async fn record(
+  state: &AppState,
+  ctx: &AuditContext,
+  action: AuditAction,
+  actor_id: Option<String>,
+  target_id: Option<String>,
+  diff: Option<Value>,
+) {
+  let entry = AuditLogActiveModel {
+    actor_id: Set(actor_id),
+    target_id: Set(target_id),
+    action: Set(action.as_str().to_string()),
+    diff: Set(diff),
+    ip: Set(ctx.ip.clone()),
+    user_agent: Set(ctx.user_agent.clone()),
+    request_id: Set(ctx.request_id.clone()),
+    created_at: Set(utc_now()),
+    ..Default::default()
+  };
+  if let Err(e) = state.repo.audit_log().create(entry).await {
+    tracing::error!("Could not write audit log {}: {:#?}", action.as_str(), e);
+  }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_audit_logs(
+  state: &AppState,
+  token: String,
+  query: AuditLogQuery,
+) -> Result<AuditLogResponseData, AppError> {
+  let email = session::service::authorize(state, &token, ApiKeyScope::Admin).await?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_email(&email)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+  if !matches!(user.r#type.as_str(), "admin" | "root") {
+    return Err(AppError::Forbidden);
+  }
+  let filter = AuditLogFilter {
+    actor_id: query.actor,
+    target_id: query.target,
+    action: query.action.map(|action| action.as_str().to_string()),
+    from: query.from,
+    to: query.to,
+  };
+  let page = query.page.unwrap_or(1).max(1) - 1;
+  let per_page = query.per_page.unwrap_or(20).clamp(1, 100);
+  let (entries, total) = state.repo.audit_log().find(filter, page, per_page).await?;
+  Ok(AuditLogResponseData {
+    total,
+    entries: entries.into_iter().map(Into::into).collect(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::testing::{self, PASSWORD},
+    components::user,
+  };
+
+  use super::*;
+
+  /// A request with every client detail, each recorded with the entry
+  fn ctx() -> AuditContext {
+    AuditContext {
+      ip: Some("203.0.113.7".to_string()),
+      user_agent: Some("curl".to_string()),
+      request_id: Some("req-1".to_string()),
+    }
+  }
+
+  fn query(action: Option<AuditAction>, actor: Option<String>) -> AuditLogQuery {
+    AuditLogQuery {
+      actor,
+      target: None,
+      action,
+      from: None,
+      to: None,
+      page: None,
+      per_page: None,
+    }
+  }
+
+  #[actix_web::test]
+  async fn logins_are_recorded_and_filtered_for_admins() {
+    let state = testing::app_state(&[]).await;
+    let mut tokens = vec![];
+    for (nickname, email) in [("Root", "root@example.com"), ("Bob", "bob@example.com")] {
+      testing::user(&state, nickname, email).await;
+      let password = PASSWORD.to_string();
+      let login = user::service::user_login(&state, &ctx(), email.into(), password)
+        .await
+        .unwrap();
+      tokens.push(login["token"].as_str().unwrap().to_string());
+    }
+    let wrong = "wrong password".to_string();
+    let email = "bob@example.com".to_string();
+    assert!(user::service::user_login(&state, &ctx(), email, wrong)
+      .await
+      .is_err());
+    let (root, bob) = (tokens[0].clone(), tokens[1].clone());
+    assert!(matches!(
+      get_audit_logs(&state, bob, query(None, None)).await,
+      Err(AppError::Forbidden)
+    ));
+    let failed = Some(AuditAction::LoginFailed);
+    let logs = get_audit_logs(&state, root.clone(), query(failed, None))
+      .await
+      .unwrap();
+    assert_eq!(logs.total, 1);
+    let entry = &logs.entries[0];
+    assert_eq!(entry.action, "login_failed");
+    assert_eq!(entry.ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(entry.user_agent.as_deref(), Some("curl"));
+    assert_eq!(entry.request_id.as_deref(), Some("req-1"));
+    let bob_id = entry.actor_id.clone();
+    assert!(bob_id.is_some());
+    let logs = get_audit_logs(&state, root.clone(), query(None, bob_id))
+      .await
+      .unwrap();
+    assert_eq!(logs.total, 2);
+    let mut paged = query(None, None);
+    paged.per_page = Some(1);
+    paged.page = Some(2);
+    let logs = get_audit_logs(&state, root, paged).await.unwrap();
+    assert_eq!((logs.total, logs.entries.len()), (3, 1));
+  }
+}
@@ -0,0 +1,166 @@
+use actix_multipart::Multipart;
+use actix_web::{
+  get,
+  http::header::{self, CacheControl, CacheDirective, ETag, EntityTag, IfNoneMatch},
+  put,
+  web::{Data, Path},
+  HttpMessage, HttpRequest, HttpResponse,
+};
+use helpers::hash;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::model::AuditContext,
+    avatar::{model::*, service},
+  },
+  error::AppError,
+  helpers::header::extract_token,
+  middlewares::rate_limit::RateLimit,
+  response::Response,
+};
+
+/// Replaces the avatar with the image of the `avatar` field, stored as PNG at every size of
+/// `avatar_sizes`
+#[utoipa::path(
+  tag = "User",
+  request_body(
+    content_type = "multipart/form-data",
+    description = "`avatar`: a JPEG, PNG, GIF or WebP image up to `avatar_max_size` bytes",
+  ),
+  responses((status = OK, body = Response<AvatarResponseData>)),
+)]
+#[put("/user/avatar", wrap = "RateLimit::upload()")]
+#[tracing::instrument(skip_all)]
+pub async fn upload_avatar(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  payload: Multipart,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::upload_avatar(&state, &ctx, token, payload).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// A stored file such as an avatar, or a redirect to it when `storage_url` is elsewhere.
+/// Keys are never reused, so files are cached for good
+#[utoipa::path(
+  tag = "File",
+  params(("key" = String, Path, description = "e.g. `avatars/<user_id>/<version>/256.png`")),
+  responses((status = OK), (status = FOUND), (status = NOT_FOUND)),
+)]
+#[get("/files/{key:.*}")]
+#[tracing::instrument(skip_all)]
+pub async fn get_file(state: Data<AppState>, path: Path<String>) -> HttpResponse {
+  let key = path.into_inner();
+  if let Some(location) = service::file_location(&state, &key) {
+    return HttpResponse::Found()
+      .insert_header((header::LOCATION, location))
+      .finish();
+  }
+  match service::get_file(&state, &key).await {
+    Ok(file) => HttpResponse::Ok()
+      .content_type(file.content_type)
+      .insert_header(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(31536000),
+        CacheDirective::Extension("immutable".to_string(), None),
+      ]))
+      .body(file.bytes),
+    Err(AppError::NotFound) => HttpResponse::NotFound().finish(),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Seconds a generated avatar is used before it is revalidated
+const IDENTICON_MAX_AGE: u32 = 3600;
+
+/// Nothing loads nor runs, even when the SVG is opened as a page
+const IDENTICON_CSP: &str = "default-src 'none'; sandbox";
+
+/// The generated SVG avatar of a user, the default `avatar` of new accounts. It is
+/// revalidated with its `ETag` after an hour, as the initials follow the nickname
+#[utoipa::path(
+  tag = "File",
+  params(("user_id" = String, Path)),
+  responses(
+    (status = OK, content_type = "image/svg+xml", body = String),
+    (status = NOT_MODIFIED),
+    (status = NOT_FOUND),
+  ),
+)]
+#[get("/avatars/{user_id}")]
+#[tracing::instrument(skip_all)]
+pub async fn get_identicon(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<String>,
+) -> HttpResponse {
+  match service::get_identicon(&state, &path.into_inner()).await {
+    Ok(svg) => {
+      let etag = EntityTag::new_strong(hash::blake3(svg.as_bytes())[..32].to_string());
+      let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(IDENTICON_MAX_AGE),
+      ]);
+      let fresh = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+      };
+      if fresh {
+        return HttpResponse::NotModified()
+          .insert_header(ETag(etag))
+          .insert_header(cache_control)
+          .finish();
+      }
+      HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header(ETag(etag))
+        .insert_header(cache_control)
+        // Nicknames end up in the SVG, keep it inert if it is opened as a page
+        .insert_header((header::CONTENT_SECURITY_POLICY, IDENTICON_CSP))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(svg)
+    }
+    Err(AppError::NotFound) => HttpResponse::NotFound().finish(),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{
+    http::StatusCode,
+    test::{call_service, init_service, read_body, TestRequest},
+    App,
+  };
+
+  use crate::app::testing;
+
+  use super::*;
+
+  #[actix_web::test]
+  async fn identicons_cannot_be_sniffed_nor_run_as_a_page() {
+    let state = testing::app_state(&[("AVATAR_STYLE", "initials")]).await;
+    let nickname = "<script>alert(1)</script> Bob";
+    let user = testing::user(&state, nickname, "bob@example.com").await;
+    let app = init_service(App::new().app_data(Data::new(state)).service(get_identicon)).await;
+    let uri = format!("/avatars/{}", user.user_id);
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let value = |name| res.headers().get(name).unwrap().to_str().unwrap();
+    assert_eq!(value(header::CONTENT_TYPE), "image/svg+xml");
+    assert_eq!(value(header::X_CONTENT_TYPE_OPTIONS), "nosniff");
+    assert_eq!(value(header::CONTENT_SECURITY_POLICY), IDENTICON_CSP);
+    let body = read_body(res).await;
+    assert!(!String::from_utf8_lossy(&body).contains("<script"));
+    let res = call_service(&app, TestRequest::get().uri("/avatars/nobody").to_request()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+  }
+}
@@ -0,0 +1,12 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+/// Registered before `user`, `/user/avatar` would otherwise match `/user/{user_id}`
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::upload_avatar);
+  cfg.service(handler::get_file);
+  cfg.service(handler::get_identicon);
+}
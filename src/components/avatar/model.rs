@@ -0,0 +1,16 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct AvatarSize {
+  /// Width and height in pixels
+  pub size: u32,
+  pub url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AvatarResponseData {
+  /// URL of the first of `avatar_sizes`, the new `avatar` of the user
+  pub avatar: String,
+  pub sizes: Vec<AvatarSize>,
+}
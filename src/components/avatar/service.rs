@@ -0,0 +1,215 @@
+use std::io::Cursor;
+
+use actix_multipart::Multipart;
+use actix_web::web;
+use futures_util::TryStreamExt;
+use helpers::uuid::{self, Alphabet};
+use image::{
+  imageops::FilterType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits,
+};
+use sea_orm::{IntoActiveModel, Set};
+use serde_json::json;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    session,
+    user::model::ApiKeyScope,
+  },
+  error::AppError,
+  helpers::identicon,
+  storage::{check_key, StoredFile},
+};
+
+use super::model::{AvatarResponseData, AvatarSize};
+
+/// Path of the generated avatars, `user.avatar` until an image is uploaded
+const IDENTICON_PATH: &str = "/api/v1/avatars";
+
+/// Field of the multipart body holding the image
+const AVATAR_FIELD: &str = "avatar";
+
+const MEDIA_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+const FORMATS: [ImageFormat; 4] = [
+  ImageFormat::Jpeg,
+  ImageFormat::Png,
+  ImageFormat::Gif,
+  ImageFormat::WebP,
+];
+
+/// Largest width and height decoded, a small file can still claim a huge canvas. Also the
+/// largest of `avatar_sizes`
+pub const MAX_DIMENSION: u32 = 8192;
+
+/// Memory the decoder may use
+const MAX_ALLOC: u64 = 256 * 1024 * 1024;
+
+fn unsupported(err: ImageError) -> AppError {
+  tracing::error!("Unsupported image: {err}");
+  AppError::UnsupportedImage
+}
+
+/// The bytes of the `avatar` field, other fields are skipped
+async fn read_avatar(payload: &mut Multipart, max_size: usize) -> Result<Vec<u8>, AppError> {
+  let invalid = |err| {
+    tracing::error!("Invalid multipart body: {err}");
+    AppError::InvalidParameter
+  };
+  while let Some(mut field) = payload.try_next().await.map_err(invalid)? {
+    if field.name() != AVATAR_FIELD {
+      while field.try_next().await.map_err(invalid)?.is_some() {}
+      continue;
+    }
+    let media_type = field.content_type().map(|mime| mime.essence_str());
+    if !media_type.is_some_and(|media_type| MEDIA_TYPES.contains(&media_type)) {
+      return Err(AppError::UnsupportedImage);
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(invalid)? {
+      if bytes.len() + chunk.len() > max_size {
+        return Err(AppError::FileTooLarge);
+      }
+      bytes.extend_from_slice(&chunk);
+    }
+    return Ok(bytes);
+  }
+  Err(AppError::InvalidParameter)
+}
+
+/// Decodes the image whatever its declared type, applies its EXIF orientation and encodes
+/// a PNG square of each size. Metadata such as EXIF is not carried over
+fn resize(bytes: Vec<u8>, sizes: &[u32]) -> Result<Vec<(u32, Vec<u8>)>, AppError> {
+  if sizes.is_empty() || sizes.iter().any(|size| !(1..=MAX_DIMENSION).contains(size)) {
+    tracing::error!("Invalid avatar sizes {sizes:?}");
+    return Err(AppError::Error);
+  }
+  let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+  if !reader
+    .format()
+    .is_some_and(|format| FORMATS.contains(&format))
+  {
+    return Err(AppError::UnsupportedImage);
+  }
+  let mut limits = Limits::default();
+  limits.max_image_width = Some(MAX_DIMENSION);
+  limits.max_image_height = Some(MAX_DIMENSION);
+  limits.max_alloc = Some(MAX_ALLOC);
+  reader.limits(limits);
+  let mut decoder = reader.into_decoder().map_err(unsupported)?;
+  let orientation = decoder.orientation().map_err(unsupported)?;
+  let mut image = DynamicImage::from_decoder(decoder).map_err(unsupported)?;
+  image.apply_orientation(orientation);
+  sizes
+    .iter()
+    .map(|&size| {
+      let resized = image.resize_to_fill(size, size, FilterType::Lanczos3);
+      let mut png = Vec::new();
+      DynamicImage::ImageRgba8(resized.to_rgba8())
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(unsupported)?;
+      Ok((size, png))
+    })
+    .collect()
+}
+
+/// Stores the uploaded image at every size of `avatar_sizes` and makes the first one the
+/// avatar of the user, the files of the previous upload are deleted
+#[tracing::instrument(skip_all)]
+pub async fn upload_avatar(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  mut payload: Multipart,
+) -> Result<AvatarResponseData, AppError> {
+  let email = session::service::authorize(state, &token, ApiKeyScope::Write).await?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_email(&email)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+  let bytes = read_avatar(&mut payload, state.avatar_max_size).await?;
+  let sizes = state.avatar_sizes.clone();
+  let images = web::block(move || resize(bytes, &sizes))
+    .await
+    .map_err(|err| {
+      tracing::error!("{:#?}", err);
+      AppError::Error
+    })??;
+  let prefix = format!("avatars/{}/", user.user_id);
+  let version = uuid::uuid(&Alphabet::NUMBERS_LOWER, 12);
+  let mut sizes = Vec::new();
+  for (size, png) in images {
+    let key = format!("{prefix}{version}/{size}.png");
+    state.storage.put(&key, png, "image/png").await?;
+    sizes.push(AvatarSize {
+      size,
+      url: format!("{}/{key}", state.storage_url),
+    });
+  }
+  let avatar = sizes.first().ok_or(AppError::Error)?.url.clone();
+  let user_id = user.user_id.clone();
+  let previous = user.avatar.clone();
+  let mut active_user = user.into_active_model();
+  active_user.avatar = Set(avatar.clone());
+  state.repo.user().update_user(active_user).await?;
+  // Earlier uploads are the only avatars under the prefix of the user
+  let previous_version = previous
+    .strip_prefix(&format!("{}/{prefix}", state.storage_url))
+    .and_then(|rest| rest.split_once('/'))
+    .map(|(version, _)| version);
+  if let Some(previous_version) = previous_version {
+    for size in state.avatar_sizes.iter() {
+      let key = format!("{prefix}{previous_version}/{size}.png");
+      if let Err(err) = state.storage.delete(&key).await {
+        tracing::error!("Could not delete the previous avatar {key}: {err:?}");
+      }
+    }
+  }
+  let diff = Some(json!({ "avatar": { "before": previous, "after": avatar } }));
+  let user_id = Some(user_id);
+  let action = AuditAction::ProfileUpdate;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(AvatarResponseData { avatar, sizes })
+}
+
+/// Where `GET /files/{key}` redirects to when files are served from elsewhere
+pub fn file_location(state: &AppState, key: &str) -> Option<String> {
+  let external =
+    state.storage_url.starts_with("http://") || state.storage_url.starts_with("https://");
+  external.then(|| format!("{}/{key}", state.storage_url))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_file(state: &AppState, key: &str) -> Result<StoredFile, AppError> {
+  check_key(key)?;
+  state.storage.get(key).await?.ok_or(AppError::NotFound)
+}
+
+/// The generated avatar of a new user
+pub fn identicon_url(user_id: &str) -> String {
+  format!("{IDENTICON_PATH}/{user_id}")
+}
+
+/// The generated avatar of `user_id`, drawn from the account whether or not an image was
+/// uploaded
+#[tracing::instrument(skip_all)]
+pub async fn get_identicon(state: &AppState, user_id: &str) -> Result<String, AppError> {
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::NotFound)?;
+  Ok(identicon::svg(
+    state.avatar_style,
+    &user.user_id,
+    &user.nickname,
+  ))
+}
@@ -1,11 +1,95 @@
-use actix_web::{get, HttpResponse};
+use actix_web::{get, web::Data, HttpResponse};
 
-use crate::components::basis::model::HealthCheckResponseData;
+use crate::{
+  app::AppState,
+  components::basis::{
+    model::{HealthCheckResponseData, HealthStatus, ReadinessResponseData},
+    service,
+  },
+};
 
 #[utoipa::path(tag = "Health", responses((status = OK, description = "成功", body = HealthCheckResponseData)))]
 #[get("/health")]
+#[tracing::instrument(skip_all)]
 async fn health_check() -> HttpResponse {
   HttpResponse::Ok().json(HealthCheckResponseData {
     status: "ok".to_string(),
   })
 }
+
+/// The process is up and serving requests
+#[utoipa::path(tag = "Health", responses((status = OK, description = "成功", body = HealthCheckResponseData)))]
+#[get("/health/live")]
+#[tracing::instrument(skip_all)]
+async fn liveness() -> HttpResponse {
+  HttpResponse::Ok().json(HealthCheckResponseData {
+    status: "ok".to_string(),
+  })
+}
+
+/// Dependencies are reachable: database and migrations applied. The mailer is reported
+/// when configured but does not affect readiness
+#[utoipa::path(
+  tag = "Health",
+  responses(
+    (status = OK, description = "成功", body = ReadinessResponseData),
+    (status = SERVICE_UNAVAILABLE, description = "依赖不可用", body = ReadinessResponseData),
+  )
+)]
+#[get("/health/ready")]
+#[tracing::instrument(skip_all)]
+async fn readiness(state: Data<AppState>) -> HttpResponse {
+  let data = service::readiness(&state).await;
+  match data.status {
+    HealthStatus::Fail => HttpResponse::ServiceUnavailable().json(data),
+    _ => HttpResponse::Ok().json(data),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    App,
+  };
+  use sea_orm::Database;
+  use serde_json::Value;
+
+  use crate::{app::testing, repository::RepositoryManager};
+
+  use super::*;
+
+  async fn ready(state: AppState) -> (StatusCode, Value) {
+    let app = test::init_service(App::new().app_data(Data::new(state)).service(readiness)).await;
+    let req = TestRequest::get().uri("/health/ready").to_request();
+    let res = test::call_service(&app, req).await;
+    (res.status(), test::read_body_json(res).await)
+  }
+
+  #[actix_web::test]
+  async fn ready_reports_every_component_with_its_latency() {
+    let (status, body) = ready(testing::app_state(&[]).await).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+    for component in ["database", "migrations"] {
+      assert_eq!(body[component]["status"], "ok", "{component}");
+      assert!(body[component]["latency_ms"].is_u64(), "{component}");
+      assert!(body[component].get("error").is_none(), "{component}");
+    }
+  }
+
+  #[actix_web::test]
+  async fn pending_migrations_make_the_instance_unready() {
+    let mut state = testing::app_state(&[]).await;
+    state.repo = RepositoryManager::new(Database::connect("sqlite::memory:").await.unwrap());
+    let (status, body) = ready(state).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "fail");
+    assert_eq!(body["database"]["status"], "ok");
+    assert_eq!(body["migrations"]["status"], "fail");
+    assert!(body["migrations"]["latency_ms"].is_u64());
+    let error = body["migrations"]["error"].as_str().unwrap();
+    assert!(error.starts_with("pending: "), "{error}");
+  }
+}
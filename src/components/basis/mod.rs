@@ -6,4 +6,6 @@ use utoipa_actix_web::service_config::ServiceConfig;
 
 pub fn config(cfg: &mut ServiceConfig) {
   cfg.service(handler::health_check);
+  cfg.service(handler::liveness);
+  cfg.service(handler::readiness);
 }
@@ -5,3 +5,31 @@ use utoipa::ToSchema;
 pub struct HealthCheckResponseData {
   pub status: String,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+  Ok,
+  Fail,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ComponentHealth {
+  pub status: HealthStatus,
+  /// Time the check took
+  pub latency_ms: u64,
+  /// Why the check failed
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessResponseData {
+  /// `ok` when the database is reachable and its migrations applied
+  pub status: HealthStatus,
+  pub database: ComponentHealth,
+  pub migrations: ComponentHealth,
+  /// Absent when mail is not configured, a failure does not make the instance unready
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub mailer: Option<ComponentHealth>,
+}
@@ -1 +1,97 @@
+use std::{
+  future::Future,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
 
+use actix_web::{rt::time::timeout, web};
+
+use crate::app::AppState;
+
+use super::model::{ComponentHealth, HealthStatus, ReadinessResponseData};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long the result of an SMTP check is reused, so frequent probes do not each open a
+/// connection to the mail server
+const MAILER_CHECK_TTL: Duration = Duration::from_secs(60);
+
+/// Last SMTP check and when it ran
+static MAILER: Mutex<Option<(Instant, ComponentHealth)>> = Mutex::new(None);
+
+/// Runs a check with [`CHECK_TIMEOUT`], timing it and keeping the reason of a failure
+async fn check<F>(component: &str, fut: F) -> ComponentHealth
+where
+  F: Future<Output = Result<(), String>>,
+{
+  let start = Instant::now();
+  let result = timeout(CHECK_TIMEOUT, fut)
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()));
+  let latency_ms = start.elapsed().as_millis() as u64;
+  match result {
+    Ok(()) => ComponentHealth {
+      status: HealthStatus::Ok,
+      latency_ms,
+      error: None,
+    },
+    Err(err) => {
+      tracing::warn!("Readiness check of {component} failed: {err}");
+      ComponentHealth {
+        status: HealthStatus::Fail,
+        latency_ms,
+        error: Some(err),
+      }
+    }
+  }
+}
+
+/// The SMTP check, reused for [`MAILER_CHECK_TTL`]. `None` when mail is not configured
+async fn mailer(state: &AppState) -> Option<ComponentHealth> {
+  let mailer = state.mailer.clone()?;
+  if let Some((at, health)) = &*MAILER.lock().unwrap() {
+    if at.elapsed() < MAILER_CHECK_TTL {
+      return Some(health.clone());
+    }
+  }
+  let health = check("mailer", async {
+    match web::block(move || mailer.test_connection()).await {
+      Ok(true) => Ok(()),
+      Ok(false) => Err("connection failed".to_string()),
+      Err(e) => Err(e.to_string()),
+    }
+  })
+  .await;
+  *MAILER.lock().unwrap() = Some((Instant::now(), health.clone()));
+  Some(health)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn readiness(state: &AppState) -> ReadinessResponseData {
+  let database = check("database", async {
+    state.repo.ping().await.map_err(|e| e.to_string())
+  });
+  let migrations = check("migrations", async {
+    match state.repo.pending_migrations().await {
+      Ok(pending) if pending.is_empty() => Ok(()),
+      Ok(pending) => Err(format!("pending: {}", pending.join(", "))),
+      Err(e) => Err(e.to_string()),
+    }
+  });
+  let (database, migrations, mailer) = tokio::join!(database, migrations, mailer(state));
+  // The mailer is reported but does not make the instance unready, mail is sent in the
+  // background and an outage of the SMTP server only delays it
+  let ready = [&database, &migrations]
+    .iter()
+    .all(|c| c.status == HealthStatus::Ok);
+  ReadinessResponseData {
+    status: if ready {
+      HealthStatus::Ok
+    } else {
+      HealthStatus::Fail
+    },
+    database,
+    migrations,
+    mailer,
+  }
+}
@@ -1,4 +1,10 @@
 //! components
 
+pub mod audit;
+pub mod avatar;
 pub mod basis;
+pub mod oauth;
+pub mod oidc;
+pub mod passkey;
+pub mod session;
 pub mod user;
@@ -0,0 +1,278 @@
+use actix_web::{
+  delete, get,
+  http::header::AUTHORIZATION,
+  post,
+  web::{Data, Form, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::percent_decode_str;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::model::AuditContext,
+    oauth::{model::*, service},
+  },
+  helpers::header::extract_token,
+  response::Response,
+};
+
+/// Decodes a value of `application/x-www-form-urlencoded`
+fn form_urldecode(value: &str) -> Option<String> {
+  percent_decode_str(&value.replace('+', " "))
+    .decode_utf8()
+    .ok()
+    .map(String::from)
+}
+
+/// Client id and secret from HTTP Basic (`client_secret_basic`), or from the form
+/// (`client_secret_post`, or only the id for public clients). In HTTP Basic both are
+/// form-urlencoded before being joined (RFC 6749 2.3.1)
+fn client_credentials(
+  req: &HttpRequest,
+  client_id: Option<String>,
+  client_secret: Option<String>,
+) -> Option<(String, Option<String>)> {
+  let basic = req
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|h| h.to_str().ok())
+    .and_then(|h| h.strip_prefix("Basic "))
+    .and_then(|h| STANDARD.decode(h).ok())
+    .and_then(|h| String::from_utf8(h).ok());
+  match basic {
+    Some(basic) => {
+      let (id, secret) = basic.split_once(':')?;
+      Some((form_urldecode(id)?, Some(form_urldecode(secret)?)))
+    }
+    None => Some((client_id?, client_secret)),
+  }
+}
+
+/// Registers an OAuth2 client. Only for admin and root users
+#[utoipa::path(
+  tag = "OAuth",
+  responses((status = OK, body = Response<CreateClientResponseData>)),
+)]
+#[post("/oauth/clients")]
+#[tracing::instrument(skip_all)]
+pub async fn create_client(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<CreateClientBody>,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::create_client(&state, &ctx, token, body.into_inner()).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[utoipa::path(
+  tag = "OAuth",
+  responses((status = OK, body = Response<ClientsResponseData>)),
+)]
+#[get("/oauth/clients")]
+#[tracing::instrument(skip_all)]
+pub async fn get_clients(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_clients(&state, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Revokes the client and its tokens
+#[utoipa::path(tag = "OAuth", responses((status = OK)))]
+#[delete("/oauth/clients/{client_id}")]
+#[tracing::instrument(skip_all)]
+pub async fn revoke_client(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<String>,
+) -> HttpResponse {
+  let client_id = path.into_inner();
+  match extract_token(&req) {
+    Ok(token) => match service::revoke_client(&state, &ctx, token, client_id).await {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Consent screen data for an authorization request, the user must be logged in
+#[utoipa::path(
+  tag = "OAuth",
+  params(AuthorizeQuery),
+  responses((status = OK, body = Response<ConsentResponseData>)),
+)]
+#[get("/oauth/authorize")]
+#[tracing::instrument(skip_all)]
+pub async fn get_consent(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<AuthorizeQuery>,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_consent(&state, token, query.into_inner()).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Approves or denies an authorization request
+#[utoipa::path(
+  tag = "OAuth",
+  responses((status = OK, body = Response<AuthorizeResponseData>)),
+)]
+#[post("/oauth/authorize")]
+#[tracing::instrument(skip_all)]
+pub async fn authorize(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<AuthorizeBody>,
+) -> HttpResponse {
+  let Json(AuthorizeBody { query, approve }) = body;
+  match extract_token(&req) {
+    Ok(token) => match service::authorize(&state, &ctx, token, query, approve).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Token endpoint of RFC 6749, errors use its status codes and format
+#[utoipa::path(
+  tag = "OAuth",
+  request_body(content = TokenForm, content_type = "application/x-www-form-urlencoded"),
+  responses(
+    (status = OK, body = TokenResponse),
+    (status = BAD_REQUEST, body = OAuthError),
+  ),
+)]
+#[post("/oauth/token")]
+#[tracing::instrument(skip_all)]
+pub async fn exchange_token(
+  req: HttpRequest,
+  state: Data<AppState>,
+  form: Form<TokenForm>,
+) -> HttpResponse {
+  let mut form = form.into_inner();
+  let credentials = client_credentials(&req, form.client_id.take(), form.client_secret.take());
+  match service::token(&state, credentials, form).await {
+    Ok(data) => HttpResponse::Ok()
+      .insert_header(("Cache-Control", "no-store"))
+      .json(data),
+    Err(err) => err.response(),
+  }
+}
+
+/// Token introspection of RFC 7662
+#[utoipa::path(
+  tag = "OAuth",
+  request_body(content = TokenHintForm, content_type = "application/x-www-form-urlencoded"),
+  responses((status = OK, body = IntrospectionResponse)),
+)]
+#[post("/oauth/introspect")]
+#[tracing::instrument(skip_all)]
+pub async fn introspect(
+  req: HttpRequest,
+  state: Data<AppState>,
+  form: Form<TokenHintForm>,
+) -> HttpResponse {
+  let mut form = form.into_inner();
+  let credentials = client_credentials(&req, form.client_id.take(), form.client_secret.take());
+  match service::introspect(&state, credentials, form).await {
+    Ok(data) => HttpResponse::Ok().json(data),
+    Err(err) => err.response(),
+  }
+}
+
+/// Token revocation of RFC 7009
+#[utoipa::path(
+  tag = "OAuth",
+  request_body(content = TokenHintForm, content_type = "application/x-www-form-urlencoded"),
+  responses((status = OK)),
+)]
+#[post("/oauth/revoke")]
+#[tracing::instrument(skip_all)]
+pub async fn revoke(
+  req: HttpRequest,
+  state: Data<AppState>,
+  form: Form<TokenHintForm>,
+) -> HttpResponse {
+  let mut form = form.into_inner();
+  let credentials = client_credentials(&req, form.client_id.take(), form.client_secret.take());
+  match service::revoke(&state, credentials, form).await {
+    Ok(_) => HttpResponse::Ok().finish(),
+    Err(err) => err.response(),
+  }
+}
+
+/// OpenID Connect userinfo, for access tokens with the `openid` scope
+#[utoipa::path(
+  tag = "OAuth",
+  responses(
+    (status = OK, body = UserInfoResponse),
+    (status = UNAUTHORIZED, body = OAuthError),
+  ),
+)]
+#[get("/oauth/userinfo")]
+#[tracing::instrument(skip_all)]
+pub async fn userinfo(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  let Ok(token) = extract_token(&req) else {
+    return OAuthError::invalid_token().response();
+  };
+  match service::userinfo(&state, &token).await {
+    Ok(data) => HttpResponse::Ok().json(data),
+    Err(err) => err.response(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::test::TestRequest;
+
+  use super::*;
+
+  fn basic(credentials: &str) -> HttpRequest {
+    let header = format!("Basic {}", STANDARD.encode(credentials));
+    TestRequest::default()
+      .insert_header((AUTHORIZATION, header))
+      .to_http_request()
+  }
+
+  #[test]
+  fn basic_credentials_are_form_urldecoded() {
+    let req = basic("wiki%3Aprod:s%2Bcr%25t+key");
+    assert_eq!(
+      client_credentials(&req, None, None),
+      Some(("wiki:prod".to_string(), Some("s+cr%t key".to_string())))
+    );
+  }
+
+  #[test]
+  fn malformed_basic_credentials_are_refused() {
+    assert_eq!(client_credentials(&basic("no separator"), None, None), None);
+    assert_eq!(client_credentials(&basic("id:%FF"), None, None), None);
+  }
+
+  #[test]
+  fn form_credentials_without_basic() {
+    let req = TestRequest::default().to_http_request();
+    let credentials = client_credentials(&req, Some("wiki".to_string()), None);
+    assert_eq!(credentials, Some(("wiki".to_string(), None)));
+  }
+}
@@ -0,0 +1,17 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::create_client);
+  cfg.service(handler::get_clients);
+  cfg.service(handler::revoke_client);
+  cfg.service(handler::get_consent);
+  cfg.service(handler::authorize);
+  cfg.service(handler::exchange_token);
+  cfg.service(handler::introspect);
+  cfg.service(handler::revoke);
+  cfg.service(handler::userinfo);
+}
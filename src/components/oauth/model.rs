@@ -0,0 +1,271 @@
+use actix_web::{http::StatusCode, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{entity::prelude::OauthClientModel, error::AppError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantType {
+  /// With PKCE, for apps acting on behalf of a user
+  AuthorizationCode,
+  /// For confidential clients acting on their own behalf
+  ClientCredentials,
+}
+
+impl GrantType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::AuthorizationCode => "authorization_code",
+      Self::ClientCredentials => "client_credentials",
+    }
+  }
+
+  pub fn parse(grant_type: &str) -> Option<Self> {
+    match grant_type {
+      "authorization_code" => Some(Self::AuthorizationCode),
+      "client_credentials" => Some(Self::ClientCredentials),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateClientBody {
+  pub name: String,
+  /// Exact urls the authorization code may be sent to
+  #[serde(default)]
+  pub redirect_uris: Vec<String>,
+  pub grant_types: Vec<GrantType>,
+  /// Scopes the client may request, `openid`, `profile` and `email` unlock userinfo
+  pub scopes: Vec<String>,
+  /// Public clients (SPAs, mobile apps) get no secret and can only use
+  /// `authorization_code`
+  #[serde(default)]
+  pub public: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClientItem {
+  pub client_id: String,
+  pub name: String,
+  pub public: bool,
+  pub redirect_uris: Vec<String>,
+  pub grant_types: Vec<GrantType>,
+  pub scopes: Vec<String>,
+  pub created_by: String,
+  pub created_at: DateTime<Utc>,
+  pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<OauthClientModel> for ClientItem {
+  fn from(client: OauthClientModel) -> Self {
+    Self {
+      public: client.client_secret_hash.is_none(),
+      redirect_uris: client
+        .redirect_uris
+        .split_whitespace()
+        .map(String::from)
+        .collect(),
+      grant_types: client
+        .grant_types
+        .split_whitespace()
+        .filter_map(GrantType::parse)
+        .collect(),
+      scopes: client.scopes.split_whitespace().map(String::from).collect(),
+      client_id: client.client_id,
+      name: client.name,
+      created_by: client.created_by,
+      created_at: client.created_at,
+      revoked_at: client.revoked_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreateClientResponseData {
+  /// Only returned here, absent for public clients
+  pub client_secret: Option<String>,
+  pub client: ClientItem,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClientsResponseData {
+  pub clients: Vec<ClientItem>,
+}
+
+/// Authorization request of RFC 6749 and RFC 7636, forwarded by the consent screen
+#[derive(Debug, Clone, Deserialize, ToSchema, IntoParams)]
+pub struct AuthorizeQuery {
+  /// Only `code`
+  pub response_type: String,
+  pub client_id: String,
+  pub redirect_uri: String,
+  /// Space separated, all the scopes of the client when omitted
+  pub scope: Option<String>,
+  pub state: Option<String>,
+  pub code_challenge: String,
+  /// Only `S256`
+  pub code_challenge_method: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConsentClient {
+  pub client_id: String,
+  pub name: String,
+}
+
+/// What the consent screen shows
+#[derive(Serialize, ToSchema)]
+pub struct ConsentResponseData {
+  pub client: ConsentClient,
+  pub scopes: Vec<String>,
+  pub redirect_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AuthorizeBody {
+  #[serde(flatten)]
+  pub query: AuthorizeQuery,
+  /// The user's decision
+  pub approve: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuthorizeResponseData {
+  /// Send the user there, carries the `code` or `error=access_denied`
+  pub redirect_uri: String,
+}
+
+/// Token request, `application/x-www-form-urlencoded`. Clients authenticate with HTTP Basic
+/// or `client_id` and `client_secret`
+#[derive(Deserialize, ToSchema)]
+pub struct TokenForm {
+  pub grant_type: String,
+  pub code: Option<String>,
+  pub redirect_uri: Option<String>,
+  pub code_verifier: Option<String>,
+  pub scope: Option<String>,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+  pub access_token: String,
+  /// Always `Bearer`
+  pub token_type: &'static str,
+  pub expires_in: i64,
+  pub scope: String,
+}
+
+/// Introspection (RFC 7662) and revocation (RFC 7009) request
+#[derive(Deserialize, ToSchema)]
+pub struct TokenHintForm {
+  pub token: String,
+  /// Only access tokens are issued, there is no other kind to look up first. Accepted and
+  /// ignored, as RFC 7009 allows
+  #[allow(dead_code)]
+  pub token_type_hint: Option<String>,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Default, ToSchema)]
+pub struct IntrospectionResponse {
+  pub active: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  /// `user_id`, absent for client credentials
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sub: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token_type: Option<&'static str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exp: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub iat: Option<i64>,
+}
+
+/// OpenID Connect userinfo, the claims follow the scopes of the token
+#[derive(Serialize, ToSchema)]
+pub struct UserInfoResponse {
+  /// `user_id`
+  pub sub: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub picture: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub email: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub email_verified: Option<bool>,
+}
+
+/// Error of the protocol endpoints, in the format of RFC 6749 section 5.2
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthError {
+  pub error: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error_description: Option<&'static str>,
+}
+
+impl OAuthError {
+  pub fn new(error: &'static str, error_description: &'static str) -> Self {
+    Self {
+      error,
+      error_description: Some(error_description),
+    }
+  }
+
+  pub fn invalid_client() -> Self {
+    Self::new("invalid_client", "Client authentication failed")
+  }
+
+  pub fn invalid_grant() -> Self {
+    Self::new(
+      "invalid_grant",
+      "Invalid, expired or already used authorization code",
+    )
+  }
+
+  pub fn invalid_token() -> Self {
+    Self::new("invalid_token", "Invalid or expired access token")
+  }
+
+  pub fn status_code(&self) -> StatusCode {
+    match self.error {
+      "invalid_client" | "invalid_token" => StatusCode::UNAUTHORIZED,
+      "insufficient_scope" => StatusCode::FORBIDDEN,
+      "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+      _ => StatusCode::BAD_REQUEST,
+    }
+  }
+
+  pub fn response(&self) -> HttpResponse {
+    let mut res = HttpResponse::build(self.status_code());
+    if self.error == "invalid_token" {
+      res.insert_header(("WWW-Authenticate", r#"Bearer error="invalid_token""#));
+    }
+    res.insert_header(("Cache-Control", "no-store")).json(self)
+  }
+}
+
+impl From<AppError> for OAuthError {
+  fn from(_: AppError) -> Self {
+    // The cause was logged where the AppError was created
+    Self {
+      error: "server_error",
+      error_description: None,
+    }
+  }
+}
+
+impl From<sea_orm::DbErr> for OAuthError {
+  fn from(err: sea_orm::DbErr) -> Self {
+    AppError::from(err).into()
+  }
+}
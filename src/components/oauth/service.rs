@@ -0,0 +1,765 @@
+use chrono::Duration;
+use helpers::{
+  hash,
+  time::utc_now,
+  uuid::{self, Alphabet},
+};
+use reqwest::Url;
+use sea_orm::Set;
+use serde_json::json;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    session,
+    user::model::ApiKeyScope,
+  },
+  entity::prelude::*,
+  error::AppError,
+  helpers::oidc::pkce_challenge,
+};
+
+use super::model::{
+  AuthorizeQuery, AuthorizeResponseData, ClientItem, ClientsResponseData, ConsentClient,
+  ConsentResponseData, CreateClientBody, CreateClientResponseData, GrantType,
+  IntrospectionResponse, OAuthError, TokenForm, TokenHintForm, TokenResponse, UserInfoResponse,
+};
+
+/// Lifetime of an authorization code
+const CODE_TTL: Duration = Duration::minutes(5);
+
+/// Lifetime of an access token, in seconds
+const TOKEN_TTL: i64 = 3600;
+
+async fn admin_user(state: &AppState, token: &str) -> Result<UserModel, AppError> {
+  let email = session::service::authorize(state, token, ApiKeyScope::Admin).await?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_email(&email)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+  if !matches!(user.r#type.as_str(), "admin" | "root") {
+    return Err(AppError::Forbidden);
+  }
+  Ok(user)
+}
+
+/// Registers a client. The secret is only returned here, the table keeps its hash
+#[tracing::instrument(skip_all)]
+pub async fn create_client(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  body: CreateClientBody,
+) -> Result<CreateClientResponseData, AppError> {
+  let user = admin_user(state, &token).await?;
+  let CreateClientBody {
+    name,
+    redirect_uris,
+    mut grant_types,
+    scopes,
+    public,
+  } = body;
+  grant_types.sort_by_key(|grant_type| *grant_type as u8);
+  grant_types.dedup();
+  let uses_code = grant_types.contains(&GrantType::AuthorizationCode);
+  let valid = !name.trim().is_empty()
+    && !grant_types.is_empty()
+    && !scopes.is_empty()
+    && scopes
+      .iter()
+      .all(|scope| !scope.is_empty() && !scope.contains(char::is_whitespace))
+    && redirect_uris
+      .iter()
+      .all(|uri| Url::parse(uri).is_ok() && !uri.contains('#'))
+    && (!uses_code || !redirect_uris.is_empty())
+    && !(public && grant_types.contains(&GrantType::ClientCredentials));
+  if !valid {
+    return Err(AppError::InvalidParameter);
+  }
+  let client_secret = (!public).then(|| uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 40));
+  let client = OauthClientActiveModel {
+    client_id: Set(uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 16)),
+    client_secret_hash: Set(
+      client_secret
+        .as_ref()
+        .map(|secret| hash::blake3(secret.as_bytes())),
+    ),
+    name: Set(name.trim().to_string()),
+    redirect_uris: Set(redirect_uris.join(" ")),
+    grant_types: Set(
+      grant_types
+        .iter()
+        .map(GrantType::as_str)
+        .collect::<Vec<_>>()
+        .join(" "),
+    ),
+    scopes: Set(scopes.join(" ")),
+    created_by: Set(user.user_id.clone()),
+    created_at: Set(utc_now()),
+    revoked_at: Set(None),
+  };
+  let client = state.repo.oauth_client().create_client(client).await?;
+  let diff = json!({ "client_id": client.client_id, "name": client.name });
+  let action = AuditAction::OauthClientCreate;
+  audit::service::record(state, ctx, action, Some(user.user_id), None, Some(diff)).await;
+  Ok(CreateClientResponseData {
+    client_secret,
+    client: client.into(),
+  })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_clients(state: &AppState, token: String) -> Result<ClientsResponseData, AppError> {
+  admin_user(state, &token).await?;
+  let clients = state.repo.oauth_client().get_clients().await?;
+  Ok(ClientsResponseData {
+    clients: clients.into_iter().map(ClientItem::from).collect(),
+  })
+}
+
+/// Revokes the client and every token issued to it
+#[tracing::instrument(skip_all)]
+pub async fn revoke_client(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  client_id: String,
+) -> Result<bool, AppError> {
+  let user = admin_user(state, &token).await?;
+  let now = utc_now();
+  if !state
+    .repo
+    .oauth_client()
+    .revoke_client(&client_id, now)
+    .await?
+  {
+    return Err(AppError::NotFound);
+  }
+  let revoked = state
+    .repo
+    .oauth_token()
+    .revoke_client_tokens(&client_id, now)
+    .await?;
+  let diff = json!({ "client_id": client_id, "revoked_tokens": revoked });
+  let action = AuditAction::OauthClientRevoke;
+  audit::service::record(state, ctx, action, Some(user.user_id), None, Some(diff)).await;
+  Ok(true)
+}
+
+/// Checks an authorization request, returns the client and the granted scopes
+async fn check_request(
+  state: &AppState,
+  query: &AuthorizeQuery,
+) -> Result<(OauthClientModel, Vec<String>), AppError> {
+  let client = state
+    .repo
+    .oauth_client()
+    .get_client(&query.client_id)
+    .await?
+    .ok_or(AppError::NotFound)?;
+  let allowed = |grant_type: &str| {
+    client
+      .grant_types
+      .split_whitespace()
+      .any(|g| g == grant_type)
+  };
+  let registered = client
+    .redirect_uris
+    .split_whitespace()
+    .any(|uri| uri == query.redirect_uri);
+  if query.response_type != "code"
+    || !allowed(GrantType::AuthorizationCode.as_str())
+    || !registered
+    || query.code_challenge_method != "S256"
+    || query.code_challenge.len() != 43
+  {
+    return Err(AppError::InvalidParameter);
+  }
+  let scopes = granted_scopes(&client, query.scope.as_deref()).ok_or(AppError::InvalidParameter)?;
+  Ok((client, scopes))
+}
+
+/// Requested scopes, or all those of the client. `None` when one is not allowed
+fn granted_scopes(client: &OauthClientModel, requested: Option<&str>) -> Option<Vec<String>> {
+  let allowed = client.scopes.split_whitespace().collect::<Vec<_>>();
+  let mut scopes = match requested {
+    Some(requested) => requested.split_whitespace().collect::<Vec<_>>(),
+    None => allowed.clone(),
+  };
+  scopes.sort_unstable();
+  scopes.dedup();
+  if scopes.is_empty() || scopes.iter().any(|scope| !allowed.contains(scope)) {
+    return None;
+  }
+  Some(scopes.into_iter().map(String::from).collect())
+}
+
+/// What the consent screen asks the logged in user
+#[tracing::instrument(skip_all)]
+pub async fn get_consent(
+  state: &AppState,
+  token: String,
+  query: AuthorizeQuery,
+) -> Result<ConsentResponseData, AppError> {
+  session::service::authenticate(state, &token).await?;
+  let (client, scopes) = check_request(state, &query).await?;
+  Ok(ConsentResponseData {
+    client: ConsentClient {
+      client_id: client.client_id,
+      name: client.name,
+    },
+    scopes,
+    redirect_uri: query.redirect_uri,
+  })
+}
+
+/// Records the decision of the logged in user and returns where to redirect them, with an
+/// authorization code when approved
+#[tracing::instrument(skip_all)]
+pub async fn authorize(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  query: AuthorizeQuery,
+  approve: bool,
+) -> Result<AuthorizeResponseData, AppError> {
+  let user = session::service::authenticate(state, &token).await?.user;
+  let (client, scopes) = check_request(state, &query).await?;
+  let mut redirect_uri = Url::parse(&query.redirect_uri).map_err(|_| AppError::InvalidParameter)?;
+  {
+    let mut params = redirect_uri.query_pairs_mut();
+    if approve {
+      let now = utc_now();
+      let code = uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32);
+      let model = OauthCodeActiveModel {
+        code_hash: Set(hash::blake3(code.as_bytes())),
+        client_id: Set(client.client_id.clone()),
+        user_id: Set(user.user_id.clone()),
+        redirect_uri: Set(query.redirect_uri.clone()),
+        scopes: Set(scopes.join(" ")),
+        code_challenge: Set(query.code_challenge.clone()),
+        created_at: Set(now),
+        expires_at: Set(now + CODE_TTL),
+      };
+      state.repo.oauth_code().create_code(model, now).await?;
+      params.append_pair("code", &code);
+    } else {
+      params.append_pair("error", "access_denied");
+    }
+    if let Some(state) = &query.state {
+      params.append_pair("state", state);
+    }
+  }
+  if approve {
+    let user_id = Some(user.user_id);
+    let diff = json!({ "client_id": client.client_id, "scopes": scopes });
+    let action = AuditAction::OauthAuthorize;
+    audit::service::record(state, ctx, action, user_id.clone(), user_id, Some(diff)).await;
+  }
+  Ok(AuthorizeResponseData {
+    redirect_uri: redirect_uri.into(),
+  })
+}
+
+/// Authenticates the client of a protocol request. Public clients only give their id
+async fn authenticate_client(
+  state: &AppState,
+  credentials: Option<(String, Option<String>)>,
+) -> Result<OauthClientModel, OAuthError> {
+  let (client_id, client_secret) = credentials.ok_or_else(OAuthError::invalid_client)?;
+  let client = state
+    .repo
+    .oauth_client()
+    .get_client(&client_id)
+    .await?
+    .ok_or_else(OAuthError::invalid_client)?;
+  let matched = match (&client.client_secret_hash, client_secret) {
+    (Some(secret_hash), Some(secret)) => *secret_hash == hash::blake3(secret.as_bytes()),
+    (None, None) => true,
+    _ => false,
+  };
+  if !matched {
+    return Err(OAuthError::invalid_client());
+  }
+  Ok(client)
+}
+
+async fn issue_token(
+  state: &AppState,
+  client: &OauthClientModel,
+  user_id: Option<String>,
+  scopes: Vec<String>,
+) -> Result<TokenResponse, OAuthError> {
+  let now = utc_now();
+  let access_token = format!("oat_{}", uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 40));
+  let scope = scopes.join(" ");
+  let token = OauthTokenActiveModel {
+    token_hash: Set(hash::blake3(access_token.as_bytes())),
+    client_id: Set(client.client_id.clone()),
+    user_id: Set(user_id),
+    scopes: Set(scope.clone()),
+    created_at: Set(now),
+    expires_at: Set(now + Duration::seconds(TOKEN_TTL)),
+    ..Default::default()
+  };
+  state.repo.oauth_token().create_token(token).await?;
+  Ok(TokenResponse {
+    access_token,
+    token_type: "Bearer",
+    expires_in: TOKEN_TTL,
+    scope,
+  })
+}
+
+/// Token endpoint, `authorization_code` with PKCE or `client_credentials`
+#[tracing::instrument(skip_all)]
+pub async fn token(
+  state: &AppState,
+  credentials: Option<(String, Option<String>)>,
+  form: TokenForm,
+) -> Result<TokenResponse, OAuthError> {
+  let client = authenticate_client(state, credentials).await?;
+  let grant_type = GrantType::parse(&form.grant_type).ok_or(OAuthError::new(
+    "unsupported_grant_type",
+    "Only authorization_code and client_credentials are supported",
+  ))?;
+  if !client
+    .grant_types
+    .split_whitespace()
+    .any(|g| g == grant_type.as_str())
+  {
+    return Err(OAuthError::new(
+      "unauthorized_client",
+      "Grant type not allowed for this client",
+    ));
+  }
+  match grant_type {
+    GrantType::AuthorizationCode => {
+      let (Some(code), Some(redirect_uri), Some(code_verifier)) =
+        (form.code, form.redirect_uri, form.code_verifier)
+      else {
+        return Err(OAuthError::new(
+          "invalid_request",
+          "code, redirect_uri and code_verifier are required",
+        ));
+      };
+      let code = state
+        .repo
+        .oauth_code()
+        .take_code(&hash::blake3(code.as_bytes()), utc_now())
+        .await?
+        .filter(|code| code.client_id == client.client_id && code.redirect_uri == redirect_uri)
+        .filter(|code| code.code_challenge == pkce_challenge(&code_verifier))
+        .ok_or_else(OAuthError::invalid_grant)?;
+      let scopes = code.scopes.split_whitespace().map(String::from).collect();
+      issue_token(state, &client, Some(code.user_id), scopes).await
+    }
+    GrantType::ClientCredentials => {
+      if client.client_secret_hash.is_none() {
+        return Err(OAuthError::invalid_client());
+      }
+      let scopes = granted_scopes(&client, form.scope.as_deref()).ok_or(OAuthError::new(
+        "invalid_scope",
+        "Scope not allowed for this client",
+      ))?;
+      issue_token(state, &client, None, scopes).await
+    }
+  }
+}
+
+/// Active, not revoked nor expired, access token
+async fn active_token(state: &AppState, token: &str) -> Result<Option<OauthTokenModel>, AppError> {
+  let now = utc_now();
+  Ok(
+    state
+      .repo
+      .oauth_token()
+      .get_token(&hash::blake3(token.as_bytes()))
+      .await?
+      .filter(|token| token.revoked_at.is_none() && token.expires_at > now),
+  )
+}
+
+/// Introspection for resource servers, which authenticate as confidential clients. Tokens of
+/// deleted users are inactive
+#[tracing::instrument(skip_all)]
+pub async fn introspect(
+  state: &AppState,
+  credentials: Option<(String, Option<String>)>,
+  form: TokenHintForm,
+) -> Result<IntrospectionResponse, OAuthError> {
+  let client = authenticate_client(state, credentials).await?;
+  if client.client_secret_hash.is_none() {
+    return Err(OAuthError::invalid_client());
+  }
+  let Some(token) = active_token(state, &form.token).await? else {
+    return Ok(IntrospectionResponse::default());
+  };
+  // A token of a user who has since been deleted no longer grants anything
+  if let Some(user_id) = &token.user_id {
+    let user = state.repo.user().get_user_by_user_id(user_id).await?;
+    if user.is_none_or(|user| user.status == "deleted") {
+      return Ok(IntrospectionResponse::default());
+    }
+  }
+  Ok(IntrospectionResponse {
+    active: true,
+    scope: Some(token.scopes),
+    client_id: Some(token.client_id),
+    sub: token.user_id,
+    token_type: Some("Bearer"),
+    exp: Some(token.expires_at.timestamp()),
+    iat: Some(token.created_at.timestamp()),
+  })
+}
+
+/// Revocation, a client can only revoke its own tokens. Unknown tokens are not an error
+#[tracing::instrument(skip_all)]
+pub async fn revoke(
+  state: &AppState,
+  credentials: Option<(String, Option<String>)>,
+  form: TokenHintForm,
+) -> Result<(), OAuthError> {
+  let client = authenticate_client(state, credentials).await?;
+  if let Some(token) = active_token(state, &form.token).await? {
+    if token.client_id == client.client_id {
+      state
+        .repo
+        .oauth_token()
+        .revoke_token(token.id, utc_now())
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+/// OpenID Connect userinfo of the user who authorized the access token
+#[tracing::instrument(skip_all)]
+pub async fn userinfo(state: &AppState, token: &str) -> Result<UserInfoResponse, OAuthError> {
+  let token = active_token(state, token)
+    .await?
+    .ok_or_else(OAuthError::invalid_token)?;
+  let scopes = token.scopes.split_whitespace().collect::<Vec<_>>();
+  let user_id = token
+    .user_id
+    .as_deref()
+    .filter(|_| scopes.contains(&"openid"))
+    .ok_or(OAuthError::new(
+      "insufficient_scope",
+      "The openid scope of a user is required",
+    ))?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or_else(OAuthError::invalid_token)?;
+  let profile = scopes.contains(&"profile");
+  let email = scopes.contains(&"email");
+  Ok(UserInfoResponse {
+    sub: user.user_id,
+    name: profile.then_some(user.nickname),
+    picture: profile.then_some(user.avatar),
+    email: email.then_some(user.email),
+    email_verified: email.then_some(user.is_email_verified != 0),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::testing::{self, ctx};
+
+  use super::*;
+
+  const REDIRECT_URI: &str = "https://app.example.com/callback";
+  const VERIFIER: &str = "a verifier of the client, kept until the token request";
+
+  /// Login tokens of the root user, who manages the clients, and of Alice
+  async fn setup() -> (AppState, String, String) {
+    let state = testing::app_state(&[]).await;
+    let mut tokens = vec![];
+    for (nickname, email) in [("Root", "root@example.com"), ("Alice", "alice@example.com")] {
+      let user = testing::user(&state, nickname, email).await;
+      let token = session::service::create_session(&state, &ctx(), &user)
+        .await
+        .unwrap();
+      tokens.push(token);
+    }
+    let alice = tokens.pop().unwrap();
+    (state, tokens.pop().unwrap(), alice)
+  }
+
+  async fn create(
+    state: &AppState,
+    admin: &str,
+    public: bool,
+    grant_types: Vec<GrantType>,
+  ) -> CreateClientResponseData {
+    let body = CreateClientBody {
+      name: "App".to_string(),
+      redirect_uris: vec![REDIRECT_URI.to_string()],
+      grant_types,
+      scopes: ["openid", "profile", "email", "read"]
+        .map(String::from)
+        .to_vec(),
+      public,
+    };
+    create_client(state, &ctx(), admin.to_string(), body)
+      .await
+      .unwrap()
+  }
+
+  fn request(client_id: &str, scope: &str) -> AuthorizeQuery {
+    AuthorizeQuery {
+      response_type: "code".to_string(),
+      client_id: client_id.to_string(),
+      redirect_uri: REDIRECT_URI.to_string(),
+      scope: Some(scope.to_string()),
+      state: Some("xyz".to_string()),
+      code_challenge: pkce_challenge(VERIFIER),
+      code_challenge_method: "S256".to_string(),
+    }
+  }
+
+  /// Code approved by the user of `token`
+  async fn code(state: &AppState, token: &str, client_id: &str, scope: &str) -> String {
+    let query = request(client_id, scope);
+    let res = authorize(state, &ctx(), token.to_string(), query, true)
+      .await
+      .unwrap();
+    let redirect_uri = Url::parse(&res.redirect_uri).unwrap();
+    assert!(res.redirect_uri.starts_with(REDIRECT_URI));
+    let param = |name| {
+      redirect_uri
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+    };
+    assert_eq!(param("state").as_deref(), Some("xyz"));
+    param("code").unwrap()
+  }
+
+  fn code_form(code: &str, redirect_uri: &str, verifier: &str) -> TokenForm {
+    TokenForm {
+      grant_type: "authorization_code".to_string(),
+      code: Some(code.to_string()),
+      redirect_uri: Some(redirect_uri.to_string()),
+      code_verifier: Some(verifier.to_string()),
+      scope: None,
+      client_id: None,
+      client_secret: None,
+    }
+  }
+
+  fn credentials_form(scope: Option<&str>) -> TokenForm {
+    TokenForm {
+      grant_type: "client_credentials".to_string(),
+      code: None,
+      redirect_uri: None,
+      code_verifier: None,
+      scope: scope.map(str::to_string),
+      client_id: None,
+      client_secret: None,
+    }
+  }
+
+  fn hint(token: &str) -> TokenHintForm {
+    TokenHintForm {
+      token: token.to_string(),
+      token_type_hint: None,
+      client_id: None,
+      client_secret: None,
+    }
+  }
+
+  fn error<T>(res: Result<T, OAuthError>) -> &'static str {
+    match res {
+      Ok(_) => panic!("expected an OAuth error"),
+      Err(err) => err.error,
+    }
+  }
+
+  #[actix_web::test]
+  async fn exchanges_a_code_once_for_a_token() {
+    let (state, admin, alice) = setup().await;
+    let client = create(&state, &admin, true, vec![GrantType::AuthorizationCode]).await;
+    assert!(client.client_secret.is_none());
+    let client_id = client.client.client_id;
+    let credentials = || Some((client_id.clone(), None));
+    let code = code(&state, &alice, &client_id, "openid email").await;
+    let form = || code_form(&code, REDIRECT_URI, VERIFIER);
+    let issued = token(&state, credentials(), form()).await.unwrap();
+    assert_eq!(issued.token_type, "Bearer");
+    assert_eq!(issued.scope, "email openid");
+    // The code is spent
+    let reused = token(&state, credentials(), form()).await;
+    assert_eq!(error(reused), "invalid_grant");
+  }
+
+  #[actix_web::test]
+  async fn refuses_a_wrong_verifier_or_redirect_uri() {
+    let (state, admin, alice) = setup().await;
+    let client = create(&state, &admin, true, vec![GrantType::AuthorizationCode]).await;
+    let client_id = client.client.client_id;
+    let credentials = || Some((client_id.clone(), None));
+    for (redirect_uri, verifier) in [
+      (REDIRECT_URI, "the verifier of an attacker"),
+      ("https://app.example.com/other", VERIFIER),
+    ] {
+      let code = code(&state, &alice, &client_id, "openid").await;
+      let form = code_form(&code, redirect_uri, verifier);
+      assert_eq!(
+        error(token(&state, credentials(), form).await),
+        "invalid_grant"
+      );
+      // A failed attempt spends the code too
+      let form = code_form(&code, REDIRECT_URI, VERIFIER);
+      assert_eq!(
+        error(token(&state, credentials(), form).await),
+        "invalid_grant"
+      );
+    }
+    // Unregistered redirect uris are refused before any code is issued
+    let mut query = request(&client_id, "openid");
+    query.redirect_uri = "https://evil.example.com/callback".to_string();
+    let res = authorize(&state, &ctx(), alice, query, true).await;
+    assert!(matches!(res, Err(AppError::InvalidParameter)));
+  }
+
+  #[actix_web::test]
+  async fn a_revoked_client_loses_its_tokens_and_codes() {
+    let (state, admin, alice) = setup().await;
+    let grant_types = vec![GrantType::AuthorizationCode, GrantType::ClientCredentials];
+    let client = create(&state, &admin, false, grant_types).await;
+    let client_id = client.client.client_id;
+    let credentials = || Some((client_id.clone(), client.client_secret.clone()));
+    let access_token = token(&state, credentials(), credentials_form(Some("read")))
+      .await
+      .unwrap()
+      .access_token;
+    let code = code(&state, &alice, &client_id, "openid").await;
+    revoke_client(&state, &ctx(), admin, client_id.clone())
+      .await
+      .unwrap();
+    let form = code_form(&code, REDIRECT_URI, VERIFIER);
+    assert_eq!(
+      error(token(&state, credentials(), form).await),
+      "invalid_client"
+    );
+    let res = authorize(&state, &ctx(), alice, request(&client_id, "openid"), true).await;
+    assert!(matches!(res, Err(AppError::NotFound)));
+    assert!(active_token(&state, &access_token).await.unwrap().is_none());
+  }
+
+  #[actix_web::test]
+  async fn client_credentials_need_a_confidential_client() {
+    let (state, admin, _) = setup().await;
+    let body = CreateClientBody {
+      name: "SPA".to_string(),
+      redirect_uris: vec![],
+      grant_types: vec![GrantType::ClientCredentials],
+      scopes: vec!["read".to_string()],
+      public: true,
+    };
+    let res = create_client(&state, &ctx(), admin.clone(), body).await;
+    assert!(matches!(res, Err(AppError::InvalidParameter)));
+    let public = create(&state, &admin, true, vec![GrantType::AuthorizationCode]).await;
+    let credentials = Some((public.client.client_id, None));
+    let res = token(&state, credentials, credentials_form(None)).await;
+    assert_eq!(error(res), "unauthorized_client");
+    let confidential = create(&state, &admin, false, vec![GrantType::ClientCredentials]).await;
+    let client_id = confidential.client.client_id;
+    // Without its secret, or with a wrong one
+    for secret in [None, Some("wrong secret".to_string())] {
+      let credentials = Some((client_id.clone(), secret));
+      let res = token(&state, credentials, credentials_form(None)).await;
+      assert_eq!(error(res), "invalid_client");
+    }
+    let credentials = Some((client_id, confidential.client_secret));
+    let res = token(&state, credentials, credentials_form(Some("read admin"))).await;
+    assert_eq!(error(res), "invalid_scope");
+  }
+
+  #[actix_web::test]
+  async fn introspects_and_revokes_only_for_the_right_clients() {
+    let (state, admin, alice) = setup().await;
+    let app = create(&state, &admin, true, vec![GrantType::AuthorizationCode]).await;
+    let api = create(&state, &admin, false, vec![GrantType::ClientCredentials]).await;
+    let app_credentials = || Some((app.client.client_id.clone(), None));
+    let api_credentials = || Some((api.client.client_id.clone(), api.client_secret.clone()));
+    let code = code(&state, &alice, &app.client.client_id, "openid").await;
+    let form = code_form(&code, REDIRECT_URI, VERIFIER);
+    let access_token = token(&state, app_credentials(), form)
+      .await
+      .unwrap()
+      .access_token;
+    // Public clients cannot introspect
+    let res = introspect(&state, app_credentials(), hint(&access_token)).await;
+    assert_eq!(error(res), "invalid_client");
+    let res = introspect(&state, api_credentials(), hint(&access_token))
+      .await
+      .unwrap();
+    assert!(res.active);
+    assert_eq!(res.client_id.as_ref(), Some(&app.client.client_id));
+    assert_eq!(res.scope.as_deref(), Some("openid"));
+    let unknown = introspect(&state, api_credentials(), hint("oat_unknown"))
+      .await
+      .unwrap();
+    assert!(!unknown.active);
+    // Another client's revocation is ignored, the owner's is not
+    revoke(&state, api_credentials(), hint(&access_token))
+      .await
+      .unwrap();
+    assert!(userinfo(&state, &access_token).await.is_ok());
+    revoke(&state, app_credentials(), hint(&access_token))
+      .await
+      .unwrap();
+    let res = introspect(&state, api_credentials(), hint(&access_token))
+      .await
+      .unwrap();
+    assert!(!res.active);
+  }
+
+  #[actix_web::test]
+  async fn userinfo_follows_the_scopes_of_the_token() {
+    let (state, admin, alice) = setup().await;
+    let grant_types = vec![GrantType::AuthorizationCode, GrantType::ClientCredentials];
+    let client = create(&state, &admin, false, grant_types).await;
+    let client_id = client.client.client_id;
+    let credentials = || Some((client_id.clone(), client.client_secret.clone()));
+    let mut access_tokens = vec![];
+    for scope in ["read", "openid", "openid profile email"] {
+      let code = code(&state, &alice, &client_id, scope).await;
+      let form = code_form(&code, REDIRECT_URI, VERIFIER);
+      let access_token = token(&state, credentials(), form).await.unwrap();
+      access_tokens.push(access_token.access_token);
+    }
+    let res = userinfo(&state, &access_tokens[0]).await;
+    assert_eq!(error(res), "insufficient_scope");
+    let openid = userinfo(&state, &access_tokens[1]).await.unwrap();
+    assert!(openid.name.is_none() && openid.email.is_none());
+    let full = userinfo(&state, &access_tokens[2]).await.unwrap();
+    assert_eq!(full.sub, openid.sub);
+    assert_eq!(full.name.as_deref(), Some("Alice"));
+    assert_eq!(full.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(full.email_verified, Some(true));
+    // A token of the client itself has no user
+    let form = credentials_form(Some("openid"));
+    let own = token(&state, credentials(), form).await.unwrap();
+    assert_eq!(
+      error(userinfo(&state, &own.access_token).await),
+      "insufficient_scope"
+    );
+    assert_eq!(
+      error(userinfo(&state, "oat_unknown").await),
+      "invalid_token"
+    );
+  }
+}
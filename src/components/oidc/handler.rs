@@ -0,0 +1,132 @@
+use actix_web::{
+  cookie::{time, Cookie, SameSite},
+  delete, get, post,
+  web::{Data, Json, Path},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::model::AuditContext,
+    oidc::{model::*, service},
+  },
+  helpers::header::extract_token,
+  response::Response,
+};
+
+/// Configured OpenID Connect providers
+#[utoipa::path(
+  tag = "OIDC",
+  responses((status = OK, body = Response<ProvidersResponseData>)),
+)]
+#[get("/oidc/providers")]
+#[tracing::instrument(skip_all)]
+pub async fn get_providers(state: Data<AppState>) -> HttpResponse {
+  let data = service::get_providers(&state);
+  HttpResponse::Ok().json(Response::success(Some(data), None))
+}
+
+/// Cookie of the flow, sent back only to the OIDC routes of the same site
+fn state_cookie(req: &HttpRequest, value: String, max_age: time::Duration) -> Cookie<'static> {
+  Cookie::build(service::STATE_COOKIE, value)
+    .path("/api/v1/oidc")
+    .http_only(true)
+    .secure(req.connection_info().scheme() == "https")
+    .same_site(SameSite::Lax)
+    .max_age(max_age)
+    .finish()
+}
+
+/// Authorization url of the provider. With a login token the provider is linked to the
+/// current user instead of logging in. Sets the `oidc_state` cookie the callback requires
+#[utoipa::path(
+  tag = "OIDC",
+  responses((status = OK, body = Response<AuthorizeResponseData>)),
+)]
+#[post("/oidc/{provider}/authorize")]
+#[tracing::instrument(skip_all)]
+pub async fn authorize(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<String>,
+) -> HttpResponse {
+  let provider = path.into_inner();
+  let token = extract_token(&req).ok();
+  match service::authorize(&state, &provider, token).await {
+    Ok((data, binding)) => {
+      let max_age = time::Duration::seconds(service::FLOW_TTL.num_seconds());
+      HttpResponse::Ok()
+        .cookie(state_cookie(&req, binding, max_age))
+        .json(Response::success(Some(data), None))
+    }
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Exchanges the `code` the provider redirected with, for a login token or a linked identity.
+/// Must come from the browser that got the authorization url, with its `oidc_state` cookie
+#[utoipa::path(
+  tag = "OIDC",
+  responses((status = OK, body = Response<CallbackResponseData>)),
+)]
+#[post("/oidc/{provider}/callback")]
+#[tracing::instrument(skip_all)]
+pub async fn callback(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<String>,
+  body: Json<CallbackBody>,
+) -> HttpResponse {
+  let provider = path.into_inner();
+  let Json(CallbackBody {
+    code,
+    state: state_param,
+  }) = body;
+  let binding = req
+    .cookie(service::STATE_COOKIE)
+    .map(|cookie| cookie.value().to_string());
+  match service::callback(&state, &ctx, &provider, code, state_param, binding).await {
+    Ok(data) => HttpResponse::Ok()
+      .cookie(state_cookie(&req, String::new(), time::Duration::ZERO))
+      .json(Response::success(Some(data), None)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Providers linked to the current user
+#[utoipa::path(
+  tag = "OIDC",
+  responses((status = OK, body = Response<IdentitiesResponseData>)),
+)]
+#[get("/user/identities")]
+#[tracing::instrument(skip_all)]
+pub async fn get_identities(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_identities(&state, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[utoipa::path(tag = "OIDC", responses((status = OK)))]
+#[delete("/user/identities/{id}")]
+#[tracing::instrument(skip_all)]
+pub async fn unlink_identity(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<u32>,
+) -> HttpResponse {
+  let id = path.into_inner();
+  match extract_token(&req) {
+    Ok(token) => match service::unlink_identity(&state, &ctx, token, id).await {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
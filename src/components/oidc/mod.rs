@@ -0,0 +1,13 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_providers);
+  cfg.service(handler::authorize);
+  cfg.service(handler::callback);
+  cfg.service(handler::get_identities);
+  cfg.service(handler::unlink_identity);
+}
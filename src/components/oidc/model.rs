@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::entity::prelude::UserIdentityModel;
+
+#[derive(Serialize, ToSchema)]
+pub struct ProvidersResponseData {
+  pub providers: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuthorizeResponseData {
+  /// Send the user there
+  pub url: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CallbackBody {
+  /// Query parameters the provider redirected with
+  pub code: String,
+  pub state: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IdentityItem {
+  pub id: u32,
+  pub provider: String,
+  /// Account id at the provider
+  pub subject: String,
+  pub email: Option<String>,
+  pub created_at: DateTime<Utc>,
+  pub last_login_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserIdentityModel> for IdentityItem {
+  fn from(identity: UserIdentityModel) -> Self {
+    Self {
+      id: identity.id,
+      provider: identity.provider,
+      subject: identity.subject,
+      email: identity.email,
+      created_at: identity.created_at,
+      last_login_at: identity.last_login_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CallbackResponseData {
+  /// Login token, absent when the flow linked the identity to the logged in user
+  pub token: Option<String>,
+  /// With the login token, stands for the login in sensitive changes
+  pub reauth_token: Option<String>,
+  pub identity: IdentityItem,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IdentitiesResponseData {
+  pub identities: Vec<IdentityItem>,
+}
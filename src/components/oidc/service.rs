@@ -0,0 +1,382 @@
+use chrono::Duration;
+use helpers::{
+  time::utc_now,
+  uuid::{self, Alphabet},
+};
+use sea_orm::Set;
+use serde_json::json;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    session, user,
+  },
+  entity::prelude::*,
+  error::AppError,
+  helpers::oidc::{
+    pkce_challenge, state_binding, verify_state_binding, OidcIdentity, OidcProvider,
+  },
+  metrics::LOGINS_TOTAL,
+};
+
+use super::model::{
+  AuthorizeResponseData, CallbackResponseData, IdentitiesResponseData, IdentityItem,
+  ProvidersResponseData,
+};
+
+/// Time the user has to come back from the provider
+pub const FLOW_TTL: Duration = Duration::minutes(10);
+
+/// Cookie binding a flow to the browser that started it, see [`state_binding`]
+pub const STATE_COOKIE: &str = "oidc_state";
+
+fn provider<'a>(state: &'a AppState, name: &str) -> Result<&'a OidcProvider, AppError> {
+  state.oidc_providers.get(name).ok_or(AppError::NotFound)
+}
+
+async fn current_user(state: &AppState, token: &str) -> Result<UserModel, AppError> {
+  Ok(session::service::authenticate(state, token).await?.user)
+}
+
+pub fn get_providers(state: &AppState) -> ProvidersResponseData {
+  let mut providers = state.oidc_providers.keys().cloned().collect::<Vec<_>>();
+  providers.sort();
+  ProvidersResponseData { providers }
+}
+
+/// Starts a login, or links the provider to the current user when a login token is given.
+/// Returns the value of [`STATE_COOKIE`] along with the authorization url
+#[tracing::instrument(skip_all)]
+pub async fn authorize(
+  state: &AppState,
+  name: &str,
+  token: Option<String>,
+) -> Result<(AuthorizeResponseData, String), AppError> {
+  let provider = provider(state, name)?;
+  let user_id = match token {
+    Some(token) => Some(current_user(state, &token).await?.user_id),
+    None => None,
+  };
+  let now = utc_now();
+  let flow = OidcFlowActiveModel {
+    state: Set(uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32)),
+    provider: Set(name.to_string()),
+    nonce: Set(uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32)),
+    code_verifier: Set(uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 64)),
+    user_id: Set(user_id),
+    created_at: Set(now),
+    expires_at: Set(now + FLOW_TTL),
+  };
+  let flow = state.repo.oidc_flow().create_flow(flow, now).await?;
+  let url = provider
+    .authorization_url(
+      &flow.state,
+      &flow.nonce,
+      &pkce_challenge(&flow.code_verifier),
+    )
+    .await?;
+  let binding = state_binding(&state.jwt_token, &flow.state);
+  Ok((AuthorizeResponseData { url }, binding))
+}
+
+/// Completes a flow started by [`authorize`] in the same browser, the one holding the
+/// [`STATE_COOKIE`] of the flow
+#[tracing::instrument(skip_all)]
+pub async fn callback(
+  state: &AppState,
+  ctx: &AuditContext,
+  name: &str,
+  code: String,
+  state_param: String,
+  binding: Option<String>,
+) -> Result<CallbackResponseData, AppError> {
+  let provider = provider(state, name)?;
+  // Otherwise a victim could be sent to the callback of a flow an attacker started, logging
+  // them in as the attacker or linking the attacker's account to theirs
+  if !binding.is_some_and(|b| verify_state_binding(&state.jwt_token, &state_param, &b)) {
+    tracing::warn!("OIDC callback without the state cookie of its flow");
+    return Err(AppError::InvalidToken);
+  }
+  let flow = state
+    .repo
+    .oidc_flow()
+    .take_flow(&state_param, utc_now())
+    .await?
+    .filter(|flow| flow.provider == name)
+    .ok_or(AppError::InvalidToken)?;
+  let identity = provider
+    .identify(&code, &flow.code_verifier, &flow.nonce)
+    .await?;
+  match flow.user_id {
+    Some(user_id) => link(state, ctx, name, user_id, identity).await,
+    None => {
+      let result = login(state, ctx, name, identity).await;
+      let label = if result.is_ok() { "success" } else { "failure" };
+      LOGINS_TOTAL.with_label_values(&[label]).inc();
+      result
+    }
+  }
+}
+
+async fn link(
+  state: &AppState,
+  ctx: &AuditContext,
+  name: &str,
+  user_id: String,
+  identity: OidcIdentity,
+) -> Result<CallbackResponseData, AppError> {
+  let repo = state.repo.user_identity();
+  if let Some(linked) = repo.get_identity(name, &identity.subject).await? {
+    if linked.user_id != user_id {
+      return Err(AppError::UserExists);
+    }
+    return Ok(CallbackResponseData {
+      token: None,
+      reauth_token: None,
+      identity: linked.into(),
+    });
+  }
+  let linked = repo
+    .create_identity(UserIdentityActiveModel {
+      user_id: Set(user_id.clone()),
+      provider: Set(name.to_string()),
+      subject: Set(identity.subject),
+      email: Set(identity.email),
+      created_at: Set(utc_now()),
+      ..Default::default()
+    })
+    .await?;
+  let user_id = Some(user_id);
+  let diff = Some(json!({ "provider": name, "subject": linked.subject }));
+  let action = AuditAction::IdentityLink;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(CallbackResponseData {
+    token: None,
+    reauth_token: None,
+    identity: linked.into(),
+  })
+}
+
+/// Logs in the user linked to the identity, or registers a new one. An unlinked identity
+/// whose email already has an account is refused, that user has to log in and link it. So
+/// is one whose email the provider has not verified: it would hold the address without
+/// proof, that user has to register or log in otherwise and link it
+async fn login(
+  state: &AppState,
+  ctx: &AuditContext,
+  name: &str,
+  identity: OidcIdentity,
+) -> Result<CallbackResponseData, AppError> {
+  let repo = state.repo.user_identity();
+  let now = utc_now();
+  let (user, linked) = match repo.get_identity(name, &identity.subject).await? {
+    Some(mut linked) => {
+      let user = state
+        .repo
+        .user()
+        .get_user_by_user_id(&linked.user_id)
+        .await?
+        .filter(|user| user.status != "deleted")
+        .ok_or(AppError::UserNotFound)?;
+      repo
+        .touch_identity(linked.id, identity.email.clone(), now)
+        .await?;
+      linked.email = identity.email;
+      linked.last_login_at = Some(now);
+      (user, linked)
+    }
+    None => {
+      let email = identity.email.as_deref().ok_or_else(|| {
+        tracing::error!("Provider {name} returned no email");
+        AppError::IdentityProvider
+      })?;
+      let email = user::service::normalize_email(email).map_err(|_| {
+        tracing::error!("Provider {name} returned an invalid email");
+        AppError::IdentityProvider
+      })?;
+      if !identity.email_verified {
+        tracing::warn!("Provider {name} has not verified the email, not registering");
+        return Err(AppError::EmailNotVerified);
+      }
+      if state.repo.user().has_user(&email).await?.is_some() {
+        return Err(AppError::UserExists);
+      }
+      let nickname = identity
+        .name
+        .clone()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+      // Password login stays impossible until the user sets one with the reauth token of a login
+      let password = state
+        .passwords
+        .hash(&uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32))
+        .await?;
+      let user = user::service::create_user(state, nickname, email, password, true).await?;
+      let linked = repo
+        .create_identity(UserIdentityActiveModel {
+          user_id: Set(user.user_id.clone()),
+          provider: Set(name.to_string()),
+          subject: Set(identity.subject),
+          email: Set(identity.email),
+          created_at: Set(now),
+          last_login_at: Set(Some(now)),
+          ..Default::default()
+        })
+        .await?;
+      (user, linked)
+    }
+  };
+  let tokens = session::service::create_login_tokens(state, ctx, &user).await?;
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "provider": name }));
+  let action = AuditAction::Login;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(CallbackResponseData {
+    token: Some(tokens.token),
+    reauth_token: Some(tokens.reauth_token),
+    identity: linked.into(),
+  })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_identities(
+  state: &AppState,
+  token: String,
+) -> Result<IdentitiesResponseData, AppError> {
+  let user = current_user(state, &token).await?;
+  let identities = state
+    .repo
+    .user_identity()
+    .get_user_identities(&user.user_id)
+    .await?;
+  Ok(IdentitiesResponseData {
+    identities: identities.into_iter().map(IdentityItem::from).collect(),
+  })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn unlink_identity(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  id: u32,
+) -> Result<bool, AppError> {
+  let user = current_user(state, &token).await?;
+  let deleted = state
+    .repo
+    .user_identity()
+    .delete_identity(&user.user_id, id)
+    .await?;
+  if !deleted {
+    return Err(AppError::NotFound);
+  }
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "identity": id }));
+  let action = AuditAction::IdentityUnlink;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, sync::Arc};
+
+  use crate::{
+    app::testing::{self, ctx},
+    helpers::oidc,
+  };
+
+  use super::*;
+
+  /// State with a provider `test` whose token endpoint is unreachable
+  async fn app_state() -> AppState {
+    let mut state = testing::app_state(&[]).await;
+    let provider = oidc::testing::provider("test");
+    state.oidc_providers = Arc::new(HashMap::from([("test".to_string(), provider)]));
+    state
+  }
+
+  fn flow_state(url: &str) -> String {
+    let url = reqwest::Url::parse(url).unwrap();
+    let (_, state) = url.query_pairs().find(|(k, _)| k == "state").unwrap();
+    state.into_owned()
+  }
+
+  #[actix_web::test]
+  async fn callback_needs_the_state_cookie_of_its_flow() {
+    let state = app_state().await;
+    let (data, binding) = authorize(&state, "test", None).await.unwrap();
+    let flow = flow_state(&data.url);
+    let (other, other_binding) = authorize(&state, "test", None).await.unwrap();
+    let other = flow_state(&other.url);
+    assert_ne!(flow, other);
+    let ctx = ctx();
+    let callback = |binding: Option<&str>| {
+      let binding = binding.map(str::to_string);
+      callback(
+        &state,
+        &ctx,
+        "test",
+        "code".to_string(),
+        flow.clone(),
+        binding,
+      )
+    };
+    for binding in [None, Some(other_binding.as_str()), Some("forged")] {
+      let result = callback(binding).await;
+      assert!(matches!(result, Err(AppError::InvalidToken)), "{binding:?}");
+    }
+    // The cookie of the flow gets to the provider, which is down, and uses the flow up
+    let result = callback(Some(&binding)).await;
+    assert!(matches!(result, Err(AppError::IdentityProvider)));
+    let result = callback(Some(&binding)).await;
+    assert!(matches!(result, Err(AppError::InvalidToken)));
+  }
+
+  fn identity(subject: &str, email: &str, email_verified: bool) -> OidcIdentity {
+    OidcIdentity {
+      subject: subject.to_string(),
+      email: Some(email.to_string()),
+      email_verified,
+      name: None,
+    }
+  }
+
+  #[actix_web::test]
+  async fn unverified_emails_do_not_register_users() {
+    let state = app_state().await;
+    let unverified = identity("1", "alice@example.com", false);
+    let result = login(&state, &ctx(), "test", unverified).await;
+    assert!(matches!(result, Err(AppError::EmailNotVerified)));
+    let users = state.repo.user();
+    assert!(users
+      .get_user_by_email("alice@example.com")
+      .await
+      .unwrap()
+      .is_none());
+
+    let verified = identity("2", "Bob@Example.com", true);
+    let data = login(&state, &ctx(), "test", verified).await.unwrap();
+    assert!(data.token.is_some());
+    assert!(users
+      .get_user_by_email("bob@example.com")
+      .await
+      .unwrap()
+      .is_some());
+  }
+
+  #[actix_web::test]
+  async fn linked_identities_log_in_whatever_the_provider_says_of_the_email() {
+    let state = app_state().await;
+    let user = testing::user(&state, "Carol", "carol@example.com").await;
+    let unverified = identity("3", "carol@example.com", false);
+    link(&state, &ctx(), "test", user.user_id, unverified.clone())
+      .await
+      .unwrap();
+    let data = login(&state, &ctx(), "test", unverified).await.unwrap();
+    assert!(data.token.is_some());
+  }
+}
@@ -0,0 +1,144 @@
+use actix_web::{
+  delete, get, post,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::model::AuditContext,
+    passkey::{model::*, service},
+  },
+  helpers::header::{extract_reauth_token, extract_token},
+  middlewares::rate_limit::RateLimit,
+  response::Response,
+};
+
+/// Options to create a passkey for the logged in user
+#[utoipa::path(
+  tag = "Passkey",
+  responses((status = OK, body = Response<RegistrationOptionsResponseData>)),
+)]
+#[post("/passkey/register/options")]
+#[tracing::instrument(skip_all)]
+pub async fn registration_options(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::registration_options(&state, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Stores the credential the authenticator created
+#[utoipa::path(
+  tag = "Passkey",
+  responses((status = OK, body = Response<PasskeyItem>)),
+)]
+#[post("/passkey/register")]
+#[tracing::instrument(skip_all)]
+pub async fn register(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<RegisterPasskeyBody>,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::register(&state, &ctx, token, body.into_inner()).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Options to log in with a passkey
+#[utoipa::path(
+  tag = "Passkey",
+  responses((status = OK, body = Response<LoginOptionsResponseData>)),
+)]
+#[post("/passkey/login/options")]
+#[tracing::instrument(skip_all)]
+pub async fn login_options(state: Data<AppState>) -> HttpResponse {
+  match service::login_options(&state).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Exchanges the assertion of the authenticator for the same login token as `POST /token`
+#[utoipa::path(tag = "Passkey", responses((status = OK)))]
+#[post("/passkey/login", wrap = "RateLimit::login()")]
+#[tracing::instrument(skip_all)]
+pub async fn login(
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<LoginPasskeyBody>,
+) -> HttpResponse {
+  let Json(LoginPasskeyBody { credential }) = body;
+  match service::login(&state, &ctx, credential).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Passkeys of the logged in user
+#[utoipa::path(
+  tag = "Passkey",
+  responses((status = OK, body = Response<PasskeysResponseData>)),
+)]
+#[get("/user/passkeys")]
+#[tracing::instrument(skip_all)]
+pub async fn get_passkeys(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_passkeys(&state, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Removes a passkey of the logged in user, with a fresh proof as for a new password. The body
+/// is optional when an `X-Reauth-Token` is sent
+#[utoipa::path(
+  tag = "Passkey",
+  params(DeletePasskeyQuery),
+  request_body(content = Option<DeletePasskeyBody>),
+  responses((status = OK)),
+)]
+#[delete("/user/passkeys/{id}", wrap = "RateLimit::reauth()")]
+#[tracing::instrument(skip_all)]
+pub async fn delete_passkey(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<u32>,
+  query: Query<DeletePasskeyQuery>,
+  body: Option<Json<DeletePasskeyBody>>,
+) -> HttpResponse {
+  let id = path.into_inner();
+  let Query(DeletePasskeyQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let body = body.map(Json::into_inner).unwrap_or_default();
+  let reauth_token = extract_reauth_token(&req);
+  match extract_token(&req) {
+    Ok(token) => match service::delete_passkey(
+      &state,
+      &ctx,
+      token,
+      reauth_token,
+      body,
+      id,
+      lang.unwrap_or("en"),
+    )
+    .await
+    {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
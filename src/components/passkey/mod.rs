@@ -0,0 +1,14 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::registration_options);
+  cfg.service(handler::register);
+  cfg.service(handler::login_options);
+  cfg.service(handler::login);
+  cfg.service(handler::get_passkeys);
+  cfg.service(handler::delete_passkey);
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::entity::prelude::PasskeyModel;
+
+#[derive(Serialize, ToSchema)]
+pub struct RelyingPartyEntity {
+  pub id: String,
+  pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+  /// base64url of the `user_id`, returned as `userHandle` on login
+  pub id: String,
+  pub name: String,
+  pub display_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CredentialParameters {
+  /// Always `public-key`
+  #[serde(rename = "type")]
+  pub kind: &'static str,
+  /// COSE algorithm
+  pub alg: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+  /// Always `public-key`
+  #[serde(rename = "type")]
+  pub kind: &'static str,
+  pub id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+  pub resident_key: &'static str,
+  pub require_resident_key: bool,
+  pub user_verification: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+  pub challenge: String,
+  pub rp: RelyingPartyEntity,
+  pub user: UserEntity,
+  pub pub_key_cred_params: Vec<CredentialParameters>,
+  /// Milliseconds
+  pub timeout: i64,
+  /// Passkeys the user already has
+  pub exclude_credentials: Vec<CredentialDescriptor>,
+  pub authenticator_selection: AuthenticatorSelection,
+  pub attestation: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RegistrationOptionsResponseData {
+  /// Argument of `navigator.credentials.create()`, binary values are base64url as in
+  /// `PublicKeyCredential.parseCreationOptionsFromJSON()`
+  #[serde(rename = "publicKey")]
+  pub public_key: CreationOptions,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+  pub challenge: String,
+  pub rp_id: String,
+  /// Milliseconds
+  pub timeout: i64,
+  /// Empty, the authenticator offers the passkeys it holds for the relying party
+  pub allow_credentials: Vec<CredentialDescriptor>,
+  pub user_verification: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LoginOptionsResponseData {
+  /// Argument of `navigator.credentials.get()`
+  #[serde(rename = "publicKey")]
+  pub public_key: RequestOptions,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+  #[serde(rename = "clientDataJSON")]
+  pub client_data_json: String,
+  pub attestation_object: String,
+}
+
+/// Result of `navigator.credentials.create()`, as `PublicKeyCredential.toJSON()` encodes it
+#[derive(Deserialize, ToSchema)]
+pub struct RegistrationCredential {
+  pub id: String,
+  pub response: AttestationResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterPasskeyBody {
+  /// Shown in the list of passkeys, e.g. the device
+  pub name: Option<String>,
+  pub credential: RegistrationCredential,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+  #[serde(rename = "clientDataJSON")]
+  pub client_data_json: String,
+  pub authenticator_data: String,
+  pub signature: String,
+  pub user_handle: Option<String>,
+}
+
+/// Result of `navigator.credentials.get()`
+#[derive(Deserialize, ToSchema)]
+pub struct AuthenticationCredential {
+  pub id: String,
+  pub response: AssertionResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoginPasskeyBody {
+  pub credential: AuthenticationCredential,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct DeletePasskeyQuery {
+  /// Language of the email
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Default)]
+pub struct DeletePasskeyBody {
+  /// Needed unless an `X-Reauth-Token` is sent
+  pub current_password: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PasskeyItem {
+  pub id: u32,
+  pub name: String,
+  /// base64url
+  pub credential_id: String,
+  pub created_at: DateTime<Utc>,
+  pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<PasskeyModel> for PasskeyItem {
+  fn from(passkey: PasskeyModel) -> Self {
+    Self {
+      id: passkey.id,
+      name: passkey.name,
+      credential_id: passkey.credential_id,
+      created_at: passkey.created_at,
+      last_used_at: passkey.last_used_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PasskeysResponseData {
+  pub passkeys: Vec<PasskeyItem>,
+}
@@ -0,0 +1,521 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Duration;
+use helpers::{
+  time::utc_now,
+  uuid::{self, Alphabet},
+};
+use sea_orm::Set;
+use serde_json::{json, Value};
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    session::{self, model::LoginTokens},
+    user,
+  },
+  entity::prelude::*,
+  error::AppError,
+  helpers::webauthn::{base64url, ClientData, RelyingParty, ALGORITHMS},
+  metrics::LOGINS_TOTAL,
+};
+
+use super::model::{
+  AuthenticationCredential, AuthenticatorSelection, CreationOptions, CredentialDescriptor,
+  CredentialParameters, DeletePasskeyBody, LoginOptionsResponseData, PasskeyItem,
+  PasskeysResponseData, RegisterPasskeyBody, RegistrationCredential,
+  RegistrationOptionsResponseData, RelyingPartyEntity, RequestOptions, UserEntity,
+};
+
+/// Time the user has to answer the authenticator
+const CHALLENGE_TTL: Duration = Duration::minutes(5);
+
+const REGISTRATION: &str = "registration";
+const AUTHENTICATION: &str = "authentication";
+
+const PUBLIC_KEY: &str = "public-key";
+
+fn relying_party(state: &AppState) -> Result<&RelyingParty, AppError> {
+  state.relying_party.as_ref().ok_or(AppError::NotFound)
+}
+
+async fn current_user(state: &AppState, token: &str) -> Result<UserModel, AppError> {
+  Ok(session::service::authenticate(state, token).await?.user)
+}
+
+async fn create_challenge(
+  state: &AppState,
+  ceremony: &str,
+  user_id: Option<String>,
+) -> Result<String, AppError> {
+  let now = utc_now();
+  let challenge = PasskeyChallengeActiveModel {
+    challenge: Set(URL_SAFE_NO_PAD.encode(uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32))),
+    ceremony: Set(ceremony.to_string()),
+    user_id: Set(user_id),
+    created_at: Set(now),
+    expires_at: Set(now + CHALLENGE_TTL),
+  };
+  let challenge = state
+    .repo
+    .passkey_challenge()
+    .create_challenge(challenge, now)
+    .await?;
+  Ok(challenge.challenge)
+}
+
+/// The challenge the client data answers, used up
+async fn take_challenge(
+  state: &AppState,
+  client_data: &ClientData,
+  ceremony: &str,
+) -> Result<PasskeyChallengeModel, AppError> {
+  state
+    .repo
+    .passkey_challenge()
+    .take_challenge(&client_data.challenge, utc_now())
+    .await?
+    .filter(|challenge| challenge.ceremony == ceremony)
+    .ok_or(AppError::Passkey)
+}
+
+/// Options to create a passkey for the current user
+#[tracing::instrument(skip_all)]
+pub async fn registration_options(
+  state: &AppState,
+  token: String,
+) -> Result<RegistrationOptionsResponseData, AppError> {
+  let rp = relying_party(state)?;
+  let user = current_user(state, &token).await?;
+  let passkeys = state
+    .repo
+    .passkey()
+    .get_user_passkeys(&user.user_id)
+    .await?;
+  let challenge = create_challenge(state, REGISTRATION, Some(user.user_id.clone())).await?;
+  Ok(RegistrationOptionsResponseData {
+    public_key: CreationOptions {
+      challenge,
+      rp: RelyingPartyEntity {
+        id: rp.id.clone(),
+        name: rp.name.clone(),
+      },
+      user: UserEntity {
+        id: URL_SAFE_NO_PAD.encode(&user.user_id),
+        name: user.email,
+        display_name: user.nickname,
+      },
+      pub_key_cred_params: ALGORITHMS
+        .iter()
+        .map(|&alg| CredentialParameters {
+          kind: PUBLIC_KEY,
+          alg,
+        })
+        .collect(),
+      timeout: CHALLENGE_TTL.num_milliseconds(),
+      exclude_credentials: passkeys
+        .into_iter()
+        .map(|passkey| CredentialDescriptor {
+          kind: PUBLIC_KEY,
+          id: passkey.credential_id,
+        })
+        .collect(),
+      authenticator_selection: AuthenticatorSelection {
+        resident_key: "required",
+        require_resident_key: true,
+        user_verification: "required",
+      },
+      attestation: "none",
+    },
+  })
+}
+
+/// Stores the passkey created with [`registration_options`]
+#[tracing::instrument(skip_all)]
+pub async fn register(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  body: RegisterPasskeyBody,
+) -> Result<PasskeyItem, AppError> {
+  let rp = relying_party(state)?;
+  let user = current_user(state, &token).await?;
+  let name = match body.name.as_deref().map(str::trim) {
+    None | Some("") => "Passkey".to_string(),
+    Some(name) if name.chars().count() <= 64 => name.to_string(),
+    Some(_) => return Err(AppError::InvalidParameter),
+  };
+  let RegistrationCredential { id, response } = body.credential;
+  let client_data = ClientData::parse(&base64url(&response.client_data_json)?)?;
+  let challenge = take_challenge(state, &client_data, REGISTRATION).await?;
+  if challenge.user_id.as_ref() != Some(&user.user_id) {
+    return Err(AppError::Passkey);
+  }
+  let credential =
+    rp.verify_registration(&client_data, &base64url(&response.attestation_object)?)?;
+  if base64url(&id)? != credential.credential_id {
+    return Err(AppError::Passkey);
+  }
+  let credential_id = URL_SAFE_NO_PAD.encode(&credential.credential_id);
+  let repo = state.repo.passkey();
+  if repo.get_passkey(&credential_id).await?.is_some() {
+    return Err(AppError::UserExists);
+  }
+  let passkey = repo
+    .create_passkey(PasskeyActiveModel {
+      user_id: Set(user.user_id.clone()),
+      credential_id: Set(credential_id),
+      public_key: Set(URL_SAFE_NO_PAD.encode(&credential.public_key)),
+      sign_count: Set(credential.sign_count),
+      name: Set(name),
+      created_at: Set(utc_now()),
+      ..Default::default()
+    })
+    .await?;
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "passkey": passkey.id, "name": passkey.name }));
+  let action = AuditAction::PasskeyRegister;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(passkey.into())
+}
+
+/// Options to log in with any passkey of the relying party
+#[tracing::instrument(skip_all)]
+pub async fn login_options(state: &AppState) -> Result<LoginOptionsResponseData, AppError> {
+  let rp = relying_party(state)?;
+  let challenge = create_challenge(state, AUTHENTICATION, None).await?;
+  Ok(LoginOptionsResponseData {
+    public_key: RequestOptions {
+      challenge,
+      rp_id: rp.id.clone(),
+      timeout: CHALLENGE_TTL.num_milliseconds(),
+      allow_credentials: vec![],
+      user_verification: "required",
+    },
+  })
+}
+
+/// Exchanges an assertion for the same login token as `POST /token`
+#[tracing::instrument(skip_all)]
+pub async fn login(
+  state: &AppState,
+  ctx: &AuditContext,
+  credential: AuthenticationCredential,
+) -> Result<Value, AppError> {
+  let result = passkey_login(state, ctx, credential).await;
+  let label = if result.is_ok() { "success" } else { "failure" };
+  LOGINS_TOTAL.with_label_values(&[label]).inc();
+  let (user, tokens) = result?;
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "method": "passkey" }));
+  let action = AuditAction::Login;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(json!({
+    "token": tokens.token,
+    "reauth_token": tokens.reauth_token
+  }))
+}
+
+async fn passkey_login(
+  state: &AppState,
+  ctx: &AuditContext,
+  credential: AuthenticationCredential,
+) -> Result<(UserModel, LoginTokens), AppError> {
+  let rp = relying_party(state)?;
+  let response = credential.response;
+  let client_data_json = base64url(&response.client_data_json)?;
+  let client_data = ClientData::parse(&client_data_json)?;
+  take_challenge(state, &client_data, AUTHENTICATION).await?;
+  let repo = state.repo.passkey();
+  let credential_id = URL_SAFE_NO_PAD.encode(base64url(&credential.id)?);
+  let passkey = repo
+    .get_passkey(&credential_id)
+    .await?
+    .ok_or(AppError::Passkey)?;
+  if let Some(user_handle) = response.user_handle.filter(|handle| !handle.is_empty()) {
+    if base64url(&user_handle)? != passkey.user_id.as_bytes() {
+      return Err(AppError::Passkey);
+    }
+  }
+  let sign_count = rp.verify_assertion(
+    &client_data_json,
+    &client_data,
+    &base64url(&response.authenticator_data)?,
+    &base64url(&response.signature)?,
+    &base64url(&passkey.public_key)?,
+  )?;
+  // A counter that does not grow means the authenticator was cloned. Passkeys synced
+  // between devices always send 0
+  if (sign_count != 0 || passkey.sign_count != 0) && sign_count <= passkey.sign_count {
+    tracing::error!("Passkey {} sign counter went back", passkey.id);
+    return Err(AppError::Passkey);
+  }
+  if !repo.touch_passkey(&passkey, sign_count, utc_now()).await? {
+    return Err(AppError::Passkey);
+  }
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(&passkey.user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::UserNotFound)?;
+  let tokens = session::service::create_login_tokens(state, ctx, &user).await?;
+  Ok((user, tokens))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_passkeys(
+  state: &AppState,
+  token: String,
+) -> Result<PasskeysResponseData, AppError> {
+  let user = current_user(state, &token).await?;
+  let passkeys = state
+    .repo
+    .passkey()
+    .get_user_passkeys(&user.user_id)
+    .await?;
+  Ok(PasskeysResponseData {
+    passkeys: passkeys.into_iter().map(PasskeyItem::from).collect(),
+  })
+}
+
+/// Removing a passkey needs [`session::service::check_reauth`] and the user is emailed once it
+/// is removed
+#[tracing::instrument(skip_all)]
+pub async fn delete_passkey(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  reauth_token: Option<String>,
+  body: DeletePasskeyBody,
+  id: u32,
+  lang: &str,
+) -> Result<bool, AppError> {
+  let user = current_user(state, &token).await?;
+  let (reauth_token, current_password) =
+    (reauth_token.as_deref(), body.current_password.as_deref());
+  session::service::check_reauth(state, &user, reauth_token, current_password).await?;
+  let passkey = state
+    .repo
+    .passkey()
+    .get_user_passkeys(&user.user_id)
+    .await?
+    .into_iter()
+    .find(|passkey| passkey.id == id)
+    .ok_or(AppError::NotFound)?;
+  let deleted = state
+    .repo
+    .passkey()
+    .delete_passkey(&user.user_id, id)
+    .await?;
+  if !deleted {
+    return Err(AppError::NotFound);
+  }
+  let (subject, notice) = ("Passkey Removed Mail", "passkey removed notice");
+  let vars = [("{name}", passkey.name.as_str())];
+  user::service::notify(state, ctx, user.email, lang, subject, notice, &vars);
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "passkey": id }));
+  let action = AuditAction::PasskeyDelete;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::testing::{self, ctx},
+    components::passkey::model::{AssertionResponse, AttestationResponse},
+    helpers::webauthn::testing::{KeyKind, SoftAuthenticator},
+  };
+
+  use super::*;
+
+  const ORIGIN: &str = "https://example.com";
+
+  /// Passkeys for example.com, and the login token of a user
+  async fn setup() -> (AppState, String) {
+    let state = testing::app_state(&[
+      ("WEBAUTHN_RP_ID", "example.com"),
+      ("WEBAUTHN_ORIGINS", ORIGIN),
+    ])
+    .await;
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    let token = session::service::create_session(&state, &ctx(), &user)
+      .await
+      .unwrap();
+    (state, token)
+  }
+
+  async fn register_passkey(state: &AppState, token: &str, auth: &SoftAuthenticator) {
+    let options = registration_options(state, token.to_string())
+      .await
+      .unwrap();
+    let registration = auth.create(&options.public_key.challenge);
+    let body = RegisterPasskeyBody {
+      name: Some("Laptop".to_string()),
+      credential: RegistrationCredential {
+        id: URL_SAFE_NO_PAD.encode(&auth.credential_id),
+        response: AttestationResponse {
+          client_data_json: URL_SAFE_NO_PAD.encode(registration.client_data_json),
+          attestation_object: URL_SAFE_NO_PAD.encode(registration.attestation_object),
+        },
+      },
+    };
+    register(state, &ctx(), token.to_string(), body)
+      .await
+      .unwrap();
+  }
+
+  fn credential(auth: &mut SoftAuthenticator, challenge: &str) -> AuthenticationCredential {
+    let assertion = auth.get(challenge);
+    AuthenticationCredential {
+      id: URL_SAFE_NO_PAD.encode(&auth.credential_id),
+      response: AssertionResponse {
+        client_data_json: URL_SAFE_NO_PAD.encode(assertion.client_data_json),
+        authenticator_data: URL_SAFE_NO_PAD.encode(assertion.authenticator_data),
+        signature: URL_SAFE_NO_PAD.encode(assertion.signature),
+        user_handle: None,
+      },
+    }
+  }
+
+  async fn passkey_login_with(
+    state: &AppState,
+    auth: &mut SoftAuthenticator,
+  ) -> Result<Value, AppError> {
+    let challenge = login_options(state).await?.public_key.challenge;
+    login(state, &ctx(), credential(auth, &challenge)).await
+  }
+
+  #[actix_web::test]
+  async fn registers_and_logs_in_with_es256_and_ed25519() {
+    let (state, token) = setup().await;
+    for kind in [KeyKind::Es256, KeyKind::Ed25519] {
+      let mut auth = SoftAuthenticator::new(kind, "example.com", ORIGIN);
+      register_passkey(&state, &token, &auth).await;
+      let login = passkey_login_with(&state, &mut auth).await.unwrap();
+      let token = login["token"].as_str().unwrap();
+      let user = session::service::authenticate(&state, token)
+        .await
+        .unwrap()
+        .user;
+      assert_eq!(user.email, "alice@example.com");
+      // The assertion is a fresh proof for sensitive changes
+      let reauth = login["reauth_token"].as_str();
+      assert!(session::service::check_reauth(&state, &user, reauth, None)
+        .await
+        .is_ok());
+    }
+    let passkeys = get_passkeys(&state, token).await.unwrap().passkeys;
+    assert_eq!(passkeys.len(), 2);
+    assert!(passkeys
+      .iter()
+      .all(|passkey| passkey.last_used_at.is_some()));
+  }
+
+  #[actix_web::test]
+  async fn refuses_a_sign_counter_going_back() {
+    let (state, token) = setup().await;
+    let mut auth = SoftAuthenticator::new(KeyKind::Es256, "example.com", ORIGIN);
+    register_passkey(&state, &token, &auth).await;
+    auth.sign_count = 4;
+    passkey_login_with(&state, &mut auth).await.unwrap();
+    // A clone of the authenticator, still at an earlier count
+    auth.sign_count = 2;
+    let cloned = passkey_login_with(&state, &mut auth).await;
+    assert!(matches!(cloned, Err(AppError::Passkey)));
+    // The same count again
+    auth.sign_count = 4;
+    let repeated = passkey_login_with(&state, &mut auth).await;
+    assert!(matches!(repeated, Err(AppError::Passkey)));
+    passkey_login_with(&state, &mut auth).await.unwrap();
+  }
+
+  #[actix_web::test]
+  async fn refuses_a_replayed_assertion_and_the_challenge_of_a_registration() {
+    let (state, token) = setup().await;
+    let mut auth = SoftAuthenticator::new(KeyKind::Ed25519, "example.com", ORIGIN);
+    register_passkey(&state, &token, &auth).await;
+    let challenge = login_options(&state).await.unwrap().public_key.challenge;
+    let assertion = credential(&mut auth, &challenge);
+    let replay = AuthenticationCredential {
+      id: assertion.id.clone(),
+      response: AssertionResponse {
+        client_data_json: assertion.response.client_data_json.clone(),
+        authenticator_data: assertion.response.authenticator_data.clone(),
+        signature: assertion.response.signature.clone(),
+        user_handle: None,
+      },
+    };
+    login(&state, &ctx(), assertion).await.unwrap();
+    let replayed = login(&state, &ctx(), replay).await;
+    assert!(matches!(replayed, Err(AppError::Passkey)));
+    // The challenge was issued for a registration
+    let options = registration_options(&state, token).await.unwrap();
+    let credential = credential(&mut auth, &options.public_key.challenge);
+    let mixed = login(&state, &ctx(), credential).await;
+    assert!(matches!(mixed, Err(AppError::Passkey)));
+  }
+
+  #[actix_web::test]
+  async fn refuses_a_registration_for_another_relying_party() {
+    let (state, token) = setup().await;
+    let auth = SoftAuthenticator::new(KeyKind::Es256, "example.org", ORIGIN);
+    let options = registration_options(&state, token.clone()).await.unwrap();
+    let registration = auth.create(&options.public_key.challenge);
+    let body = RegisterPasskeyBody {
+      name: None,
+      credential: RegistrationCredential {
+        id: URL_SAFE_NO_PAD.encode(&auth.credential_id),
+        response: AttestationResponse {
+          client_data_json: URL_SAFE_NO_PAD.encode(registration.client_data_json),
+          attestation_object: URL_SAFE_NO_PAD.encode(registration.attestation_object),
+        },
+      },
+    };
+    let registered = register(&state, &ctx(), token.clone(), body).await;
+    assert!(matches!(registered, Err(AppError::Passkey)));
+    assert!(get_passkeys(&state, token)
+      .await
+      .unwrap()
+      .passkeys
+      .is_empty());
+  }
+
+  #[actix_web::test]
+  async fn removing_a_passkey_needs_a_fresh_proof() {
+    let (state, token) = setup().await;
+    let auth = SoftAuthenticator::new(KeyKind::Es256, "example.com", ORIGIN);
+    register_passkey(&state, &token, &auth).await;
+    let id = get_passkeys(&state, token.clone()).await.unwrap().passkeys[0].id;
+    let ctx = ctx();
+    let delete = |reauth_token: Option<String>, id| {
+      let body = DeletePasskeyBody::default();
+      delete_passkey(&state, &ctx, token.clone(), reauth_token, body, id, "en")
+    };
+    assert!(matches!(
+      delete(None, id).await,
+      Err(AppError::ReauthRequired)
+    ));
+    let user = session::service::authenticate(&state, &token)
+      .await
+      .unwrap()
+      .user;
+    let login = session::service::create_login_tokens(&state, &ctx, &user)
+      .await
+      .unwrap();
+    let reauth = Some(login.reauth_token);
+    assert!(matches!(
+      delete(reauth.clone(), id + 1).await,
+      Err(AppError::NotFound)
+    ));
+    assert!(delete(reauth, id).await.unwrap());
+    let passkeys = get_passkeys(&state, token.clone()).await.unwrap().passkeys;
+    assert!(passkeys.is_empty());
+  }
+}
@@ -0,0 +1,103 @@
+use actix_web::{
+  delete, get, post,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::model::AuditContext,
+    session::{model::*, service},
+  },
+  helpers::header::extract_token,
+  middlewares::rate_limit::RateLimit,
+  response::Response,
+};
+
+/// Active sessions of the current user, or every login with `all=true`
+#[utoipa::path(
+  tag = "Session",
+  params(GetSessionsQuery),
+  responses((status = OK, body = Response<SessionsResponseData>)),
+)]
+#[get("/user/sessions")]
+#[tracing::instrument(skip_all)]
+pub async fn get_sessions(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<GetSessionsQuery>,
+) -> HttpResponse {
+  let all = query.all.unwrap_or(false);
+  match extract_token(&req) {
+    Ok(token) => match service::get_sessions(&state, token, all).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Logs out everywhere
+#[utoipa::path(
+  tag = "Session",
+  responses((status = OK, body = Response<RevokeSessionsResponseData>)),
+)]
+#[delete("/user/sessions")]
+#[tracing::instrument(skip_all)]
+pub async fn revoke_all_sessions(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::revoke_all_sessions(&state, &ctx, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[utoipa::path(tag = "Session", responses((status = OK)))]
+#[delete("/user/sessions/{id}")]
+#[tracing::instrument(skip_all)]
+pub async fn revoke_session(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<String>,
+) -> HttpResponse {
+  let id = path.into_inner();
+  match extract_token(&req) {
+    Ok(token) => match service::revoke_session(&state, &ctx, token, id).await {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Confirms the current password, the returned token is sent as `X-Reauth-Token` with
+/// sensitive changes such as a new password
+#[utoipa::path(
+  tag = "Session",
+  responses((status = OK, body = Response<ReauthResponseData>)),
+)]
+#[post("/user/reauth", wrap = "RateLimit::reauth()")]
+#[tracing::instrument(skip_all)]
+pub async fn reauthenticate(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<ReauthBody>,
+) -> HttpResponse {
+  let Json(ReauthBody { password }) = body;
+  match extract_token(&req) {
+    Ok(token) => match service::reauthenticate(&state, &ctx, token, password).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
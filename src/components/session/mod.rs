@@ -0,0 +1,13 @@
+pub mod handler;
+pub mod model;
+pub mod service;
+
+use utoipa_actix_web::service_config::ServiceConfig;
+
+/// Registered before `user`, `/user/sessions` would otherwise match `/user/{user_id}`
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_sessions);
+  cfg.service(handler::revoke_all_sessions);
+  cfg.service(handler::revoke_session);
+  cfg.service(handler::reauthenticate);
+}
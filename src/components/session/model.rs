@@ -0,0 +1,93 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::entity::prelude::{SessionModel, UserModel};
+
+/// Data of the JWT returned by `POST /token`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionClaims {
+  /// Email at login, requests resolve the user from the session instead
+  pub email: String,
+  /// Session id, checked on every request so revoking it logs the token out
+  pub sid: String,
+}
+
+/// A login token whose session is active, with the user of that session
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+  pub sid: String,
+  pub user: UserModel,
+}
+
+/// Data of the JWT returned by `POST /user/reauth`, only valid while its session is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReauthClaims {
+  pub user_id: String,
+  pub sid: String,
+}
+
+/// Tokens of a login
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LoginTokens {
+  pub token: String,
+  /// Stands for the login in sensitive changes, as the token of `POST /user/reauth` does
+  pub reauth_token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReauthBody {
+  pub password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReauthResponseData {
+  /// Sent as `X-Reauth-Token` with sensitive changes
+  pub reauth_token: String,
+  /// Seconds
+  pub expires_in: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GetSessionsQuery {
+  /// Also list revoked and expired sessions, i.e. the login history
+  pub all: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionItem {
+  pub id: String,
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  pub created_at: DateTime<Utc>,
+  pub last_seen_at: DateTime<Utc>,
+  pub expires_at: DateTime<Utc>,
+  pub revoked_at: Option<DateTime<Utc>>,
+  /// The session of the token making the request
+  pub current: bool,
+}
+
+impl SessionItem {
+  pub fn new(model: SessionModel, current_sid: &str) -> Self {
+    Self {
+      current: model.id == current_sid,
+      id: model.id,
+      ip: model.ip,
+      user_agent: model.user_agent,
+      created_at: model.created_at,
+      last_seen_at: model.last_seen_at,
+      expires_at: model.expires_at,
+      revoked_at: model.revoked_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionsResponseData {
+  pub sessions: Vec<SessionItem>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevokeSessionsResponseData {
+  pub revoked: u64,
+}
@@ -0,0 +1,430 @@
+use chrono::Duration;
+use helpers::{
+  hash, jwt,
+  time::utc_now,
+  uuid::{self, Alphabet},
+};
+use sea_orm::Set;
+use serde_json::json;
+
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    user::model::{ApiKeyScope, API_KEY_PREFIX},
+  },
+  entity::prelude::*,
+  error::AppError,
+};
+
+use super::model::{
+  Authenticated, LoginTokens, ReauthClaims, ReauthResponseData, RevokeSessionsResponseData,
+  SessionClaims, SessionItem, SessionsResponseData,
+};
+
+/// Lifetime of a session and of its token, in seconds
+pub const SESSION_TTL: i64 = 2592000;
+
+/// Lifetime of a re-authentication token, in seconds
+pub const REAUTH_TTL: i64 = 300;
+
+/// `last_seen_at` of sessions and `last_used_at` of API keys are written at most this often
+const TOUCH_INTERVAL: Duration = Duration::seconds(60);
+
+/// Opens a session for a successful login and returns its token
+#[tracing::instrument(skip_all)]
+pub async fn create_session(
+  state: &AppState,
+  ctx: &AuditContext,
+  user: &UserModel,
+) -> Result<String, AppError> {
+  Ok(open_session(state, ctx, user).await?.1)
+}
+
+/// Opens a session like [`create_session`] and also returns a reauth token for it. The login
+/// that just happened is as fresh a proof as [`reauthenticate`], so accounts without a known
+/// password, registered through OIDC or only using magic links and passkeys, log in again to
+/// make a sensitive change
+#[tracing::instrument(skip_all)]
+pub async fn create_login_tokens(
+  state: &AppState,
+  ctx: &AuditContext,
+  user: &UserModel,
+) -> Result<LoginTokens, AppError> {
+  let (sid, token) = open_session(state, ctx, user).await?;
+  Ok(LoginTokens {
+    token,
+    reauth_token: reauth_token(state, user, sid)?,
+  })
+}
+
+/// Session id and login token of a new session
+async fn open_session(
+  state: &AppState,
+  ctx: &AuditContext,
+  user: &UserModel,
+) -> Result<(String, String), AppError> {
+  let now = utc_now();
+  let session = SessionActiveModel {
+    id: Set(uuid::uuid(&Alphabet::DEFAULT, 21)),
+    user_id: Set(user.user_id.clone()),
+    ip: Set(ctx.ip.clone()),
+    user_agent: Set(ctx.user_agent.clone()),
+    created_at: Set(now),
+    last_seen_at: Set(now),
+    expires_at: Set(now + Duration::seconds(SESSION_TTL)),
+    revoked_at: Set(None),
+  };
+  let session = state.repo.session().create_session(session).await?;
+  let claims = SessionClaims {
+    email: user.email.clone(),
+    sid: session.id.clone(),
+  };
+  let token = jwt::sign(claims, &state.jwt_token, SESSION_TTL)?;
+  Ok((session.id, token))
+}
+
+fn reauth_token(state: &AppState, user: &UserModel, sid: String) -> Result<String, AppError> {
+  let claims = ReauthClaims {
+    user_id: user.user_id.clone(),
+    sid,
+  };
+  Ok(jwt::sign(claims, &state.jwt_token, REAUTH_TTL)?)
+}
+
+/// Verifies the token and that its session is still active. The user is the one the session
+/// was opened for, whatever email the token carries, and must not be deleted
+#[tracing::instrument(skip_all)]
+pub async fn authenticate(state: &AppState, token: &str) -> Result<Authenticated, AppError> {
+  let claims = jwt::verify::<SessionClaims>(token, &state.jwt_token)?
+    .claims
+    .data;
+  let session = state
+    .repo
+    .session()
+    .get_session(&claims.sid)
+    .await?
+    .ok_or(AppError::InvalidToken)?;
+  let now = utc_now();
+  if session.revoked_at.is_some() || session.expires_at <= now {
+    return Err(AppError::InvalidToken);
+  }
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(&session.user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::InvalidToken)?;
+  if now - session.last_seen_at >= TOUCH_INTERVAL {
+    state.repo.session().touch_session(&session.id, now).await?;
+  }
+  Ok(Authenticated {
+    sid: session.id,
+    user,
+  })
+}
+
+/// Id of the key in an API key token, `None` for login tokens
+pub fn api_key_id(token: &str) -> Option<&str> {
+  token
+    .strip_prefix(API_KEY_PREFIX)?
+    .split_once('_')
+    .map(|(id, _)| id)
+}
+
+/// The active API key of `token`, checked against its stored hash
+#[tracing::instrument(skip_all)]
+pub async fn verify_api_key(state: &AppState, token: &str) -> Result<ApiKeyModel, AppError> {
+  let id = api_key_id(token).ok_or(AppError::InvalidToken)?;
+  let api_key = state
+    .repo
+    .api_key()
+    .get_api_key(id)
+    .await?
+    .filter(|api_key| api_key.key_hash == hash::blake3(token.as_bytes()))
+    .ok_or(AppError::InvalidToken)?;
+  let now = utc_now();
+  if api_key.revoked_at.is_some() || api_key.expires_at.is_some_and(|at| at <= now) {
+    return Err(AppError::InvalidToken);
+  }
+  Ok(api_key)
+}
+
+/// Email of the user behind a login token, or behind an API key granting `scope`.
+/// Endpoints managing sessions and API keys use [`authenticate`] and refuse API keys
+#[tracing::instrument(skip_all)]
+pub async fn authorize(
+  state: &AppState,
+  token: &str,
+  scope: ApiKeyScope,
+) -> Result<String, AppError> {
+  if !token.starts_with(API_KEY_PREFIX) {
+    return Ok(authenticate(state, token).await?.user.email);
+  }
+  let api_key = verify_api_key(state, token).await?;
+  if !api_key.scopes.split(',').any(|s| s == scope.as_str()) {
+    return Err(AppError::Forbidden);
+  }
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(&api_key.user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::InvalidToken)?;
+  let now = utc_now();
+  if api_key
+    .last_used_at
+    .is_none_or(|at| now - at >= TOUCH_INTERVAL)
+  {
+    state.repo.api_key().touch_api_key(&api_key.id, now).await?;
+  }
+  Ok(user.email)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_sessions(
+  state: &AppState,
+  token: String,
+  all: bool,
+) -> Result<SessionsResponseData, AppError> {
+  let Authenticated { sid, user } = authenticate(state, &token).await?;
+  let sessions = state
+    .repo
+    .session()
+    .get_user_sessions(&user.user_id, utc_now(), all)
+    .await?;
+  Ok(SessionsResponseData {
+    sessions: sessions
+      .into_iter()
+      .map(|session| SessionItem::new(session, &sid))
+      .collect(),
+  })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn revoke_session(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  id: String,
+) -> Result<bool, AppError> {
+  let user = authenticate(state, &token).await?.user;
+  let revoked = state
+    .repo
+    .session()
+    .revoke_session(&user.user_id, &id, utc_now())
+    .await?;
+  if !revoked {
+    return Err(AppError::NotFound);
+  }
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "sessions": [id] }));
+  let action = AuditAction::SessionRevoke;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(true)
+}
+
+/// Logs out everywhere, including the current session
+#[tracing::instrument(skip_all)]
+pub async fn revoke_all_sessions(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+) -> Result<RevokeSessionsResponseData, AppError> {
+  let user = authenticate(state, &token).await?.user;
+  let revoked = state
+    .repo
+    .session()
+    .revoke_user_sessions(&user.user_id, utc_now())
+    .await?;
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "sessions": "all", "revoked": revoked }));
+  let action = AuditAction::SessionRevoke;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(RevokeSessionsResponseData { revoked })
+}
+
+/// Confirms the password of the logged-in user and returns a token that stands for it in
+/// sensitive changes for [`REAUTH_TTL`]
+#[tracing::instrument(skip_all)]
+pub async fn reauthenticate(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  password: String,
+) -> Result<ReauthResponseData, AppError> {
+  let Authenticated { sid, user } = authenticate(state, &token).await?;
+  if !state.passwords.verify(&password, &user.password).await? {
+    return Err(AppError::PasswordIncorrect);
+  }
+  let reauth_token = reauth_token(state, &user, sid)?;
+  let user_id = Some(user.user_id);
+  let action = AuditAction::Reauth;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, None).await;
+  Ok(ReauthResponseData {
+    reauth_token,
+    expires_in: REAUTH_TTL,
+  })
+}
+
+/// Proof that the owner of the account is making a sensitive change: a token of
+/// [`reauthenticate`] whose session is still active, or else the current password
+#[tracing::instrument(skip_all)]
+pub async fn check_reauth(
+  state: &AppState,
+  user: &UserModel,
+  reauth_token: Option<&str>,
+  current_password: Option<&str>,
+) -> Result<(), AppError> {
+  if let Some(token) = reauth_token {
+    let claims = jwt::verify::<ReauthClaims>(token, &state.jwt_token)
+      .map_err(|_| AppError::ReauthRequired)?
+      .claims
+      .data;
+    let now = utc_now();
+    let active = state
+      .repo
+      .session()
+      .get_session(&claims.sid)
+      .await?
+      .is_some_and(|session| session.revoked_at.is_none() && session.expires_at > now);
+    if claims.user_id != user.user_id || !active {
+      return Err(AppError::ReauthRequired);
+    }
+    return Ok(());
+  }
+  match current_password {
+    Some(password) if state.passwords.verify(password, &user.password).await? => Ok(()),
+    Some(_) => Err(AppError::PasswordIncorrect),
+    None => Err(AppError::ReauthRequired),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::testing::{self, PASSWORD};
+
+  use super::*;
+
+  /// A request of the device `user_agent`
+  fn ctx(user_agent: &str) -> AuditContext {
+    AuditContext {
+      ip: Some("203.0.113.7".to_string()),
+      user_agent: Some(user_agent.to_string()),
+      ..testing::ctx()
+    }
+  }
+
+  async fn alice(state: &AppState) -> UserModel {
+    testing::user(state, "Alice", "alice@example.com").await
+  }
+
+  #[actix_web::test]
+  async fn sessions_are_listed_and_revoked_one_by_one() {
+    let state = testing::app_state(&[]).await;
+    let user = alice(&state).await;
+    let laptop = create_session(&state, &ctx("laptop"), &user).await.unwrap();
+    let phone = create_session(&state, &ctx("phone"), &user).await.unwrap();
+    let sessions = get_sessions(&state, laptop.clone(), false)
+      .await
+      .unwrap()
+      .sessions;
+    assert_eq!(sessions.len(), 2);
+    let current = sessions.iter().find(|session| session.current).unwrap();
+    assert_eq!(current.user_agent.as_deref(), Some("laptop"));
+    assert_eq!(current.ip.as_deref(), Some("203.0.113.7"));
+    let other = sessions.iter().find(|session| !session.current).unwrap();
+    let id = other.id.clone();
+    assert!(
+      revoke_session(&state, &ctx("laptop"), laptop.clone(), id.clone())
+        .await
+        .unwrap()
+    );
+    assert!(matches!(
+      authenticate(&state, &phone).await,
+      Err(AppError::InvalidToken)
+    ));
+    assert!(matches!(
+      revoke_session(&state, &ctx("laptop"), laptop.clone(), id).await,
+      Err(AppError::NotFound)
+    ));
+    let active = get_sessions(&state, laptop.clone(), false).await.unwrap();
+    assert_eq!(active.sessions.len(), 1);
+    let all = get_sessions(&state, laptop, true).await.unwrap().sessions;
+    assert_eq!(all.len(), 2);
+    assert!(all.iter().any(|session| session.revoked_at.is_some()));
+  }
+
+  #[actix_web::test]
+  async fn sessions_of_other_users_cannot_be_revoked() {
+    let state = testing::app_state(&[]).await;
+    let alice = alice(&state).await;
+    let bob = testing::user(&state, "Bob", "bob@example.com").await;
+    let alice_token = create_session(&state, &ctx("laptop"), &alice)
+      .await
+      .unwrap();
+    let bob_token = create_session(&state, &ctx("phone"), &bob).await.unwrap();
+    let bob_sid = get_sessions(&state, bob_token.clone(), false)
+      .await
+      .unwrap()
+      .sessions[0]
+      .id
+      .clone();
+    assert!(matches!(
+      revoke_session(&state, &ctx("laptop"), alice_token, bob_sid).await,
+      Err(AppError::NotFound)
+    ));
+    assert!(authenticate(&state, &bob_token).await.is_ok());
+  }
+
+  #[actix_web::test]
+  async fn logging_out_everywhere_includes_the_current_session() {
+    let state = testing::app_state(&[]).await;
+    let user = alice(&state).await;
+    let laptop = create_session(&state, &ctx("laptop"), &user).await.unwrap();
+    let phone = create_session(&state, &ctx("phone"), &user).await.unwrap();
+    let revoked = revoke_all_sessions(&state, &ctx("laptop"), laptop.clone())
+      .await
+      .unwrap()
+      .revoked;
+    assert_eq!(revoked, 2);
+    for token in [laptop, phone] {
+      assert!(matches!(
+        authenticate(&state, &token).await,
+        Err(AppError::InvalidToken)
+      ));
+    }
+  }
+
+  #[actix_web::test]
+  async fn reauth_tokens_need_their_session_to_be_active() {
+    let state = testing::app_state(&[]).await;
+    let user = alice(&state).await;
+    let token = create_session(&state, &ctx("laptop"), &user).await.unwrap();
+    let password = "wrong password".to_string();
+    assert!(matches!(
+      reauthenticate(&state, &ctx("laptop"), token.clone(), password).await,
+      Err(AppError::PasswordIncorrect)
+    ));
+    let password = PASSWORD.to_string();
+    let reauth = reauthenticate(&state, &ctx("laptop"), token.clone(), password)
+      .await
+      .unwrap()
+      .reauth_token;
+    assert!(check_reauth(&state, &user, Some(&reauth), None)
+      .await
+      .is_ok());
+    revoke_all_sessions(&state, &ctx("laptop"), token)
+      .await
+      .unwrap();
+    assert!(matches!(
+      check_reauth(&state, &user, Some(&reauth), None).await,
+      Err(AppError::ReauthRequired)
+    ));
+  }
+}
@@ -1,14 +1,17 @@
 use actix_web::{
-  get, post, put,
+  delete, get, post, put,
   web::{Data, Json, Path, Query},
   HttpRequest, HttpResponse,
 };
 
 use crate::{
   app::AppState,
-  components::user::{model::*, service},
-  error::AppError,
-  helpers::header::{extract_ip, extract_token},
+  components::{
+    audit::model::AuditContext,
+    user::{model::*, service},
+  },
+  helpers::header::{extract_reauth_token, extract_token},
+  middlewares::rate_limit::RateLimit,
   response::Response,
 };
 
@@ -21,17 +24,13 @@ use crate::{
     (status = 200, body = Response<UserRegisterResponseData>),
   ),
 )]
-#[post("/user")]
+#[post("/user", wrap = "RateLimit::register()")]
+#[tracing::instrument(skip_all)]
 pub async fn user_register(
-  req: HttpRequest,
   state: Data<AppState>,
   query: Query<UserRegisterQuery>,
   body: Json<UserRegisterBody>,
 ) -> HttpResponse {
-  let pass = state.rate_limiter.check_and_update(&extract_ip(&req), 1);
-  if !pass {
-    return HttpResponse::Ok().json(Response::<()>::error(AppError::FrequencyLimited, None));
-  }
   let Query(UserRegisterQuery { lang }) = query;
   let Json(UserRegisterBody {
     nickname,
@@ -45,10 +44,49 @@ pub async fn user_register(
 }
 
 #[utoipa::path(tag = "User", responses((status = OK)))]
-#[post("/token")]
-pub async fn user_login(state: Data<AppState>, body: Json<UserLoginBody>) -> HttpResponse {
+#[post("/token", wrap = "RateLimit::login()")]
+#[tracing::instrument(skip_all)]
+pub async fn user_login(
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<UserLoginBody>,
+) -> HttpResponse {
   let Json(UserLoginBody { email, password }) = body;
-  match service::user_login(&state, email, password).await {
+  match service::user_login(&state, &ctx, email, password).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Emails a single-use login link, the answer is the same whether the account exists or not
+#[utoipa::path(tag = "User", params(MagicLinkQuery), responses((status = OK)))]
+#[post("/token/magic-link", wrap = "RateLimit::magic_link()")]
+#[tracing::instrument(skip_all)]
+pub async fn request_magic_link(
+  state: Data<AppState>,
+  query: Query<MagicLinkQuery>,
+  body: Json<MagicLinkBody>,
+) -> HttpResponse {
+  let Query(MagicLinkQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Json(MagicLinkBody { email }) = body;
+  match service::request_magic_link(&state, email, lang.unwrap_or("en")).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+/// Exchanges the token of a login link for the same login token as `POST /token`
+#[utoipa::path(tag = "User", responses((status = OK)))]
+#[post("/token/magic-link/exchange")]
+#[tracing::instrument(skip_all)]
+pub async fn exchange_magic_link(
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<ExchangeMagicLinkBody>,
+) -> HttpResponse {
+  let Json(ExchangeMagicLinkBody { token }) = body;
+  match service::exchange_magic_link(&state, &ctx, token).await {
     Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
     Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
   }
@@ -56,6 +94,7 @@ pub async fn user_login(state: Data<AppState>, body: Json<UserLoginBody>) -> Htt
 
 #[utoipa::path(tag = "User", responses((status = OK)))]
 #[get("/user")]
+#[tracing::instrument(skip_all)]
 async fn get_user_info(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
   match extract_token(&req) {
     Ok(token) => match service::get_login_user_info(&state, token).await {
@@ -66,35 +105,207 @@ async fn get_user_info(req: HttpRequest, state: Data<AppState>) -> HttpResponse
   }
 }
 
-#[utoipa::path(tag = "User", responses((status = OK)))]
-#[put("/user")]
+#[utoipa::path(tag = "User", params(SetUserProfileQuery), responses((status = OK)))]
+#[put("/user", wrap = "RateLimit::reauth()")]
+#[tracing::instrument(skip_all)]
 pub async fn set_user_profile(
   req: HttpRequest,
   state: Data<AppState>,
+  ctx: AuditContext,
+  query: Query<SetUserProfileQuery>,
   body: Json<SetUserProfileBody>,
 ) -> HttpResponse {
-  let Json(SetUserProfileBody { nickname, password }) = body;
+  let Query(SetUserProfileQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let reauth_token = extract_reauth_token(&req);
   match extract_token(&req) {
-    Ok(token) => match service::set_user_profile(&state, token, nickname, password).await {
-      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
-      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    Ok(token) => match service::set_user_profile(
+      &state,
+      &ctx,
+      token,
+      reauth_token,
+      body.into_inner(),
+      lang.unwrap_or("en"),
+    )
+    .await
+    {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
     },
-    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+/// Deletes the account of the logged in user, with a fresh proof as for a new password. The
+/// body is optional when an `X-Reauth-Token` is sent
+#[utoipa::path(
+  tag = "User",
+  params(DeleteAccountQuery),
+  request_body(content = Option<DeleteAccountBody>),
+  responses((status = OK)),
+)]
+#[delete("/user", wrap = "RateLimit::reauth()")]
+#[tracing::instrument(skip_all)]
+pub async fn delete_account(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  query: Query<DeleteAccountQuery>,
+  body: Option<Json<DeleteAccountBody>>,
+) -> HttpResponse {
+  let Query(DeleteAccountQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let body = body.map(Json::into_inner).unwrap_or_default();
+  let reauth_token = extract_reauth_token(&req);
+  match extract_token(&req) {
+    Ok(token) => match service::delete_account(
+      &state,
+      &ctx,
+      token,
+      reauth_token,
+      body,
+      lang.unwrap_or("en"),
+    )
+    .await
+    {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
   }
 }
 
 #[utoipa::path(tag = "User", responses((status = OK)))]
 #[put("/user/{user_id}")]
+#[tracing::instrument(skip_all)]
 pub async fn set_user_type(
   req: HttpRequest,
   state: Data<AppState>,
+  ctx: AuditContext,
   path: Path<u32>,
   body: Json<SetUserTypeBody>,
 ) -> HttpResponse {
   let user_id = path.into_inner();
   let Json(SetUserTypeBody { r#type }) = body;
   match extract_token(&req) {
-    Ok(token) => match service::set_user_type(&state, token, user_id, r#type).await {
+    Ok(token) => match service::set_user_type(&state, &ctx, token, user_id, r#type).await {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+/// Emails a confirmation link to the new address and a notice to the current one, the
+/// email changes once the link is confirmed
+#[utoipa::path(tag = "User", params(EmailChangeQuery), responses((status = OK)))]
+#[post("/user/email", wrap = "RateLimit::reauth()")]
+#[tracing::instrument(skip_all)]
+pub async fn request_email_change(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  query: Query<EmailChangeQuery>,
+  body: Json<ChangeEmailBody>,
+) -> HttpResponse {
+  let Query(EmailChangeQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let reauth_token = extract_reauth_token(&req);
+  match extract_token(&req) {
+    Ok(token) => match service::request_email_change(
+      &state,
+      &ctx,
+      token,
+      reauth_token,
+      body.into_inner(),
+      lang.unwrap_or("en"),
+    )
+    .await
+    {
+      Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+/// Switches to the new email with the token of the confirmation link, every session is
+/// logged out
+#[utoipa::path(tag = "User", params(EmailChangeQuery), responses((status = OK)))]
+#[post("/user/email/confirm")]
+#[tracing::instrument(skip_all)]
+pub async fn confirm_email_change(
+  state: Data<AppState>,
+  ctx: AuditContext,
+  query: Query<EmailChangeQuery>,
+  body: Json<ConfirmEmailChangeBody>,
+) -> HttpResponse {
+  let Query(EmailChangeQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Json(ConfirmEmailChangeBody { token }) = body;
+  match service::confirm_email_change(&state, &ctx, token).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+/// Creates a personal API key. The key is only shown in this response
+#[utoipa::path(
+  tag = "User",
+  responses((status = OK, body = Response<CreateApiKeyResponseData>)),
+)]
+#[post("/user/api-keys")]
+#[tracing::instrument(skip_all)]
+pub async fn create_api_key(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  body: Json<CreateApiKeyBody>,
+) -> HttpResponse {
+  let Json(CreateApiKeyBody {
+    name,
+    scopes,
+    expires_at,
+  }) = body;
+  match extract_token(&req) {
+    Ok(token) => {
+      match service::create_api_key(&state, &ctx, token, name, scopes, expires_at).await {
+        Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+        Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+      }
+    }
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[utoipa::path(
+  tag = "User",
+  responses((status = OK, body = Response<ApiKeysResponseData>)),
+)]
+#[get("/user/api-keys")]
+#[tracing::instrument(skip_all)]
+pub async fn get_api_keys(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  match extract_token(&req) {
+    Ok(token) => match service::get_api_keys(&state, token).await {
+      Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
+      Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    },
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  }
+}
+
+#[utoipa::path(tag = "User", responses((status = OK)))]
+#[delete("/user/api-keys/{id}")]
+#[tracing::instrument(skip_all)]
+pub async fn revoke_api_key(
+  req: HttpRequest,
+  state: Data<AppState>,
+  ctx: AuditContext,
+  path: Path<String>,
+) -> HttpResponse {
+  let id = path.into_inner();
+  match extract_token(&req) {
+    Ok(token) => match service::revoke_api_key(&state, &ctx, token, id).await {
       Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
       Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
     },
@@ -1,13 +1,21 @@
 pub mod handler;
 pub mod model;
-mod service;
+pub mod service;
 
 use utoipa_actix_web::service_config::ServiceConfig;
 
 pub fn config(cfg: &mut ServiceConfig) {
   cfg.service(handler::user_register);
   cfg.service(handler::user_login);
+  cfg.service(handler::request_magic_link);
+  cfg.service(handler::exchange_magic_link);
+  cfg.service(handler::create_api_key);
+  cfg.service(handler::get_api_keys);
+  cfg.service(handler::revoke_api_key);
+  cfg.service(handler::request_email_change);
+  cfg.service(handler::confirm_email_change);
   cfg.service(handler::set_user_type);
   cfg.service(handler::set_user_profile);
+  cfg.service(handler::delete_account);
   cfg.service(handler::get_user_info);
 }
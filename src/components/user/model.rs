@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::entity::prelude::ApiKeyModel;
 
 #[derive(Deserialize, ToSchema)]
 pub struct UserRegisterQuery {
@@ -22,13 +25,154 @@ pub struct UserLoginBody {
   pub password: String,
 }
 
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct SetUserProfileQuery {
+  /// Language of the validation errors
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangeEmailBody {
+  pub email: String,
+  /// Needed unless an `X-Reauth-Token` is sent
+  pub current_password: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConfirmEmailChangeBody {
+  pub token: String,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct EmailChangeQuery {
+  /// Language of the emails
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct DeleteAccountQuery {
+  /// Language of the email
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Default)]
+pub struct DeleteAccountBody {
+  /// Needed unless an `X-Reauth-Token` is sent
+  pub current_password: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct MagicLinkQuery {
+  /// Language of the email
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MagicLinkBody {
+  pub email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ExchangeMagicLinkBody {
+  /// Token of the emailed link
+  pub token: String,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct SetUserProfileBody {
   pub nickname: Option<String>,
   pub password: Option<String>,
+  /// Needed with `password` unless an `X-Reauth-Token` is sent
+  pub current_password: Option<String>,
 }
 
 #[derive(Deserialize, ToSchema)]
 pub struct SetUserTypeBody {
   pub r#type: String,
 }
+
+/// Start of every API key, `ak_<id>_<secret>`
+pub const API_KEY_PREFIX: &str = "ak_";
+
+/// What an API key may do. Scopes are independent, `write` does not imply `read`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+  /// `GET /user`
+  Read,
+  /// `PUT /user`
+  Write,
+  /// Admin endpoints, only grantable by admin and root users
+  Admin,
+}
+
+impl ApiKeyScope {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Read => "read",
+      Self::Write => "write",
+      Self::Admin => "admin",
+    }
+  }
+
+  pub fn parse(scope: &str) -> Option<Self> {
+    match scope {
+      "read" => Some(Self::Read),
+      "write" => Some(Self::Write),
+      "admin" => Some(Self::Admin),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyBody {
+  pub name: String,
+  pub scopes: Vec<ApiKeyScope>,
+  /// Never expires when omitted
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyItem {
+  pub id: String,
+  pub name: String,
+  /// Start of the key, to tell keys apart
+  pub prefix: String,
+  pub scopes: Vec<ApiKeyScope>,
+  pub created_at: DateTime<Utc>,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub last_used_at: Option<DateTime<Utc>>,
+  pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKeyModel> for ApiKeyItem {
+  fn from(api_key: ApiKeyModel) -> Self {
+    Self {
+      scopes: api_key
+        .scopes
+        .split(',')
+        .filter_map(ApiKeyScope::parse)
+        .collect(),
+      id: api_key.id,
+      name: api_key.name,
+      prefix: api_key.prefix,
+      created_at: api_key.created_at,
+      expires_at: api_key.expires_at,
+      last_used_at: api_key.last_used_at,
+      revoked_at: api_key.revoked_at,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreateApiKeyResponseData {
+  /// The full key, only returned here
+  pub key: String,
+  pub api_key: ApiKeyItem,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeysResponseData {
+  pub api_keys: Vec<ApiKeyItem>,
+}
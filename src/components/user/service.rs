@@ -1,33 +1,101 @@
+use chrono::{DateTime, Duration, Utc};
 use helpers::{
-  hash, jwt,
+  hash,
   time::utc_now,
   uuid::{self, Alphabet},
 };
-use sea_orm::{IntoActiveModel, Set};
-use serde_json::{json, Value};
+use sea_orm::{IntoActiveModel, Set, SqlErr};
+use serde_json::{json, Map, Value};
 
-use crate::{app::AppState, entity::prelude::*, error::AppError};
+use crate::{
+  app::AppState,
+  components::{
+    audit::{
+      self,
+      model::{AuditAction, AuditContext},
+    },
+    avatar,
+    session::{self, model::LoginTokens},
+  },
+  entity::prelude::*,
+  error::AppError,
+  helpers::email,
+  locales::get_translation,
+  metrics::LOGINS_TOTAL,
+};
 
-use super::model::UserRegisterResponseData;
+use super::model::{
+  ApiKeyItem, ApiKeyScope, ApiKeysResponseData, ChangeEmailBody, CreateApiKeyResponseData,
+  DeleteAccountBody, SetUserProfileBody, UserRegisterResponseData, API_KEY_PREFIX,
+};
 
+#[tracing::instrument(skip_all)]
 pub async fn user_register(
   state: &AppState,
   nickname: String,
   email: String,
   password: String,
 ) -> Result<UserRegisterResponseData, AppError> {
+  let email = normalize_email(&email)?;
   if state.repo.user().has_user(&email).await?.is_some() {
     return Err(AppError::UserExists);
   }
-  let hashed = hash::bcrypt_custom(&password, 8, hash::Version::TwoA)?;
+  state
+    .password_policy
+    .check(&password, &email, &nickname)
+    .await?;
+  let hashed = state.passwords.hash(&password).await?;
+  create_user(state, nickname, email, hashed, false).await?;
+  Ok(UserRegisterResponseData {})
+}
+
+/// Trimmed and lowercased, so an address is registered once whatever its case. Refuses what
+/// cannot be an address: no single `@`, an empty side, no dot in the domain or whitespace
+pub fn normalize_email(email: &str) -> Result<String, AppError> {
+  let email = email.trim().to_lowercase();
+  let valid = email.len() <= 254
+    && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+    && email.split_once('@').is_some_and(|(local, domain)| {
+      !local.is_empty()
+        && !domain.contains('@')
+        && domain.split('.').count() > 1
+        && domain.split('.').all(|label| !label.is_empty())
+    });
+  if !valid {
+    return Err(AppError::InvalidParameter);
+  }
+  Ok(email)
+}
+
+/// The account of an email typed at login, also as typed for accounts registered before
+/// emails were normalized
+async fn find_user_by_email(state: &AppState, email: &str) -> Result<Option<UserModel>, AppError> {
+  let repo = state.repo.user();
+  if let Ok(normalized) = normalize_email(email) {
+    if let Some(user) = repo.get_user_by_email(&normalized).await? {
+      return Ok(Some(user));
+    }
+  }
+  Ok(repo.get_user_by_email(email.trim()).await?)
+}
+
+/// Inserts a user, the first one becomes root
+pub async fn create_user(
+  state: &AppState,
+  nickname: String,
+  email: String,
+  hashed_password: String,
+  is_email_verified: bool,
+) -> Result<UserModel, AppError> {
+  let user_id = uuid::uuid(&Alphabet::DEFAULT, 8);
   let mut user = UserActiveModel {
-    user_id: Set(uuid::uuid(&Alphabet::DEFAULT, 8)),
+    user_id: Set(user_id.clone()),
     nickname: Set(nickname),
-    password: Set(hashed),
+    password: Set(hashed_password),
     email: Set(email),
     r#type: Set("normal".to_string()),
-    avatar: Set("v2/avatars/default.png".to_string()),
-    is_email_verified: Set(0),
+    avatar: Set(avatar::service::identicon_url(&user_id)),
+    is_email_verified: Set(is_email_verified.into()),
     is_phone_verified: Set(0),
     created_at: Set(utc_now()),
     ..Default::default()
@@ -35,32 +103,152 @@ pub async fn user_register(
   if state.repo.user().is_first_user().await? {
     user.r#type = Set("root".to_owned());
   }
-  state.repo.user().create_user(user).await?;
-  Ok(UserRegisterResponseData {})
+  Ok(state.repo.user().create_user(user).await?)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn user_login(
   state: &AppState,
+  ctx: &AuditContext,
   email: String,
   password: String,
 ) -> Result<Value, AppError> {
-  if let Some(user) = state.repo.user().get_user_by_email(&email).await? {
-    let matched = hash::verify_bcrypt(&password, &user.password)?;
-    if matched {
-      let token = jwt::sign(user.email, &state.jwt_token, 2592000)?;
-      Ok(json!({
-        "token": token
-      }))
-    } else {
-      Err(AppError::PasswordIncorrect)
+  let user = find_user_by_email(state, &email)
+    .await?
+    .filter(|user| user.status != "deleted");
+  let result = match &user {
+    Some(user) => login(state, ctx, user, &password).await,
+    None => Err(AppError::UserNotFound),
+  };
+  let (label, action) = match result {
+    Ok(_) => ("success", AuditAction::Login),
+    Err(_) => ("failure", AuditAction::LoginFailed),
+  };
+  LOGINS_TOTAL.with_label_values(&[label]).inc();
+  let user_id = user.map(|user| user.user_id);
+  // Unknown accounts have no user_id, keep the attempted email instead
+  let diff = user_id.is_none().then(|| json!({ "email": email }));
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  result
+}
+
+async fn login(
+  state: &AppState,
+  ctx: &AuditContext,
+  user: &UserModel,
+  password: &str,
+) -> Result<Value, AppError> {
+  let matched = state.passwords.verify(password, &user.password).await?;
+  if matched {
+    if state.passwords.needs_rehash(&user.password) {
+      let rehashed = async {
+        let rehashed = state.passwords.hash(password).await?;
+        state
+          .repo
+          .user()
+          .rehash_password(user.id, &user.password, rehashed)
+          .await?;
+        Ok::<_, AppError>(())
+      };
+      // The password was right, keeping the old hash one more time is no reason to refuse
+      if let Err(err) = rehashed.await {
+        tracing::error!("Could not rehash the password of user {}: {err:?}", user.id);
+      }
     }
+    let tokens = session::service::create_login_tokens(state, ctx, user).await?;
+    Ok(json!({
+      "token": tokens.token,
+      "reauth_token": tokens.reauth_token
+    }))
   } else {
-    Err(AppError::UserNotFound)
+    Err(AppError::PasswordIncorrect)
   }
 }
 
+/// Time a login link stays valid
+const MAGIC_LINK_TTL: Duration = Duration::minutes(15);
+
+/// Emails a single-use login link. Unknown emails get the same answer and no email, so the
+/// endpoint does not tell which accounts exist
+#[tracing::instrument(skip_all)]
+pub async fn request_magic_link(
+  state: &AppState,
+  email: String,
+  lang: &str,
+) -> Result<(), AppError> {
+  let Some(url) = &state.magic_link_url else {
+    return Err(AppError::NotFound);
+  };
+  let user = find_user_by_email(state, &email)
+    .await?
+    .filter(|user| user.status != "deleted");
+  let Some(user) = user else {
+    return Ok(());
+  };
+  let token = uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32);
+  let now = utc_now();
+  let link = MagicLinkActiveModel {
+    token_hash: Set(hash::blake3(token.as_bytes())),
+    user_id: Set(user.user_id),
+    created_at: Set(now),
+    expires_at: Set(now + MAGIC_LINK_TTL),
+  };
+  state.repo.magic_link().create_link(link, now).await?;
+  let url = url.replace("{token}", &token);
+  let subject = get_translation(lang, "Magic Link Mail");
+  let body = get_translation(lang, "magic link login").replace("{url}", &url);
+  if let Some(mailer) = &state.mailer {
+    mailer.mail_in_background(user.email, subject, body);
+  }
+  Ok(())
+}
+
+/// Logs in with the token of a link sent by [`request_magic_link`], the link is used up
+#[tracing::instrument(skip_all)]
+pub async fn exchange_magic_link(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+) -> Result<Value, AppError> {
+  let result = magic_link_login(state, ctx, &token).await;
+  let label = if result.is_ok() { "success" } else { "failure" };
+  LOGINS_TOTAL.with_label_values(&[label]).inc();
+  let (user, tokens) = result?;
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "method": "magic_link" }));
+  let action = AuditAction::Login;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(json!({
+    "token": tokens.token,
+    "reauth_token": tokens.reauth_token
+  }))
+}
+
+async fn magic_link_login(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: &str,
+) -> Result<(UserModel, LoginTokens), AppError> {
+  let link = state
+    .repo
+    .magic_link()
+    .take_link(&hash::blake3(token.as_bytes()), utc_now())
+    .await?
+    .ok_or(AppError::InvalidToken)?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(&link.user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::UserNotFound)?;
+  let tokens = session::service::create_login_tokens(state, ctx, &user).await?;
+  Ok((user, tokens))
+}
+
+#[tracing::instrument(skip_all)]
 pub async fn get_login_user_info(state: &AppState, token: String) -> Result<Value, AppError> {
-  let email = jwt::verify::<String>(&token, &state.jwt_token)?.claims.data;
+  let email = session::service::authorize(state, &token, ApiKeyScope::Read).await?;
   if let Some(user) = state.repo.user().get_user_by_email(&email).await? {
     Ok(json! ({
         "nickname": user.nickname,
@@ -72,53 +260,859 @@ pub async fn get_login_user_info(state: &AppState, token: String) -> Result<Valu
   }
 }
 
+/// A new password needs [`session::service::check_reauth`], the user is emailed once it is
+/// changed and the other sessions and API keys of the user are revoked
+#[tracing::instrument(skip_all)]
 pub async fn set_user_profile(
   state: &AppState,
+  ctx: &AuditContext,
   token: String,
-  nickname: Option<String>,
-  password: Option<String>,
+  reauth_token: Option<String>,
+  body: SetUserProfileBody,
+  lang: &str,
 ) -> Result<bool, AppError> {
-  let email = jwt::verify::<String>(&token, &state.jwt_token)?.claims.data;
-  let mut active_user = state
+  let SetUserProfileBody {
+    nickname,
+    password,
+    current_password,
+  } = body;
+  let email = session::service::authorize(state, &token, ApiKeyScope::Write).await?;
+  let user = state
     .repo
     .user()
     .get_user_by_email(&email)
     .await?
-    .ok_or(AppError::UserNotFound)?
-    .into_active_model();
+    .ok_or(AppError::UserNotFound)?;
+  if let Some(password) = &password {
+    let (reauth_token, current_password) = (reauth_token.as_deref(), current_password.as_deref());
+    session::service::check_reauth(state, &user, reauth_token, current_password).await?;
+    let nickname = nickname.as_deref().unwrap_or(&user.nickname);
+    state
+      .password_policy
+      .check(password, &user.email, nickname)
+      .await?;
+  }
+  let user_id = user.user_id.clone();
+  let mut diff = Map::new();
+  if let Some(nickname) = &nickname {
+    diff.insert(
+      "nickname".to_string(),
+      json!({ "before": user.nickname, "after": nickname }),
+    );
+  }
+  if password.is_some() {
+    diff.insert("password".to_string(), json!("changed"));
+  }
+  let mut active_user = user.into_active_model();
   if let Some(nickname) = nickname {
     active_user.nickname = Set(nickname);
   }
   if let Some(password) = password {
-    active_user.password = Set(hash::bcrypt(&password)?);
+    active_user.password = Set(state.passwords.hash(&password).await?);
   }
   let res = state.repo.user().update_user(active_user).await;
+  if res.is_ok() && diff.contains_key("password") {
+    // Whoever knew the old password is logged out everywhere but here
+    let now = utc_now();
+    let api_key = session::service::api_key_id(&token);
+    let sid = match api_key {
+      Some(_) => None,
+      None => Some(session::service::authenticate(state, &token).await?.sid),
+    };
+    state
+      .repo
+      .session()
+      .revoke_other_sessions(&user_id, sid.as_deref(), now)
+      .await?;
+    state
+      .repo
+      .api_key()
+      .revoke_other_api_keys(&user_id, api_key, now)
+      .await?;
+    let (subject, body) = ("Password Changed Mail", "password changed notice");
+    notify(state, ctx, email, lang, subject, body, &[]);
+  }
+  if res.is_ok() && !diff.is_empty() {
+    let action = AuditAction::ProfileUpdate;
+    let diff = Some(Value::Object(diff));
+    audit::service::record(
+      state,
+      ctx,
+      action,
+      Some(user_id.clone()),
+      Some(user_id),
+      diff,
+    )
+    .await;
+  }
   Ok(res.is_ok())
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn set_user_type(
   state: &AppState,
+  ctx: &AuditContext,
   token: String,
   user_id: u32,
   r#type: String,
 ) -> Result<bool, AppError> {
-  let email = jwt::verify::<String>(&token, &state.jwt_token)?.claims.data;
+  let email = session::service::authorize(state, &token, ApiKeyScope::Admin).await?;
   if state.repo.user().is_admin_user(&email).await? {
-    let mut active_user = state
+    let user = state
       .repo
       .user()
       .get_user_by_id(user_id)
       .await?
-      .ok_or(AppError::Unauthorized)?
-      .into_active_model();
+      .ok_or(AppError::Unauthorized)?;
 
     if state.repo.user().is_root_user(user_id).await? {
       return Err(AppError::Forbidden);
     }
+    let target_id = user.user_id.clone();
+    let diff = json!({ "type": { "before": user.r#type, "after": r#type } });
+    let mut active_user = user.into_active_model();
     active_user.r#type = Set(r#type);
     state.repo.user().update_user(active_user).await?;
+    let actor_id = actor_id(state, &email).await?;
+    let action = AuditAction::RoleChange;
+    audit::service::record(state, ctx, action, actor_id, Some(target_id), Some(diff)).await;
     Ok(true)
   } else {
     Err(AppError::Forbidden)
   }
 }
+
+/// Soft deletes the account of the token with [`session::service::check_reauth`], revokes its
+/// sessions and API keys and emails the owner. The root user cannot be deleted
+#[tracing::instrument(skip_all)]
+pub async fn delete_account(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  reauth_token: Option<String>,
+  body: DeleteAccountBody,
+  lang: &str,
+) -> Result<bool, AppError> {
+  let email = session::service::authorize(state, &token, ApiKeyScope::Write).await?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_email(&email)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::UserNotFound)?;
+  let (reauth_token, current_password) =
+    (reauth_token.as_deref(), body.current_password.as_deref());
+  session::service::check_reauth(state, &user, reauth_token, current_password).await?;
+  if user.r#type == "root" {
+    return Err(AppError::Forbidden);
+  }
+  let user_id = user.user_id.clone();
+  let diff = json!({ "status": { "before": user.status, "after": "deleted" } });
+  let now = utc_now();
+  let mut active_user = user.into_active_model();
+  active_user.status = Set("deleted".to_string());
+  active_user.deleted_at = Set(Some(now));
+  state.repo.user().update_user(active_user).await?;
+  state
+    .repo
+    .session()
+    .revoke_user_sessions(&user_id, now)
+    .await?;
+  state
+    .repo
+    .api_key()
+    .revoke_other_api_keys(&user_id, None, now)
+    .await?;
+  let (subject, body) = ("Account Deleted Mail", "account deleted notice");
+  notify(state, ctx, email, lang, subject, body, &[]);
+  let user_id = Some(user_id);
+  let action = AuditAction::UserDelete;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, Some(diff)).await;
+  Ok(true)
+}
+
+/// Emails the owner of the account about a sensitive change made by the request of `ctx`,
+/// when mail is configured. `vars` are replaced in the body besides `{email}`, `{time}` and `{ip}`
+pub(crate) fn notify(
+  state: &AppState,
+  ctx: &AuditContext,
+  to: String,
+  lang: &str,
+  subject: &str,
+  body: &str,
+  vars: &[(&str, &str)],
+) {
+  let Some(mailer) = &state.mailer else {
+    return;
+  };
+  let subject = get_translation(lang, subject);
+  let body = notice_body(ctx, &to, lang, body, vars);
+  mailer.mail_in_background(to, subject, body);
+}
+
+/// Body of a [`notify`] mail. The values are HTML escaped and replaced in a single pass, so
+/// a value cannot inject markup nor a placeholder of its own
+fn notice_body(
+  ctx: &AuditContext,
+  to: &str,
+  lang: &str,
+  body: &str,
+  vars: &[(&str, &str)],
+) -> String {
+  let time = utc_now().to_rfc2822();
+  let ip = ctx.ip.as_deref().unwrap_or("-");
+  let vars: Vec<_> = [("{email}", to), ("{time}", time.as_str()), ("{ip}", ip)]
+    .into_iter()
+    .chain(vars.iter().copied())
+    .collect();
+  let template = get_translation(lang, body);
+  let mut body = String::with_capacity(template.len());
+  let mut rest = template.as_str();
+  while let Some(start) = rest.find('{') {
+    body.push_str(&rest[..start]);
+    rest = &rest[start..];
+    match vars.iter().find(|(name, _)| rest.starts_with(name)) {
+      Some((name, value)) => {
+        body.push_str(&email::escape_html(value));
+        rest = &rest[name.len()..];
+      }
+      None => {
+        body.push('{');
+        rest = &rest[1..];
+      }
+    }
+  }
+  body.push_str(rest);
+  body
+}
+
+/// Time a confirmation link of a new email stays valid
+const EMAIL_CHANGE_TTL: Duration = Duration::hours(1);
+
+/// Asks the new address to confirm the change and tells the old one, the email is only
+/// switched by [`confirm_email_change`]
+#[tracing::instrument(skip_all)]
+pub async fn request_email_change(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  reauth_token: Option<String>,
+  body: ChangeEmailBody,
+  lang: &str,
+) -> Result<(), AppError> {
+  let Some(url) = &state.email_change_url else {
+    return Err(AppError::NotFound);
+  };
+  let email = session::service::authorize(state, &token, ApiKeyScope::Write).await?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_email(&email)
+    .await?
+    .ok_or(AppError::UserNotFound)?;
+  let ChangeEmailBody {
+    email: new_email,
+    current_password,
+  } = body;
+  let (reauth_token, current_password) = (reauth_token.as_deref(), current_password.as_deref());
+  session::service::check_reauth(state, &user, reauth_token, current_password).await?;
+  let new_email = normalize_email(&new_email)?;
+  if new_email == user.email {
+    return Err(AppError::InvalidParameter);
+  }
+  if state.repo.user().has_user(&new_email).await?.is_some() {
+    return Err(AppError::UserExists);
+  }
+  let token = uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32);
+  let now = utc_now();
+  let change = EmailChangeActiveModel {
+    token_hash: Set(hash::blake3(token.as_bytes())),
+    user_id: Set(user.user_id),
+    new_email: Set(new_email.clone()),
+    created_at: Set(now),
+    expires_at: Set(now + EMAIL_CHANGE_TTL),
+  };
+  state.repo.email_change().create_change(change, now).await?;
+  let url = url.replace("{token}", &token);
+  let subject = get_translation(lang, "Email Change Mail");
+  let body = get_translation(lang, "confirm email change").replace("{url}", &url);
+  if let Some(mailer) = &state.mailer {
+    mailer.mail_in_background(new_email.clone(), subject, body);
+  }
+  let (subject, body) = ("Email Change Notice Mail", "email change notice");
+  notify(
+    state,
+    ctx,
+    email,
+    lang,
+    subject,
+    body,
+    &[("{new_email}", &new_email)],
+  );
+  Ok(())
+}
+
+/// Switches the email with the token of a link sent by [`request_email_change`], the link
+/// is used up. Login tokens carry the email, so every session of the user is logged out
+#[tracing::instrument(skip_all)]
+pub async fn confirm_email_change(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+) -> Result<(), AppError> {
+  let change = state
+    .repo
+    .email_change()
+    .take_change(&hash::blake3(token.as_bytes()), utc_now())
+    .await?
+    .ok_or(AppError::InvalidToken)?;
+  let user = state
+    .repo
+    .user()
+    .get_user_by_user_id(&change.user_id)
+    .await?
+    .filter(|user| user.status != "deleted")
+    .ok_or(AppError::UserNotFound)?;
+  // The address may have been registered, or confirmed by another account, since the request
+  if state
+    .repo
+    .user()
+    .has_user(&change.new_email)
+    .await?
+    .is_some()
+  {
+    return Err(AppError::UserExists);
+  }
+  let changed = match state
+    .repo
+    .user()
+    .change_email(user.id, &user.email, &change.new_email)
+    .await
+  {
+    Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+      return Err(AppError::UserExists);
+    }
+    res => res?,
+  };
+  if !changed {
+    return Err(AppError::InvalidToken);
+  }
+  state
+    .repo
+    .session()
+    .revoke_user_sessions(&user.user_id, utc_now())
+    .await?;
+  let user_id = Some(user.user_id);
+  let diff = json!({ "email": { "before": user.email, "after": change.new_email } });
+  let action = AuditAction::EmailChange;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, Some(diff)).await;
+  Ok(())
+}
+
+/// `user_id` of the user behind a verified token
+async fn actor_id(state: &AppState, email: &str) -> Result<Option<String>, AppError> {
+  let user = state.repo.user().get_user_by_email(email).await?;
+  Ok(user.map(|user| user.user_id))
+}
+
+/// Creates an API key for the current user. The full key is only returned here,
+/// the table keeps its hash
+#[tracing::instrument(skip_all)]
+pub async fn create_api_key(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  name: String,
+  mut scopes: Vec<ApiKeyScope>,
+  expires_at: Option<DateTime<Utc>>,
+) -> Result<CreateApiKeyResponseData, AppError> {
+  let user = session::service::authenticate(state, &token).await?.user;
+  let now = utc_now();
+  scopes.sort_by_key(|scope| *scope as u8);
+  scopes.dedup();
+  if name.trim().is_empty() || scopes.is_empty() || expires_at.is_some_and(|at| at <= now) {
+    return Err(AppError::InvalidParameter);
+  }
+  if scopes.contains(&ApiKeyScope::Admin) && !matches!(user.r#type.as_str(), "admin" | "root") {
+    return Err(AppError::Forbidden);
+  }
+  let id = uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 8);
+  let prefix = format!("{API_KEY_PREFIX}{id}");
+  let key = format!(
+    "{prefix}_{}",
+    uuid::uuid(&Alphabet::NUMBERS_LOWER_UPPER, 32)
+  );
+  let api_key = ApiKeyActiveModel {
+    id: Set(id),
+    user_id: Set(user.user_id.clone()),
+    name: Set(name.trim().to_string()),
+    prefix: Set(prefix),
+    key_hash: Set(hash::blake3(key.as_bytes())),
+    scopes: Set(
+      scopes
+        .iter()
+        .map(ApiKeyScope::as_str)
+        .collect::<Vec<_>>()
+        .join(","),
+    ),
+    created_at: Set(now),
+    expires_at: Set(expires_at),
+    last_used_at: Set(None),
+    revoked_at: Set(None),
+  };
+  let api_key = state.repo.api_key().create_api_key(api_key).await?;
+  let user_id = Some(user.user_id);
+  let diff = json!({ "api_key": api_key.id, "name": api_key.name, "scopes": api_key.scopes });
+  let action = AuditAction::ApiKeyCreate;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, Some(diff)).await;
+  Ok(CreateApiKeyResponseData {
+    key,
+    api_key: api_key.into(),
+  })
+}
+
+/// API keys of the current user, newest first, revoked ones included
+#[tracing::instrument(skip_all)]
+pub async fn get_api_keys(
+  state: &AppState,
+  token: String,
+) -> Result<ApiKeysResponseData, AppError> {
+  let user = session::service::authenticate(state, &token).await?.user;
+  let api_keys = state
+    .repo
+    .api_key()
+    .get_user_api_keys(&user.user_id)
+    .await?;
+  Ok(ApiKeysResponseData {
+    api_keys: api_keys.into_iter().map(ApiKeyItem::from).collect(),
+  })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn revoke_api_key(
+  state: &AppState,
+  ctx: &AuditContext,
+  token: String,
+  id: String,
+) -> Result<bool, AppError> {
+  let user = session::service::authenticate(state, &token).await?.user;
+  let revoked = state
+    .repo
+    .api_key()
+    .revoke_api_key(&user.user_id, &id, utc_now())
+    .await?;
+  if !revoked {
+    return Err(AppError::NotFound);
+  }
+  let user_id = Some(user.user_id);
+  let diff = Some(json!({ "api_key": id }));
+  let action = AuditAction::ApiKeyRevoke;
+  audit::service::record(state, ctx, action, user_id.clone(), user_id, diff).await;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::testing::{self, ctx, PASSWORD};
+
+  use super::*;
+
+  #[test]
+  fn emails_are_trimmed_lowercased_and_validated() {
+    let normalized = normalize_email(" Alice.Smith@Example.COM\n").unwrap();
+    assert_eq!(normalized, "alice.smith@example.com");
+    for email in [
+      "",
+      "alice",
+      "@example.com",
+      "alice@",
+      "alice@localhost",
+      "alice@example.",
+      "alice@@example.com",
+      "a@b@example.com",
+      "alice smith@example.com",
+    ] {
+      assert!(normalize_email(email).is_err(), "{email}");
+    }
+  }
+
+  #[actix_web::test]
+  async fn password_change_revokes_the_other_sessions_and_api_keys() {
+    let state = testing::app_state(&[]).await;
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    let token = session::service::create_session(&state, &ctx(), &user)
+      .await
+      .unwrap();
+    let other = session::service::create_session(&state, &ctx(), &user)
+      .await
+      .unwrap();
+    let scopes = vec![ApiKeyScope::Read];
+    let api_key = create_api_key(
+      &state,
+      &ctx(),
+      token.clone(),
+      "CI".to_string(),
+      scopes,
+      None,
+    )
+    .await
+    .unwrap()
+    .key;
+    let body = SetUserProfileBody {
+      nickname: None,
+      password: Some("amber falcon meadow".to_string()),
+      current_password: Some(PASSWORD.to_string()),
+    };
+    assert!(
+      set_user_profile(&state, &ctx(), token.clone(), None, body, "en")
+        .await
+        .unwrap()
+    );
+    assert!(session::service::authenticate(&state, &token).await.is_ok());
+    assert!(matches!(
+      session::service::authenticate(&state, &other).await,
+      Err(AppError::InvalidToken)
+    ));
+    assert!(matches!(
+      session::service::authorize(&state, &api_key, ApiKeyScope::Read).await,
+      Err(AppError::InvalidToken)
+    ));
+  }
+
+  #[actix_web::test]
+  async fn email_changes_are_normalized_like_registration() {
+    let state = testing::app_state(&[(
+      "EMAIL_CHANGE_URL",
+      "https://example.com/email?token={token}",
+    )])
+    .await;
+    user_register(
+      &state,
+      "Alice".to_string(),
+      " Alice@Example.com ".to_string(),
+      PASSWORD.to_string(),
+    )
+    .await
+    .unwrap();
+    user_register(
+      &state,
+      "Bob".to_string(),
+      "bob@example.com".to_string(),
+      "amber falcon meadow".to_string(),
+    )
+    .await
+    .unwrap();
+    let user = state
+      .repo
+      .user()
+      .get_user_by_email("alice@example.com")
+      .await
+      .unwrap()
+      .unwrap();
+    let token = session::service::create_session(&state, &ctx(), &user)
+      .await
+      .unwrap();
+    let change = |email: &str| ChangeEmailBody {
+      email: email.to_string(),
+      current_password: Some(PASSWORD.to_string()),
+    };
+    for (email, expected) in [
+      ("not an email", AppError::InvalidParameter),
+      (" ALICE@example.com", AppError::InvalidParameter),
+      ("Bob@Example.com ", AppError::UserExists),
+    ] {
+      let body = change(email);
+      let err = request_email_change(&state, &ctx(), token.clone(), None, body, "en")
+        .await
+        .unwrap_err();
+      assert_eq!(
+        std::mem::discriminant(&err),
+        std::mem::discriminant(&expected),
+        "{email}"
+      );
+    }
+    request_email_change(
+      &state,
+      &ctx(),
+      token,
+      None,
+      change(" Alice@New.Example "),
+      "en",
+    )
+    .await
+    .unwrap();
+  }
+
+  #[test]
+  fn notice_values_are_html_escaped() {
+    let ctx = AuditContext {
+      ip: Some("<b>203.0.113.7</b>".to_string()),
+      ..ctx()
+    };
+    let new_email = r#""><img src=x>{ip}@evil.example"#;
+    let body = notice_body(
+      &ctx,
+      "a&b@example.com",
+      "en",
+      "email change notice",
+      &[("{new_email}", new_email)],
+    );
+    assert!(
+      body.contains("from a&amp;b@example.com to &quot;&gt;&lt;img src=x&gt;{ip}@evil.example")
+    );
+    assert!(body.contains("from &lt;b&gt;203.0.113.7&lt;/b&gt;"));
+    assert!(!body.contains('<'));
+  }
+
+  #[actix_web::test]
+  async fn login_rehashes_a_bcrypt_password_with_argon2id() {
+    let vars = [("ARGON2_MEMORY", "1024"), ("ARGON2_ITERATIONS", "1")];
+    let state = testing::app_state(&vars).await;
+    let bcrypt = hash::bcrypt_custom(PASSWORD, 4, hash::Version::TwoB).unwrap();
+    let email = "alice@example.com".to_string();
+    let user = create_user(&state, "Alice".into(), email.clone(), bcrypt, true)
+      .await
+      .unwrap();
+    let ctx = ctx();
+    let login = |password: &str| user_login(&state, &ctx, email.clone(), password.into());
+    assert!(matches!(
+      login("amber falcon meadow").await,
+      Err(AppError::PasswordIncorrect)
+    ));
+    let stored = state.repo.user().get_user_by_id(user.id).await.unwrap();
+    assert!(stored.unwrap().password.starts_with("$2b$04$"));
+    assert!(login(PASSWORD).await.is_ok());
+    let stored = state.repo.user().get_user_by_id(user.id).await.unwrap();
+    let stored = stored.unwrap().password;
+    assert!(stored.starts_with("$argon2id$v=19$m=1024,t=1,"), "{stored}");
+    assert!(login(PASSWORD).await.is_ok());
+  }
+
+  /// Stores a login link for `user` with a known token, expiring `ttl` from now
+  async fn magic_link(state: &AppState, user: &UserModel, token: &str, ttl: Duration) {
+    let now = utc_now();
+    let link = MagicLinkActiveModel {
+      token_hash: Set(hash::blake3(token.as_bytes())),
+      user_id: Set(user.user_id.clone()),
+      created_at: Set(now),
+      expires_at: Set(now + ttl),
+    };
+    state
+      .repo
+      .magic_link()
+      .create_link(link, now)
+      .await
+      .unwrap();
+  }
+
+  #[actix_web::test]
+  async fn magic_links_are_single_use_and_expire() {
+    let state = testing::app_state(&[]).await;
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    magic_link(&state, &user, "fresh", MAGIC_LINK_TTL).await;
+    magic_link(&state, &user, "stale", Duration::seconds(-1)).await;
+    let ctx = ctx();
+    let exchange = |token: &str| exchange_magic_link(&state, &ctx, token.to_string());
+    let token = exchange("fresh").await.unwrap()["token"].clone();
+    let token = token.as_str().unwrap().to_string();
+    assert!(session::service::authenticate(&state, &token).await.is_ok());
+    for token in ["fresh", "stale", "unknown"] {
+      assert!(
+        matches!(exchange(token).await, Err(AppError::InvalidToken)),
+        "{token}"
+      );
+    }
+  }
+
+  #[actix_web::test]
+  async fn magic_links_need_the_url_and_an_active_account() {
+    let state = testing::app_state(&[]).await;
+    let request = |email: &str| request_magic_link(&state, email.to_string(), "en");
+    assert!(matches!(
+      request("alice@example.com").await,
+      Err(AppError::NotFound)
+    ));
+    let url = ("MAGIC_LINK_URL", "https://example.com/login?token={token}");
+    let state = testing::app_state(&[url]).await;
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    let request = |email: &str| request_magic_link(&state, email.to_string(), "en");
+    assert!(request("nobody@example.com").await.is_ok());
+    assert!(request(" Alice@Example.com").await.is_ok());
+    // A link of a deleted account does not log in either
+    magic_link(&state, &user, "pending", MAGIC_LINK_TTL).await;
+    let mut deleted = user.into_active_model();
+    deleted.status = Set("deleted".to_string());
+    state.repo.user().update_user(deleted).await.unwrap();
+    assert!(matches!(
+      exchange_magic_link(&state, &ctx(), "pending".to_string()).await,
+      Err(AppError::UserNotFound)
+    ));
+  }
+
+  #[actix_web::test]
+  async fn api_keys_only_grant_their_scopes() {
+    let state = testing::app_state(&[]).await;
+    // The first user is root, Alice is a plain user
+    let root = testing::user(&state, "Root", "root@example.com").await;
+    let alice = testing::user(&state, "Alice", "alice@example.com").await;
+    let email = alice.email.clone();
+    assert_eq!(root.r#type, "root");
+    let token = session::service::create_session(&state, &ctx(), &alice)
+      .await
+      .unwrap();
+    let ctx = ctx();
+    let create = |scopes: Vec<ApiKeyScope>| {
+      create_api_key(&state, &ctx, token.clone(), "CI".to_string(), scopes, None)
+    };
+    assert!(matches!(
+      create(vec![ApiKeyScope::Admin]).await,
+      Err(AppError::Forbidden)
+    ));
+    assert!(matches!(
+      create(vec![]).await,
+      Err(AppError::InvalidParameter)
+    ));
+    let read = create(vec![ApiKeyScope::Read, ApiKeyScope::Read])
+      .await
+      .unwrap();
+    assert_eq!(read.api_key.scopes, [ApiKeyScope::Read]);
+    assert!(read.key.starts_with(&read.api_key.prefix));
+    assert_eq!(
+      session::service::authorize(&state, &read.key, ApiKeyScope::Read)
+        .await
+        .unwrap(),
+      email
+    );
+    for scope in [ApiKeyScope::Write, ApiKeyScope::Admin] {
+      assert!(matches!(
+        session::service::authorize(&state, &read.key, scope).await,
+        Err(AppError::Forbidden)
+      ));
+    }
+    // Login tokens carry every scope, API keys cannot manage keys nor sessions
+    assert!(
+      session::service::authorize(&state, &token, ApiKeyScope::Admin)
+        .await
+        .is_ok()
+    );
+    assert!(matches!(
+      get_api_keys(&state, read.key.clone()).await,
+      Err(AppError::InvalidToken)
+    ));
+    let tampered = format!("{}x", read.key);
+    assert!(matches!(
+      session::service::authorize(&state, &tampered, ApiKeyScope::Read).await,
+      Err(AppError::InvalidToken)
+    ));
+    let id = read.api_key.id.clone();
+    assert!(revoke_api_key(&state, &ctx, token.clone(), id)
+      .await
+      .unwrap());
+    assert!(matches!(
+      session::service::authorize(&state, &read.key, ApiKeyScope::Read).await,
+      Err(AppError::InvalidToken)
+    ));
+  }
+
+  #[actix_web::test]
+  async fn api_keys_expire() {
+    let state = testing::app_state(&[]).await;
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    let token = session::service::create_session(&state, &ctx(), &user)
+      .await
+      .unwrap();
+    let scopes = vec![ApiKeyScope::Read];
+    let past = Some(utc_now() - Duration::seconds(1));
+    let ctx = ctx();
+    let create = create_api_key(&state, &ctx, token.clone(), "CI".into(), scopes, past);
+    assert!(matches!(create.await, Err(AppError::InvalidParameter)));
+    let scopes = vec![ApiKeyScope::Read];
+    let soon = Some(utc_now() + Duration::seconds(1));
+    let key = create_api_key(&state, &ctx, token, "CI".into(), scopes, soon)
+      .await
+      .unwrap()
+      .key;
+    assert!(session::service::authorize(&state, &key, ApiKeyScope::Read)
+      .await
+      .is_ok());
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(matches!(
+      session::service::authorize(&state, &key, ApiKeyScope::Read).await,
+      Err(AppError::InvalidToken)
+    ));
+  }
+
+  #[actix_web::test]
+  async fn a_fresh_login_sets_the_password_of_an_account_without_one() {
+    let state = testing::app_state(&[]).await;
+    // As registered through OIDC, with a password nobody knows
+    let hashed = state
+      .passwords
+      .hash("unknown random password")
+      .await
+      .unwrap();
+    let email = "alice@example.com".to_string();
+    let user = create_user(&state, "Alice".into(), email.clone(), hashed, true)
+      .await
+      .unwrap();
+    magic_link(&state, &user, "fresh", MAGIC_LINK_TTL).await;
+    let ctx = ctx();
+    let login = exchange_magic_link(&state, &ctx, "fresh".into())
+      .await
+      .unwrap();
+    let token = login["token"].as_str().unwrap().to_string();
+    let reauth = login["reauth_token"].as_str().map(str::to_string);
+    let body = |current_password: Option<&str>| SetUserProfileBody {
+      nickname: None,
+      password: Some("amber falcon meadow".to_string()),
+      current_password: current_password.map(str::to_string),
+    };
+    let set = set_user_profile(&state, &ctx, token.clone(), None, body(None), "en");
+    assert!(matches!(set.await, Err(AppError::ReauthRequired)));
+    assert!(
+      set_user_profile(&state, &ctx, token, reauth, body(None), "en")
+        .await
+        .unwrap()
+    );
+    let password = "amber falcon meadow".to_string();
+    assert!(user_login(&state, &ctx, email, password).await.is_ok());
+  }
+
+  #[actix_web::test]
+  async fn account_deletion_needs_a_fresh_proof_and_spares_root() {
+    let state = testing::app_state(&[]).await;
+    let mut tokens = vec![];
+    for (nickname, email) in [("Root", "root@example.com"), ("Alice", "alice@example.com")] {
+      let user = testing::user(&state, nickname, email).await;
+      let token = session::service::create_session(&state, &ctx(), &user)
+        .await
+        .unwrap();
+      tokens.push(token);
+    }
+    let ctx = ctx();
+    let delete = |token: &String, password: Option<&str>| {
+      let body = DeleteAccountBody {
+        current_password: password.map(str::to_string),
+      };
+      delete_account(&state, &ctx, token.clone(), None, body, "en")
+    };
+    let (root, alice) = (&tokens[0], &tokens[1]);
+    let root_delete = delete(root, Some(PASSWORD)).await;
+    assert!(matches!(root_delete, Err(AppError::Forbidden)));
+    assert!(matches!(
+      delete(alice, None).await,
+      Err(AppError::ReauthRequired)
+    ));
+    assert!(matches!(
+      delete(alice, Some("wrong password")).await,
+      Err(AppError::PasswordIncorrect)
+    ));
+    assert!(delete(alice, Some(PASSWORD)).await.unwrap());
+    // Logged out and unable to log in again
+    assert!(session::service::authenticate(&state, alice).await.is_err());
+    let email = "alice@example.com".to_string();
+    let password = PASSWORD.to_string();
+    assert!(user_login(&state, &ctx, email, password).await.is_err());
+  }
+}
@@ -1,10 +1,16 @@
 //! config
 
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 
 use serde::Deserialize;
 
-use crate::error::AppError;
+use crate::{
+  error::AppError,
+  helpers::{identicon::IdenticonStyle, password::PasswordHasherKind},
+  logging::{LogFormat, LogRotation},
+  middlewares::rate_limit::{RateLimitAlgorithm, RateLimitStoreKind},
+  storage::StorageKind,
+};
 
 fn default_workers() -> usize {
   1
@@ -17,31 +23,312 @@ fn default_port() -> u16 {
     .unwrap_or(3000)
 }
 
-fn default_ipqps() -> u64 {
-  60
+fn default_rate_limit() -> String {
+  "60/min".to_string()
 }
 
-fn default_host() -> Ipv4Addr {
-  Ipv4Addr::new(0, 0, 0, 0)
+fn default_rate_limit_login() -> String {
+  "5/min".to_string()
 }
 
-#[derive(Deserialize)]
+fn default_rate_limit_register() -> String {
+  "1/min".to_string()
+}
+
+fn default_rate_limit_magic_link() -> String {
+  "3/10min".to_string()
+}
+
+fn default_rate_limit_upload() -> String {
+  "10/hour".to_string()
+}
+
+fn default_rate_limit_fail_closed() -> Vec<String> {
+  ["login", "register", "magic_link"]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_argon2_memory() -> u32 {
+  19456
+}
+
+fn default_argon2_iterations() -> u32 {
+  2
+}
+
+fn default_argon2_parallelism() -> u32 {
+  1
+}
+
+fn default_bcrypt_cost() -> u32 {
+  12
+}
+
+fn default_password_min_length() -> usize {
+  8
+}
+
+fn default_password_min_classes() -> usize {
+  1
+}
+
+fn default_password_min_score() -> u8 {
+  2
+}
+
+fn default_storage_dir() -> String {
+  "./uploads".to_string()
+}
+
+fn default_storage_url() -> String {
+  "/api/v1/files".to_string()
+}
+
+fn default_s3_region() -> String {
+  "us-east-1".to_string()
+}
+
+fn default_avatar_max_size() -> usize {
+  5 * 1024 * 1024
+}
+
+fn default_avatar_sizes() -> Vec<u32> {
+  vec![256, 64]
+}
+
+fn default_otel_service_name() -> String {
+  env!("CARGO_PKG_NAME").to_string()
+}
+
+fn default_log_level() -> String {
+  "debug,sqlx::query=off,rustls=off".to_string()
+}
+
+fn default_log_file_max_files() -> usize {
+  7
+}
+
+fn default_host() -> IpAddr {
+  IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+}
+
+fn default_metrics_host() -> IpAddr {
+  IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_tls_port() -> u16 {
+  3443
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+  ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec()
+}
+
+fn default_cors_allowed_headers() -> Vec<String> {
+  [
+    "Authorization",
+    "Content-Type",
+    "Accept",
+    "X-API-Key",
+    "X-Reauth-Token",
+    "X-Request-Id",
+  ]
+  .map(String::from)
+  .to_vec()
+}
+
+fn default_cors_expose_headers() -> Vec<String> {
+  [
+    "X-Request-Id",
+    "RateLimit-Limit",
+    "RateLimit-Remaining",
+    "RateLimit-Reset",
+    "RateLimit-Policy",
+    "Retry-After",
+  ]
+  .map(String::from)
+  .to_vec()
+}
+
+#[derive(Deserialize, Clone)]
 pub struct EnvConfig {
   #[serde(default = "default_workers")]
   pub workers: usize,
   #[serde(default = "default_host")]
-  pub host: Ipv4Addr,
+  pub host: IpAddr,
   #[serde(default = "default_port")]
   pub port: u16,
+  /// Listen addresses replacing `host:port`, e.g. `0.0.0.0:3000,[::]:3000,unix:/run/app.sock`
+  #[serde(default)]
+  pub listen: Vec<String>,
+  /// PEM certificate chain, enables the HTTPS listener together with `tls_key_path`
+  pub tls_cert_path: Option<String>,
+  /// PEM private key (PKCS#8, RSA or SEC1)
+  pub tls_key_path: Option<String>,
+  #[serde(default = "default_tls_port")]
+  pub tls_port: u16,
+  /// HTTPS listen addresses replacing `host:tls_port`
+  #[serde(default)]
+  pub tls_listen: Vec<String>,
+  /// PEM CA bundle, when set clients must present a certificate signed by it
+  pub tls_client_ca_path: Option<String>,
+  /// Serve only redirects to HTTPS on `port`
+  #[serde(default)]
+  pub tls_redirect_http: bool,
   pub database_url: String,
   pub jwt_token: String,
+  /// Scheme of new password hashes, `argon2id` or `bcrypt`. Hashes of the other one are
+  /// still verified and replaced on login
+  #[serde(default)]
+  pub password_hasher: PasswordHasherKind,
+  /// Argon2id memory in KiB, iterations and lanes, the OWASP recommendation by default
+  #[serde(default = "default_argon2_memory")]
+  pub argon2_memory: u32,
+  #[serde(default = "default_argon2_iterations")]
+  pub argon2_iterations: u32,
+  #[serde(default = "default_argon2_parallelism")]
+  pub argon2_parallelism: u32,
+  #[serde(default = "default_bcrypt_cost")]
+  pub bcrypt_cost: u32,
+  #[serde(default = "default_password_min_length")]
+  pub password_min_length: usize,
+  /// Of lowercase letters, uppercase letters, digits and symbols
+  #[serde(default = "default_password_min_classes")]
+  pub password_min_classes: usize,
+  /// zxcvbn-style strength from 0 to 4
+  #[serde(default = "default_password_min_score")]
+  pub password_min_score: u8,
+  /// SHA-1 hashes of breached passwords, one per line and sorted as in the Have I Been Pwned
+  /// "ordered by hash" download
+  pub breached_passwords_file: Option<String>,
+  /// `local` or `s3`, where uploaded files such as avatars are kept
+  #[serde(default)]
+  pub storage: StorageKind,
+  /// Directory of the `local` storage
+  #[serde(default = "default_storage_dir")]
+  pub storage_dir: String,
+  /// URL prefix the stored files are linked with, `GET /api/v1/files` of this server by
+  /// default. An absolute URL, e.g. a CDN or a public bucket, makes that route redirect there
+  #[serde(default = "default_storage_url")]
+  pub storage_url: String,
+  /// S3 compatible endpoint, e.g. `https://s3.us-east-1.amazonaws.com` or
+  /// `http://127.0.0.1:9000` for a local MinIO
+  pub s3_endpoint: Option<String>,
+  #[serde(default = "default_s3_region")]
+  pub s3_region: String,
+  pub s3_bucket: Option<String>,
+  pub s3_access_key: Option<String>,
+  pub s3_secret_key: Option<String>,
+  /// Largest avatar upload in bytes
+  #[serde(default = "default_avatar_max_size")]
+  pub avatar_max_size: usize,
+  /// Square sizes in pixels avatars are stored at, the first one is the `avatar` of the user
+  #[serde(default = "default_avatar_sizes")]
+  pub avatar_sizes: Vec<u32>,
+  /// `identicon` or `initials`, the avatar of users who have not uploaded one
+  #[serde(default)]
+  pub avatar_style: IdenticonStyle,
   pub smtp_service: Option<String>,
   pub smtp_host: Option<String>,
   pub smtp_port: Option<u16>,
   pub smtp_user: Option<String>,
   pub smtp_pass: Option<String>,
-  #[serde(default = "default_ipqps")]
-  pub ipqps: u64,
+  /// `memory`, `redis` or `database`, the latter two share limits between replicas
+  #[serde(default)]
+  pub rate_limit_store: RateLimitStoreKind,
+  /// e.g. `redis://127.0.0.1:6379/0`
+  pub redis_url: Option<String>,
+  /// `gcra` (token bucket) or `sliding_window`
+  #[serde(default)]
+  pub rate_limit_algorithm: RateLimitAlgorithm,
+  /// Quota of every request per client, e.g. `60/min`
+  #[serde(default = "default_rate_limit")]
+  pub rate_limit: String,
+  /// Quota of `POST /token` per IP and per email
+  #[serde(default = "default_rate_limit_login")]
+  pub rate_limit_login: String,
+  /// Quota of `POST /user` per IP
+  #[serde(default = "default_rate_limit_register")]
+  pub rate_limit_register: String,
+  /// Quota of `POST /token/magic-link` per IP and per email
+  #[serde(default = "default_rate_limit_magic_link")]
+  pub rate_limit_magic_link: String,
+  /// Quota of `PUT /user/avatar` per user
+  #[serde(default = "default_rate_limit_upload")]
+  pub rate_limit_upload: String,
+  /// Policies whose requests are refused with `503` while the store is unavailable, the
+  /// others are let through. By default the ones guarding credentials
+  #[serde(default = "default_rate_limit_fail_closed")]
+  pub rate_limit_fail_closed: Vec<String>,
+  /// Proxies (CIDRs or addresses) whose `Forwarded`/`X-Forwarded-For` headers are honoured
+  #[serde(default)]
+  pub trusted_proxies: Vec<String>,
+  /// Allow any origin, method and header. Only meant for local development
+  #[serde(default)]
+  pub cors_permissive: bool,
+  /// Exact origins or wildcard subdomains, e.g. `https://*.example.com`
+  #[serde(default)]
+  pub cors_allowed_origins: Vec<String>,
+  #[serde(default = "default_cors_allowed_methods")]
+  pub cors_allowed_methods: Vec<String>,
+  #[serde(default = "default_cors_allowed_headers")]
+  pub cors_allowed_headers: Vec<String>,
+  /// Response headers readable by scripts, besides the CORS-safelisted ones
+  #[serde(default = "default_cors_expose_headers")]
+  pub cors_expose_headers: Vec<String>,
+  pub cors_max_age: Option<usize>,
+  /// Refused together with a `*` origin, which would let any site make credentialed calls
+  #[serde(default)]
+  pub cors_supports_credentials: bool,
+  /// Serve `/metrics` on `metrics_host:metrics_port` instead of the public listeners
+  pub metrics_port: Option<u16>,
+  /// Loopback by default, so the metrics port is not reachable from outside unless asked
+  #[serde(default = "default_metrics_host")]
+  pub metrics_host: IpAddr,
+  /// Bearer token required by `/metrics`. Without it `/metrics` is only served on
+  /// `metrics_port`
+  pub metrics_token: Option<String>,
+  /// OTLP/HTTP collector, e.g. `http://localhost:4318`. Spans are only exported when set
+  pub otel_exporter_otlp_endpoint: Option<String>,
+  #[serde(default = "default_otel_service_name")]
+  pub otel_service_name: String,
+  /// `text` or `json`
+  #[serde(default)]
+  pub log_format: LogFormat,
+  /// Default level and per-target levels, e.g. `info,sqlx::query=off,actix_web=debug`.
+  /// `RUST_LOG` takes precedence
+  #[serde(default = "default_log_level")]
+  pub log_level: String,
+  /// Also write logs to this file, e.g. `logs/app.log`
+  pub log_file: Option<String>,
+  /// `daily`, `hourly` or `never`
+  #[serde(default)]
+  pub log_file_rotation: LogRotation,
+  /// Rotate once the file reaches this many bytes
+  pub log_file_max_size: Option<u64>,
+  /// Rotated files kept, older ones are deleted
+  #[serde(default = "default_log_file_max_files")]
+  pub log_file_max_files: usize,
+  /// Names of the OpenID Connect providers, each configured by `OIDC_<NAME>_*`
+  #[serde(default)]
+  pub oidc_providers: Vec<String>,
+  /// Frontend page the providers redirect to, `{provider}` is replaced by the name, e.g.
+  /// `https://example.com/login/{provider}/callback`
+  pub oidc_redirect_url: Option<String>,
+  /// Frontend page of the login links, `{token}` is replaced by the token, e.g.
+  /// `https://example.com/login/magic?token={token}`. Magic links are disabled without it
+  pub magic_link_url: Option<String>,
+  /// Frontend page confirming a new email address, `{token}` is replaced by the token, e.g.
+  /// `https://example.com/user/email?token={token}`. Email changes are disabled without it
+  pub email_change_url: Option<String>,
+  /// Domain passkeys are bound to, e.g. `example.com`. Passkeys are disabled without it
+  pub webauthn_rp_id: Option<String>,
+  /// Shown by authenticators, defaults to `webauthn_rp_id`
+  pub webauthn_rp_name: Option<String>,
+  /// Origins of the frontend, defaults to `https://<webauthn_rp_id>`
+  #[serde(default)]
+  pub webauthn_origins: Vec<String>,
 }
 
 impl EnvConfig {
@@ -2,4 +2,17 @@
 
 pub mod prelude;
 
+pub mod api_key;
+pub mod audit_log;
+pub mod email_change;
+pub mod magic_link;
+pub mod oauth_client;
+pub mod oauth_code;
+pub mod oauth_token;
+pub mod oidc_flow;
+pub mod passkey;
+pub mod passkey_challenge;
+pub mod rate_limit;
+pub mod session;
 pub mod user;
+pub mod user_identity;
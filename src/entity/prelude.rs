@@ -1,6 +1,58 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.4
 
+pub use super::api_key::ActiveModel as ApiKeyActiveModel;
+pub use super::api_key::Column as ApiKeyColumn;
+pub use super::api_key::Entity as ApiKeyEntity;
+pub use super::api_key::Model as ApiKeyModel;
+pub use super::audit_log::ActiveModel as AuditLogActiveModel;
+pub use super::audit_log::Column as AuditLogColumn;
+pub use super::audit_log::Entity as AuditLogEntity;
+pub use super::audit_log::Model as AuditLogModel;
+pub use super::email_change::ActiveModel as EmailChangeActiveModel;
+pub use super::email_change::Column as EmailChangeColumn;
+pub use super::email_change::Entity as EmailChangeEntity;
+pub use super::email_change::Model as EmailChangeModel;
+pub use super::magic_link::ActiveModel as MagicLinkActiveModel;
+pub use super::magic_link::Column as MagicLinkColumn;
+pub use super::magic_link::Entity as MagicLinkEntity;
+pub use super::magic_link::Model as MagicLinkModel;
+pub use super::oauth_client::ActiveModel as OauthClientActiveModel;
+pub use super::oauth_client::Column as OauthClientColumn;
+pub use super::oauth_client::Entity as OauthClientEntity;
+pub use super::oauth_client::Model as OauthClientModel;
+pub use super::oauth_code::ActiveModel as OauthCodeActiveModel;
+pub use super::oauth_code::Column as OauthCodeColumn;
+pub use super::oauth_code::Entity as OauthCodeEntity;
+pub use super::oauth_code::Model as OauthCodeModel;
+pub use super::oauth_token::ActiveModel as OauthTokenActiveModel;
+pub use super::oauth_token::Column as OauthTokenColumn;
+pub use super::oauth_token::Entity as OauthTokenEntity;
+pub use super::oauth_token::Model as OauthTokenModel;
+pub use super::oidc_flow::ActiveModel as OidcFlowActiveModel;
+pub use super::oidc_flow::Column as OidcFlowColumn;
+pub use super::oidc_flow::Entity as OidcFlowEntity;
+pub use super::oidc_flow::Model as OidcFlowModel;
+pub use super::passkey::ActiveModel as PasskeyActiveModel;
+pub use super::passkey::Column as PasskeyColumn;
+pub use super::passkey::Entity as PasskeyEntity;
+pub use super::passkey::Model as PasskeyModel;
+pub use super::passkey_challenge::ActiveModel as PasskeyChallengeActiveModel;
+pub use super::passkey_challenge::Column as PasskeyChallengeColumn;
+pub use super::passkey_challenge::Entity as PasskeyChallengeEntity;
+pub use super::passkey_challenge::Model as PasskeyChallengeModel;
+pub use super::rate_limit::ActiveModel as RateLimitActiveModel;
+pub use super::rate_limit::Column as RateLimitColumn;
+pub use super::rate_limit::Entity as RateLimitEntity;
+pub use super::rate_limit::Model as RateLimitModel;
+pub use super::session::ActiveModel as SessionActiveModel;
+pub use super::session::Column as SessionColumn;
+pub use super::session::Entity as SessionEntity;
+pub use super::session::Model as SessionModel;
 pub use super::user::ActiveModel as UserActiveModel;
 pub use super::user::Column as UserColumn;
 pub use super::user::Entity as UserEntity;
 pub use super::user::Model as UserModel;
+pub use super::user_identity::ActiveModel as UserIdentityActiveModel;
+pub use super::user_identity::Column as UserIdentityColumn;
+pub use super::user_identity::Entity as UserIdentityEntity;
+pub use super::user_identity::Model as UserIdentityModel;
@@ -1,4 +1,4 @@
-use crate::locales::get_translation;
+use crate::{helpers::password_policy::PasswordViolation, locales::get_translation};
 
 #[derive(Debug)]
 pub enum AppError {
@@ -12,6 +12,15 @@ pub enum AppError {
   InvalidToken,
   FrequencyLimited,
   PasswordIncorrect,
+  NotFound,
+  InvalidParameter,
+  IdentityProvider,
+  Passkey,
+  PasswordPolicy(PasswordViolation),
+  ReauthRequired,
+  FileTooLarge,
+  UnsupportedImage,
+  EmailNotVerified,
 }
 
 impl AppError {
@@ -27,6 +36,15 @@ impl AppError {
       Self::FrequencyLimited => 1006,
       Self::UserExists => 1007,
       Self::PasswordIncorrect => 1008,
+      Self::NotFound => 1009,
+      Self::InvalidParameter => 1010,
+      Self::IdentityProvider => 1011,
+      Self::Passkey => 1012,
+      Self::PasswordPolicy(_) => 1013,
+      Self::ReauthRequired => 1014,
+      Self::FileTooLarge => 1015,
+      Self::UnsupportedImage => 1016,
+      Self::EmailNotVerified => 1017,
     }
   }
   pub fn message(&self, lang: &str) -> String {
@@ -41,6 +59,25 @@ impl AppError {
       Self::FrequencyLimited => get_translation(lang, "Frequency limited"),
       Self::UserExists => get_translation(lang, "User exists"),
       Self::PasswordIncorrect => get_translation(lang, "Password incorrect"),
+      Self::NotFound => get_translation(lang, "Not found"),
+      Self::InvalidParameter => get_translation(lang, "Invalid parameter"),
+      Self::IdentityProvider => get_translation(lang, "Identity provider error"),
+      Self::Passkey => get_translation(lang, "Passkey verification failed"),
+      Self::PasswordPolicy(violation) => match violation {
+        PasswordViolation::TooShort(min) => {
+          get_translation(lang, "Password too short").replace("{min}", &min.to_string())
+        }
+        PasswordViolation::TooFewClasses(min) => {
+          get_translation(lang, "Password too few classes").replace("{min}", &min.to_string())
+        }
+        PasswordViolation::SameAsAccount => get_translation(lang, "Password same as account"),
+        PasswordViolation::TooWeak => get_translation(lang, "Password too weak"),
+        PasswordViolation::Breached => get_translation(lang, "Password breached"),
+      },
+      Self::ReauthRequired => get_translation(lang, "Re-authentication required"),
+      Self::FileTooLarge => get_translation(lang, "File too large"),
+      Self::UnsupportedImage => get_translation(lang, "Unsupported image"),
+      Self::EmailNotVerified => get_translation(lang, "Email not verified"),
     }
   }
 }
@@ -80,6 +117,41 @@ impl From<helpers::hash::BcryptError> for AppError {
   }
 }
 
+impl From<helpers::hash::Error> for AppError {
+  fn from(err: helpers::hash::Error) -> Self {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  }
+}
+
+impl From<rate_limiter::InvalidQuota> for AppError {
+  fn from(err: rate_limiter::InvalidQuota) -> Self {
+    tracing::error!("{err}");
+    AppError::Error
+  }
+}
+
+impl From<redis::RedisError> for AppError {
+  fn from(err: redis::RedisError) -> Self {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  }
+}
+
+impl From<opentelemetry::trace::TraceError> for AppError {
+  fn from(err: opentelemetry::trace::TraceError) -> Self {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  }
+}
+
+impl From<reqwest::Error> for AppError {
+  fn from(err: reqwest::Error) -> Self {
+    tracing::error!("{:#?}", err);
+    AppError::IdentityProvider
+  }
+}
+
 impl From<actix_web::http::header::ToStrError> for AppError {
   fn from(err: actix_web::http::header::ToStrError) -> Self {
     tracing::error!("{:#?}", err);
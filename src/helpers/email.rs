@@ -1,9 +1,15 @@
 use lettre::{
-  message::header::ContentType, transport::smtp::authentication::Credentials, Message,
-  SmtpTransport, Transport,
+  message::{
+    header::{ContentType, Header, HeaderName, HeaderValue},
+    Mailbox,
+  },
+  transport::smtp::authentication::Credentials,
+  Message, SmtpTransport, Transport,
 };
 
-use crate::config::EnvConfig;
+use crate::{
+  config::EnvConfig, error::AppError, metrics::EMAILS_TOTAL, middlewares::request_id::RequestId,
+};
 
 struct SmtpConfig {
   host: &'static str,
@@ -40,10 +46,46 @@ impl SmtpService {
   }
 }
 
+/// Escapes text inserted into an HTML mail body
+pub fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// `X-Request-Id` of the request that triggered the email
+#[derive(Clone)]
+struct XRequestId(String);
+
+impl Header for XRequestId {
+  fn name() -> HeaderName {
+    HeaderName::new_from_ascii_str("X-Request-Id")
+  }
+
+  fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(Self(s.to_string()))
+  }
+
+  fn display(&self) -> HeaderValue {
+    HeaderValue::new(Self::name(), self.0.clone())
+  }
+}
+
+#[allow(dead_code)]
 pub enum NotifyType {
   Notify,
 }
 
+#[allow(dead_code)]
 pub struct EmailNotification<'a> {
   pub notify_type: NotifyType,
   pub to_email: &'a str,
@@ -52,7 +94,8 @@ pub struct EmailNotification<'a> {
   pub lang: Option<&'a str>,
 }
 
-pub fn send_email_notification(notification: EmailNotification) {
+#[allow(dead_code, clippy::needless_borrow)]
+pub fn send_email_notification(mailer: &Mailer, notification: EmailNotification) {
   let to: &str;
   let subject;
   let body;
@@ -64,60 +107,176 @@ pub fn send_email_notification(notification: EmailNotification) {
       tracing::debug!("Body: {:#?}", body);
     }
   }
-  mail(to, &subject, body);
+  mailer.mail(to, &subject, body);
+}
+
+/// SMTP transport built once from config, with the sender address. The transport keeps a
+/// connection pool, clones share it
+#[derive(Debug, Clone)]
+pub struct Mailer {
+  transport: SmtpTransport,
+  from: Mailbox,
 }
 
-pub fn mail(to: &str, subject: &str, body: &str) {
-  let EnvConfig {
-    smtp_service,
-    smtp_host,
-    smtp_port,
-    smtp_user,
-    smtp_pass,
-    ..
-  } = EnvConfig::load_env().unwrap();
-  let host;
-  let port;
-  if smtp_user.is_none() || smtp_pass.is_none() {
-    return;
-  }
-  if smtp_host.is_some() || smtp_port.is_some() {
-    host = smtp_host.unwrap();
-    port = smtp_port.unwrap();
-  } else if smtp_service.is_some() {
-    let smtp_service = match smtp_service.unwrap().as_str() {
-      "QQ" => SmtpService::QQ,
-      "Gmail" => SmtpService::Gmail,
-      "126" => SmtpService::NetEase126,
-      "163" => SmtpService::NetEase163,
+impl Mailer {
+  /// `None` when mail is not configured
+  pub fn from_config(config: &EnvConfig) -> Result<Option<Mailer>, AppError> {
+    let EnvConfig {
+      smtp_service,
+      smtp_host,
+      smtp_port,
+      smtp_user,
+      smtp_pass,
+      ..
+    } = config;
+    let (Some(smtp_user), Some(smtp_pass)) = (smtp_user, smtp_pass) else {
+      return Ok(None);
+    };
+    let from = format!("sender <{smtp_user}>").parse().map_err(|e| {
+      tracing::error!("SMTP_USER is not a valid email address: {e}");
+      AppError::Error
+    })?;
+    let (host, port) = match (smtp_host, smtp_port, smtp_service) {
+      (Some(host), Some(port), _) => (host.clone(), *port),
+      (None, None, Some(smtp_service)) => {
+        let smtp_service = match smtp_service.as_str() {
+          "QQ" => SmtpService::QQ,
+          "Gmail" => SmtpService::Gmail,
+          "126" => SmtpService::NetEase126,
+          "163" => SmtpService::NetEase163,
+          _ => {
+            tracing::error!("Unsupported SMTP service {smtp_service}");
+            return Err(AppError::Error);
+          }
+        };
+        let config = smtp_service.config();
+        (config.host.to_owned(), config.port)
+      }
+      (None, None, None) => return Ok(None),
       _ => {
-        tracing::error!("Unsupported SMTP service");
-        return;
+        tracing::error!("SMTP_HOST and SMTP_PORT must be set together");
+        return Err(AppError::Error);
       }
     };
-    host = smtp_service.config().host.to_owned();
-    port = smtp_service.config().port;
-  } else {
-    return;
-  }
-  let msg = Message::builder()
-    .from(
-      format!("{} <{}>", "sender", smtp_user.clone().unwrap())
-        .parse()
-        .unwrap(),
-    )
-    .to(to.parse().unwrap())
-    .subject(subject)
-    .header(ContentType::TEXT_HTML)
-    .body(body.to_string())
-    .unwrap();
-  let mailer = SmtpTransport::relay(&host)
-    .unwrap()
-    .credentials(Credentials::new(smtp_user.unwrap(), smtp_pass.unwrap()))
-    .port(port)
-    .build();
-  match mailer.send(&msg) {
-    Ok(resp) => tracing::info!("{:#?}", resp),
-    Err(e) => tracing::error!("Could not send email: {e:?}"),
+    let transport = SmtpTransport::relay(&host)
+      .map_err(|e| {
+        tracing::error!("Invalid SMTP host {host}: {e:?}");
+        AppError::Error
+      })?
+      .credentials(Credentials::new(smtp_user.clone(), smtp_pass.clone()))
+      .port(port)
+      .build();
+    Ok(Some(Mailer { transport, from }))
+  }
+
+  /// Sends an HTML email, tagged with the `X-Request-Id` when called while handling a request
+  #[allow(dead_code)]
+  pub fn mail(&self, to: &str, subject: &str, body: &str) {
+    self.send(to, subject, body, RequestId::current());
+  }
+
+  /// Sends the email from a blocking thread without waiting for it, still tagged with the
+  /// `X-Request-Id` of the current request
+  pub fn mail_in_background(&self, to: String, subject: String, body: String) {
+    let (mailer, request_id) = (self.clone(), RequestId::current());
+    actix_web::rt::task::spawn_blocking(move || mailer.send(&to, &subject, &body, request_id));
+  }
+
+  fn send(&self, to: &str, subject: &str, body: &str, request_id: Option<String>) {
+    let Ok(to) = to.parse() else {
+      EMAILS_TOTAL.with_label_values(&["failed"]).inc();
+      tracing::error!("Could not send email: invalid address {to}");
+      return;
+    };
+    let mut msg = Message::builder()
+      .from(self.from.clone())
+      .to(to)
+      .subject(subject)
+      .header(ContentType::TEXT_HTML);
+    if let Some(request_id) = request_id {
+      msg = msg.header(XRequestId(request_id));
+    }
+    let msg = msg.body(body.to_string()).unwrap();
+    match self.transport.send(&msg) {
+      Ok(resp) => {
+        EMAILS_TOTAL.with_label_values(&["sent"]).inc();
+        tracing::info!("{:#?}", resp)
+      }
+      Err(e) => {
+        EMAILS_TOTAL.with_label_values(&["failed"]).inc();
+        tracing::error!("Could not send email: {e:?}")
+      }
+    }
+  }
+
+  /// Checks that the SMTP server accepts a connection. Blocking
+  pub fn test_connection(&self) -> bool {
+    match self.transport.test_connection() {
+      Ok(connected) => connected,
+      Err(e) => {
+        tracing::error!("Could not connect to SMTP server: {e:?}");
+        false
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(vars: &[(&str, &str)]) -> EnvConfig {
+    let mut env = vec![("DATABASE_URL", "sqlite::memory:"), ("JWT_TOKEN", "test")];
+    env.extend_from_slice(vars);
+    envy::from_iter(env.iter().map(|(k, v)| (k.to_string(), v.to_string()))).unwrap()
+  }
+
+  const CREDENTIALS: [(&str, &str); 2] = [("SMTP_USER", "me@example.com"), ("SMTP_PASS", "pass")];
+
+  #[test]
+  fn mail_needs_credentials_and_a_server() {
+    assert!(Mailer::from_config(&config(&[])).unwrap().is_none());
+    let host = [("SMTP_HOST", "smtp.example.com"), ("SMTP_PORT", "465")];
+    assert!(Mailer::from_config(&config(&host)).unwrap().is_none());
+    assert!(Mailer::from_config(&config(&CREDENTIALS))
+      .unwrap()
+      .is_none());
+    let mailer = Mailer::from_config(&config(&[CREDENTIALS, host].concat())).unwrap();
+    assert_eq!(mailer.unwrap().from.email.to_string(), "me@example.com");
+    let service = [CREDENTIALS.as_slice(), &[("SMTP_SERVICE", "Gmail")]].concat();
+    assert!(Mailer::from_config(&config(&service)).unwrap().is_some());
+  }
+
+  #[test]
+  fn refuses_an_incomplete_server() {
+    for server in [
+      ("SMTP_HOST", "smtp.example.com"),
+      ("SMTP_PORT", "465"),
+      ("SMTP_SERVICE", "Unknown"),
+    ] {
+      let vars = [CREDENTIALS.as_slice(), &[server]].concat();
+      assert!(Mailer::from_config(&config(&vars)).is_err(), "{server:?}");
+    }
+  }
+
+  #[test]
+  fn refuses_a_malformed_sender() {
+    let server = [("SMTP_HOST", "smtp.example.com"), ("SMTP_PORT", "465")];
+    for user in ["me", "me@example.com>", "<me@example.com"] {
+      let vars = [
+        server.as_slice(),
+        &[("SMTP_USER", user), ("SMTP_PASS", "pass")],
+      ]
+      .concat();
+      assert!(Mailer::from_config(&config(&vars)).is_err(), "{user}");
+    }
+  }
+
+  #[test]
+  fn escapes_html() {
+    assert_eq!(
+      escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+      "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+    );
   }
 }
@@ -1,48 +1,273 @@
-use actix_web::HttpRequest;
+use std::{
+  convert::Infallible,
+  future::{ready, Ready},
+  net::{IpAddr, Ipv4Addr},
+};
 
-use crate::error::AppError;
+use actix_web::{dev::Payload, http::header, web::Data, FromRequest, HttpRequest};
+use ipnet::IpNet;
 
+use crate::{app::AppState, error::AppError};
+
+/// Header carrying the token of `POST /user/reauth`
+pub const REAUTH_HEADER: &str = "X-Reauth-Token";
+
+/// Bearer token of `Authorization`, or an API key sent as `X-API-Key`
 pub fn extract_token(req: &HttpRequest) -> Result<String, AppError> {
-  let auth_header = req
-    .headers()
-    .get("Authorization")
-    .ok_or(AppError::Unauthorized)?
-    .to_str()?;
+  let headers = req.headers();
+  let Some(auth_header) = headers.get("Authorization") else {
+    let api_key = headers.get("X-API-Key").ok_or(AppError::Unauthorized)?;
+    return Ok(api_key.to_str()?.to_string());
+  };
+  let auth_header = auth_header.to_str()?;
   if !auth_header.starts_with("Bearer ") {
     return Err(AppError::Unauthorized);
   }
   Ok(auth_header[7..].to_string()) // Skip "Bearer " prefix
 }
 
-pub fn extract_ip(req: &HttpRequest) -> String {
-  if let Some(h) = req.headers().get("X-Forwarded-For") {
-    let s = h.to_str().unwrap_or("0.0.0.0").to_string();
-    s
-  } else if let Some(h) = req.headers().get("X-Real-IP") {
-    let s = h.to_str().ok().unwrap_or("0.0.0.0").to_string();
-    s
-  } else {
-    req
-      .peer_addr()
-      .map(|s| s.ip().to_string())
-      .unwrap_or_default()
+/// Parses a `trusted_proxies` entry, a CIDR (`10.0.0.0/8`) or a single address
+pub fn parse_trusted_proxy(proxy: &str) -> Result<IpNet, AppError> {
+  proxy
+    .parse::<IpNet>()
+    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+    .map_err(|err| {
+      tracing::error!("Invalid trusted proxy {proxy}: {err}");
+      AppError::Error
+    })
+}
+
+/// Parses a node of `X-Forwarded-For` or of a `Forwarded` `for=` parameter:
+/// `192.0.2.60`, `192.0.2.60:4711`, `2001:db8::1` or `"[2001:db8::1]:4711"`
+fn parse_node(node: &str) -> Option<IpAddr> {
+  let node = node.trim().trim_matches('"');
+  if let Some(rest) = node.strip_prefix('[') {
+    return rest.split_once(']')?.0.parse().ok();
   }
+  node.parse().ok().or_else(|| {
+    node
+      .rsplit_once(':')
+      .and_then(|(ip, _)| ip.parse::<Ipv4Addr>().ok())
+      .map(IpAddr::V4)
+  })
 }
 
-pub fn extract_host(req: &HttpRequest) -> String {
+/// Client addresses as appended by proxies, nearest proxy last. `Forwarded` (RFC 7239)
+/// wins over `X-Forwarded-For`
+fn forwarded_chain(req: &HttpRequest) -> Vec<Option<IpAddr>> {
+  let headers = req.headers();
+  let forwarded = headers
+    .get_all(header::FORWARDED)
+    .filter_map(|h| h.to_str().ok())
+    .flat_map(|h| h.split(','))
+    .map(|element| {
+      element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+        .and_then(|(_, value)| parse_node(value))
+    })
+    .collect::<Vec<_>>();
+  if !forwarded.is_empty() {
+    return forwarded;
+  }
+  headers
+    .get_all("X-Forwarded-For")
+    .filter_map(|h| h.to_str().ok())
+    .flat_map(|h| h.split(','))
+    .map(parse_node)
+    .collect()
+}
+
+/// Resolves the client address. Forwarding headers are only honoured when the peer is a
+/// trusted proxy, and are walked right to left skipping further trusted proxies, so a
+/// client cannot spoof its address by sending the headers itself. Requests over a unix
+/// domain socket have no peer address and come from a local proxy, so they are trusted
+pub fn extract_ip(req: &HttpRequest, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+  let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+  let peer = req.peer_addr().map(|addr| addr.ip());
+  if peer.as_ref().is_some_and(|ip| !is_trusted(ip)) {
+    return peer;
+  }
+  let chain = forwarded_chain(req);
+  for hop in chain.iter().rev() {
+    match hop {
+      Some(ip) if is_trusted(ip) => {}
+      Some(ip) => return Some(*ip),
+      // An obfuscated or malformed hop, whatever is left of it may come from the client
+      None => return peer,
+    }
+  }
+  // Every hop is a trusted proxy, the leftmost one is the closest to the client
+  if let Some(Some(ip)) = chain.first() {
+    return Some(*ip);
+  }
   req
     .headers()
-    .get("Host")
+    .get("X-Real-IP")
     .and_then(|h| h.to_str().ok())
-    .unwrap_or_default()
-    .to_string()
+    .and_then(parse_node)
+    .or(peer)
+}
+
+/// Client address resolved with the configured trusted proxies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+  /// `0.0.0.0` when the address is unknown, e.g. over a unix domain socket without proxy
+  /// headers
+  pub fn resolve(req: &HttpRequest) -> Self {
+    let trusted_proxies = req
+      .app_data::<Data<AppState>>()
+      .map(|state| state.trusted_proxies.as_slice())
+      .unwrap_or_default();
+    ClientIp(extract_ip(req, trusted_proxies).unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))
+  }
+}
+
+impl FromRequest for ClientIp {
+  type Error = Infallible;
+  type Future = Ready<Result<Self, Self::Error>>;
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Ok(ClientIp::resolve(req)))
+  }
+}
+
+impl std::fmt::Display for ClientIp {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
+/// Token of `POST /user/reauth` sent as `X-Reauth-Token`
+pub fn extract_reauth_token(req: &HttpRequest) -> Option<String> {
+  req
+    .headers()
+    .get(REAUTH_HEADER)
+    .and_then(|h| h.to_str().ok())
+    .map(str::to_string)
 }
 
-pub fn extract_referer(req: &HttpRequest) -> String {
+pub fn extract_host(req: &HttpRequest) -> String {
   req
     .headers()
-    .get("referer")
+    .get("Host")
     .and_then(|h| h.to_str().ok())
     .unwrap_or_default()
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+  use actix_web::test::TestRequest;
+
+  use super::*;
+
+  fn proxies() -> Vec<IpNet> {
+    ["10.0.0.0/8", "2001:db8::1"]
+      .iter()
+      .map(|proxy| parse_trusted_proxy(proxy).unwrap())
+      .collect()
+  }
+
+  fn ip(req: TestRequest) -> Option<IpAddr> {
+    extract_ip(&req.to_http_request(), &proxies())
+  }
+
+  fn from(peer: &str) -> TestRequest {
+    TestRequest::default().peer_addr(peer.parse().unwrap())
+  }
+
+  #[test]
+  fn ignores_a_spoofed_leftmost_forwarded_for() {
+    let req = from("10.0.0.1:443").insert_header(("X-Forwarded-For", "1.2.3.4, 203.0.113.7"));
+    assert_eq!(ip(req), Some("203.0.113.7".parse().unwrap()));
+  }
+
+  #[test]
+  fn skips_trusted_proxies_right_to_left() {
+    let req = from("10.0.0.1:443").insert_header((
+      "X-Forwarded-For",
+      "1.2.3.4, 203.0.113.7, 10.0.0.2, 10.0.0.3",
+    ));
+    assert_eq!(ip(req), Some("203.0.113.7".parse().unwrap()));
+  }
+
+  #[test]
+  fn forwarded_for_of_several_headers_is_one_list() {
+    let req = from("10.0.0.1:443")
+      .append_header(("X-Forwarded-For", "1.2.3.4"))
+      .append_header(("X-Forwarded-For", "203.0.113.7"));
+    assert_eq!(ip(req), Some("203.0.113.7".parse().unwrap()));
+  }
+
+  #[test]
+  fn ignores_headers_from_untrusted_peers() {
+    let peer = "198.51.100.1:5000";
+    let req = from(peer)
+      .insert_header(("X-Forwarded-For", "203.0.113.7"))
+      .insert_header((header::FORWARDED, "for=203.0.113.8"))
+      .insert_header(("X-Real-IP", "203.0.113.9"));
+    assert_eq!(ip(req), Some("198.51.100.1".parse().unwrap()));
+  }
+
+  #[test]
+  fn every_hop_trusted_gives_the_leftmost() {
+    let req = from("10.0.0.1:443").insert_header(("X-Forwarded-For", "10.0.0.5, 10.0.0.2"));
+    assert_eq!(ip(req), Some("10.0.0.5".parse().unwrap()));
+  }
+
+  #[test]
+  fn forwarded_with_a_quoted_ipv6_and_port() {
+    let req = from("[2001:db8::1]:443").insert_header((
+      header::FORWARDED,
+      r#"for=192.0.2.43;proto=https, for="[2001:db8:cafe::17]:4711";by=10.0.0.1"#,
+    ));
+    assert_eq!(ip(req), Some("2001:db8:cafe::17".parse().unwrap()));
+  }
+
+  #[test]
+  fn forwarded_wins_over_forwarded_for() {
+    let req = from("10.0.0.1:443")
+      .insert_header((header::FORWARDED, "For=192.0.2.60:4711"))
+      .insert_header(("X-Forwarded-For", "203.0.113.7"));
+    assert_eq!(ip(req), Some("192.0.2.60".parse().unwrap()));
+  }
+
+  #[test]
+  fn malformed_hops_fall_back_to_the_peer() {
+    let peer = "10.0.0.1".parse().ok();
+    let garbage = from("10.0.0.1:443").insert_header(("X-Forwarded-For", "not an ip, ,"));
+    assert_eq!(ip(garbage), peer);
+    // What is left of an unknown hop may be client supplied
+    let hidden =
+      from("10.0.0.1:443").insert_header((header::FORWARDED, "for=1.2.3.4, for=unknown"));
+    assert_eq!(ip(hidden), peer);
+    let missing_bracket = from("10.0.0.1:443").insert_header((
+      header::FORWARDED,
+      r#"for=1.2.3.4, for="[2001:db8:cafe::17""#,
+    ));
+    assert_eq!(ip(missing_bracket), peer);
+  }
+
+  #[test]
+  fn real_ip_without_forwarding_headers() {
+    let req = from("10.0.0.1:443").insert_header(("X-Real-IP", "203.0.113.9"));
+    assert_eq!(ip(req), Some("203.0.113.9".parse().unwrap()));
+  }
+
+  #[test]
+  fn unix_socket_requests_trust_the_headers() {
+    let req = TestRequest::default().insert_header(("X-Forwarded-For", "203.0.113.7"));
+    assert_eq!(ip(req), Some("203.0.113.7".parse().unwrap()));
+    assert_eq!(ip(TestRequest::default()), None);
+  }
+
+  #[test]
+  fn client_ip_defaults_to_unspecified() {
+    let req = TestRequest::default().to_http_request();
+    assert_eq!(ClientIp::resolve(&req).to_string(), "0.0.0.0");
+  }
+}
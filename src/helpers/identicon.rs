@@ -0,0 +1,140 @@
+//! Default avatars drawn from the account, as SVG so they scale to any size
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::email::escape_html;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdenticonStyle {
+  /// A mirrored 5x5 pattern, as GitHub draws them
+  #[default]
+  Identicon,
+  /// Up to two initials of the nickname
+  Initials,
+}
+
+/// Cells per side of the pattern, the left three columns are mirrored to the right
+const GRID: usize = 5;
+
+/// First letters of the first two words of `nickname`, `?` without any
+fn initials(nickname: &str) -> String {
+  let initials: String = nickname
+    .split_whitespace()
+    .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+    .take(2)
+    .flat_map(char::to_uppercase)
+    .collect();
+  if initials.is_empty() {
+    "?".to_string()
+  } else {
+    initials
+  }
+}
+
+/// The avatar of `user_id`, the same for the same account and differing between accounts.
+/// The hue and the pattern come from the hash of `user_id`, which never changes
+pub fn svg(style: IdenticonStyle, user_id: &str, nickname: &str) -> String {
+  let hash = Sha256::digest(user_id.as_bytes());
+  let hue = u16::from_be_bytes([hash[0], hash[1]]) % 360;
+  match style {
+    IdenticonStyle::Identicon => {
+      let columns = GRID.div_ceil(2);
+      let path: String = (0..GRID * columns)
+        .filter(|i| (hash[2 + i / 8] >> (i % 8)) & 1 == 1)
+        .flat_map(|i| {
+          let (x, y) = (i % columns, i / columns);
+          let mirrored = GRID - 1 - x;
+          let cell = move |x: usize| format!("M{x} {y}h1v1h-1z");
+          std::iter::once(cell(x)).chain((mirrored != x).then(|| cell(mirrored)))
+        })
+        .collect();
+      format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-0.5 -0.5 6 6" shape-rendering="crispEdges"><rect x="-0.5" y="-0.5" width="6" height="6" fill="#f0f0f0"/><path fill="hsl({hue},55%,50%)" d="{path}"/></svg>"##
+      )
+    }
+    IdenticonStyle::Initials => format!(
+      r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100"><rect width="100" height="100" fill="hsl({hue},55%,45%)"/><text x="50" y="50" dy=".35em" text-anchor="middle" fill="#fff" font-family="sans-serif" font-size="40">{}</text></svg>"##,
+      escape_html(&initials(nickname))
+    ),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use super::*;
+
+  /// The filled cells of an identicon, from the `M{x} {y}h1v1h-1z` path
+  fn cells(svg: &str) -> HashSet<(usize, usize)> {
+    let path = svg
+      .split(" d=\"")
+      .nth(1)
+      .unwrap()
+      .split('"')
+      .next()
+      .unwrap();
+    path
+      .split('M')
+      .filter(|cell| !cell.is_empty())
+      .map(|cell| {
+        let (x, y) = cell.trim_end_matches("h1v1h-1z").split_once(' ').unwrap();
+        (x.parse().unwrap(), y.parse().unwrap())
+      })
+      .collect()
+  }
+
+  #[test]
+  fn same_account_same_avatar() {
+    for style in [IdenticonStyle::Identicon, IdenticonStyle::Initials] {
+      assert_eq!(
+        svg(style, "abc12345", "Alice"),
+        svg(style, "abc12345", "Alice")
+      );
+      assert_ne!(
+        svg(style, "abc12345", "Alice"),
+        svg(style, "xyz67890", "Alice")
+      );
+    }
+    // The pattern does not follow the nickname, which can change
+    let identicon = IdenticonStyle::Identicon;
+    assert_eq!(
+      svg(identicon, "abc12345", "Alice"),
+      svg(identicon, "abc12345", "Bob")
+    );
+  }
+
+  #[test]
+  fn grid_is_mirrored() {
+    for user_id in ["abc12345", "xyz67890", "00000000", "zzzzzzzz"] {
+      let cells = cells(&svg(IdenticonStyle::Identicon, user_id, ""));
+      assert!(!cells.is_empty(), "{user_id}");
+      for &(x, y) in &cells {
+        assert!(x < GRID && y < GRID, "{user_id}: {x} {y}");
+        assert!(cells.contains(&(GRID - 1 - x, y)), "{user_id}: {x} {y}");
+      }
+    }
+  }
+
+  #[test]
+  fn nickname_cannot_inject_markup() {
+    for nickname in [
+      "<script>alert(1)</script>",
+      "\"><svg onload=alert(1)>",
+      "' onmouseover='alert(1)",
+      "&lt; &amp;",
+    ] {
+      let svg = svg(IdenticonStyle::Initials, "abc12345", nickname);
+      let (markup, _) = svg.split_once("</text>").unwrap();
+      let (_, text) = markup.rsplit_once('>').unwrap();
+      assert!(!text.contains(['<', '>', '"', '\'']), "{nickname}: {text}");
+      assert!(
+        !svg.contains("<script") && !svg.contains("onload"),
+        "{nickname}"
+      );
+    }
+    assert_eq!(initials("alice  bob carol"), "AB");
+    assert_eq!(initials("<&>"), "?");
+  }
+}
@@ -2,3 +2,8 @@
 
 pub mod email;
 pub mod header;
+pub mod identicon;
+pub mod oidc;
+pub mod password;
+pub mod password_policy;
+pub mod webauthn;
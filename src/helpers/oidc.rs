@@ -0,0 +1,754 @@
+//! Generic OpenID Connect client: discovery, PKCE, id token validation. Providers without
+//! discovery (e.g. GitHub, plain OAuth2) are configured with explicit endpoints and are
+//! identified through their userinfo endpoint
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex, OnceLock},
+  time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+  jwk::{Jwk, JwkSet},
+  Algorithm, DecodingKey, Validation,
+};
+use reqwest::{header::ACCEPT, Client, Url};
+use ring::hmac;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// How long the signing keys of a provider are reused. A key id missing from them is
+/// refetched at once, providers publish a rotated key before signing with it
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
+fn default_scopes() -> String {
+  "openid email profile".to_string()
+}
+
+fn default_subject_claim() -> String {
+  "sub".to_string()
+}
+
+fn default_email_claim() -> String {
+  "email".to_string()
+}
+
+fn default_name_claim() -> String {
+  "name".to_string()
+}
+
+/// `OIDC_<NAME>_*` variables of a provider listed in `oidc_providers`
+#[derive(Deserialize, Clone)]
+pub struct OidcProviderConfig {
+  pub client_id: String,
+  pub client_secret: String,
+  /// Discovery through `<issuer>/.well-known/openid-configuration`
+  pub issuer: Option<String>,
+  /// Endpoints of providers without discovery, also override the discovered ones
+  pub authorization_url: Option<String>,
+  pub token_url: Option<String>,
+  pub userinfo_url: Option<String>,
+  /// Lists the addresses of the account when the claims carry no verified email, as GitHub
+  /// `https://api.github.com/user/emails`: `[{"email", "primary", "verified"}]`
+  pub emails_url: Option<String>,
+  /// Overrides `oidc_redirect_url`
+  pub redirect_url: Option<String>,
+  /// Space separated
+  #[serde(default = "default_scopes")]
+  pub scopes: String,
+  /// Claims of the id token or userinfo holding the account id, email and name
+  #[serde(default = "default_subject_claim")]
+  pub subject_claim: String,
+  #[serde(default = "default_email_claim")]
+  pub email_claim: String,
+  #[serde(default = "default_name_claim")]
+  pub name_claim: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+  issuer: String,
+  authorization_endpoint: String,
+  token_endpoint: String,
+  userinfo_endpoint: Option<String>,
+  jwks_uri: String,
+}
+
+/// Address listed by `emails_url`
+#[derive(Debug, Deserialize)]
+struct ProviderEmail {
+  email: String,
+  #[serde(default)]
+  primary: bool,
+  #[serde(default)]
+  verified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  access_token: String,
+  id_token: Option<String>,
+}
+
+/// The account at the provider
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+  pub subject: String,
+  pub email: Option<String>,
+  pub email_verified: bool,
+  pub name: Option<String>,
+}
+
+pub struct OidcProvider {
+  pub name: String,
+  /// Registered at the provider, the frontend posts the `code` and `state` it receives
+  pub redirect_uri: String,
+  config: OidcProviderConfig,
+  client: Client,
+  metadata: OnceLock<ProviderMetadata>,
+  jwks: Mutex<Option<(Instant, Arc<JwkSet>)>>,
+}
+
+impl std::fmt::Debug for OidcProvider {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("OidcProvider")
+      .field("name", &self.name)
+      .field("issuer", &self.config.issuer)
+      .finish()
+  }
+}
+
+/// `code_challenge` of a PKCE `code_verifier`, method `S256`
+pub fn pkce_challenge(verifier: &str) -> String {
+  URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn state_key(secret: &str) -> hmac::Key {
+  hmac::Key::new(hmac::HMAC_SHA256, format!("oidc-state:{secret}").as_bytes())
+}
+
+/// Value of the cookie binding the flow of `state` to the browser that started it, signed
+/// with `secret` so it cannot be made up for another flow
+pub fn state_binding(secret: &str, state: &str) -> String {
+  URL_SAFE_NO_PAD.encode(hmac::sign(&state_key(secret), state.as_bytes()))
+}
+
+/// Whether `binding` is the cookie set for the flow of `state`, compared in constant time
+pub fn verify_state_binding(secret: &str, state: &str, binding: &str) -> bool {
+  URL_SAFE_NO_PAD
+    .decode(binding)
+    .is_ok_and(|tag| hmac::verify(&state_key(secret), state.as_bytes(), &tag).is_ok())
+}
+
+/// Providers listed in `oidc_providers`, by name
+pub fn providers(config: &EnvConfig) -> Result<HashMap<String, OidcProvider>, AppError> {
+  let client = Client::builder().build()?;
+  config
+    .oidc_providers
+    .iter()
+    .map(|name| {
+      let prefix = format!("OIDC_{}_", name.to_uppercase());
+      let provider_config = envy::prefixed(&prefix).from_env::<OidcProviderConfig>()?;
+      let has_endpoints =
+        provider_config.authorization_url.is_some() && provider_config.token_url.is_some();
+      if provider_config.issuer.is_none() && !has_endpoints {
+        tracing::error!(
+          "{prefix}ISSUER or {prefix}AUTHORIZATION_URL and {prefix}TOKEN_URL required"
+        );
+        return Err(AppError::Error);
+      }
+      let redirect_uri = provider_config
+        .redirect_url
+        .clone()
+        .or_else(|| {
+          config
+            .oidc_redirect_url
+            .as_ref()
+            .map(|url| url.replace("{provider}", name))
+        })
+        .ok_or_else(|| {
+          tracing::error!("OIDC_REDIRECT_URL or {prefix}REDIRECT_URL required");
+          AppError::Error
+        })?;
+      let provider = OidcProvider {
+        name: name.clone(),
+        redirect_uri,
+        config: provider_config,
+        client: client.clone(),
+        metadata: OnceLock::new(),
+        jwks: Mutex::new(None),
+      };
+      Ok((name.clone(), provider))
+    })
+    .collect()
+}
+
+impl OidcProvider {
+  /// Discovery document, fetched once
+  async fn metadata(&self) -> Result<Option<&ProviderMetadata>, AppError> {
+    let Some(issuer) = &self.config.issuer else {
+      return Ok(None);
+    };
+    if let Some(metadata) = self.metadata.get() {
+      return Ok(Some(metadata));
+    }
+    let url = format!(
+      "{}/.well-known/openid-configuration",
+      issuer.trim_end_matches('/')
+    );
+    let metadata = self
+      .client
+      .get(url)
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<ProviderMetadata>()
+      .await?;
+    if metadata.issuer != *issuer {
+      tracing::error!("Issuer mismatch: {} != {}", metadata.issuer, issuer);
+      return Err(AppError::IdentityProvider);
+    }
+    Ok(Some(self.metadata.get_or_init(|| metadata)))
+  }
+
+  async fn endpoint(
+    &self,
+    configured: &Option<String>,
+    discovered: impl Fn(&ProviderMetadata) -> Option<&String>,
+  ) -> Result<String, AppError> {
+    if let Some(url) = configured {
+      return Ok(url.clone());
+    }
+    self
+      .metadata()
+      .await?
+      .and_then(discovered)
+      .cloned()
+      .ok_or(AppError::IdentityProvider)
+  }
+
+  /// Where to send the user, `state`, `nonce` and the PKCE challenge are checked on callback
+  pub async fn authorization_url(
+    &self,
+    state: &str,
+    nonce: &str,
+    code_challenge: &str,
+  ) -> Result<String, AppError> {
+    let endpoint = self
+      .endpoint(&self.config.authorization_url, |m| {
+        Some(&m.authorization_endpoint)
+      })
+      .await?;
+    let url = Url::parse_with_params(
+      &endpoint,
+      [
+        ("response_type", "code"),
+        ("client_id", &self.config.client_id),
+        ("redirect_uri", &self.redirect_uri),
+        ("scope", &self.config.scopes),
+        ("state", state),
+        ("nonce", nonce),
+        ("code_challenge", code_challenge),
+        ("code_challenge_method", "S256"),
+      ],
+    )
+    .map_err(|e| {
+      tracing::error!("Invalid authorization url {endpoint}: {e}");
+      AppError::IdentityProvider
+    })?;
+    Ok(url.into())
+  }
+
+  /// Exchanges the authorization code and resolves the account, from the validated id token
+  /// when the provider issues one, otherwise from userinfo
+  pub async fn identify(
+    &self,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+  ) -> Result<OidcIdentity, AppError> {
+    let token_url = self
+      .endpoint(&self.config.token_url, |m| Some(&m.token_endpoint))
+      .await?;
+    let token = self
+      .client
+      .post(token_url)
+      .header(ACCEPT, "application/json")
+      .form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &self.redirect_uri),
+        ("client_id", &self.config.client_id),
+        ("client_secret", &self.config.client_secret),
+        ("code_verifier", code_verifier),
+      ])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<TokenResponse>()
+      .await?;
+    let mut claims = match (&token.id_token, self.metadata().await?) {
+      (Some(id_token), Some(metadata)) => self.validate_id_token(metadata, id_token, nonce).await?,
+      _ => Value::Object(Default::default()),
+    };
+    if claims.get(&self.config.email_claim).is_none() {
+      let userinfo = self.userinfo(&token.access_token).await?;
+      // The subject of userinfo must be the one of the id token
+      if let (Some(sub), Some(userinfo_sub)) = (
+        claims.get(&self.config.subject_claim),
+        userinfo.get(&self.config.subject_claim),
+      ) {
+        if sub != userinfo_sub {
+          tracing::error!("Userinfo subject mismatch");
+          return Err(AppError::IdentityProvider);
+        }
+      }
+      claims = userinfo;
+    }
+    let text = |claim: &str| match claims.get(claim) {
+      Some(Value::String(s)) => Some(s.clone()),
+      Some(Value::Number(n)) => Some(n.to_string()),
+      _ => None,
+    };
+    let mut email = text(&self.config.email_claim);
+    let mut email_verified = claims
+      .get("email_verified")
+      .and_then(Value::as_bool)
+      .unwrap_or(false);
+    if let (false, Some(url)) = (email_verified, &self.config.emails_url) {
+      let emails = self.get(url, &token.access_token).await?;
+      if let Some(verified) = verified_email(emails, email.as_deref()) {
+        (email, email_verified) = (Some(verified), true);
+      }
+    }
+    Ok(OidcIdentity {
+      subject: text(&self.config.subject_claim).ok_or(AppError::IdentityProvider)?,
+      email,
+      email_verified,
+      name: text(&self.config.name_claim),
+    })
+  }
+
+  async fn validate_id_token(
+    &self,
+    metadata: &ProviderMetadata,
+    id_token: &str,
+    nonce: &str,
+  ) -> Result<Value, AppError> {
+    let header = jsonwebtoken::decode_header(id_token)?;
+    if matches!(
+      header.alg,
+      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+      tracing::error!("Refusing id token signed with {:?}", header.alg);
+      return Err(AppError::IdentityProvider);
+    }
+    let jwk = self.jwk(metadata, header.kid.as_deref()).await?;
+    let key = DecodingKey::from_jwk(&jwk)?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&self.config.client_id]);
+    validation.set_issuer(&[&metadata.issuer]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = jsonwebtoken::decode::<Value>(id_token, &key, &validation)?.claims;
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+      tracing::error!("Id token nonce mismatch");
+      return Err(AppError::IdentityProvider);
+    }
+    Ok(claims)
+  }
+
+  /// Signing keys of the provider, cached for [`JWKS_TTL`] unless `refresh`
+  async fn jwks(
+    &self,
+    metadata: &ProviderMetadata,
+    refresh: bool,
+  ) -> Result<Arc<JwkSet>, AppError> {
+    if let Some((fetched_at, jwks)) = &*self.jwks.lock().unwrap() {
+      if !refresh && fetched_at.elapsed() < JWKS_TTL {
+        return Ok(jwks.clone());
+      }
+    }
+    let jwks = self
+      .client
+      .get(&metadata.jwks_uri)
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<JwkSet>()
+      .await?;
+    let jwks = Arc::new(jwks);
+    *self.jwks.lock().unwrap() = Some((Instant::now(), jwks.clone()));
+    Ok(jwks)
+  }
+
+  /// Key `kid` of the provider, the first one without. Refetches the keys once when `kid` is
+  /// not among the cached ones
+  async fn jwk(&self, metadata: &ProviderMetadata, kid: Option<&str>) -> Result<Jwk, AppError> {
+    let find = |jwks: &JwkSet| match kid {
+      Some(kid) => jwks.find(kid).cloned(),
+      None => jwks.keys.first().cloned(),
+    };
+    if let Some(jwk) = find(&*self.jwks(metadata, false).await?) {
+      return Ok(jwk);
+    }
+    find(&*self.jwks(metadata, true).await?).ok_or_else(|| {
+      tracing::error!("Unknown id token key {kid:?}");
+      AppError::IdentityProvider
+    })
+  }
+
+  async fn userinfo(&self, access_token: &str) -> Result<Value, AppError> {
+    let url = self
+      .endpoint(&self.config.userinfo_url, |m| m.userinfo_endpoint.as_ref())
+      .await?;
+    self.get(&url, access_token).await
+  }
+
+  /// JSON of an API of the provider on behalf of the user
+  async fn get<T: DeserializeOwned>(&self, url: &str, access_token: &str) -> Result<T, AppError> {
+    Ok(
+      self
+        .client
+        .get(url)
+        .bearer_auth(access_token)
+        .header(ACCEPT, "application/json")
+        // GitHub rejects requests without one
+        .header(reqwest::header::USER_AGENT, env!("CARGO_PKG_NAME"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<T>()
+        .await?,
+    )
+  }
+}
+
+/// The claimed address when the provider lists it as verified, otherwise the primary one if
+/// verified
+fn verified_email(emails: Vec<ProviderEmail>, claimed: Option<&str>) -> Option<String> {
+  let verified = emails
+    .into_iter()
+    .filter(|email| email.verified)
+    .collect::<Vec<_>>();
+  claimed
+    .and_then(|claimed| {
+      verified
+        .iter()
+        .find(|email| email.email.eq_ignore_ascii_case(claimed))
+    })
+    .or_else(|| verified.iter().find(|email| email.primary))
+    .map(|email| email.email.clone())
+}
+
+/// A provider without discovery whose token endpoint is unreachable, its endpoints and
+/// claims can be changed from the tests of this module
+#[cfg(test)]
+pub mod testing {
+  use super::*;
+
+  pub fn provider(name: &str) -> OidcProvider {
+    OidcProvider {
+      name: name.to_string(),
+      redirect_uri: format!("https://app.example/{name}"),
+      config: OidcProviderConfig {
+        client_id: "client".to_string(),
+        client_secret: String::new(),
+        issuer: None,
+        authorization_url: Some("https://provider.example/authorize".to_string()),
+        token_url: Some("http://127.0.0.1:9/token".to_string()),
+        userinfo_url: None,
+        emails_url: None,
+        redirect_url: None,
+        scopes: default_scopes(),
+        subject_claim: default_subject_claim(),
+        email_claim: default_email_claim(),
+        name_claim: default_name_claim(),
+      },
+      client: Client::new(),
+      metadata: OnceLock::new(),
+      jwks: Mutex::new(None),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use actix_web::{web, App, HttpResponse, HttpServer};
+  use jsonwebtoken::{EncodingKey, Header};
+  use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+  };
+  use serde_json::json;
+
+  use super::{testing::provider, *};
+
+  fn metadata(jwks_uri: String) -> ProviderMetadata {
+    ProviderMetadata {
+      issuer: "https://issuer.example".to_string(),
+      authorization_endpoint: String::new(),
+      token_endpoint: String::new(),
+      userinfo_endpoint: None,
+      jwks_uri,
+    }
+  }
+
+  /// A P-256 signing key of the provider, published as `kid`
+  struct SigningKey {
+    kid: &'static str,
+    pkcs8: Vec<u8>,
+    jwk: Jwk,
+  }
+
+  impl SigningKey {
+    fn new(kid: &'static str) -> Self {
+      let rng = SystemRandom::new();
+      let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+      let pair =
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+      // Uncompressed point: 0x04, x then y
+      let point = pair.public_key().as_ref();
+      let jwk = json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        "kid": kid,
+        "alg": "ES256",
+      });
+      SigningKey {
+        kid,
+        pkcs8: pkcs8.as_ref().to_vec(),
+        jwk: serde_json::from_value(jwk).unwrap(),
+      }
+    }
+
+    fn sign(&self, claims: &Value) -> String {
+      let mut header = Header::new(Algorithm::ES256);
+      header.kid = Some(self.kid.to_string());
+      let key = EncodingKey::from_ec_der(&self.pkcs8);
+      jsonwebtoken::encode(&header, claims, &key).unwrap()
+    }
+  }
+
+  fn claims() -> Value {
+    json!({
+      "iss": "https://issuer.example",
+      "aud": "client",
+      "sub": "alice",
+      "exp": jsonwebtoken::get_current_timestamp() + 300,
+      "nonce": "nonce",
+      "email": "alice@example.com",
+      "email_verified": true,
+    })
+  }
+
+  /// Validates a token signed with `key`, the provider publishing only `published`
+  async fn validate(published: &SigningKey, key: &SigningKey, claims: Value) -> bool {
+    let provider = provider("test");
+    let jwks = JwkSet {
+      keys: vec![published.jwk.clone()],
+    };
+    *provider.jwks.lock().unwrap() = Some((Instant::now(), Arc::new(jwks)));
+    // Nothing listens there, a key missing from the cache cannot be fetched
+    let metadata = metadata("http://127.0.0.1:9/jwks".to_string());
+    provider
+      .validate_id_token(&metadata, &key.sign(&claims), "nonce")
+      .await
+      .is_ok()
+  }
+
+  #[actix_web::test]
+  async fn id_tokens_of_the_provider_are_accepted() {
+    let key = SigningKey::new("a");
+    assert!(validate(&key, &key, claims()).await);
+  }
+
+  #[actix_web::test]
+  async fn id_tokens_with_other_claims_are_refused() {
+    let key = SigningKey::new("a");
+    for (claim, value) in [
+      ("nonce", json!("replayed")),
+      ("aud", json!("other client")),
+      ("iss", json!("https://evil.example")),
+      ("exp", json!(jsonwebtoken::get_current_timestamp() - 600)),
+    ] {
+      let mut claims = claims();
+      claims[claim] = value;
+      assert!(!validate(&key, &key, claims).await, "{claim}");
+    }
+    let mut claims = claims();
+    claims.as_object_mut().unwrap().remove("nonce");
+    assert!(!validate(&key, &key, claims).await);
+  }
+
+  #[actix_web::test]
+  async fn id_tokens_signed_by_another_key_are_refused() {
+    let published = SigningKey::new("a");
+    // Same key id, other key
+    assert!(!validate(&published, &SigningKey::new("a"), claims()).await);
+    // Unknown key id, the keys cannot be refetched
+    assert!(!validate(&published, &SigningKey::new("b"), claims()).await);
+  }
+
+  #[actix_web::test]
+  async fn id_tokens_signed_with_a_shared_secret_are_refused() {
+    let provider = provider("test");
+    let metadata = metadata("http://127.0.0.1:9/jwks".to_string());
+    let key = EncodingKey::from_secret(b"client secret");
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims(), &key).unwrap();
+    assert!(provider
+      .validate_id_token(&metadata, &token, "nonce")
+      .await
+      .is_err());
+  }
+
+  #[test]
+  fn state_binding_is_tied_to_the_state_and_the_secret() {
+    let binding = state_binding("secret", "state");
+    assert!(verify_state_binding("secret", "state", &binding));
+    assert!(!verify_state_binding("secret", "other state", &binding));
+    assert!(!verify_state_binding("other secret", "state", &binding));
+    assert!(!verify_state_binding("secret", "state", ""));
+    assert!(!verify_state_binding("secret", "state", "not base64!"));
+  }
+
+  fn jwk(kid: &str) -> Value {
+    json!({ "kty": "EC", "crv": "P-256", "x": "AAAA", "y": "AAAA", "kid": kid })
+  }
+
+  #[actix_web::test]
+  async fn jwks_are_cached_and_refetched_for_an_unknown_key() {
+    // Serves key `a`, then `a` and `b` once rotated
+    let fetches = web::Data::new(AtomicUsize::new(0));
+    let server_fetches = fetches.clone();
+    let server = HttpServer::new(move || {
+      App::new().app_data(server_fetches.clone()).route(
+        "/jwks",
+        web::get().to(|fetches: web::Data<AtomicUsize>| async move {
+          let keys = match fetches.fetch_add(1, Ordering::SeqCst) {
+            0 => vec![jwk("a")],
+            _ => vec![jwk("a"), jwk("b")],
+          };
+          HttpResponse::Ok().json(json!({ "keys": keys }))
+        }),
+      )
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let provider = provider("test");
+    let metadata = metadata(format!("http://{addr}/jwks"));
+    let kid = |jwk: Jwk| jwk.common.key_id.unwrap();
+    assert_eq!(kid(provider.jwk(&metadata, Some("a")).await.unwrap()), "a");
+    assert_eq!(kid(provider.jwk(&metadata, None).await.unwrap()), "a");
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    assert_eq!(kid(provider.jwk(&metadata, Some("b")).await.unwrap()), "b");
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    assert!(provider.jwk(&metadata, Some("c")).await.is_err());
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    handle.stop(false).await;
+  }
+
+  fn email(email: &str, primary: bool, verified: bool) -> ProviderEmail {
+    ProviderEmail {
+      email: email.to_string(),
+      primary,
+      verified,
+    }
+  }
+
+  #[test]
+  fn picks_the_claimed_or_the_primary_verified_email() {
+    let emails = || {
+      vec![
+        email("old@example.com", false, false),
+        email("work@example.com", false, true),
+        email("alice@example.com", true, true),
+      ]
+    };
+    let pick = |claimed| verified_email(emails(), claimed);
+    assert_eq!(pick(None).as_deref(), Some("alice@example.com"));
+    assert_eq!(
+      pick(Some("Work@Example.com")).as_deref(),
+      Some("work@example.com")
+    );
+    // Not verified, the primary one is used instead
+    assert_eq!(
+      pick(Some("old@example.com")).as_deref(),
+      Some("alice@example.com")
+    );
+    let unverified = vec![email("alice@example.com", true, false)];
+    assert_eq!(verified_email(unverified, None), None);
+  }
+
+  #[actix_web::test]
+  async fn a_private_github_email_is_read_from_the_emails_endpoint() {
+    // GitHub answers userinfo with a null email when the address is private
+    let server = HttpServer::new(|| {
+      App::new()
+        .route(
+          "/token",
+          web::post().to(|| async { HttpResponse::Ok().json(json!({ "access_token": "gho" })) }),
+        )
+        .route(
+          "/user",
+          web::get().to(|| async {
+            HttpResponse::Ok().json(json!({ "id": 42, "login": "alice", "email": null }))
+          }),
+        )
+        .route(
+          "/user/emails",
+          web::get().to(|| async {
+            HttpResponse::Ok().json(json!([
+              { "email": "alice@users.noreply.github.com", "primary": false, "verified": true },
+              { "email": "alice@example.com", "primary": true, "verified": true },
+            ]))
+          }),
+        )
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let github = |emails_url: Option<String>| {
+      let mut provider = provider("test");
+      provider.config.token_url = Some(format!("http://{addr}/token"));
+      provider.config.userinfo_url = Some(format!("http://{addr}/user"));
+      provider.config.emails_url = emails_url;
+      provider.config.subject_claim = "id".to_string();
+      provider.config.name_claim = "login".to_string();
+      provider
+    };
+    let identity = github(Some(format!("http://{addr}/user/emails")))
+      .identify("code", "verifier", "nonce")
+      .await
+      .unwrap();
+    assert_eq!(identity.subject, "42");
+    assert_eq!(identity.name.as_deref(), Some("alice"));
+    assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+    assert!(identity.email_verified);
+    // Without the endpoint the account has no usable email
+    let identity = github(None)
+      .identify("code", "verifier", "nonce")
+      .await
+      .unwrap();
+    assert_eq!(identity.email, None);
+    assert!(!identity.email_verified);
+    handle.stop(false).await;
+  }
+}
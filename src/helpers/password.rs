@@ -0,0 +1,271 @@
+//! Password hashing. New hashes use the configured [`PasswordHasher`], Argon2id by default,
+//! hashes of the other schemes are still verified and flagged for rehashing
+use std::sync::Arc;
+
+use actix_web::web;
+use argon2::{Config, Variant, Version};
+use helpers::hash;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// A password hashing scheme
+pub trait PasswordHasher: Send + Sync + std::fmt::Debug {
+  /// Hashes with a random salt
+  fn hash(&self, password: &str) -> Result<String, AppError>;
+  /// Whether `hash` was produced by this scheme
+  fn recognizes(&self, hash: &str) -> bool;
+  fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError>;
+  /// Whether a hash of this scheme was made with other parameters than the current ones
+  fn is_outdated(&self, hash: &str) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordHasherKind {
+  #[default]
+  Argon2id,
+  Bcrypt,
+}
+
+/// Argon2id, in the PHC string format
+#[derive(Debug, Clone)]
+pub struct Argon2id {
+  /// KiB
+  pub memory: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+impl Argon2id {
+  fn config(&self) -> Config<'static> {
+    Config {
+      variant: Variant::Argon2id,
+      version: Version::Version13,
+      mem_cost: self.memory,
+      time_cost: self.iterations,
+      lanes: self.parallelism,
+      ..Config::default()
+    }
+  }
+}
+
+impl PasswordHasher for Argon2id {
+  fn hash(&self, password: &str) -> Result<String, AppError> {
+    let mut salt = [0u8; 16];
+    SystemRandom::new().fill(&mut salt).map_err(|_| {
+      tracing::error!("Could not generate a password salt");
+      AppError::Error
+    })?;
+    Ok(argon2::hash_encoded(
+      password.as_bytes(),
+      &salt,
+      &self.config(),
+    )?)
+  }
+
+  fn recognizes(&self, hash: &str) -> bool {
+    hash.starts_with("$argon2")
+  }
+
+  fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+    Ok(argon2::verify_encoded(hash, password.as_bytes())?)
+  }
+
+  fn is_outdated(&self, hash: &str) -> bool {
+    let current = format!(
+      "$argon2id$v=19$m={},t={},p={}$",
+      self.memory, self.iterations, self.parallelism
+    );
+    !hash.starts_with(&current)
+  }
+}
+
+/// bcrypt, the scheme of older accounts
+#[derive(Debug, Clone)]
+pub struct Bcrypt {
+  pub cost: u32,
+}
+
+impl PasswordHasher for Bcrypt {
+  fn hash(&self, password: &str) -> Result<String, AppError> {
+    Ok(hash::bcrypt_custom(
+      password,
+      self.cost,
+      hash::Version::TwoB,
+    )?)
+  }
+
+  fn recognizes(&self, hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+      .iter()
+      .any(|prefix| hash.starts_with(prefix))
+  }
+
+  fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+    Ok(hash::verify_bcrypt(password, hash)?)
+  }
+
+  fn is_outdated(&self, hash: &str) -> bool {
+    // $2b$<cost>$...
+    hash.get(4..6) != Some(format!("{:02}", self.cost).as_str())
+  }
+}
+
+async fn blocking<T: Send + 'static>(
+  f: impl FnOnce() -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+  web::block(f).await.map_err(|err| {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  })?
+}
+
+/// Hashes with the configured scheme and verifies hashes of every scheme
+#[derive(Debug, Clone)]
+pub struct Passwords {
+  current: Arc<dyn PasswordHasher>,
+  others: Vec<Arc<dyn PasswordHasher>>,
+}
+
+impl Passwords {
+  pub fn from_config(config: &EnvConfig) -> Self {
+    let argon2id = Arc::new(Argon2id {
+      memory: config.argon2_memory,
+      iterations: config.argon2_iterations,
+      parallelism: config.argon2_parallelism,
+    });
+    let bcrypt = Arc::new(Bcrypt {
+      cost: config.bcrypt_cost,
+    });
+    match config.password_hasher {
+      PasswordHasherKind::Argon2id => Self {
+        current: argon2id,
+        others: vec![bcrypt],
+      },
+      PasswordHasherKind::Bcrypt => Self {
+        current: bcrypt,
+        others: vec![argon2id],
+      },
+    }
+  }
+
+  /// Runs on the blocking thread pool, as [`Passwords::verify`] does: hashing is slow on
+  /// purpose and would stall the other requests of the worker
+  pub async fn hash(&self, password: &str) -> Result<String, AppError> {
+    let (current, password) = (self.current.clone(), password.to_string());
+    blocking(move || current.hash(&password)).await
+  }
+
+  /// `false` as well for hashes of no known scheme
+  pub async fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+    let Some(scheme) = self.scheme(hash).cloned() else {
+      tracing::error!("Unknown password hash scheme");
+      return Ok(false);
+    };
+    let (password, hash) = (password.to_string(), hash.to_string());
+    blocking(move || scheme.verify(&password, &hash)).await
+  }
+
+  /// Whether a verified hash should be replaced by [`Passwords::hash`]
+  pub fn needs_rehash(&self, hash: &str) -> bool {
+    !self.current.recognizes(hash) || self.current.is_outdated(hash)
+  }
+
+  fn scheme(&self, hash: &str) -> Option<&Arc<dyn PasswordHasher>> {
+    std::iter::once(&self.current)
+      .chain(&self.others)
+      .find(|scheme| scheme.recognizes(hash))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Cheap parameters, the tests hash a lot
+  fn argon2id(iterations: u32) -> Arc<Argon2id> {
+    Arc::new(Argon2id {
+      memory: 1024,
+      iterations,
+      parallelism: 1,
+    })
+  }
+
+  fn bcrypt(cost: u32) -> Arc<Bcrypt> {
+    Arc::new(Bcrypt { cost })
+  }
+
+  #[actix_web::test]
+  async fn argon2id_hashes_are_salted_and_verified() {
+    let passwords = Passwords {
+      current: argon2id(1),
+      others: vec![bcrypt(4)],
+    };
+    let hash = passwords.hash("violet rocket harbor").await.unwrap();
+    assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"), "{hash}");
+    assert_ne!(hash, passwords.hash("violet rocket harbor").await.unwrap());
+    assert!(passwords
+      .verify("violet rocket harbor", &hash)
+      .await
+      .unwrap());
+    assert!(!passwords
+      .verify("amber falcon meadow", &hash)
+      .await
+      .unwrap());
+    assert!(!passwords.needs_rehash(&hash));
+  }
+
+  #[actix_web::test]
+  async fn bcrypt_hashes_are_verified_and_flagged_for_rehash() {
+    let hash = bcrypt(4).hash("violet rocket harbor").unwrap();
+    assert!(hash.starts_with("$2b$04$"), "{hash}");
+    let passwords = Passwords {
+      current: argon2id(1),
+      others: vec![bcrypt(4)],
+    };
+    assert!(passwords
+      .verify("violet rocket harbor", &hash)
+      .await
+      .unwrap());
+    assert!(!passwords
+      .verify("amber falcon meadow", &hash)
+      .await
+      .unwrap());
+    assert!(passwords.needs_rehash(&hash));
+  }
+
+  #[test]
+  fn outdated_parameters_need_a_rehash() {
+    let hash = argon2id(1).hash("violet rocket harbor").unwrap();
+    let passwords = Passwords {
+      current: argon2id(2),
+      others: vec![],
+    };
+    assert!(passwords.needs_rehash(&hash));
+    let hash = bcrypt(4).hash("violet rocket harbor").unwrap();
+    let passwords = Passwords {
+      current: bcrypt(5),
+      others: vec![argon2id(1)],
+    };
+    assert!(passwords.needs_rehash(&hash));
+    let current = Passwords {
+      current: bcrypt(4),
+      others: vec![],
+    };
+    assert!(!current.needs_rehash(&hash));
+  }
+
+  #[actix_web::test]
+  async fn unknown_schemes_do_not_verify() {
+    let passwords = Passwords {
+      current: argon2id(1),
+      others: vec![bcrypt(4)],
+    };
+    let md5 = "$1$salt$qJH7.N4xYta3aEG/dfqo/0";
+    assert!(!passwords.verify("password", md5).await.unwrap());
+    assert!(!passwords.verify("password", "password").await.unwrap());
+    assert!(passwords.needs_rehash(md5));
+  }
+}
@@ -0,0 +1,433 @@
+//! Password policy: length, character classes, similarity to the account, a zxcvbn-style
+//! strength score and an optional offline list of breached passwords
+use std::{
+  cmp::Ordering,
+  fs::File,
+  io::{BufRead, BufReader, Seek, SeekFrom},
+};
+
+use actix_web::web;
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// Most used passwords, also matched after undoing leetspeak and dropping trailing digits
+/// and symbols
+const COMMON_PASSWORDS: &[&str] = &[
+  "password",
+  "passw0rd",
+  "123456",
+  "12345678",
+  "123456789",
+  "1234567890",
+  "qwerty",
+  "qwertyuiop",
+  "abc123",
+  "111111",
+  "123123",
+  "admin",
+  "administrator",
+  "letmein",
+  "welcome",
+  "monkey",
+  "dragon",
+  "football",
+  "baseball",
+  "iloveyou",
+  "master",
+  "sunshine",
+  "princess",
+  "shadow",
+  "superman",
+  "trustno1",
+  "login",
+  "starwars",
+  "whatever",
+  "freedom",
+  "hello",
+  "charlie",
+  "secret",
+  "access",
+  "computer",
+  "michael",
+  "jordan",
+  "killer",
+  "pokemon",
+  "batman",
+  "changeme",
+  "default",
+  "root",
+  "test",
+  "guest",
+  "zaq12wsx",
+  "1q2w3e4r",
+  "asdfghjkl",
+  "zxcvbnm",
+  "qazwsx",
+];
+
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Why a password was refused, each has a translated message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordViolation {
+  TooShort(usize),
+  TooFewClasses(usize),
+  SameAsAccount,
+  TooWeak,
+  Breached,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+  pub min_length: usize,
+  /// Of lowercase letters, uppercase letters, digits and symbols
+  pub min_classes: usize,
+  /// 0 to 4, see [`score`]
+  pub min_score: u8,
+  breached: Option<BreachedList>,
+}
+
+impl PasswordPolicy {
+  pub fn from_config(config: &EnvConfig) -> Result<Self, AppError> {
+    let breached = match &config.breached_passwords_file {
+      Some(path) => Some(BreachedList::open(path)?),
+      None => None,
+    };
+    Ok(Self {
+      min_length: config.password_min_length,
+      min_classes: config.password_min_classes,
+      min_score: config.password_min_score,
+      breached,
+    })
+  }
+
+  /// Checks a new password of the account with this email and nickname
+  pub async fn check(&self, password: &str, email: &str, nickname: &str) -> Result<(), AppError> {
+    let violation = |violation| Err(AppError::PasswordPolicy(violation));
+    if password.chars().count() < self.min_length {
+      return violation(PasswordViolation::TooShort(self.min_length));
+    }
+    if classes(password) < self.min_classes {
+      return violation(PasswordViolation::TooFewClasses(self.min_classes));
+    }
+    let lowered = password.trim().to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+    if [email, local_part, nickname]
+      .iter()
+      .any(|value| !value.is_empty() && lowered == value.trim().to_lowercase())
+    {
+      return violation(PasswordViolation::SameAsAccount);
+    }
+    if score(password) < self.min_score {
+      return violation(PasswordViolation::TooWeak);
+    }
+    if let Some(breached) = self.breached.clone() {
+      let hash = sha1(password);
+      let found = web::block(move || breached.contains(&hash))
+        .await
+        .map_err(|err| {
+          tracing::error!("{:#?}", err);
+          AppError::Error
+        })??;
+      if found {
+        return violation(PasswordViolation::Breached);
+      }
+    }
+    Ok(())
+  }
+}
+
+fn sha1(password: &str) -> [u8; 20] {
+  let mut hash = [0u8; 20];
+  hash.copy_from_slice(digest(&SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes()).as_ref());
+  hash
+}
+
+/// The hex SHA-1 at the start of a line, before an optional `:<count>`
+fn parse_hash(line: &[u8]) -> Option<[u8; 20]> {
+  let line = std::str::from_utf8(line).ok()?;
+  let hex = line.split(':').next()?.trim();
+  let mut hash = [0u8; 20];
+  let valid = hex.len() == 40
+    && hash.iter_mut().enumerate().all(|(i, byte)| {
+      u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+        .map(|value| *byte = value)
+        .is_ok()
+    });
+  valid.then_some(hash)
+}
+
+/// SHA-1 hashes of breached passwords, searched on disk so lists of any size take no memory.
+/// One hash per line in hex of either case, optionally followed by `:<count>`, sorted by hash
+/// as the "ordered by hash" Have I Been Pwned download (about 40 GB and 35 reads a lookup).
+/// An unsorted file misses hashes
+#[derive(Debug, Clone)]
+struct BreachedList {
+  path: String,
+  len: u64,
+}
+
+impl BreachedList {
+  fn open(path: &str) -> Result<Self, AppError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut first = Vec::new();
+    reader.read_until(b'\n', &mut first)?;
+    if parse_hash(&first).is_none() {
+      tracing::error!("{path} does not start with a SHA-1 per line");
+      return Err(AppError::Error);
+    }
+    let len = reader.get_ref().metadata()?.len();
+    tracing::info!("Checking passwords against {len} bytes of breached password hashes");
+    Ok(Self {
+      path: path.to_string(),
+      len,
+    })
+  }
+
+  /// Binary search over byte offsets, each step reads the first line starting at or after
+  /// the middle of the range left
+  fn contains(&self, hash: &[u8; 20]) -> Result<bool, AppError> {
+    let mut reader = BufReader::with_capacity(256, File::open(&self.path)?);
+    let mut line = Vec::new();
+    // Lines starting in lo..hi are left to search
+    let (mut lo, mut hi) = (0, self.len);
+    while lo < hi {
+      let mid = lo + (hi - lo) / 2;
+      let mut start = mid;
+      if mid > 0 {
+        reader.seek(SeekFrom::Start(mid - 1))?;
+        line.clear();
+        start = mid - 1 + reader.read_until(b'\n', &mut line)? as u64;
+      } else {
+        reader.seek(SeekFrom::Start(0))?;
+      }
+      if start >= hi {
+        hi = mid;
+        continue;
+      }
+      line.clear();
+      let read = reader.read_until(b'\n', &mut line)? as u64;
+      let Some(found) = parse_hash(&line) else {
+        tracing::error!("Invalid SHA-1 at byte {start} of {}", self.path);
+        return Err(AppError::Error);
+      };
+      match found.cmp(hash) {
+        Ordering::Equal => return Ok(true),
+        Ordering::Less => lo = start + read,
+        Ordering::Greater => hi = mid,
+      }
+    }
+    Ok(false)
+  }
+}
+
+fn classes(password: &str) -> usize {
+  let checks: [fn(&char) -> bool; 4] = [
+    char::is_ascii_lowercase,
+    char::is_ascii_uppercase,
+    char::is_ascii_digit,
+    |c| !c.is_ascii_alphanumeric(),
+  ];
+  checks
+    .iter()
+    .filter(|check| password.chars().any(|c| check(&c)))
+    .count()
+}
+
+fn unleet(c: char) -> char {
+  match c {
+    '0' => 'o',
+    '1' | '!' => 'i',
+    '3' => 'e',
+    '4' | '@' => 'a',
+    '5' | '$' => 's',
+    '7' => 't',
+    _ => c,
+  }
+}
+
+fn is_common(password: &str) -> bool {
+  let lowered = password.to_lowercase();
+  let stripped = lowered.trim_end_matches(|c: char| !c.is_ascii_alphabetic());
+  [lowered.as_str(), stripped].iter().any(|candidate| {
+    let unleeted = candidate.chars().map(unleet).collect::<String>();
+    // Both sides, for common passwords holding digits as `trustno1`
+    COMMON_PASSWORDS
+      .iter()
+      .any(|common| candidate == common || unleeted.chars().eq(common.chars().map(unleet)))
+  })
+}
+
+/// Whether `b` continues `a` in the alphabet, a digit run or a keyboard row, either way
+fn is_run(a: char, b: char) -> bool {
+  let (a, b) = (a.to_ascii_lowercase(), b.to_ascii_lowercase());
+  if a == b || (a as i32 - b as i32).abs() == 1 {
+    return true;
+  }
+  KEYBOARD_ROWS.iter().any(|row| {
+    let (Some(i), Some(j)) = (row.find(a), row.find(b)) else {
+      return false;
+    };
+    i.abs_diff(j) == 1
+  })
+}
+
+/// Strength from 0 (too guessable) to 4 (very unguessable), like zxcvbn: common passwords
+/// score 0, repeats, sequences and keyboard runs add little, the rest counts by the size of
+/// the character set
+pub fn score(password: &str) -> u8 {
+  if password.is_empty() || is_common(password) {
+    return 0;
+  }
+  let chars = password.chars().collect::<Vec<_>>();
+  let pool = [
+    (chars.iter().any(char::is_ascii_lowercase), 26.0),
+    (chars.iter().any(char::is_ascii_uppercase), 26.0),
+    (chars.iter().any(char::is_ascii_digit), 10.0),
+    (
+      chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric()),
+      33.0,
+    ),
+    (chars.iter().any(|c| !c.is_ascii()), 100.0),
+  ]
+  .iter()
+  .filter(|(present, _)| *present)
+  .map(|(_, size)| size)
+  .sum::<f64>();
+  let length = chars
+    .windows(2)
+    .map(|pair| if is_run(pair[0], pair[1]) { 0.25 } else { 1.0 })
+    .sum::<f64>()
+    + 1.0;
+  // log10 of the guesses of an exhaustive search
+  let guesses = length * pool.log10();
+  match guesses {
+    g if g < 3.0 => 0,
+    g if g < 6.0 => 1,
+    g if g < 8.0 => 2,
+    g if g < 10.0 => 3,
+    _ => 4,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use ::helpers::uuid::{uuid, Alphabet};
+
+  use super::*;
+
+  fn hex(hash: &[u8; 20]) -> String {
+    hash.iter().map(|byte| format!("{byte:02X}")).collect()
+  }
+
+  /// A sorted list as the Have I Been Pwned download, with CRLF line ends and counts
+  fn list(passwords: &[&str]) -> BreachedList {
+    let mut hashes = passwords.iter().map(|p| sha1(p)).collect::<Vec<_>>();
+    hashes.sort();
+    let path = std::env::temp_dir().join(format!("breached_{}.txt", uuid(&Alphabet::DEFAULT, 16)));
+    let mut file = File::create(&path).unwrap();
+    for (i, hash) in hashes.iter().enumerate() {
+      write!(file, "{}:{}\r\n", hex(hash), i * 1000 + 1).unwrap();
+    }
+    BreachedList::open(path.to_str().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn finds_every_listed_hash_and_no_other() {
+    let passwords = (0..200).map(|i| format!("password{i}")).collect::<Vec<_>>();
+    let passwords = passwords.iter().map(String::as_str).collect::<Vec<_>>();
+    for count in [1, 2, 3, 200] {
+      let breached = list(&passwords[..count]);
+      for password in &passwords[..count] {
+        assert!(
+          breached.contains(&sha1(password)).unwrap(),
+          "{password} of {count}"
+        );
+      }
+      for password in ["", "not listed", "password200", "zzzz"] {
+        assert!(
+          !breached.contains(&sha1(password)).unwrap(),
+          "{password} of {count}"
+        );
+      }
+      assert!(!breached.contains(&[0; 20]).unwrap());
+      assert!(!breached.contains(&[0xff; 20]).unwrap());
+    }
+  }
+
+  #[test]
+  fn refuses_a_file_without_hashes() {
+    let path = std::env::temp_dir().join(format!("breached_{}.txt", uuid(&Alphabet::DEFAULT, 16)));
+    std::fs::write(&path, "password\n").unwrap();
+    assert!(BreachedList::open(path.to_str().unwrap()).is_err());
+  }
+
+  fn policy(min_classes: usize) -> PasswordPolicy {
+    PasswordPolicy {
+      min_length: 8,
+      min_classes,
+      min_score: 2,
+      breached: None,
+    }
+  }
+
+  #[actix_web::test]
+  async fn checks_length_classes_and_the_account() {
+    use PasswordViolation::*;
+    let (email, nickname) = ("alice.smith@example.com", "Wonderland Alice");
+    for (min_classes, password, expected) in [
+      (1, "", Some(TooShort(8))),
+      (1, "amber7", Some(TooShort(8))),
+      // Counted in characters, not bytes
+      (1, "éééééé", Some(TooShort(8))),
+      (3, "amberfalconmeadow", Some(TooFewClasses(3))),
+      (3, "amber falcon meadow", Some(TooFewClasses(3))),
+      (1, "alice.smith@example.com", Some(SameAsAccount)),
+      (1, "Alice.Smith", Some(SameAsAccount)),
+      (1, " wonderland alice ", Some(SameAsAccount)),
+      (1, "aaaaaaaaaa", Some(TooWeak)),
+      (1, "abcdefghij", Some(TooWeak)),
+      (1, "P@ssw0rd123!", Some(TooWeak)),
+      (3, "Amber falcon meadow 7", None),
+      (1, "amber falcon meadow", None),
+    ] {
+      let res = policy(min_classes).check(password, email, nickname).await;
+      let violation = match res {
+        Ok(()) => None,
+        Err(AppError::PasswordPolicy(violation)) => Some(violation),
+        Err(err) => panic!("{password}: {err:?}"),
+      };
+      assert_eq!(violation, expected, "{password}");
+    }
+  }
+
+  #[test]
+  fn common_and_leetspeak_passwords_score_0() {
+    for password in [
+      "",
+      "password",
+      "PASSWORD",
+      "p@55w0rd",
+      "Passw0rd!",
+      "letmein2024",
+      "Tru$tno1",
+      "dr@g0n!!",
+      "qwertyuiop",
+    ] {
+      assert_eq!(score(password), 0, "{password}");
+    }
+  }
+
+  #[test]
+  fn passphrases_score_high_and_runs_low() {
+    assert_eq!(score("correct horse battery staple"), 4);
+    assert_eq!(score("amber falcon meadow"), 4);
+    assert!(score("abcdefgh") < 2);
+    assert!(score("asdfghjkl;") < 2);
+  }
+}
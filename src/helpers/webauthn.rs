@@ -0,0 +1,767 @@
+//! WebAuthn relying party: checks passkey registrations (attestation `none`, statements are
+//! not verified) and assertions with ES256, EdDSA or RS256 keys
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::signature::{
+  RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ED25519,
+  RSA_PKCS1_2048_8192_SHA256,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// COSE algorithms offered to authenticators, by preference
+pub const ALGORITHMS: [i64; 3] = [-7, -8, -257];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+/// Authenticators send at most a few hundred bytes, nested a few levels deep
+const CBOR_MAX_DEPTH: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+  /// Domain the passkeys are bound to
+  pub id: String,
+  pub name: String,
+  /// Origins the ceremonies may run on
+  pub origins: Vec<String>,
+}
+
+/// `clientDataJSON` of a ceremony
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// base64url, as sent in the options
+  pub challenge: String,
+  pub origin: String,
+  #[serde(rename = "crossOrigin", default)]
+  pub cross_origin: bool,
+}
+
+impl ClientData {
+  pub fn parse(client_data_json: &[u8]) -> Result<Self, AppError> {
+    serde_json::from_slice(client_data_json).map_err(|err| {
+      tracing::error!("Invalid clientDataJSON: {err}");
+      AppError::Passkey
+    })
+  }
+}
+
+/// A credential created by a registration ceremony
+#[derive(Debug, Clone)]
+pub struct NewCredential {
+  pub credential_id: Vec<u8>,
+  /// COSE_Key
+  pub public_key: Vec<u8>,
+  pub sign_count: u32,
+}
+
+/// Decodes base64url, with or without padding
+pub fn base64url(value: &str) -> Result<Vec<u8>, AppError> {
+  URL_SAFE_NO_PAD
+    .decode(value.trim_end_matches('='))
+    .map_err(|_| AppError::Passkey)
+}
+
+fn reject(reason: &str) -> AppError {
+  tracing::error!("Passkey rejected: {reason}");
+  AppError::Passkey
+}
+
+impl RelyingParty {
+  /// `None` when passkeys are not configured
+  pub fn from_config(config: &EnvConfig) -> Option<Self> {
+    let id = config.webauthn_rp_id.clone()?;
+    let origins = match config.webauthn_origins.is_empty() {
+      true => vec![format!("https://{id}")],
+      false => config.webauthn_origins.clone(),
+    };
+    Some(Self {
+      name: config
+        .webauthn_rp_name
+        .clone()
+        .unwrap_or_else(|| id.clone()),
+      id,
+      origins,
+    })
+  }
+
+  fn check_client_data(&self, client_data: &ClientData, kind: &str) -> Result<(), AppError> {
+    if client_data.kind != kind {
+      return Err(reject("unexpected ceremony type"));
+    }
+    if client_data.cross_origin || !self.origins.contains(&client_data.origin) {
+      return Err(reject("unexpected origin"));
+    }
+    Ok(())
+  }
+
+  fn check_authenticator_data(&self, data: &AuthenticatorData) -> Result<(), AppError> {
+    if data.rp_id_hash != Sha256::digest(self.id.as_bytes()).as_slice() {
+      return Err(reject("unexpected relying party id"));
+    }
+    let required = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+    if data.flags & required != required {
+      return Err(reject("user not present or not verified"));
+    }
+    Ok(())
+  }
+
+  /// Checks a `navigator.credentials.create()` response whose challenge was already
+  /// matched by the caller
+  pub fn verify_registration(
+    &self,
+    client_data: &ClientData,
+    attestation_object: &[u8],
+  ) -> Result<NewCredential, AppError> {
+    self.check_client_data(client_data, "webauthn.create")?;
+    let (attestation, _) =
+      Cbor::decode(attestation_object).ok_or_else(|| reject("invalid attestationObject"))?;
+    let Some(Cbor::Bytes(auth_data)) = attestation.get(&Cbor::Text("authData".to_string())) else {
+      return Err(reject("missing authData"));
+    };
+    let data = AuthenticatorData::parse(auth_data)?;
+    self.check_authenticator_data(&data)?;
+    let (credential_id, public_key) = data
+      .attested
+      .ok_or_else(|| reject("missing attested credential data"))?;
+    CoseKey::parse(&public_key)?;
+    Ok(NewCredential {
+      credential_id,
+      public_key,
+      sign_count: data.sign_count,
+    })
+  }
+
+  /// Checks a `navigator.credentials.get()` response with the stored key of the credential,
+  /// returns the new signature counter
+  pub fn verify_assertion(
+    &self,
+    client_data_json: &[u8],
+    client_data: &ClientData,
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+  ) -> Result<u32, AppError> {
+    self.check_client_data(client_data, "webauthn.get")?;
+    let data = AuthenticatorData::parse(authenticator_data)?;
+    self.check_authenticator_data(&data)?;
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+    if !CoseKey::parse(public_key)?.verify(&message, signature) {
+      return Err(reject("invalid signature"));
+    }
+    Ok(data.sign_count)
+  }
+}
+
+struct AuthenticatorData<'a> {
+  rp_id_hash: &'a [u8],
+  flags: u8,
+  sign_count: u32,
+  /// Credential id and COSE_Key
+  attested: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> AuthenticatorData<'a> {
+  fn parse(data: &'a [u8]) -> Result<Self, AppError> {
+    let invalid = || reject("invalid authenticatorData");
+    if data.len() < 37 {
+      return Err(invalid());
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+    let attested = if flags & FLAG_ATTESTED_DATA != 0 {
+      // aaguid (16 bytes), credential id length (2 bytes), credential id, COSE_Key
+      let rest = data.get(37 + 16..).ok_or_else(invalid)?;
+      let len = usize::from(u16::from_be_bytes([
+        *rest.first().ok_or_else(invalid)?,
+        *rest.get(1).ok_or_else(invalid)?,
+      ]));
+      let credential_id = rest.get(2..2 + len).ok_or_else(invalid)?;
+      let (_, key_len) = Cbor::decode(&rest[2 + len..]).ok_or_else(invalid)?;
+      let public_key = &rest[2 + len..2 + len + key_len];
+      Some((credential_id.to_vec(), public_key.to_vec()))
+    } else {
+      None
+    };
+    Ok(Self {
+      rp_id_hash: &data[..32],
+      flags,
+      sign_count,
+      attested,
+    })
+  }
+}
+
+enum CoseKey {
+  Es256 { x: Vec<u8>, y: Vec<u8> },
+  Ed25519 { x: Vec<u8> },
+  Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+  fn parse(bytes: &[u8]) -> Result<Self, AppError> {
+    let invalid = || reject("invalid or unsupported public key");
+    let (key, _) = Cbor::decode(bytes).ok_or_else(invalid)?;
+    let int = |label: i128| match key.get(&Cbor::Int(label)) {
+      Some(Cbor::Int(value)) => Some(*value),
+      _ => None,
+    };
+    let bytes = |label: i128| match key.get(&Cbor::Int(label)) {
+      Some(Cbor::Bytes(value)) => Some(value.clone()),
+      _ => None,
+    };
+    // kty (1), alg (3), crv (-1) and the key parameters
+    match (int(1), int(3)) {
+      (Some(2), Some(-7)) if int(-1) == Some(1) => {
+        let (x, y) = (
+          bytes(-2).ok_or_else(invalid)?,
+          bytes(-3).ok_or_else(invalid)?,
+        );
+        if x.len() != 32 || y.len() != 32 {
+          return Err(invalid());
+        }
+        Ok(Self::Es256 { x, y })
+      }
+      (Some(1), Some(-8)) if int(-1) == Some(6) => {
+        let x = bytes(-2).filter(|x| x.len() == 32).ok_or_else(invalid)?;
+        Ok(Self::Ed25519 { x })
+      }
+      (Some(3), Some(-257)) => Ok(Self::Rs256 {
+        n: bytes(-1).ok_or_else(invalid)?,
+        e: bytes(-2).ok_or_else(invalid)?,
+      }),
+      _ => Err(invalid()),
+    }
+  }
+
+  fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+    match self {
+      Self::Es256 { x, y } => {
+        let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, point)
+          .verify(message, signature)
+          .is_ok()
+      }
+      Self::Ed25519 { x } => UnparsedPublicKey::new(&ED25519, x)
+        .verify(message, signature)
+        .is_ok(),
+      Self::Rs256 { n, e } => RsaPublicKeyComponents { n, e }
+        .verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+        .is_ok(),
+    }
+  }
+}
+
+/// The subset of CBOR authenticators use: no floats, tags or indefinite lengths
+#[derive(Debug, Clone, PartialEq)]
+enum Cbor {
+  Int(i128),
+  Bytes(Vec<u8>),
+  Text(String),
+  Array(Vec<Cbor>),
+  Map(Vec<(Cbor, Cbor)>),
+  Bool(bool),
+  Null,
+}
+
+impl Cbor {
+  /// The first value of `data` and its length in bytes
+  fn decode(data: &[u8]) -> Option<(Self, usize)> {
+    let mut reader = CborReader { data, pos: 0 };
+    let value = reader.value(CBOR_MAX_DEPTH)?;
+    Some((value, reader.pos))
+  }
+
+  fn get(&self, key: &Cbor) -> Option<&Cbor> {
+    match self {
+      Self::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+}
+
+struct CborReader<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> CborReader<'a> {
+  fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+    let end = self.pos.checked_add(len)?;
+    let bytes = self.data.get(self.pos..end)?;
+    self.pos = end;
+    Some(bytes)
+  }
+
+  fn value(&mut self, depth: u8) -> Option<Cbor> {
+    let depth = depth.checked_sub(1)?;
+    let head = self.take(1)?[0];
+    let (major, info) = (head >> 5, head & 0x1f);
+    if major == 7 {
+      return match info {
+        20 => Some(Cbor::Bool(false)),
+        21 => Some(Cbor::Bool(true)),
+        22 | 23 => Some(Cbor::Null),
+        _ => None,
+      };
+    }
+    let arg = match info {
+      0..=23 => u64::from(info),
+      24 => u64::from(self.take(1)?[0]),
+      25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().ok()?)),
+      26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().ok()?)),
+      27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+      _ => return None,
+    };
+    let len = || usize::try_from(arg).ok();
+    match major {
+      0 => Some(Cbor::Int(i128::from(arg))),
+      1 => Some(Cbor::Int(-1 - i128::from(arg))),
+      2 => Some(Cbor::Bytes(self.take(len()?)?.to_vec())),
+      3 => Some(Cbor::Text(
+        String::from_utf8(self.take(len()?)?.to_vec()).ok()?,
+      )),
+      4 => (0..len()?)
+        .map(|_| self.value(depth))
+        .collect::<Option<_>>()
+        .map(Cbor::Array),
+      5 => (0..len()?)
+        .map(|_| Some((self.value(depth)?, self.value(depth)?)))
+        .collect::<Option<_>>()
+        .map(Cbor::Map),
+      _ => None,
+    }
+  }
+}
+
+/// A software authenticator holding one credential, answering ceremonies as a browser
+/// would encode them. Its fields can be changed to produce invalid responses
+#[cfg(test)]
+pub mod testing {
+  use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+  };
+  use serde_json::json;
+
+  use super::*;
+
+  #[derive(Debug, Clone, Copy)]
+  pub enum KeyKind {
+    Es256,
+    Ed25519,
+  }
+
+  enum Key {
+    Es256(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+  }
+
+  pub struct SoftAuthenticator {
+    key: Key,
+    rng: SystemRandom,
+    pub credential_id: Vec<u8>,
+    /// Hashed into the authenticator data
+    pub rp_id: String,
+    pub origin: String,
+    pub cross_origin: bool,
+    pub flags: u8,
+    /// Incremented before each assertion
+    pub sign_count: u32,
+  }
+
+  /// `navigator.credentials.create()` response
+  pub struct Registration {
+    pub client_data_json: Vec<u8>,
+    pub attestation_object: Vec<u8>,
+  }
+
+  /// `navigator.credentials.get()` response
+  pub struct Assertion {
+    pub client_data_json: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub signature: Vec<u8>,
+  }
+
+  pub(super) fn cbor(value: &Cbor) -> Vec<u8> {
+    fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+      let major = major << 5;
+      match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend([major | 24, arg as u8]),
+        0x100..=0xffff => {
+          out.push(major | 25);
+          out.extend((arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+          out.push(major | 26);
+          out.extend((arg as u32).to_be_bytes());
+        }
+        _ => {
+          out.push(major | 27);
+          out.extend(arg.to_be_bytes());
+        }
+      }
+    }
+    fn write(out: &mut Vec<u8>, value: &Cbor) {
+      match value {
+        Cbor::Int(n) if *n >= 0 => head(out, 0, *n as u64),
+        Cbor::Int(n) => head(out, 1, (-1 - n) as u64),
+        Cbor::Bytes(bytes) => {
+          head(out, 2, bytes.len() as u64);
+          out.extend(bytes);
+        }
+        Cbor::Text(text) => {
+          head(out, 3, text.len() as u64);
+          out.extend(text.as_bytes());
+        }
+        Cbor::Array(items) => {
+          head(out, 4, items.len() as u64);
+          items.iter().for_each(|item| write(out, item));
+        }
+        Cbor::Map(entries) => {
+          head(out, 5, entries.len() as u64);
+          for (key, value) in entries {
+            write(out, key);
+            write(out, value);
+          }
+        }
+        Cbor::Bool(value) => out.push(0xf4 | u8::from(*value)),
+        Cbor::Null => out.push(0xf6),
+      }
+    }
+    let mut out = vec![];
+    write(&mut out, value);
+    out
+  }
+
+  /// The attestation object of a `none` attestation
+  pub fn attestation_object(auth_data: Vec<u8>) -> Vec<u8> {
+    let text = |s: &str| Cbor::Text(s.to_string());
+    cbor(&Cbor::Map(vec![
+      (text("fmt"), text("none")),
+      (text("attStmt"), Cbor::Map(vec![])),
+      (text("authData"), Cbor::Bytes(auth_data)),
+    ]))
+  }
+
+  impl SoftAuthenticator {
+    /// A credential for `rp_id`, used from `origin`, with the user present and verified
+    pub fn new(kind: KeyKind, rp_id: &str, origin: &str) -> Self {
+      let rng = SystemRandom::new();
+      let key = match kind {
+        KeyKind::Es256 => {
+          let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+          Key::Es256(
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+              .unwrap(),
+          )
+        }
+        KeyKind::Ed25519 => {
+          let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+          Key::Ed25519(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap())
+        }
+      };
+      let mut credential_id = vec![0; 16];
+      rng.fill(&mut credential_id).unwrap();
+      Self {
+        key,
+        rng,
+        credential_id,
+        rp_id: rp_id.to_string(),
+        origin: origin.to_string(),
+        cross_origin: false,
+        flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+        sign_count: 0,
+      }
+    }
+
+    /// The public key as COSE_Key
+    pub fn cose_key(&self) -> Vec<u8> {
+      let int = |n: i128| Cbor::Int(n);
+      let entries = match &self.key {
+        Key::Es256(key) => {
+          let point = key.public_key().as_ref();
+          vec![
+            (int(1), int(2)),
+            (int(3), int(-7)),
+            (int(-1), int(1)),
+            (int(-2), Cbor::Bytes(point[1..33].to_vec())),
+            (int(-3), Cbor::Bytes(point[33..].to_vec())),
+          ]
+        }
+        Key::Ed25519(key) => vec![
+          (int(1), int(1)),
+          (int(3), int(-8)),
+          (int(-1), int(6)),
+          (int(-2), Cbor::Bytes(key.public_key().as_ref().to_vec())),
+        ],
+      };
+      cbor(&Cbor::Map(entries))
+    }
+
+    pub fn client_data_json(&self, kind: &str, challenge: &str) -> Vec<u8> {
+      json!({
+        "type": kind,
+        "challenge": challenge,
+        "origin": self.origin,
+        "crossOrigin": self.cross_origin,
+      })
+      .to_string()
+      .into_bytes()
+    }
+
+    /// Authenticator data, with the attested credential data of a registration
+    pub fn authenticator_data(&self, attested: bool) -> Vec<u8> {
+      let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+      data.push(self.flags | if attested { FLAG_ATTESTED_DATA } else { 0 });
+      data.extend(self.sign_count.to_be_bytes());
+      if attested {
+        // AAGUID, zero for a `none` attestation
+        data.extend([0; 16]);
+        data.extend((self.credential_id.len() as u16).to_be_bytes());
+        data.extend(&self.credential_id);
+        data.extend(self.cose_key());
+      }
+      data
+    }
+
+    pub fn create(&self, challenge: &str) -> Registration {
+      Registration {
+        client_data_json: self.client_data_json("webauthn.create", challenge),
+        attestation_object: attestation_object(self.authenticator_data(true)),
+      }
+    }
+
+    pub fn get(&mut self, challenge: &str) -> Assertion {
+      self.sign_count += 1;
+      let client_data_json = self.client_data_json("webauthn.get", challenge);
+      let authenticator_data = self.authenticator_data(false);
+      let mut message = authenticator_data.clone();
+      message.extend(Sha256::digest(&client_data_json));
+      let signature = match &self.key {
+        Key::Es256(key) => key.sign(&self.rng, &message).unwrap().as_ref().to_vec(),
+        Key::Ed25519(key) => key.sign(&message).as_ref().to_vec(),
+      };
+      Assertion {
+        client_data_json,
+        authenticator_data,
+        signature,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    testing::{attestation_object, cbor, KeyKind, SoftAuthenticator},
+    *,
+  };
+
+  const RP_ID: &str = "example.com";
+  const ORIGIN: &str = "https://example.com";
+
+  fn rp() -> RelyingParty {
+    RelyingParty {
+      id: RP_ID.to_string(),
+      name: "Example".to_string(),
+      origins: vec![ORIGIN.to_string()],
+    }
+  }
+
+  fn authenticator(kind: KeyKind) -> SoftAuthenticator {
+    SoftAuthenticator::new(kind, RP_ID, ORIGIN)
+  }
+
+  fn register(auth: &SoftAuthenticator) -> Result<NewCredential, AppError> {
+    let registration = auth.create("challenge");
+    let client_data = ClientData::parse(&registration.client_data_json)?;
+    rp().verify_registration(&client_data, &registration.attestation_object)
+  }
+
+  fn assert(auth: &mut SoftAuthenticator, public_key: &[u8]) -> Result<u32, AppError> {
+    let assertion = auth.get("challenge");
+    let client_data = ClientData::parse(&assertion.client_data_json)?;
+    rp().verify_assertion(
+      &assertion.client_data_json,
+      &client_data,
+      &assertion.authenticator_data,
+      &assertion.signature,
+      public_key,
+    )
+  }
+
+  #[test]
+  fn registers_and_asserts_with_es256_and_ed25519() {
+    for kind in [KeyKind::Es256, KeyKind::Ed25519] {
+      let mut auth = authenticator(kind);
+      let credential = register(&auth).unwrap();
+      assert_eq!(credential.credential_id, auth.credential_id);
+      assert_eq!(credential.public_key, auth.cose_key());
+      assert_eq!(credential.sign_count, 0);
+      assert_eq!(assert(&mut auth, &credential.public_key).unwrap(), 1);
+      assert_eq!(assert(&mut auth, &credential.public_key).unwrap(), 2);
+    }
+  }
+
+  #[test]
+  fn refuses_a_signature_of_another_key_or_message() {
+    let mut auth = authenticator(KeyKind::Es256);
+    let other = authenticator(KeyKind::Es256);
+    assert!(assert(&mut auth, &other.cose_key()).is_err());
+    let public_key = auth.cose_key();
+    let mut assertion = auth.get("challenge");
+    assertion.authenticator_data[36] ^= 1;
+    let client_data = ClientData::parse(&assertion.client_data_json).unwrap();
+    let verified = rp().verify_assertion(
+      &assertion.client_data_json,
+      &client_data,
+      &assertion.authenticator_data,
+      &assertion.signature,
+      &public_key,
+    );
+    assert!(verified.is_err());
+  }
+
+  #[test]
+  fn refuses_another_relying_party() {
+    let mut auth = authenticator(KeyKind::Ed25519);
+    let public_key = register(&auth).unwrap().public_key;
+    auth.rp_id = "example.org".to_string();
+    assert!(register(&auth).is_err());
+    assert!(assert(&mut auth, &public_key).is_err());
+  }
+
+  #[test]
+  fn requires_the_user_present_and_verified() {
+    for flags in [0, FLAG_USER_PRESENT, FLAG_USER_VERIFIED] {
+      let mut auth = authenticator(KeyKind::Es256);
+      let public_key = auth.cose_key();
+      auth.flags = flags;
+      assert!(register(&auth).is_err(), "flags {flags:#x}");
+      assert!(assert(&mut auth, &public_key).is_err(), "flags {flags:#x}");
+    }
+  }
+
+  #[test]
+  fn refuses_other_origins_and_cross_origin_frames() {
+    let mut auth = authenticator(KeyKind::Es256);
+    let public_key = auth.cose_key();
+    for origin in [
+      "https://evil.example",
+      "http://example.com",
+      "https://example.com:8443",
+    ] {
+      auth.origin = origin.to_string();
+      assert!(register(&auth).is_err(), "{origin}");
+      assert!(assert(&mut auth, &public_key).is_err(), "{origin}");
+    }
+    auth.origin = ORIGIN.to_string();
+    auth.cross_origin = true;
+    assert!(register(&auth).is_err());
+    assert!(assert(&mut auth, &public_key).is_err());
+  }
+
+  #[test]
+  fn refuses_the_other_ceremony() {
+    let mut auth = authenticator(KeyKind::Es256);
+    let public_key = auth.cose_key();
+    // A registration answered with the client data of an assertion
+    let registration = auth.create("challenge");
+    let client_data =
+      ClientData::parse(&auth.client_data_json("webauthn.get", "challenge")).unwrap();
+    assert!(rp()
+      .verify_registration(&client_data, &registration.attestation_object)
+      .is_err());
+    // An assertion signed over the client data of a registration
+    let client_data_json = auth.client_data_json("webauthn.create", "challenge");
+    let mut assertion = auth.get("challenge");
+    assertion.client_data_json = client_data_json;
+    let client_data = ClientData::parse(&assertion.client_data_json).unwrap();
+    assert!(rp()
+      .verify_assertion(
+        &assertion.client_data_json,
+        &client_data,
+        &assertion.authenticator_data,
+        &assertion.signature,
+        &public_key,
+      )
+      .is_err());
+  }
+
+  #[test]
+  fn refuses_truncated_attestation_objects() {
+    let auth = authenticator(KeyKind::Es256);
+    let registration = auth.create("challenge");
+    let client_data = ClientData::parse(&registration.client_data_json).unwrap();
+    let object = &registration.attestation_object;
+    for len in 0..object.len() {
+      assert!(rp()
+        .verify_registration(&client_data, &object[..len])
+        .is_err());
+    }
+    // Truncated inside the authenticator data, the CBOR around it still well formed
+    let auth_data = auth.authenticator_data(true);
+    for len in 0..auth_data.len() {
+      let object = attestation_object(auth_data[..len].to_vec());
+      assert!(rp().verify_registration(&client_data, &object).is_err());
+    }
+  }
+
+  #[test]
+  fn refuses_cbor_nested_too_deep() {
+    let nested = (0..CBOR_MAX_DEPTH).fold(Cbor::Array(vec![]), |inner, _| Cbor::Array(vec![inner]));
+    assert!(Cbor::decode(&cbor(&nested)).is_none());
+    let within = (1..CBOR_MAX_DEPTH).fold(Cbor::Array(vec![]), |inner, _| Cbor::Array(vec![inner]));
+    assert_eq!(Cbor::decode(&cbor(&within)).unwrap().0, within);
+    // Far deeper than any stack would allow to recurse through
+    let mut deep = vec![0x81; 1_000_000];
+    deep.push(0x80);
+    assert!(Cbor::decode(&deep).is_none());
+    // Lengths far past the end of the data
+    for head in [
+      &[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff][..],
+      &[0x5a, 0xff, 0xff, 0xff, 0xff],
+      &[0xbb, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+    ] {
+      assert!(Cbor::decode(head).is_none());
+    }
+    // The attestation statement nested too deep
+    let auth = authenticator(KeyKind::Es256);
+    let registration = auth.create("challenge");
+    let client_data = ClientData::parse(&registration.client_data_json).unwrap();
+    let text = |s: &str| Cbor::Text(s.to_string());
+    let object = cbor(&Cbor::Map(vec![
+      (text("fmt"), text("none")),
+      (text("attStmt"), nested),
+      (text("authData"), Cbor::Bytes(auth.authenticator_data(true))),
+    ]));
+    assert!(rp().verify_registration(&client_data, &object).is_err());
+  }
+
+  #[test]
+  fn refuses_a_credential_id_overrunning_the_authenticator_data() {
+    let auth = authenticator(KeyKind::Es256);
+    let registration = auth.create("challenge");
+    let client_data = ClientData::parse(&registration.client_data_json).unwrap();
+    let auth_data = auth.authenticator_data(true);
+    // Credential id length, after the rp id hash, flags, counter and AAGUID
+    let at = 32 + 1 + 4 + 16;
+    let rest = auth_data.len() - at - 2;
+    for len in [rest as u16, rest as u16 + 1, u16::MAX] {
+      let mut data = auth_data.clone();
+      data[at..at + 2].copy_from_slice(&len.to_be_bytes());
+      let object = attestation_object(data);
+      assert!(
+        rp().verify_registration(&client_data, &object).is_err(),
+        "{len}"
+      );
+    }
+  }
+}
@@ -0,0 +1,170 @@
+//! listener
+
+use std::net::{IpAddr, SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::{
+  io::ErrorKind,
+  os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+  },
+};
+
+use listenfd::ListenFd;
+
+use crate::{config::EnvConfig, error::AppError};
+
+pub enum Listener {
+  Tcp(TcpListener),
+  #[cfg(unix)]
+  Unix(UnixListener),
+}
+
+/// Binds a listen address: `0.0.0.0:3000`, `[::]:3000` or `unix:/run/app.sock`
+pub fn bind(addr: &str) -> Result<Listener, AppError> {
+  if let Some(path) = addr.strip_prefix("unix:") {
+    return bind_unix(path);
+  }
+  let addr: SocketAddr = addr.parse().map_err(|err| {
+    tracing::error!("Invalid listen address {addr}: {err}");
+    AppError::Error
+  })?;
+  Ok(Listener::Tcp(TcpListener::bind(addr)?))
+}
+
+/// A socket file left behind by a previous run would make bind fail, it is removed. Refuses
+/// to touch anything else at `path`: a file that is not a socket, or the socket of a running
+/// instance
+#[cfg(unix)]
+fn bind_unix(path: &str) -> Result<Listener, AppError> {
+  if let Ok(metadata) = std::fs::symlink_metadata(path) {
+    if !metadata.file_type().is_socket() {
+      tracing::error!("Refusing to listen on {path}: the file exists and is not a socket");
+      return Err(AppError::Error);
+    }
+    match UnixStream::connect(path) {
+      Err(err) if err.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+      Ok(_) => {
+        tracing::error!("Refusing to listen on {path}: another process is listening on it");
+        return Err(AppError::Error);
+      }
+      Err(err) => {
+        tracing::error!("Refusing to listen on {path}: {err}");
+        return Err(AppError::Error);
+      }
+    }
+  }
+  Ok(Listener::Unix(UnixListener::bind(path)?))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(path: &str) -> Result<Listener, AppError> {
+  tracing::error!("Unix domain sockets are not supported on this platform: {path}");
+  Err(AppError::Error)
+}
+
+/// Takes the sockets passed by the service manager (systemd `LISTEN_FDS`)
+pub fn inherited() -> Vec<Listener> {
+  let mut fds = ListenFd::from_env();
+  let mut listeners = vec![];
+  for idx in 0..fds.len() {
+    if let Ok(Some(listener)) = fds.take_tcp_listener(idx) {
+      listeners.push(Listener::Tcp(listener));
+      continue;
+    }
+    #[cfg(unix)]
+    if let Ok(Some(listener)) = fds.take_unix_listener(idx) {
+      listeners.push(Listener::Unix(listener));
+      continue;
+    }
+    tracing::warn!("Ignoring inherited file descriptor #{idx}");
+  }
+  listeners
+}
+
+fn bind_all(addrs: &[String], host: IpAddr, port: u16) -> Result<Vec<Listener>, AppError> {
+  if addrs.is_empty() {
+    return Ok(vec![Listener::Tcp(TcpListener::bind((host, port))?)]);
+  }
+  addrs.iter().map(|addr| bind(addr)).collect()
+}
+
+/// Plain HTTP listeners: inherited sockets, then `listen`, then `host:port`
+pub fn http_listeners(config: &EnvConfig) -> Result<Vec<Listener>, AppError> {
+  let listeners = inherited();
+  if !listeners.is_empty() {
+    tracing::info!("Using {} inherited socket(s)", listeners.len());
+    return Ok(listeners);
+  }
+  bind_all(&config.listen, config.host, config.port)
+}
+
+/// HTTPS listeners: `tls_listen`, then `host:tls_port`. TLS is only served over TCP
+pub fn https_listeners(config: &EnvConfig) -> Result<Vec<TcpListener>, AppError> {
+  bind_all(&config.tls_listen, config.host, config.tls_port)?
+    .into_iter()
+    .map(|listener| match listener {
+      Listener::Tcp(listener) => Ok(listener),
+      #[cfg(unix)]
+      Listener::Unix(_) => {
+        tracing::error!("TLS over unix domain sockets is not supported");
+        Err(AppError::Error)
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use helpers::uuid::{uuid, Alphabet};
+
+  use super::*;
+
+  fn temp_path(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("listener_{}", uuid(&Alphabet::DEFAULT, 16)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).display().to_string()
+  }
+
+  #[test]
+  fn binds_tcp_addresses() {
+    for addr in ["127.0.0.1:0", "[::1]:0"] {
+      let Ok(Listener::Tcp(listener)) = bind(addr) else {
+        // No IPv6 in some sandboxes
+        assert_eq!(addr, "[::1]:0");
+        continue;
+      };
+      assert_ne!(listener.local_addr().unwrap().port(), 0);
+    }
+    assert!(bind("localhost:3000").is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn replaces_a_stale_socket() {
+    let path = temp_path("app.sock");
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(matches!(
+      bind(&format!("unix:{path}")),
+      Ok(Listener::Unix(_))
+    ));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn refuses_a_live_socket() {
+    let path = temp_path("app.sock");
+    let _live = UnixListener::bind(&path).unwrap();
+    assert!(bind(&format!("unix:{path}")).is_err());
+    assert!(UnixStream::connect(&path).is_ok());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn refuses_to_remove_a_regular_file() {
+    let path = temp_path("app.conf");
+    std::fs::write(&path, "keep me").unwrap();
+    assert!(bind(&format!("unix:{path}")).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+  }
+}
@@ -21,6 +21,49 @@ fn en() -> TranslationMap {
   );
   m.insert("confirm registration", "Please click <a href=\"{url}\">{url}<a/> to confirm registration, the link is valid for 1 hour. If you are not registering, please ignore this email.");
   m.insert("Registration confirm mail send failed", "Registration confirm mail send failed, please {%- if isAdmin -%}check your mail configuration{%- else -%}check your email address and contact administrator{%- endif -%}.");
+  m.insert("Magic Link Mail", "Your login link");
+  m.insert("magic link login", "Please click <a href=\"{url}\">{url}</a> to log in, the link is valid for 15 minutes and can only be used once. If you did not ask to log in, please ignore this email.");
+  m.insert(
+    "Password too short",
+    "Password must be at least {min} characters",
+  );
+  m.insert(
+    "Password too few classes",
+    "Password must mix at least {min} of lowercase letters, uppercase letters, digits and symbols",
+  );
+  m.insert(
+    "Password same as account",
+    "Password must not be your email or nickname",
+  );
+  m.insert("Password too weak", "Password is too easy to guess");
+  m.insert(
+    "Password breached",
+    "Password has appeared in a data breach, please choose another one",
+  );
+  m.insert("Password Changed Mail", "Your password was changed");
+  m.insert("password changed notice", "The password of your account {email} was changed at {time} from {ip}. If this was not you, please reset your password right away and log out your other sessions.");
+  m.insert("Email Change Mail", "Confirm your new email address");
+  m.insert("confirm email change", "Please click <a href=\"{url}\">{url}</a> to use this address for your account, the link is valid for 1 hour. If you did not ask for it, please ignore this email.");
+  m.insert(
+    "Email Change Notice Mail",
+    "Your email address is being changed",
+  );
+  m.insert("email change notice", "Changing the email address of your account from {email} to {new_email} was requested at {time} from {ip}, it takes effect once confirmed from the new address. If this was not you, please change your password right away and log out your other sessions.");
+  m.insert(
+    "Passkey Removed Mail",
+    "A passkey was removed from your account",
+  );
+  m.insert("Account Deleted Mail", "Your account was deleted");
+  m.insert("account deleted notice", "Your account {email} was deleted at {time} from {ip}. If this was not you, please contact the administrator right away.");
+  m.insert("passkey removed notice", "The passkey {name} was removed from your account {email} at {time} from {ip}. If this was not you, please change your password right away and log out your other sessions.");
+  m.insert(
+    "Unsupported image",
+    "Unsupported image, please use a JPEG, PNG, GIF or WebP file",
+  );
+  m.insert(
+    "Email not verified",
+    "The provider has not verified this email, please register or log in, then link the provider",
+  );
   m
 }
 
@@ -32,9 +75,46 @@ fn zh_cn() -> TranslationMap {
   m.insert("TOKEN_EXPIRED", "密钥已过期");
   m.insert("TWO_FACTOR_AUTH_ERROR_DETAIL", "二步验证失败");
   m.insert("Unauthorized", "没有授权");
+  m.insert("Not found", "资源不存在");
+  m.insert("Invalid parameter", "参数错误");
+  m.insert("Identity provider error", "第三方登录失败");
+  m.insert("Passkey verification failed", "通行密钥验证失败");
   m.insert("Registration Confirm Mail", "【{name}】注册确认邮件");
   m.insert("confirm registration", "请点击 <a href='{url}'>{url}</a> 确认注册，链接有效时间为 1 个小时。如果不是你在注册，请忽略这封邮件。");
   m.insert("Registration confirm mail send failed", "注册确认邮件发送失败，请{%- if isAdmin -%}检查一下网站的邮件相关配置{% else %}确认你的邮箱输入无误并联系管理员{%- endif -%}。");
+  m.insert("Magic Link Mail", "你的登录链接");
+  m.insert("magic link login", "请点击 <a href='{url}'>{url}</a> 登录，链接有效时间为 15 分钟且只能使用一次。如果不是你在登录，请忽略这封邮件。");
+  m.insert("Password too short", "密码至少需要 {min} 个字符");
+  m.insert(
+    "Password too few classes",
+    "密码需要包含小写字母、大写字母、数字和符号中的至少 {min} 种",
+  );
+  m.insert("Password same as account", "密码不能与邮箱或昵称相同");
+  m.insert("Password too weak", "密码太容易被猜到");
+  m.insert("Password breached", "该密码已在数据泄露中出现，请换一个");
+  m.insert("Re-authentication required", "请先输入当前密码重新验证身份");
+  m.insert("Password Changed Mail", "你的密码已修改");
+  m.insert("password changed notice", "你的账号 {email} 的密码已于 {time} 从 {ip} 修改。如果不是你本人操作，请立即重置密码并退出其他登录会话。");
+  m.insert("Email Change Mail", "确认你的新邮箱");
+  m.insert("confirm email change", "请点击 <a href=\"{url}\">{url}</a> 将此邮箱用于你的账号，链接 1 小时内有效。如果不是你本人操作，请忽略此邮件。");
+  m.insert("Email Change Notice Mail", "你的邮箱正在被修改");
+  m.insert("email change notice", "你的账号邮箱于 {time} 从 {ip} 申请由 {email} 改为 {new_email}，新邮箱确认后生效。如果不是你本人操作，请立即修改密码并退出其他登录会话。");
+  m.insert("Passkey Removed Mail", "你的账号移除了一个通行密钥");
+  m.insert("Account Deleted Mail", "你的账号已删除");
+  m.insert(
+    "account deleted notice",
+    "你的账号 {email} 已于 {time} 从 {ip} 删除。如果不是你本人操作，请立即联系管理员。",
+  );
+  m.insert("passkey removed notice", "通行密钥 {name} 已于 {time} 从 {ip} 从你的账号 {email} 移除。如果不是你本人操作，请立即修改密码并退出其他登录会话。");
+  m.insert("File too large", "文件太大");
+  m.insert(
+    "Unsupported image",
+    "不支持的图片，请使用 JPEG、PNG、GIF 或 WebP 文件",
+  );
+  m.insert(
+    "Email not verified",
+    "第三方未验证该邮箱，请先注册或登录，再绑定第三方账号",
+  );
   m
 }
 
@@ -46,9 +126,46 @@ fn zh_tw() -> TranslationMap {
   m.insert("TOKEN_EXPIRED", "密鑰已過期");
   m.insert("TWO_FACTOR_AUTH_ERROR_DETAIL", "二步驗證失敗");
   m.insert("Unauthorized", "Unauthorized");
+  m.insert("Not found", "資源不存在");
+  m.insert("Invalid parameter", "參數錯誤");
+  m.insert("Identity provider error", "第三方登入失敗");
+  m.insert("Passkey verification failed", "通行金鑰驗證失敗");
   m.insert("Registration Confirm Mail", "『{name}』註冊確認郵件");
   m.insert("confirm registration", "請點擊 <a href=\"{url}\">{url}</a> 確認註冊，鏈接有效時間為 1 個小時。如果不是你在註冊，請忽略這封郵件。");
   m.insert("Registration confirm mail send failed", "註冊確認郵件發送失敗，{%- if isAdmin -%}檢查一下網站的郵件相關配置{% else %}確認你的郵箱輸入無誤後聯繫管理員{%- endif -%}。");
+  m.insert("Magic Link Mail", "你的登入鏈接");
+  m.insert("magic link login", "請點擊 <a href=\"{url}\">{url}</a> 登入，鏈接有效時間為 15 分鐘且只能使用一次。如果不是你在登入，請忽略這封郵件。");
+  m.insert("Password too short", "密碼至少需要 {min} 個字元");
+  m.insert(
+    "Password too few classes",
+    "密碼需要包含小寫字母、大寫字母、數字和符號中的至少 {min} 種",
+  );
+  m.insert("Password same as account", "密碼不能與郵箱或暱稱相同");
+  m.insert("Password too weak", "密碼太容易被猜到");
+  m.insert("Password breached", "該密碼已在資料外洩中出現，請換一個");
+  m.insert("Re-authentication required", "請先輸入目前密碼重新驗證身分");
+  m.insert("Password Changed Mail", "你的密碼已修改");
+  m.insert("password changed notice", "你的帳號 {email} 的密碼已於 {time} 從 {ip} 修改。如果不是你本人操作，請立即重設密碼並登出其他登入工作階段。");
+  m.insert("Email Change Mail", "確認你的新郵箱");
+  m.insert("confirm email change", "請點擊 <a href=\"{url}\">{url}</a> 將此郵箱用於你的帳號，連結 1 小時內有效。如果不是你本人操作，請忽略此郵件。");
+  m.insert("Email Change Notice Mail", "你的郵箱正在被修改");
+  m.insert("email change notice", "你的帳號郵箱於 {time} 從 {ip} 申請由 {email} 改為 {new_email}，新郵箱確認後生效。如果不是你本人操作，請立即修改密碼並登出其他登入工作階段。");
+  m.insert("Passkey Removed Mail", "你的帳號移除了一個密碼金鑰");
+  m.insert("Account Deleted Mail", "你的帳號已刪除");
+  m.insert(
+    "account deleted notice",
+    "你的帳號 {email} 已於 {time} 從 {ip} 刪除。如果不是你本人操作，請立即聯絡管理員。",
+  );
+  m.insert("passkey removed notice", "密碼金鑰 {name} 已於 {time} 從 {ip} 從你的帳號 {email} 移除。如果不是你本人操作，請立即修改密碼並登出其他登入工作階段。");
+  m.insert("File too large", "檔案太大");
+  m.insert(
+    "Unsupported image",
+    "不支援的圖片，請使用 JPEG、PNG、GIF 或 WebP 檔案",
+  );
+  m.insert(
+    "Email not verified",
+    "第三方未驗證該郵箱，請先註冊或登入，再綁定第三方帳號",
+  );
   m
 }
 
@@ -0,0 +1,270 @@
+//! logging
+use std::{
+  io::{self, Write},
+  path::Path,
+  sync::LazyLock,
+};
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use regex::{Captures, Regex};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use serde::Deserialize;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+  fmt::{self, MakeWriter},
+  layer::SubscriberExt,
+  util::SubscriberInitExt,
+  EnvFilter, Layer, Registry,
+};
+
+use crate::{config::EnvConfig, error::AppError};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+  /// Human readable
+  #[default]
+  Text,
+  /// One JSON object per line, for log shippers
+  Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+  #[default]
+  Daily,
+  Hourly,
+  /// Only rotate on `log_file_max_size`
+  Never,
+}
+
+/// Words ending the keys of credentials, as regex alternatives
+const SENSITIVE_KEYS: &[&str] = &[
+  "password",
+  "passwd",
+  "secret",
+  "token",
+  "authorization",
+  "api[_-]?key",
+  "cookie",
+];
+
+/// Values of credential keys, each alternative capturing the key and separator then the value.
+/// A key is one of [`SENSITIVE_KEYS`] or ends with one, as `client_secret` or `X-Reauth-Token`,
+/// so `token_count` or `password_policy` are kept:
+/// 1. a quoted string, `"token": "..."` or `password="..."`, whole whatever it contains
+/// 2. the same, escaped inside a JSON log line: `\"password\":\"...\"`
+/// 3. a header, `authorization: Bearer ...`, up to the end of the line (or of the JSON
+///    string holding it)
+/// 4. a bare value, `password=...` in a query string or a JSON number, up to a separator. The
+///    value is quoted when the key is, so a JSON line stays valid
+///
+/// Also matches across the ANSI styling of field names
+static SENSITIVE: LazyLock<Regex> = LazyLock::new(|| {
+  let ansi = r"(?:\x1b\[[0-9;]*m)*";
+  let key = format!(r"(?:{}){ansi}", SENSITIVE_KEYS.join("|"));
+  let separator = format!(r#"(?:\\?"{ansi})?\s*[:=]\s*{ansi}"#);
+  Regex::new(&format!(
+    r#"(?i)({key}{separator})"(?:[^"\\\n]|\\.)*"?|({key}{separator})\\"(?:[^"\\\n]|\\[^"\n])*\\"|({key}\s*:\s*{ansi})(?:[^"\\\n]|\\[^"\n])+|({key}{separator})(?:(?:bearer|basic)\s+)?[^\s"',;&}}\x1b]+"#
+  ))
+  .unwrap()
+});
+
+fn redact(line: &str) -> std::borrow::Cow<'_, str> {
+  SENSITIVE.replace_all(line, |caps: &Captures| {
+    let (prefix, quote) = match (caps.get(1), caps.get(2), caps.get(3), caps.get(4)) {
+      (Some(prefix), ..) => (prefix, "\""),
+      (_, Some(prefix), ..) => (prefix, r#"\""#),
+      (_, _, Some(prefix), _) => (prefix, ""),
+      (.., Some(prefix)) if prefix.as_str().contains(r#"\""#) => (prefix, r#"\""#),
+      (.., Some(prefix)) if prefix.as_str().contains('"') => (prefix, "\""),
+      (.., Some(prefix)) => (prefix, ""),
+      _ => unreachable!("every alternative captures its prefix"),
+    };
+    format!("{}{quote}[REDACTED]{quote}", prefix.as_str())
+  })
+}
+
+/// Writer redacting credentials from every formatted line before it reaches the output
+pub struct Redact<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redact<M> {
+  type Writer = RedactWriter<M::Writer>;
+
+  fn make_writer(&'a self) -> Self::Writer {
+    RedactWriter(self.0.make_writer())
+  }
+}
+
+pub struct RedactWriter<W>(W);
+
+impl<W: Write> Write for RedactWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    // The fmt layer writes each event with a single call
+    let line = String::from_utf8_lossy(buf);
+    self.0.write_all(redact(&line).as_bytes())?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()
+  }
+}
+
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+  W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+  let layer = fmt::layer()
+    .with_timer(fmt::time::LocalTime::rfc_3339())
+    .with_writer(Redact(writer));
+  match format {
+    LogFormat::Text => layer.with_ansi(ansi).boxed(),
+    LogFormat::Json => layer.json().flatten_event(true).boxed(),
+  }
+}
+
+/// Installs the global subscriber: stdout, the optional rolling `log_file` and the
+/// OpenTelemetry layer. The returned guard flushes the log file when dropped
+pub fn init(
+  config: &EnvConfig,
+  provider: &SdkTracerProvider,
+) -> Result<Option<WorkerGuard>, AppError> {
+  // `RUST_LOG` still wins, for one-off debugging
+  let filter = match EnvFilter::try_from_default_env() {
+    Ok(filter) => filter,
+    Err(_) => EnvFilter::try_new(&config.log_level).map_err(|e| {
+      eprintln!("Invalid LOG_LEVEL: {e}");
+      AppError::Error
+    })?,
+  };
+  let mut layers = vec![
+    tracing_opentelemetry::layer()
+      .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+      .boxed(),
+    fmt_layer(config.log_format, io::stdout, true),
+  ];
+  let mut guard = None;
+  if let Some(log_file) = &config.log_file {
+    if let Some(dir) = Path::new(log_file).parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut condition = RollingConditionBasic::new();
+    condition = match config.log_file_rotation {
+      LogRotation::Daily => condition.daily(),
+      LogRotation::Hourly => condition.hourly(),
+      LogRotation::Never => condition,
+    };
+    if let Some(max_size) = config.log_file_max_size {
+      condition = condition.max_size(max_size);
+    }
+    let appender = BasicRollingFileAppender::new(log_file, condition, config.log_file_max_files)?;
+    let (writer, file_guard) = tracing_appender::non_blocking(appender);
+    layers.push(fmt_layer(config.log_format, writer, false));
+    guard = Some(file_guard);
+  }
+  tracing_subscriber::registry()
+    .with(layers)
+    .with(filter)
+    .init();
+  Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn redacts_whole_json_string_values() {
+    assert_eq!(
+      redact(r#"{"email":"a@b.c","password":"correct horse, battery"}"#),
+      r#"{"email":"a@b.c","password":"[REDACTED]"}"#
+    );
+    assert_eq!(
+      redact(r#"{"token": "a \"quoted\" secret", "next": 1}"#),
+      r#"{"token": "[REDACTED]", "next": 1}"#
+    );
+  }
+
+  #[test]
+  fn redacts_json_escaped_in_a_json_log_line() {
+    assert_eq!(
+      redact(r#"{"message":"body {\"password\":\"correct horse\"}","level":"INFO"}"#),
+      r#"{"message":"body {\"password\":\"[REDACTED]\"}","level":"INFO"}"#
+    );
+  }
+
+  #[test]
+  fn json_numbers_stay_valid() {
+    let line = redact(r#"{"token":5,"api_key":null,"nested":{"secret":-1.5e3},"ok":true}"#);
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(json["token"], "[REDACTED]");
+    assert_eq!(json["api_key"], "[REDACTED]");
+    assert_eq!(json["nested"]["secret"], "[REDACTED]");
+    assert_eq!(json["ok"], true);
+    let line = redact(r#"{"message":"body {\"password\":42}","level":"INFO"}"#);
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    let message: serde_json::Value =
+      serde_json::from_str(&json["message"].as_str().unwrap()[5..]).unwrap();
+    assert_eq!(message["password"], "[REDACTED]");
+  }
+
+  #[test]
+  fn keeps_keys_merely_starting_like_a_credential() {
+    for line in [
+      r#"{"token_count":5,"tokens_total":7}"#,
+      "password_policy=strict secret_name=db",
+      "token_count: 5",
+    ] {
+      assert_eq!(redact(line), line);
+    }
+    assert_eq!(
+      redact("client_secret=abc X-Reauth-Token: xyz"),
+      "client_secret=[REDACTED] X-Reauth-Token: [REDACTED]"
+    );
+  }
+
+  #[test]
+  fn redacts_headers_up_to_the_end_of_the_line() {
+    assert_eq!(
+      redact("authorization: Bearer xyz abc\nnext line"),
+      "authorization: [REDACTED]\nnext line"
+    );
+    assert_eq!(redact("Cookie: sid=abc; theme=dark"), "Cookie: [REDACTED]");
+    assert_eq!(
+      redact(r#"{"message":"authorization: Basic dXNlcjpwYXNz","level":"INFO"}"#),
+      r#"{"message":"authorization: [REDACTED]","level":"INFO"}"#
+    );
+    assert_eq!(
+      redact(r#"{"authorization": "Bearer xyz abc"}"#),
+      r#"{"authorization": "[REDACTED]"}"#
+    );
+  }
+
+  #[test]
+  fn redacts_query_string_values() {
+    assert_eq!(
+      redact("GET /login?token=abc%20def&next=/home HTTP/1.1"),
+      "GET /login?token=[REDACTED]&next=/home HTTP/1.1"
+    );
+    assert_eq!(
+      redact("authorization=Bearer xyz"),
+      "authorization=[REDACTED]"
+    );
+  }
+
+  #[test]
+  fn redacts_values_containing_spaces() {
+    assert_eq!(
+      redact(r#"login password="correct horse battery" user=alice"#),
+      r#"login password="[REDACTED]" user=alice"#
+    );
+    let styled = "\x1b[3mpassword\x1b[0m\x1b[2m=\x1b[0m\"correct horse\" user=alice";
+    assert_eq!(
+      redact(styled),
+      "\x1b[3mpassword\x1b[0m\x1b[2m=\x1b[0m\"[REDACTED]\" user=alice"
+    );
+  }
+}
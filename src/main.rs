@@ -1,6 +1,3 @@
-use tracing::level_filters::LevelFilter;
-use tracing_subscriber::{filter, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-
 mod api;
 mod app;
 mod components;
@@ -8,24 +5,27 @@ mod config;
 mod entity;
 mod error;
 mod helpers;
+mod listener;
 mod locales;
+mod logging;
+mod metrics;
+mod middlewares;
 mod repository;
 mod response;
+mod storage;
+mod telemetry;
+mod tls;
+#[allow(dead_code)]
 mod traits;
 
 #[actix_web::main]
 async fn main() -> Result<(), error::AppError> {
-  let target_filter = filter::Targets::new()
-    .with_default(LevelFilter::TRACE)
-    .with_target("sqlx::query", LevelFilter::OFF)
-    .with_target("rustls", LevelFilter::OFF);
-  let env_filter = EnvFilter::try_from_default_env()
-    .or_else(|_| EnvFilter::try_new("debug"))
-    .unwrap();
-  tracing_subscriber::registry()
-    .with(tracing_subscriber::fmt::layer().with_timer(fmt::time::LocalTime::rfc_3339()))
-    .with(target_filter)
-    .with(env_filter)
-    .init();
-  app::start().await
+  let config = config::EnvConfig::load_env()?;
+  let provider = telemetry::tracer_provider(&config)?;
+  let _guard = logging::init(&config, &provider)?;
+  let result = app::start(config).await;
+  if let Err(e) = provider.shutdown() {
+    tracing::error!("{:#?}", e);
+  }
+  result
 }
@@ -0,0 +1,186 @@
+//! Prometheus metrics
+use std::sync::LazyLock;
+
+use actix_web::{web::Data, HttpRequest, HttpResponse};
+use prometheus::{
+  histogram_opts, opts, Encoder, HistogramVec, IntCounterVec, IntGaugeVec, Registry, TextEncoder,
+};
+
+use ring::constant_time;
+
+use crate::{app::AppState, helpers::header::extract_token};
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: T) -> T {
+  REGISTRY.register(Box::new(metric.clone())).unwrap();
+  metric
+}
+
+pub static HTTP_REQUESTS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+  register(
+    IntCounterVec::new(
+      opts!(
+        "http_requests_total",
+        "HTTP requests by route template and status"
+      ),
+      &["method", "route", "status"],
+    )
+    .unwrap(),
+  )
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+  register(
+    HistogramVec::new(
+      histogram_opts!(
+        "http_request_duration_seconds",
+        "HTTP request latency by route template and status"
+      ),
+      &["method", "route", "status"],
+    )
+    .unwrap(),
+  )
+});
+
+pub static DB_POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+  register(
+    IntGaugeVec::new(
+      opts!("db_pool_connections", "Database pool connections by state"),
+      &["state"],
+    )
+    .unwrap(),
+  )
+});
+
+pub static RATE_LIMIT_REJECTIONS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+  register(
+    IntCounterVec::new(
+      opts!(
+        "rate_limit_rejections_total",
+        "Requests rejected by rate limit policy"
+      ),
+      &["policy"],
+    )
+    .unwrap(),
+  )
+});
+
+pub static LOGINS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+  register(
+    IntCounterVec::new(
+      opts!("logins_total", "Login attempts by result"),
+      &["result"],
+    )
+    .unwrap(),
+  )
+});
+
+pub static EMAILS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+  register(
+    IntCounterVec::new(
+      opts!("emails_total", "Outbound emails by result"),
+      &["result"],
+    )
+    .unwrap(),
+  )
+});
+
+/// Registers every metric, and the fixed label values, so they are exported before their
+/// first event
+pub fn init() {
+  LazyLock::force(&HTTP_REQUESTS_TOTAL);
+  LazyLock::force(&HTTP_REQUEST_DURATION);
+  LazyLock::force(&DB_POOL_CONNECTIONS);
+  LazyLock::force(&RATE_LIMIT_REJECTIONS_TOTAL);
+  for result in ["success", "failure"] {
+    LOGINS_TOTAL.with_label_values(&[result]);
+  }
+  for result in ["sent", "failed"] {
+    EMAILS_TOTAL.with_label_values(&[result]);
+  }
+}
+
+/// `GET /metrics` in the Prometheus text format. Needs `Authorization: Bearer <metrics_token>`
+/// when a token is configured
+pub async fn handler(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  if let Some(metrics_token) = &state.metrics_token {
+    let token = extract_token(&req).unwrap_or_default();
+    if constant_time::verify_slices_are_equal(token.as_bytes(), metrics_token.as_bytes()).is_err() {
+      return HttpResponse::Unauthorized().finish();
+    }
+  }
+  if let Some((size, idle)) = state.repo.pool_status() {
+    DB_POOL_CONNECTIONS
+      .with_label_values(&["idle"])
+      .set(idle as i64);
+    DB_POOL_CONNECTIONS
+      .with_label_values(&["in_use"])
+      .set((size as usize).saturating_sub(idle) as i64);
+  }
+  let encoder = TextEncoder::new();
+  let mut buffer = vec![];
+  if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
+    tracing::error!("{:#?}", e);
+    return HttpResponse::InternalServerError().finish();
+  }
+  HttpResponse::Ok()
+    .content_type(encoder.format_type())
+    .body(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web, App,
+  };
+
+  use crate::app::testing;
+
+  use super::*;
+
+  async fn status(metrics_token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+    let vars: Vec<_> = metrics_token
+      .map(|t| ("METRICS_TOKEN", t))
+      .into_iter()
+      .collect();
+    let state = testing::app_state(&vars).await;
+    let app = test::init_service(
+      App::new()
+        .app_data(Data::new(state))
+        .route("/metrics", web::get().to(handler)),
+    )
+    .await;
+    let mut req = TestRequest::get().uri("/metrics");
+    if let Some(authorization) = authorization {
+      req = req.insert_header(("Authorization", authorization));
+    }
+    test::call_service(&app, req.to_request()).await.status()
+  }
+
+  #[actix_web::test]
+  async fn metrics_need_the_configured_token() {
+    let token = Some("s3cret");
+    assert_eq!(status(token, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+      status(token, Some("Bearer wrong")).await,
+      StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+      status(token, Some("Bearer s3cre")).await,
+      StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+      status(token, Some("s3cret")).await,
+      StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(status(token, Some("Bearer s3cret")).await, StatusCode::OK);
+  }
+
+  #[actix_web::test]
+  async fn metrics_are_open_without_a_token() {
+    assert_eq!(status(None, None).await, StatusCode::OK);
+  }
+}
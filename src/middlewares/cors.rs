@@ -0,0 +1,137 @@
+use actix_cors::Cors;
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// Checks whether `origin` is allowed by `pattern`.
+///
+/// `*` matches any origin, `https://*.example.com` matches any subdomain of
+/// `example.com` (but not `example.com` itself), anything else must match exactly.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+  if pattern == "*" {
+    return true;
+  }
+  match pattern.split_once("://*.") {
+    Some((scheme, domain)) => origin
+      .strip_prefix(scheme)
+      .and_then(|rest| rest.strip_prefix("://"))
+      .and_then(|host| host.strip_suffix(domain))
+      .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+    None => pattern.eq_ignore_ascii_case(origin),
+  }
+}
+
+/// Refuses settings that would let any site make credentialed calls: `*` is answered with
+/// the origin of the request, so with credentials it trusts every origin
+pub fn check(config: &EnvConfig) -> Result<(), AppError> {
+  let any_origin = config.cors_allowed_origins.iter().any(|p| p == "*");
+  if !config.cors_permissive && any_origin && config.cors_supports_credentials {
+    tracing::error!("CORS_ALLOWED_ORIGINS=* cannot be used with CORS_SUPPORTS_CREDENTIALS=true");
+    return Err(AppError::Error);
+  }
+  Ok(())
+}
+
+/// Builds the CORS middleware from the `CORS_*` settings
+pub fn cors(config: &EnvConfig) -> Cors {
+  if config.cors_permissive {
+    tracing::warn!("CORS is permissive, do not use this in production");
+    return Cors::permissive();
+  }
+  let origins = config.cors_allowed_origins.clone();
+  let mut cors = Cors::default()
+    .allowed_origin_fn(move |origin, _| {
+      origin
+        .to_str()
+        .is_ok_and(|origin| origins.iter().any(|p| origin_matches(p, origin)))
+    })
+    .allowed_methods(config.cors_allowed_methods.iter().map(String::as_str))
+    .allowed_headers(config.cors_allowed_headers.iter().map(String::as_str))
+    .max_age(config.cors_max_age);
+  if !config.cors_expose_headers.is_empty() {
+    cors = cors.expose_headers(config.cors_expose_headers.iter().map(String::as_str));
+  }
+  if config.cors_supports_credentials {
+    cors = cors.supports_credentials();
+  }
+  cors
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{
+    http::{header, StatusCode},
+    test::{call_service, init_service, TestRequest},
+    web, App, HttpResponse,
+  };
+
+  use super::*;
+
+  fn config(vars: &[(&str, &str)]) -> EnvConfig {
+    let mut env = vec![("DATABASE_URL", "sqlite::memory:"), ("JWT_TOKEN", "test")];
+    env.extend_from_slice(vars);
+    envy::from_iter(env.iter().map(|(k, v)| (k.to_string(), v.to_string()))).unwrap()
+  }
+
+  #[test]
+  fn matches_exact_and_wildcard_origins() {
+    assert!(origin_matches("*", "https://anything.example"));
+    assert!(origin_matches(
+      "https://app.example.com",
+      "https://APP.example.com"
+    ));
+    assert!(!origin_matches(
+      "https://app.example.com",
+      "http://app.example.com"
+    ));
+    let wildcard = "https://*.example.com";
+    assert!(origin_matches(wildcard, "https://app.example.com"));
+    assert!(origin_matches(wildcard, "https://a.b.example.com"));
+    for origin in [
+      "https://example.com",
+      "https://.example.com",
+      "http://app.example.com",
+      "https://app.evilexample.com",
+      "https://app.example.com.evil.com",
+    ] {
+      assert!(!origin_matches(wildcard, origin), "{origin}");
+    }
+  }
+
+  #[test]
+  fn refuses_any_origin_with_credentials() {
+    let any = ("CORS_ALLOWED_ORIGINS", "*");
+    let credentials = ("CORS_SUPPORTS_CREDENTIALS", "true");
+    assert!(check(&config(&[any])).is_ok());
+    assert!(check(&config(&[any, credentials])).is_err());
+    let permissive = ("CORS_PERMISSIVE", "true");
+    assert!(check(&config(&[any, credentials, permissive])).is_ok());
+  }
+
+  #[actix_web::test]
+  async fn only_allowed_origins_get_the_headers() {
+    let origins = ("CORS_ALLOWED_ORIGINS", "https://*.example.com");
+    let config = config(&[origins]);
+    let app = init_service(
+      App::new()
+        .wrap(cors(&config))
+        .route("/", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+    let get = |origin: &str| {
+      TestRequest::get()
+        .uri("/")
+        .insert_header((header::ORIGIN, origin))
+        .to_request()
+    };
+    let res = call_service(&app, get("https://app.example.com")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let allowed = res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    assert_eq!(allowed.unwrap(), "https://app.example.com");
+    // Served, but without the header the browser does not hand the response to the page
+    let res = call_service(&app, get("https://evil.com")).await;
+    assert!(res
+      .headers()
+      .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+      .is_none());
+  }
+}
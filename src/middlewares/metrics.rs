@@ -0,0 +1,103 @@
+use std::{
+  future::{ready, Ready},
+  time::Instant,
+};
+
+use actix_web::{
+  dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+  http::Method,
+  Error,
+};
+use futures_util::future::LocalBoxFuture;
+
+use crate::metrics::{HTTP_REQUESTS_TOTAL, HTTP_REQUEST_DURATION};
+
+/// Records the count and latency of every request. Requests are labelled by route template
+/// (`/api/v1/user/{id}`), not by path, and by standard method, to keep the number of series
+/// bounded
+pub struct Metrics;
+
+impl<S, B> Transform<S, ServiceRequest> for Metrics
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Transform = MetricsMiddleware<S>;
+  type InitError = ();
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ready(Ok(MetricsMiddleware { service }))
+  }
+}
+
+/// `OTHER` for extension methods, any client could otherwise add series
+fn method_label(method: &Method) -> &'static str {
+  match *method {
+    Method::GET => "GET",
+    Method::HEAD => "HEAD",
+    Method::POST => "POST",
+    Method::PUT => "PUT",
+    Method::DELETE => "DELETE",
+    Method::CONNECT => "CONNECT",
+    Method::OPTIONS => "OPTIONS",
+    Method::TRACE => "TRACE",
+    Method::PATCH => "PATCH",
+    _ => "OTHER",
+  }
+}
+
+pub struct MetricsMiddleware<S> {
+  service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddleware<S>
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  forward_ready!(service);
+
+  fn call(&self, req: ServiceRequest) -> Self::Future {
+    let start = Instant::now();
+    let method = method_label(req.method());
+    let route = req
+      .match_pattern()
+      .unwrap_or_else(|| "unmatched".to_string());
+    let fut = self.service.call(req);
+    Box::pin(async move {
+      let res = fut.await;
+      let status = match &res {
+        Ok(res) => res.status(),
+        Err(err) => err.as_response_error().status_code(),
+      };
+      let labels = [method, route.as_str(), status.as_str()];
+      HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+      HTTP_REQUEST_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+      res
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn extension_methods_share_one_label() {
+    assert_eq!(method_label(&Method::GET), "GET");
+    assert_eq!(method_label(&Method::PATCH), "PATCH");
+    for method in ["PURGE", "FOO", "get"] {
+      let method = Method::from_bytes(method.as_bytes()).unwrap();
+      assert_eq!(method_label(&method), "OTHER");
+    }
+  }
+}
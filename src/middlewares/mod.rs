@@ -0,0 +1,7 @@
+//! middlewares
+
+pub mod cors;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod trace;
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+
+use async_trait::async_trait;
+
+use super::{
+  gcra, now_millis, sliding_window, Quota, RateLimitAlgorithm, RateLimitDecision, RateLimitStore,
+};
+use crate::{error::AppError, repository::RepositoryManager};
+
+/// Store shared by all replicas through the `rate_limit` table, for deployments without
+/// redis. Every check is a read-modify-write transaction on one row
+#[derive(Debug)]
+pub struct DatabaseStore {
+  repo: RepositoryManager,
+}
+
+impl DatabaseStore {
+  pub fn new(repo: RepositoryManager) -> Self {
+    Self { repo }
+  }
+}
+
+#[async_trait]
+impl RateLimitStore for DatabaseStore {
+  async fn check(
+    &self,
+    key: &str,
+    quota: Quota,
+    algorithm: RateLimitAlgorithm,
+  ) -> Result<RateLimitDecision, AppError> {
+    let now = now_millis();
+    let decision = self
+      .repo
+      .rate_limit()
+      .update_bucket(key, |state| {
+        let (state, decision) = match algorithm {
+          RateLimitAlgorithm::Gcra => {
+            let mut tat = state.and_then(|s| s.parse().ok()).unwrap_or(now);
+            let decision = gcra(&mut tat, quota, now);
+            (tat.to_string(), decision)
+          }
+          RateLimitAlgorithm::SlidingWindow => {
+            let mut hits = state
+              .map(|s| s.split(',').filter_map(|hit| hit.parse().ok()).collect())
+              .unwrap_or_else(VecDeque::new);
+            let decision = sliding_window(&mut hits, quota, now);
+            let hits = hits.iter().map(u64::to_string).collect::<Vec<_>>();
+            (hits.join(","), decision)
+          }
+        };
+        let expires_at = now + decision.reset_after.as_millis() as u64;
+        (state, expires_at as i64, decision)
+      })
+      .await?;
+    Ok(decision)
+  }
+
+  async fn evict_expired(&self) -> Result<(), AppError> {
+    self
+      .repo
+      .rate_limit()
+      .delete_expired(now_millis() as i64)
+      .await?;
+    Ok(())
+  }
+}
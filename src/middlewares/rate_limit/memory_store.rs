@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+
+use super::{now_millis, Quota, RateLimitAlgorithm, RateLimitDecision, RateLimitStore};
+use crate::error::AppError;
+
+pub use rate_limiter::MemoryStore;
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+  async fn check(
+    &self,
+    key: &str,
+    quota: Quota,
+    algorithm: RateLimitAlgorithm,
+  ) -> Result<RateLimitDecision, AppError> {
+    Ok(self.check_at(key, quota, algorithm, now_millis()))
+  }
+
+  async fn evict_expired(&self) -> Result<(), AppError> {
+    self.evict_expired_at(now_millis());
+    Ok(())
+  }
+}
@@ -0,0 +1,513 @@
+mod database_store;
+mod memory_store;
+mod redis_store;
+
+use std::{
+  collections::{HashMap, HashSet},
+  fmt::Debug,
+  future::{ready, Ready},
+  rc::Rc,
+  sync::Arc,
+  time::Duration,
+};
+
+use actix_web::{
+  body::EitherBody,
+  dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+  error::PayloadError,
+  http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+  web::{Bytes, Data},
+  Error, HttpResponse,
+};
+use async_trait::async_trait;
+use futures_util::{future::LocalBoxFuture, stream};
+use helpers::jwt;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+  app::AppState,
+  components::{
+    session::{model::SessionClaims, service::verify_api_key},
+    user::{model::API_KEY_PREFIX, service::normalize_email},
+  },
+  config::EnvConfig,
+  error::AppError,
+  helpers::header::{extract_token, ClientIp},
+  metrics::RATE_LIMIT_REJECTIONS_TOTAL,
+  repository::RepositoryManager,
+  response::Response,
+};
+
+pub use database_store::DatabaseStore;
+pub use memory_store::MemoryStore;
+pub use rate_limiter::{
+  gcra, gcra_params, now_millis, sliding_window, Quota, RateLimitAlgorithm, RateLimitDecision,
+};
+pub use redis_store::RedisStore;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitStoreKind {
+  /// Per process, each replica counts on its own
+  #[default]
+  Memory,
+  /// Shared by all replicas through `redis_url`
+  Redis,
+  /// Shared by all replicas through the `rate_limit` table
+  Database,
+}
+
+/// Where the rate limit state is kept. `check` must count and decide atomically
+#[async_trait]
+pub trait RateLimitStore: Send + Sync + Debug {
+  async fn check(
+    &self,
+    key: &str,
+    quota: Quota,
+    algorithm: RateLimitAlgorithm,
+  ) -> Result<RateLimitDecision, AppError>;
+
+  /// Drops the entries whose quota is fully available again, for stores without native expiry
+  async fn evict_expired(&self) -> Result<(), AppError> {
+    Ok(())
+  }
+}
+
+/// Builds the store selected by `rate_limit_store`
+pub async fn store(
+  config: &EnvConfig,
+  repo: &RepositoryManager,
+) -> Result<Box<dyn RateLimitStore>, AppError> {
+  Ok(match config.rate_limit_store {
+    RateLimitStoreKind::Memory => Box::new(MemoryStore::default()),
+    RateLimitStoreKind::Redis => {
+      let Some(url) = &config.redis_url else {
+        tracing::error!("RATE_LIMIT_STORE=redis requires REDIS_URL");
+        return Err(AppError::Error);
+      };
+      Box::new(RedisStore::connect(url).await?)
+    }
+    RateLimitStoreKind::Database => Box::new(DatabaseStore::new(repo.clone())),
+  })
+}
+
+/// Applies the configured quotas on top of a [`RateLimitStore`]
+#[derive(Debug)]
+pub struct RateLimiter {
+  algorithm: RateLimitAlgorithm,
+  policies: HashMap<String, Quota>,
+  /// Policies refused rather than let through while the store is unavailable
+  fail_closed: HashSet<String>,
+  store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+  /// `policies` must contain a `default` entry, used for policies without their own quota
+  pub fn new(
+    algorithm: RateLimitAlgorithm,
+    policies: HashMap<String, Quota>,
+    fail_closed: HashSet<String>,
+    store: Box<dyn RateLimitStore>,
+  ) -> Self {
+    RateLimiter {
+      algorithm,
+      policies,
+      fail_closed,
+      store,
+    }
+  }
+
+  pub fn quota(&self, policy: &str) -> Quota {
+    self
+      .policies
+      .get(policy)
+      .or_else(|| self.policies.get("default"))
+      .copied()
+      .unwrap_or(Quota {
+        limit: 60,
+        window: Duration::from_secs(60),
+      })
+  }
+
+  /// Counts a request for `key` of `policy` and tells whether it is within `quota`. While
+  /// the store is unavailable the request is let through, unless `policy` fails closed
+  pub async fn check(
+    &self,
+    policy: &str,
+    key: &str,
+    quota: Quota,
+  ) -> Result<RateLimitDecision, AppError> {
+    if quota.limit == 0 {
+      return Ok(RateLimitDecision::denied(quota));
+    }
+    match self
+      .store
+      .check(&format!("{policy}:{key}"), quota, self.algorithm)
+      .await
+    {
+      Ok(decision) => Ok(decision),
+      Err(err) if self.fail_closed.contains(policy) => {
+        tracing::error!("Rate limit store unavailable, refusing the request of {policy}");
+        Err(err)
+      }
+      Err(_) => {
+        tracing::warn!("Rate limit store unavailable, letting the request of {policy} through");
+        Ok(RateLimitDecision {
+          allowed: true,
+          limit: quota.limit,
+          remaining: quota.limit,
+          reset_after: Duration::ZERO,
+          retry_after: Duration::ZERO,
+        })
+      }
+    }
+  }
+
+  /// Runs [`RateLimitStore::evict_expired`] every `period`
+  pub fn spawn_eviction(self: &Arc<Self>, period: Duration) {
+    let limiter = Arc::clone(self);
+    actix_web::rt::spawn(async move {
+      let mut interval = actix_web::rt::time::interval(period);
+      loop {
+        interval.tick().await;
+        limiter.store.evict_expired().await.ok();
+      }
+    });
+  }
+}
+
+/// What requests are counted by
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitKey {
+  /// Client IP
+  Ip,
+  /// Verified API key, else the logged-in user, else the client IP
+  Identity,
+  /// An email field of the JSON body, normalized like at login so every spelling of an
+  /// account shares its bucket
+  JsonField(&'static str),
+}
+
+/// Rate limiting middleware. The quota of `policy` comes from config, every key is
+/// counted separately and the request is rejected when any of them is over the quota
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+  policy: &'static str,
+  keys: &'static [RateLimitKey],
+  /// Path prefixes the policy does not apply to
+  exempt: &'static [&'static str],
+}
+
+impl RateLimit {
+  pub fn new(policy: &'static str, keys: &'static [RateLimitKey]) -> Self {
+    Self {
+      policy,
+      keys,
+      exempt: &[],
+    }
+  }
+
+  /// Applied to every request but the health probes, `RATE_LIMIT`
+  pub fn global() -> Self {
+    Self {
+      exempt: &["/api/v1/health"],
+      ..Self::new("default", &[RateLimitKey::Identity])
+    }
+  }
+
+  /// Per IP and per email, `RATE_LIMIT_LOGIN`
+  pub fn login() -> Self {
+    Self::new(
+      "login",
+      &[RateLimitKey::Ip, RateLimitKey::JsonField("email")],
+    )
+  }
+
+  /// Password checks of a logged-in user, per user with the `RATE_LIMIT_LOGIN` quota
+  pub fn reauth() -> Self {
+    Self::new("login", &[RateLimitKey::Identity])
+  }
+
+  /// Per IP and per email, `RATE_LIMIT_MAGIC_LINK`
+  pub fn magic_link() -> Self {
+    Self::new(
+      "magic_link",
+      &[RateLimitKey::Ip, RateLimitKey::JsonField("email")],
+    )
+  }
+
+  /// Per IP, `RATE_LIMIT_REGISTER`
+  pub fn register() -> Self {
+    Self::new("register", &[RateLimitKey::Ip])
+  }
+
+  /// Uploads of a logged-in user, per user, `RATE_LIMIT_UPLOAD`
+  pub fn upload() -> Self {
+    Self::new("upload", &[RateLimitKey::Identity])
+  }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<EitherBody<B>>;
+  type Error = Error;
+  type Transform = RateLimitMiddleware<S>;
+  type InitError = ();
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ready(Ok(RateLimitMiddleware {
+      service: Rc::new(service),
+      limit: *self,
+    }))
+  }
+}
+
+pub struct RateLimitMiddleware<S> {
+  service: Rc<S>,
+  limit: RateLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<EitherBody<B>>;
+  type Error = Error;
+  type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  forward_ready!(service);
+
+  fn call(&self, mut req: ServiceRequest) -> Self::Future {
+    let service = self.service.clone();
+    let RateLimit {
+      policy,
+      keys,
+      exempt,
+    } = self.limit;
+    Box::pin(async move {
+      let exempted = exempt.iter().any(|path| req.path().starts_with(path));
+      let Some(state) = req
+        .app_data::<Data<AppState>>()
+        .cloned()
+        .filter(|_| !exempted)
+      else {
+        return service
+          .call(req)
+          .await
+          .map(ServiceResponse::map_into_left_body);
+      };
+      let quota = state.rate_limiter.quota(policy);
+      let mut decision: Option<RateLimitDecision> = None;
+      for key in keys {
+        let Some(value) = key_value(&mut req, &state, *key).await else {
+          continue;
+        };
+        let current = match state.rate_limiter.check(policy, &value, quota).await {
+          Ok(current) => current,
+          Err(err) => {
+            let res = HttpResponse::ServiceUnavailable().json(Response::<()>::error(err, None));
+            return Ok(req.into_response(res).map_into_right_body());
+          }
+        };
+        if decision.is_none_or(|d| current.is_tighter_than(&d)) {
+          decision = Some(current);
+        }
+      }
+      let Some(decision) = decision else {
+        return service
+          .call(req)
+          .await
+          .map(ServiceResponse::map_into_left_body);
+      };
+      if !decision.allowed {
+        tracing::warn!("Rate limit {policy} exceeded");
+        RATE_LIMIT_REJECTIONS_TOTAL
+          .with_label_values(&[policy])
+          .inc();
+        let mut res = HttpResponse::TooManyRequests()
+          .json(Response::<()>::error(AppError::FrequencyLimited, None));
+        insert_headers(res.headers_mut(), &decision, quota);
+        res.headers_mut().insert(
+          RETRY_AFTER,
+          HeaderValue::from(ceil_secs(decision.retry_after).max(1)),
+        );
+        return Ok(req.into_response(res).map_into_right_body());
+      }
+      let mut res = service.call(req).await?;
+      insert_headers(res.headers_mut(), &decision, quota);
+      Ok(res.map_into_left_body())
+    })
+  }
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+  duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// Sets the `RateLimit-*` headers unless an inner, more specific policy already did
+fn insert_headers(headers: &mut HeaderMap, decision: &RateLimitDecision, quota: Quota) {
+  let limit = HeaderName::from_static("ratelimit-limit");
+  if headers.contains_key(&limit) {
+    return;
+  }
+  headers.insert(limit, HeaderValue::from(decision.limit));
+  headers.insert(
+    HeaderName::from_static("ratelimit-remaining"),
+    HeaderValue::from(decision.remaining),
+  );
+  headers.insert(
+    HeaderName::from_static("ratelimit-reset"),
+    HeaderValue::from(ceil_secs(decision.reset_after)),
+  );
+  if let Ok(policy) =
+    HeaderValue::from_str(&format!("{};w={}", quota.limit, quota.window.as_secs()))
+  {
+    headers.insert(HeaderName::from_static("ratelimit-policy"), policy);
+  }
+}
+
+async fn key_value(
+  req: &mut ServiceRequest,
+  state: &AppState,
+  key: RateLimitKey,
+) -> Option<String> {
+  match key {
+    RateLimitKey::Ip => Some(format!("ip:{}", ClientIp::resolve(req.request()))),
+    RateLimitKey::Identity => {
+      let token = extract_token(req.request()).ok();
+      if let Some(token) = token.as_ref().filter(|t| t.starts_with(API_KEY_PREFIX)) {
+        // Unknown keys share the bucket of their IP, made up ones must not open a new one
+        return Some(match verify_api_key(state, token).await {
+          Ok(api_key) => format!("key:{}", api_key.id),
+          Err(_) => format!("ip:{}", ClientIp::resolve(req.request())),
+        });
+      }
+      let user =
+        token.and_then(|token| jwt::verify::<SessionClaims>(&token, &state.jwt_token).ok());
+      match user {
+        Some(user) => Some(format!("user:{}", user.claims.data.email)),
+        None => Some(format!("ip:{}", ClientIp::resolve(req.request()))),
+      }
+    }
+    RateLimitKey::JsonField(field) => {
+      let body = req.extract::<Bytes>().await.ok()?;
+      let value = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|json| json.get(field)?.as_str().map(email_key));
+      // Put the body back for the handler
+      let payload = stream::once(async move { Ok::<_, PayloadError>(body) });
+      req.set_payload(Payload::Stream {
+        payload: Box::pin(payload),
+      });
+      value.map(|value| format!("{field}:{value}"))
+    }
+  }
+}
+
+/// Key of an email, the address login resolves it to, else trimmed and lowercased
+fn email_key(email: &str) -> String {
+  normalize_email(email).unwrap_or_else(|_| email.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::test::TestRequest;
+
+  use crate::{
+    app::testing,
+    components::{
+      session,
+      user::{self, model::ApiKeyScope},
+    },
+  };
+
+  use super::*;
+
+  #[derive(Debug)]
+  struct UnavailableStore;
+
+  #[async_trait]
+  impl RateLimitStore for UnavailableStore {
+    async fn check(
+      &self,
+      _: &str,
+      _: Quota,
+      _: RateLimitAlgorithm,
+    ) -> Result<RateLimitDecision, AppError> {
+      Err(AppError::Error)
+    }
+  }
+
+  fn limiter() -> RateLimiter {
+    RateLimiter::new(
+      RateLimitAlgorithm::Gcra,
+      HashMap::from([("default".to_string(), "60/min".parse().unwrap())]),
+      HashSet::from(["login".to_string()]),
+      Box::new(UnavailableStore),
+    )
+  }
+
+  #[actix_web::test]
+  async fn unavailable_store_lets_other_policies_through() {
+    let limiter = limiter();
+    let decision = limiter
+      .check("default", "ip:203.0.113.7", limiter.quota("default"))
+      .await
+      .unwrap();
+    assert!(decision.allowed);
+  }
+
+  #[test]
+  fn email_spellings_of_an_account_share_a_key() {
+    for email in [" a@b.c", "a@b.c ", "A@B.C", "\ta@b.C\n"] {
+      assert_eq!(email_key(email), "a@b.c");
+    }
+    assert_eq!(email_key(" Not An Email "), "not an email");
+  }
+
+  #[actix_web::test]
+  async fn only_verified_api_keys_get_their_own_bucket() {
+    let state = testing::app_state(&[]).await;
+    let ctx = testing::ctx();
+    let user = testing::user(&state, "Alice", "alice@example.com").await;
+    let token = session::service::create_session(&state, &ctx, &user)
+      .await
+      .unwrap();
+    let name = "CI".to_string();
+    let created =
+      user::service::create_api_key(&state, &ctx, token, name, vec![ApiKeyScope::Read], None)
+        .await
+        .unwrap();
+    let id = session::service::api_key_id(&created.key)
+      .unwrap()
+      .to_string();
+    let made_up = format!("{API_KEY_PREFIX}{id}_forged");
+    for (api_key, expected) in [
+      (created.key.as_str(), format!("key:{id}")),
+      (made_up.as_str(), "ip:203.0.113.7".to_string()),
+      ("ak_random", "ip:203.0.113.7".to_string()),
+    ] {
+      let mut req = TestRequest::default()
+        .peer_addr("203.0.113.7:5000".parse().unwrap())
+        .insert_header(("X-API-Key", api_key))
+        .to_srv_request();
+      let value = key_value(&mut req, &state, RateLimitKey::Identity).await;
+      assert_eq!(value, Some(expected), "{api_key}");
+    }
+  }
+
+  #[actix_web::test]
+  async fn unavailable_store_refuses_fail_closed_policies() {
+    let limiter = limiter();
+    let decision = limiter
+      .check("login", "ip:203.0.113.7", limiter.quota("login"))
+      .await;
+    assert!(decision.is_err());
+  }
+}
@@ -0,0 +1,390 @@
+use std::{fmt::Debug, time::Duration};
+
+use async_trait::async_trait;
+use helpers::uuid::{self, Alphabet};
+use redis::{aio::ConnectionManager, Script};
+
+use super::{gcra_params, Quota, RateLimitAlgorithm, RateLimitDecision, RateLimitStore};
+use crate::error::AppError;
+
+/// Same as `gcra`, using the redis clock so all replicas agree on the time
+const GCRA: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local interval = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local tat = math.max(tonumber(redis.call('GET', KEYS[1])) or now, now)
+local new_tat = tat + interval
+if new_tat <= now + window then
+  redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+  return {1, new_tat - now, 0}
+end
+return {0, tat - now, new_tat - now - window}
+"#;
+
+/// Same as `sliding_window`, the requests are members of a sorted set scored by time
+const SLIDING_WINDOW: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+local count = redis.call('ZCARD', KEYS[1])
+local allowed = 0
+if count < limit then
+  redis.call('ZADD', KEYS[1], now, ARGV[3])
+  count = count + 1
+  allowed = 1
+end
+local reset_after, retry_after = 0, 0
+if count > 0 then
+  local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+  local newest = redis.call('ZRANGE', KEYS[1], -1, -1, 'WITHSCORES')
+  reset_after = tonumber(newest[2]) + window - now
+  if allowed == 0 then
+    retry_after = tonumber(oldest[2]) + window - now
+  end
+  redis.call('PEXPIRE', KEYS[1], reset_after)
+end
+return {allowed, count, reset_after, retry_after}
+"#;
+
+/// Store shared by all replicas, each check runs as one Lua script so it is atomic
+pub struct RedisStore {
+  conn: ConnectionManager,
+  gcra: Script,
+  sliding_window: Script,
+}
+
+impl RedisStore {
+  pub async fn connect(url: &str) -> Result<Self, AppError> {
+    let client = redis::Client::open(url)?;
+    Ok(Self {
+      conn: client.get_connection_manager().await?,
+      gcra: Script::new(GCRA),
+      sliding_window: Script::new(SLIDING_WINDOW),
+    })
+  }
+}
+
+impl Debug for RedisStore {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("RedisStore").finish_non_exhaustive()
+  }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+  async fn check(
+    &self,
+    key: &str,
+    quota: Quota,
+    algorithm: RateLimitAlgorithm,
+  ) -> Result<RateLimitDecision, AppError> {
+    let mut conn = self.conn.clone();
+    let key = format!("ratelimit:{key}");
+    let (window, interval) = gcra_params(quota);
+    match algorithm {
+      RateLimitAlgorithm::Gcra => {
+        let reply = self
+          .gcra
+          .key(key)
+          .arg(interval)
+          .arg(window)
+          .invoke_async(&mut conn)
+          .await?;
+        Ok(gcra_decision(quota, reply))
+      }
+      RateLimitAlgorithm::SlidingWindow => {
+        let reply = self
+          .sliding_window
+          .key(key)
+          .arg(quota.limit)
+          .arg(window)
+          .arg(uuid::uuid(&Alphabet::DEFAULT, 16))
+          .invoke_async(&mut conn)
+          .await?;
+        Ok(sliding_window_decision(quota, reply))
+      }
+    }
+  }
+}
+
+/// The reply of `GCRA`: allowed, then the reset and retry delays in milliseconds
+fn gcra_decision(
+  quota: Quota,
+  (allowed, reset_after, retry_after): (bool, u64, u64),
+) -> RateLimitDecision {
+  let (window, interval) = gcra_params(quota);
+  RateLimitDecision {
+    allowed,
+    limit: quota.limit,
+    remaining: (window.saturating_sub(reset_after) / interval) as u32,
+    reset_after: Duration::from_millis(reset_after),
+    retry_after: Duration::from_millis(retry_after),
+  }
+}
+
+/// The reply of `SLIDING_WINDOW`: allowed, the requests in the window, then the reset and
+/// retry delays in milliseconds
+fn sliding_window_decision(
+  quota: Quota,
+  (allowed, count, reset_after, retry_after): (bool, u32, u64, u64),
+) -> RateLimitDecision {
+  RateLimitDecision {
+    allowed,
+    limit: quota.limit,
+    remaining: quota.limit.saturating_sub(count),
+    reset_after: Duration::from_millis(reset_after),
+    retry_after: Duration::from_millis(retry_after),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::VecDeque;
+
+  use mlua::Lua;
+  use redis::AsyncCommands;
+
+  use super::*;
+  use crate::middlewares::rate_limit::{gcra, sliding_window};
+
+  /// The commands the scripts use, on keys kept in Lua tables with a clock set by the test.
+  /// Arguments are turned into strings and emptied sorted sets removed, as redis does
+  const FAKE_REDIS: &str = r#"
+now_ms = 0
+local strings, zsets, expires = {}, {}, {}
+
+local function delete(key)
+  strings[key], zsets[key], expires[key] = nil, nil, nil
+end
+
+redis = {}
+function redis.call(cmd, key, ...)
+  for k, at in pairs(expires) do
+    if at <= now_ms then delete(k) end
+  end
+  local args = {}
+  for i, arg in ipairs({...}) do
+    args[i] = type(arg) == 'number' and string.format('%.17g', arg) or arg
+  end
+  if cmd == 'TIME' then
+    return {tostring(math.floor(now_ms / 1000)), tostring(now_ms % 1000 * 1000)}
+  elseif cmd == 'GET' then
+    return strings[key] or false
+  elseif cmd == 'SET' then
+    assert(args[2] == 'PX' and tonumber(args[3]) > 0, 'SET needs a positive PX')
+    delete(key)
+    strings[key], expires[key] = args[1], now_ms + tonumber(args[3])
+    return 'OK'
+  elseif cmd == 'PEXPIRE' then
+    assert(tonumber(args[1]) > 0, 'PEXPIRE needs a positive delay')
+    if not (strings[key] or zsets[key]) then return 0 end
+    expires[key] = now_ms + tonumber(args[1])
+    return 1
+  elseif cmd == 'PTTL' then
+    if not (strings[key] or zsets[key]) then return -2 end
+    return expires[key] and expires[key] - now_ms or -1
+  end
+  local set = zsets[key] or {}
+  if cmd == 'ZREMRANGEBYSCORE' then
+    assert(args[1] == '-inf', 'only from -inf')
+    local kept = {}
+    for _, entry in ipairs(set) do
+      if entry.score > tonumber(args[2]) then table.insert(kept, entry) end
+    end
+    zsets[key] = #kept > 0 and kept or nil
+    if not zsets[key] then delete(key) end
+    return #set - #kept
+  elseif cmd == 'ZCARD' then
+    return #set
+  elseif cmd == 'ZADD' then
+    local entry, at = {score = tonumber(args[1]), member = args[2]}, #set + 1
+    while at > 1 and set[at - 1].score > entry.score do at = at - 1 end
+    table.insert(set, at, entry)
+    zsets[key] = set
+    return 1
+  elseif cmd == 'ZRANGE' then
+    assert(args[1] == args[2] and args[3] == 'WITHSCORES', 'only one member with its score')
+    local index = tonumber(args[1])
+    local entry = set[index < 0 and #set + 1 + index or index + 1]
+    return {entry.member, string.format('%.17g', entry.score)}
+  end
+  error('unexpected command ' .. cmd)
+end
+"#;
+
+  struct FakeRedis(Lua);
+
+  impl FakeRedis {
+    fn new() -> Self {
+      let lua = Lua::new();
+      lua.load(FAKE_REDIS).exec().unwrap();
+      Self(lua)
+    }
+
+    fn eval(&self, script: &str, now: u64, key: &str, args: &[String]) -> Vec<u64> {
+      let globals = self.0.globals();
+      globals.set("now_ms", now).unwrap();
+      globals.set("KEYS", [key]).unwrap();
+      globals.set("ARGV", args).unwrap();
+      self.0.load(script).eval().unwrap()
+    }
+
+    fn pttl(&self, now: u64, key: &str) -> i64 {
+      self.0.globals().set("now_ms", now).unwrap();
+      let call: mlua::Function = self.0.load("redis.call").eval().unwrap();
+      call.call(("PTTL", key)).unwrap()
+    }
+  }
+
+  /// Checks at the same times as the in-memory algorithm give the same decisions, and the
+  /// key expires exactly when the full quota is available again. `times` end with a check
+  /// that leaves the key set
+  fn assert_same_as_memory(
+    script: &str,
+    algorithm: RateLimitAlgorithm,
+    quota: &str,
+    times: &[u64],
+  ) {
+    let quota: Quota = quota.parse().unwrap();
+    let (window, interval) = gcra_params(quota);
+    let redis = FakeRedis::new();
+    let (mut tat, mut hits) = (0, VecDeque::new());
+    for (i, &now) in times.iter().enumerate() {
+      let (decision, expected) = match algorithm {
+        RateLimitAlgorithm::Gcra => {
+          let reply = redis.eval(
+            script,
+            now,
+            "k",
+            &[interval.to_string(), window.to_string()],
+          );
+          let reply = (reply[0] == 1, reply[1], reply[2]);
+          (gcra_decision(quota, reply), gcra(&mut tat, quota, now))
+        }
+        RateLimitAlgorithm::SlidingWindow => {
+          let args = [
+            quota.limit.to_string(),
+            window.to_string(),
+            format!("hit{i}"),
+          ];
+          let reply = redis.eval(script, now, "k", &args);
+          let reply = (reply[0] == 1, reply[1] as u32, reply[2], reply[3]);
+          (
+            sliding_window_decision(quota, reply),
+            sliding_window(&mut hits, quota, now),
+          )
+        }
+      };
+      assert_eq!(decision, expected, "check {i} at {now}");
+      let reset_after = decision.reset_after.as_millis() as i64;
+      let ttl = if reset_after > 0 { reset_after } else { -2 };
+      assert_eq!(redis.pttl(now, "k"), ttl, "ttl after check {i}");
+    }
+    // The clock of the fake only moves forward, expired keys are gone for good
+    let last = *times.last().unwrap();
+    let expiry = last + redis.pttl(last, "k") as u64;
+    assert!(redis.pttl(expiry - 1, "k") > 0);
+    assert_eq!(redis.pttl(expiry, "k"), -2);
+  }
+
+  #[test]
+  fn gcra_script_matches_the_memory_store() {
+    let times = [
+      10_000, 10_000, 10_000, 10_000, 10_500, 11_000, 11_000, 11_999, 13_999, 20_000, 20_001,
+    ];
+    assert_same_as_memory(GCRA, RateLimitAlgorithm::Gcra, "3/3s", &times);
+    assert_same_as_memory(GCRA, RateLimitAlgorithm::Gcra, "1000/s", &[5_000; 1_002]);
+  }
+
+  #[test]
+  fn sliding_window_script_matches_the_memory_store() {
+    let times = [
+      10_000, 10_100, 10_200, 10_300, 10_999, 11_000, 11_100, 11_150, 11_200, 15_000, 15_000,
+    ];
+    assert_same_as_memory(
+      SLIDING_WINDOW,
+      RateLimitAlgorithm::SlidingWindow,
+      "3/s",
+      &times,
+    );
+  }
+
+  /// The store on a live redis-server, skipped unless asked for:
+  /// `REDIS_URL=redis://127.0.0.1:6379 cargo test -- --ignored redis_store`
+  async fn live_store() -> (RedisStore, String) {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    let store = RedisStore::connect(&url).await.unwrap();
+    (
+      store,
+      format!("test:{}", uuid::uuid(&Alphabet::DEFAULT, 16)),
+    )
+  }
+
+  async fn live_pttl(store: &RedisStore, key: &str) -> i64 {
+    store
+      .conn
+      .clone()
+      .pttl(format!("ratelimit:{key}"))
+      .await
+      .unwrap()
+  }
+
+  #[actix_web::test]
+  #[ignore = "needs a redis-server at REDIS_URL"]
+  async fn live_gcra_limits_and_expires() {
+    let (store, key) = live_store().await;
+    let quota: Quota = "3/s".parse().unwrap();
+    for remaining in [2, 1, 0] {
+      let decision = store
+        .check(&key, quota, RateLimitAlgorithm::Gcra)
+        .await
+        .unwrap();
+      assert!(decision.allowed);
+      assert_eq!(decision.remaining, remaining);
+    }
+    let denied = store
+      .check(&key, quota, RateLimitAlgorithm::Gcra)
+      .await
+      .unwrap();
+    assert!(!denied.allowed);
+    assert!(
+      denied.retry_after > Duration::ZERO && denied.retry_after <= Duration::from_millis(334)
+    );
+    let ttl = live_pttl(&store, &key).await;
+    assert!(ttl > 0 && ttl <= 1_000, "ttl {ttl}");
+    actix_web::rt::time::sleep(Duration::from_millis(1_100)).await;
+    assert_eq!(live_pttl(&store, &key).await, -2);
+    let decision = store
+      .check(&key, quota, RateLimitAlgorithm::Gcra)
+      .await
+      .unwrap();
+    assert!(decision.allowed);
+    assert_eq!(decision.remaining, 2);
+  }
+
+  #[actix_web::test]
+  #[ignore = "needs a redis-server at REDIS_URL"]
+  async fn live_sliding_window_limits_and_expires() {
+    let (store, key) = live_store().await;
+    let quota: Quota = "2/s".parse().unwrap();
+    let algorithm = RateLimitAlgorithm::SlidingWindow;
+    for remaining in [1, 0] {
+      let decision = store.check(&key, quota, algorithm).await.unwrap();
+      assert!(decision.allowed);
+      assert_eq!(decision.remaining, remaining);
+    }
+    let denied = store.check(&key, quota, algorithm).await.unwrap();
+    assert!(!denied.allowed);
+    assert!(denied.retry_after > Duration::ZERO && denied.retry_after <= Duration::from_secs(1));
+    let ttl = live_pttl(&store, &key).await;
+    assert!(ttl > 0 && ttl <= 1_000, "ttl {ttl}");
+    actix_web::rt::time::sleep(Duration::from_millis(1_100)).await;
+    assert_eq!(live_pttl(&store, &key).await, -2);
+    let decision = store.check(&key, quota, algorithm).await.unwrap();
+    assert!(decision.allowed);
+    assert_eq!(decision.remaining, 1);
+  }
+}
@@ -0,0 +1,166 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+  dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+  http::header::{HeaderName, HeaderValue},
+  Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use helpers::uuid::{self, Alphabet};
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+  static CURRENT: RequestId;
+}
+
+/// Id of the request, taken from a valid incoming `X-Request-Id` or generated. Stored in the
+/// request extensions and usable as an extractor
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+  /// Id of the request being handled, `None` outside of one (e.g. in `web::block`)
+  pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+  }
+
+  fn from_header(value: &HeaderValue) -> Option<Self> {
+    let value = value.to_str().ok()?;
+    let valid = (1..=128).contains(&value.len())
+      && value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+    valid.then(|| Self(value.to_string()))
+  }
+}
+
+impl FromRequest for RequestId {
+  type Error = Error;
+  type Future = Ready<Result<Self, Self::Error>>;
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    let id = req.extensions().get::<RequestId>().cloned();
+    ready(Ok(id.unwrap_or_else(|| {
+      RequestId(uuid::uuid(&Alphabet::DEFAULT, 21))
+    })))
+  }
+}
+
+/// Assigns the [`RequestId`], records it on the request span and echoes it in the
+/// `X-Request-Id` response header
+pub struct SetRequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for SetRequestId
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Transform = SetRequestIdMiddleware<S>;
+  type InitError = ();
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ready(Ok(SetRequestIdMiddleware { service }))
+  }
+}
+
+pub struct SetRequestIdMiddleware<S> {
+  service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for SetRequestIdMiddleware<S>
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  forward_ready!(service);
+
+  fn call(&self, req: ServiceRequest) -> Self::Future {
+    let id = req
+      .headers()
+      .get(X_REQUEST_ID)
+      .and_then(RequestId::from_header)
+      .unwrap_or_else(|| RequestId(uuid::uuid(&Alphabet::DEFAULT, 21)));
+    tracing::Span::current().record("request_id", id.0.as_str());
+    req.extensions_mut().insert(id.clone());
+    let fut = CURRENT.sync_scope(id.clone(), || self.service.call(req));
+    Box::pin(CURRENT.scope(id.clone(), async move {
+      let mut res = fut.await?;
+      if let Ok(value) = HeaderValue::from_str(&id.0) {
+        res.headers_mut().insert(X_REQUEST_ID, value);
+      }
+      Ok(res)
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{test, web, App, HttpResponse};
+
+  use super::*;
+
+  /// Answers with the id of the extractor and the one seen by [`RequestId::current`]
+  async fn echo(id: RequestId) -> HttpResponse {
+    let current = RequestId::current().unwrap_or_default();
+    HttpResponse::Ok().body(format!("{} {current}", id.0))
+  }
+
+  async fn call(header: Option<&str>) -> (String, String) {
+    let app = test::init_service(
+      App::new()
+        .wrap(SetRequestId)
+        .route("/", web::get().to(echo)),
+    )
+    .await;
+    let mut req = test::TestRequest::get().uri("/");
+    if let Some(header) = header {
+      req = req.insert_header((X_REQUEST_ID, header));
+    }
+    let res = test::call_service(&app, req.to_request()).await;
+    let echoed = res
+      .headers()
+      .get(X_REQUEST_ID)
+      .unwrap()
+      .to_str()
+      .unwrap()
+      .to_string();
+    let body = test::read_body(res).await;
+    (echoed, String::from_utf8(body.to_vec()).unwrap())
+  }
+
+  #[actix_web::test]
+  async fn keeps_a_valid_incoming_id() {
+    let (echoed, body) = call(Some("abc-123_x.y")).await;
+    assert_eq!(echoed, "abc-123_x.y");
+    assert_eq!(body, "abc-123_x.y abc-123_x.y");
+  }
+
+  #[actix_web::test]
+  async fn replaces_an_invalid_incoming_id() {
+    let long = "a".repeat(129);
+    for header in [
+      None,
+      Some(""),
+      Some("a b"),
+      Some("<script>"),
+      Some(long.as_str()),
+    ] {
+      let (echoed, body) = call(header).await;
+      assert_eq!(echoed.len(), 21, "{header:?}");
+      assert_eq!(body, format!("{echoed} {echoed}"));
+    }
+  }
+
+  #[actix_web::test]
+  async fn no_current_id_outside_of_a_request() {
+    assert_eq!(RequestId::current(), None);
+  }
+}
@@ -0,0 +1,93 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+  dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+  Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use opentelemetry::{global, trace::TraceContextExt};
+use tracing::{field::Empty, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::telemetry::HeaderExtractor;
+
+/// Trace id of the request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct TraceId(pub String);
+
+/// Opens the root span of every request, continuing the trace of an incoming `traceparent`
+/// header. Its `trace_id` field shows up in every log line of the request
+pub struct Trace;
+
+impl<S, B> Transform<S, ServiceRequest> for Trace
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Transform = TraceMiddleware<S>;
+  type InitError = ();
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ready(Ok(TraceMiddleware { service }))
+  }
+}
+
+pub struct TraceMiddleware<S> {
+  service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for TraceMiddleware<S>
+where
+  S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+  B: 'static,
+{
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  forward_ready!(service);
+
+  fn call(&self, req: ServiceRequest) -> Self::Future {
+    let route = req
+      .match_pattern()
+      .unwrap_or_else(|| "unmatched".to_string());
+    let span = tracing::info_span!(
+      "HTTP request",
+      otel.name = format!("{} {}", req.method(), route),
+      otel.kind = "server",
+      http.request.method = %req.method(),
+      http.route = route,
+      url.path = req.path(),
+      http.response.status_code = Empty,
+      otel.status_code = Empty,
+      trace_id = Empty,
+      request_id = Empty,
+    );
+    let parent = global::get_text_map_propagator(|propagator| {
+      propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+    let trace_id = span.context().span().span_context().trace_id();
+    span.record("trace_id", trace_id.to_string());
+    req.extensions_mut().insert(TraceId(trace_id.to_string()));
+    let fut = span.in_scope(|| self.service.call(req));
+    Box::pin(
+      async move {
+        let res = fut.await;
+        let status = match &res {
+          Ok(res) => res.status(),
+          Err(err) => err.as_response_error().status_code(),
+        };
+        tracing::Span::current().record("http.response.status_code", status.as_u16());
+        if status.is_server_error() {
+          tracing::Span::current().record("otel.status_code", "ERROR");
+        }
+        res
+      }
+      .instrument(span),
+    )
+  }
+}
@@ -0,0 +1,79 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+  QueryTrait,
+};
+
+#[derive(Debug, Clone)]
+pub struct ApiKeyRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> ApiKeyRepository<'a> {
+  #[tracing::instrument(skip_all)]
+  pub async fn create_api_key(&self, api_key: ApiKeyActiveModel) -> Result<ApiKeyModel, DbErr> {
+    api_key.insert(self.db).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_api_key(&self, id: &str) -> Result<Option<ApiKeyModel>, DbErr> {
+    ApiKeyEntity::find_by_id(id).one(self.db).await
+  }
+
+  /// Newest first, revoked keys included
+  #[tracing::instrument(skip_all)]
+  pub async fn get_user_api_keys(&self, user_id: &str) -> Result<Vec<ApiKeyModel>, DbErr> {
+    ApiKeyEntity::find()
+      .filter(ApiKeyColumn::UserId.eq(user_id))
+      .order_by_desc(ApiKeyColumn::CreatedAt)
+      .all(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn touch_api_key(&self, id: &str, now: DateTimeUtc) -> Result<(), DbErr> {
+    ApiKeyEntity::update_many()
+      .col_expr(ApiKeyColumn::LastUsedAt, Expr::value(now))
+      .filter(ApiKeyColumn::Id.eq(id))
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+
+  /// Revokes one key of the user, returns whether it was active
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_api_key(
+    &self,
+    user_id: &str,
+    id: &str,
+    now: DateTimeUtc,
+  ) -> Result<bool, DbErr> {
+    let res = ApiKeyEntity::update_many()
+      .col_expr(ApiKeyColumn::RevokedAt, Expr::value(now))
+      .filter(ApiKeyColumn::Id.eq(id))
+      .filter(ApiKeyColumn::UserId.eq(user_id))
+      .filter(ApiKeyColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+
+  /// Revokes the active keys of the user but `except`, returns how many
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_other_api_keys(
+    &self,
+    user_id: &str,
+    except: Option<&str>,
+    now: DateTimeUtc,
+  ) -> Result<u64, DbErr> {
+    let res = ApiKeyEntity::update_many()
+      .col_expr(ApiKeyColumn::RevokedAt, Expr::value(now))
+      .filter(ApiKeyColumn::UserId.eq(user_id))
+      .filter(ApiKeyColumn::RevokedAt.is_null())
+      .apply_if(except, |query, id| query.filter(ApiKeyColumn::Id.ne(id)))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected)
+  }
+}
@@ -0,0 +1,57 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+  PaginatorTrait, QueryFilter, QueryOrder,
+};
+
+/// Conditions of [`AuditLogRepository::find`], `None` matches everything
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+  pub actor_id: Option<String>,
+  pub target_id: Option<String>,
+  pub action: Option<String>,
+  pub from: Option<DateTimeUtc>,
+  pub to: Option<DateTimeUtc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLogRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> AuditLogRepository<'a> {
+  pub async fn create(&self, entry: AuditLogActiveModel) -> Result<AuditLogModel, DbErr> {
+    entry.insert(self.db).await
+  }
+
+  /// Newest first. Returns the page and the total number of matching entries
+  pub async fn find(
+    &self,
+    filter: AuditLogFilter,
+    page: u64,
+    per_page: u64,
+  ) -> Result<(Vec<AuditLogModel>, u64), DbErr> {
+    let mut query = AuditLogEntity::find();
+    if let Some(actor_id) = filter.actor_id {
+      query = query.filter(AuditLogColumn::ActorId.eq(actor_id));
+    }
+    if let Some(target_id) = filter.target_id {
+      query = query.filter(AuditLogColumn::TargetId.eq(target_id));
+    }
+    if let Some(action) = filter.action {
+      query = query.filter(AuditLogColumn::Action.eq(action));
+    }
+    if let Some(from) = filter.from {
+      query = query.filter(AuditLogColumn::CreatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+      query = query.filter(AuditLogColumn::CreatedAt.lt(to));
+    }
+    let paginator = query
+      .order_by_desc(AuditLogColumn::Id)
+      .paginate(self.db, per_page);
+    let total = paginator.num_items().await?;
+    let entries = paginator.fetch_page(page).await?;
+    Ok((entries, total))
+  }
+}
@@ -0,0 +1,54 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+  EntityTrait, QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct EmailChangeRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> EmailChangeRepository<'a> {
+  /// Replaces the pending change of the user, and drops the changes expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn create_change(
+    &self,
+    change: EmailChangeActiveModel,
+    now: DateTimeUtc,
+  ) -> Result<EmailChangeModel, DbErr> {
+    let mut stale = Condition::any().add(EmailChangeColumn::ExpiresAt.lte(now));
+    if let Some(user_id) = change.user_id.try_as_ref() {
+      stale = stale.add(EmailChangeColumn::UserId.eq(user_id.as_str()));
+    }
+    EmailChangeEntity::delete_many()
+      .filter(stale)
+      .exec(self.db)
+      .await?;
+    change.insert(self.db).await
+  }
+
+  /// Deletes the change and returns it, so a confirmation link can only be used once. `None`
+  /// when unknown, already used or expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn take_change(
+    &self,
+    token_hash: &str,
+    now: DateTimeUtc,
+  ) -> Result<Option<EmailChangeModel>, DbErr> {
+    let Some(change) = EmailChangeEntity::find_by_id(token_hash)
+      .one(self.db)
+      .await?
+    else {
+      return Ok(None);
+    };
+    let res = EmailChangeEntity::delete_by_id(token_hash)
+      .exec(self.db)
+      .await?;
+    // Lost the race against a concurrent confirmation of the same link
+    if res.rows_affected == 0 || change.expires_at <= now {
+      return Ok(None);
+    }
+    Ok(Some(change))
+  }
+}
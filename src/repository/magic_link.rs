@@ -0,0 +1,47 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+  QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct MagicLinkRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> MagicLinkRepository<'a> {
+  /// Also drops the links expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn create_link(
+    &self,
+    link: MagicLinkActiveModel,
+    now: DateTimeUtc,
+  ) -> Result<MagicLinkModel, DbErr> {
+    MagicLinkEntity::delete_many()
+      .filter(MagicLinkColumn::ExpiresAt.lte(now))
+      .exec(self.db)
+      .await?;
+    link.insert(self.db).await
+  }
+
+  /// Deletes the link and returns it, so a link can only be used once. `None` when unknown,
+  /// already used or expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn take_link(
+    &self,
+    token_hash: &str,
+    now: DateTimeUtc,
+  ) -> Result<Option<MagicLinkModel>, DbErr> {
+    let Some(link) = MagicLinkEntity::find_by_id(token_hash).one(self.db).await? else {
+      return Ok(None);
+    };
+    let res = MagicLinkEntity::delete_by_id(token_hash)
+      .exec(self.db)
+      .await?;
+    // Lost the race against a concurrent exchange of the same link
+    if res.rows_affected == 0 || link.expires_at <= now {
+      return Ok(None);
+    }
+    Ok(Some(link))
+  }
+}
@@ -1,8 +1,35 @@
+mod api_key;
+mod audit_log;
+mod email_change;
+mod magic_link;
+mod oauth_client;
+mod oauth_code;
+mod oauth_token;
+mod oidc_flow;
+mod passkey;
+mod passkey_challenge;
+mod rate_limit;
+mod session;
 mod user;
+mod user_identity;
 
-use sea_orm::DatabaseConnection;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr};
 
+pub use api_key::ApiKeyRepository;
+pub use audit_log::{AuditLogFilter, AuditLogRepository};
+pub use email_change::EmailChangeRepository;
+pub use magic_link::MagicLinkRepository;
+pub use oauth_client::OauthClientRepository;
+pub use oauth_code::OauthCodeRepository;
+pub use oauth_token::OauthTokenRepository;
+pub use oidc_flow::OidcFlowRepository;
+pub use passkey::PasskeyRepository;
+pub use passkey_challenge::PasskeyChallengeRepository;
+pub use rate_limit::RateLimitRepository;
+pub use session::SessionRepository;
 pub use user::UserRepository;
+pub use user_identity::UserIdentityRepository;
 
 #[derive(Debug, Clone)]
 pub struct RepositoryManager {
@@ -14,7 +41,84 @@ impl RepositoryManager {
     Self { db }
   }
 
-  pub fn user(&self) -> UserRepository {
+  pub async fn ping(&self) -> Result<(), DbErr> {
+    self.db.ping().await
+  }
+
+  /// Connections opened and idle in the pool
+  pub fn pool_status(&self) -> Option<(u32, usize)> {
+    match self.db.get_database_backend() {
+      DatabaseBackend::MySql => {
+        let pool = self.db.get_mysql_connection_pool();
+        Some((pool.size(), pool.num_idle()))
+      }
+      DatabaseBackend::Sqlite => {
+        let pool = self.db.get_sqlite_connection_pool();
+        Some((pool.size(), pool.num_idle()))
+      }
+      _ => None,
+    }
+  }
+
+  /// Names of the migrations not applied yet
+  pub async fn pending_migrations(&self) -> Result<Vec<String>, DbErr> {
+    let pending = Migrator::get_pending_migrations(&self.db).await?;
+    Ok(pending.iter().map(|m| m.name().to_string()).collect())
+  }
+
+  pub fn session(&self) -> SessionRepository<'_> {
+    SessionRepository { db: &self.db }
+  }
+
+  pub fn user(&self) -> UserRepository<'_> {
     UserRepository { db: &self.db }
   }
+
+  pub fn user_identity(&self) -> UserIdentityRepository<'_> {
+    UserIdentityRepository { db: &self.db }
+  }
+
+  pub fn oidc_flow(&self) -> OidcFlowRepository<'_> {
+    OidcFlowRepository { db: &self.db }
+  }
+
+  pub fn magic_link(&self) -> MagicLinkRepository<'_> {
+    MagicLinkRepository { db: &self.db }
+  }
+
+  pub fn email_change(&self) -> EmailChangeRepository<'_> {
+    EmailChangeRepository { db: &self.db }
+  }
+
+  pub fn passkey(&self) -> PasskeyRepository<'_> {
+    PasskeyRepository { db: &self.db }
+  }
+
+  pub fn passkey_challenge(&self) -> PasskeyChallengeRepository<'_> {
+    PasskeyChallengeRepository { db: &self.db }
+  }
+
+  pub fn oauth_client(&self) -> OauthClientRepository<'_> {
+    OauthClientRepository { db: &self.db }
+  }
+
+  pub fn oauth_code(&self) -> OauthCodeRepository<'_> {
+    OauthCodeRepository { db: &self.db }
+  }
+
+  pub fn oauth_token(&self) -> OauthTokenRepository<'_> {
+    OauthTokenRepository { db: &self.db }
+  }
+
+  pub fn api_key(&self) -> ApiKeyRepository<'_> {
+    ApiKeyRepository { db: &self.db }
+  }
+
+  pub fn audit_log(&self) -> AuditLogRepository<'_> {
+    AuditLogRepository { db: &self.db }
+  }
+
+  pub fn rate_limit(&self) -> RateLimitRepository<'_> {
+    RateLimitRepository { db: &self.db }
+  }
 }
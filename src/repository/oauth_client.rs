@@ -0,0 +1,50 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Debug, Clone)]
+pub struct OauthClientRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> OauthClientRepository<'a> {
+  #[tracing::instrument(skip_all)]
+  pub async fn create_client(
+    &self,
+    client: OauthClientActiveModel,
+  ) -> Result<OauthClientModel, DbErr> {
+    client.insert(self.db).await
+  }
+
+  /// Only clients not revoked
+  #[tracing::instrument(skip_all)]
+  pub async fn get_client(&self, client_id: &str) -> Result<Option<OauthClientModel>, DbErr> {
+    OauthClientEntity::find_by_id(client_id)
+      .filter(OauthClientColumn::RevokedAt.is_null())
+      .one(self.db)
+      .await
+  }
+
+  /// Newest first, revoked clients included
+  #[tracing::instrument(skip_all)]
+  pub async fn get_clients(&self) -> Result<Vec<OauthClientModel>, DbErr> {
+    OauthClientEntity::find()
+      .order_by_desc(OauthClientColumn::CreatedAt)
+      .all(self.db)
+      .await
+  }
+
+  /// Returns whether the client was active
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_client(&self, client_id: &str, now: DateTimeUtc) -> Result<bool, DbErr> {
+    let res = OauthClientEntity::update_many()
+      .col_expr(OauthClientColumn::RevokedAt, Expr::value(now))
+      .filter(OauthClientColumn::ClientId.eq(client_id))
+      .filter(OauthClientColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+}
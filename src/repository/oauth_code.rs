@@ -0,0 +1,46 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+  QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct OauthCodeRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> OauthCodeRepository<'a> {
+  /// Also drops the codes expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn create_code(
+    &self,
+    code: OauthCodeActiveModel,
+    now: DateTimeUtc,
+  ) -> Result<OauthCodeModel, DbErr> {
+    OauthCodeEntity::delete_many()
+      .filter(OauthCodeColumn::ExpiresAt.lte(now))
+      .exec(self.db)
+      .await?;
+    code.insert(self.db).await
+  }
+
+  /// Deletes the code and returns it, so it can only be exchanged once. `None` when unknown,
+  /// already used or expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn take_code(
+    &self,
+    code_hash: &str,
+    now: DateTimeUtc,
+  ) -> Result<Option<OauthCodeModel>, DbErr> {
+    let Some(code) = OauthCodeEntity::find_by_id(code_hash).one(self.db).await? else {
+      return Ok(None);
+    };
+    let res = OauthCodeEntity::delete_by_id(code_hash)
+      .exec(self.db)
+      .await?;
+    if res.rows_affected == 0 || code.expires_at <= now {
+      return Ok(None);
+    }
+    Ok(Some(code))
+  }
+}
@@ -0,0 +1,52 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct OauthTokenRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> OauthTokenRepository<'a> {
+  #[tracing::instrument(skip_all)]
+  pub async fn create_token(&self, token: OauthTokenActiveModel) -> Result<OauthTokenModel, DbErr> {
+    token.insert(self.db).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_token(&self, token_hash: &str) -> Result<Option<OauthTokenModel>, DbErr> {
+    OauthTokenEntity::find()
+      .filter(OauthTokenColumn::TokenHash.eq(token_hash))
+      .one(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_token(&self, id: u32, now: DateTimeUtc) -> Result<(), DbErr> {
+    OauthTokenEntity::update_many()
+      .col_expr(OauthTokenColumn::RevokedAt, Expr::value(now))
+      .filter(OauthTokenColumn::Id.eq(id))
+      .filter(OauthTokenColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+
+  /// Revokes every token of a client, returns how many were active
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_client_tokens(
+    &self,
+    client_id: &str,
+    now: DateTimeUtc,
+  ) -> Result<u64, DbErr> {
+    let res = OauthTokenEntity::update_many()
+      .col_expr(OauthTokenColumn::RevokedAt, Expr::value(now))
+      .filter(OauthTokenColumn::ClientId.eq(client_id))
+      .filter(OauthTokenColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected)
+  }
+}
@@ -0,0 +1,45 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+  QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct OidcFlowRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> OidcFlowRepository<'a> {
+  /// Also drops the flows expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn create_flow(
+    &self,
+    flow: OidcFlowActiveModel,
+    now: DateTimeUtc,
+  ) -> Result<OidcFlowModel, DbErr> {
+    OidcFlowEntity::delete_many()
+      .filter(OidcFlowColumn::ExpiresAt.lte(now))
+      .exec(self.db)
+      .await?;
+    flow.insert(self.db).await
+  }
+
+  /// Deletes the flow and returns it, so a `state` can only be used once. `None` when
+  /// unknown, already used or expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn take_flow(
+    &self,
+    state: &str,
+    now: DateTimeUtc,
+  ) -> Result<Option<OidcFlowModel>, DbErr> {
+    let Some(flow) = OidcFlowEntity::find_by_id(state).one(self.db).await? else {
+      return Ok(None);
+    };
+    let res = OidcFlowEntity::delete_by_id(state).exec(self.db).await?;
+    // Lost the race against a concurrent callback with the same state
+    if res.rows_affected == 0 || flow.expires_at <= now {
+      return Ok(None);
+    }
+    Ok(Some(flow))
+  }
+}
@@ -0,0 +1,65 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Debug, Clone)]
+pub struct PasskeyRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> PasskeyRepository<'a> {
+  /// Fails on the unique `credential_id` when the credential is already registered
+  #[tracing::instrument(skip_all)]
+  pub async fn create_passkey(&self, passkey: PasskeyActiveModel) -> Result<PasskeyModel, DbErr> {
+    passkey.insert(self.db).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_passkey(&self, credential_id: &str) -> Result<Option<PasskeyModel>, DbErr> {
+    PasskeyEntity::find()
+      .filter(PasskeyColumn::CredentialId.eq(credential_id))
+      .one(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_user_passkeys(&self, user_id: &str) -> Result<Vec<PasskeyModel>, DbErr> {
+    PasskeyEntity::find()
+      .filter(PasskeyColumn::UserId.eq(user_id))
+      .order_by_asc(PasskeyColumn::CreatedAt)
+      .all(self.db)
+      .await
+  }
+
+  /// Stores the signature counter of a login, only if no concurrent login with the same
+  /// counter won. Returns whether it did
+  #[tracing::instrument(skip_all)]
+  pub async fn touch_passkey(
+    &self,
+    passkey: &PasskeyModel,
+    sign_count: u32,
+    now: DateTimeUtc,
+  ) -> Result<bool, DbErr> {
+    let res = PasskeyEntity::update_many()
+      .col_expr(PasskeyColumn::SignCount, Expr::value(sign_count))
+      .col_expr(PasskeyColumn::LastUsedAt, Expr::value(now))
+      .filter(PasskeyColumn::Id.eq(passkey.id))
+      .filter(PasskeyColumn::SignCount.eq(passkey.sign_count))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+
+  /// Deletes one passkey of the user, returns whether it existed
+  #[tracing::instrument(skip_all)]
+  pub async fn delete_passkey(&self, user_id: &str, id: u32) -> Result<bool, DbErr> {
+    let res = PasskeyEntity::delete_many()
+      .filter(PasskeyColumn::Id.eq(id))
+      .filter(PasskeyColumn::UserId.eq(user_id))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+}
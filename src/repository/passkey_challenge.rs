@@ -0,0 +1,50 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+  QueryFilter,
+};
+
+#[derive(Debug, Clone)]
+pub struct PasskeyChallengeRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> PasskeyChallengeRepository<'a> {
+  /// Also drops the challenges expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn create_challenge(
+    &self,
+    challenge: PasskeyChallengeActiveModel,
+    now: DateTimeUtc,
+  ) -> Result<PasskeyChallengeModel, DbErr> {
+    PasskeyChallengeEntity::delete_many()
+      .filter(PasskeyChallengeColumn::ExpiresAt.lte(now))
+      .exec(self.db)
+      .await?;
+    challenge.insert(self.db).await
+  }
+
+  /// Deletes the challenge and returns it, so a challenge can only be answered once. `None`
+  /// when unknown, already used or expired at `now`
+  #[tracing::instrument(skip_all)]
+  pub async fn take_challenge(
+    &self,
+    challenge: &str,
+    now: DateTimeUtc,
+  ) -> Result<Option<PasskeyChallengeModel>, DbErr> {
+    let Some(found) = PasskeyChallengeEntity::find_by_id(challenge)
+      .one(self.db)
+      .await?
+    else {
+      return Ok(None);
+    };
+    let res = PasskeyChallengeEntity::delete_by_id(challenge)
+      .exec(self.db)
+      .await?;
+    // Lost the race against a concurrent answer to the same challenge
+    if res.rows_affected == 0 || found.expires_at <= now {
+      return Ok(None);
+    }
+    Ok(Some(found))
+  }
+}
@@ -0,0 +1,159 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  sea_query::{OnConflict, Query},
+  sqlx::{self, mysql::MySqlDatabaseError},
+  ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+  EntityTrait, QueryFilter, QuerySelect, RuntimeErr, Set, TransactionTrait,
+};
+
+/// Runs of a bucket update before a lost lock race is returned as an error
+const UPDATE_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct RateLimitRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> RateLimitRepository<'a> {
+  /// Reads the state of bucket `id` and writes back what `update` returns, in one transaction.
+  /// `update` gets the current state and returns the new state, its expiry and a result.
+  /// It runs again when the transaction deadlocks or finds the database busy
+  pub async fn update_bucket<T>(
+    &self,
+    id: &str,
+    mut update: impl FnMut(Option<&str>) -> (String, i64, T),
+  ) -> Result<T, DbErr> {
+    let mut attempt = 1;
+    loop {
+      match self.try_update_bucket(id, &mut update).await {
+        Err(err) if attempt < UPDATE_ATTEMPTS && is_contention(&err) => {
+          tracing::warn!("Rate limit bucket {id} contended, retrying: {err}");
+          attempt += 1;
+        }
+        res => return res,
+      }
+    }
+  }
+
+  async fn try_update_bucket<T>(
+    &self,
+    id: &str,
+    update: &mut impl FnMut(Option<&str>) -> (String, i64, T),
+  ) -> Result<T, DbErr> {
+    let txn = self.db.begin().await?;
+    let backend = txn.get_database_backend();
+    // Writing first, an empty bucket when it is missing: SQLite takes its write lock for the
+    // whole transaction instead of failing to upgrade a read lock with SQLITE_BUSY, and MySQL
+    // gets a row to lock, as `FOR UPDATE` does not lock a missing one. Concurrent checks of
+    // the same key wait here for this one to commit
+    let insert = Query::insert()
+      .into_table(RateLimitEntity)
+      .columns([
+        RateLimitColumn::Id,
+        RateLimitColumn::State,
+        RateLimitColumn::ExpiresAt,
+      ])
+      .values_panic([id.into(), "".into(), 0.into()])
+      .on_conflict(
+        OnConflict::column(RateLimitColumn::Id)
+          .do_nothing_on([RateLimitColumn::Id])
+          .to_owned(),
+      )
+      .to_owned();
+    txn.execute(backend.build(&insert)).await?;
+    let mut query = RateLimitEntity::find_by_id(id);
+    if backend == DbBackend::MySql {
+      query = query.lock_exclusive();
+    }
+    let existing: Option<RateLimitModel> = query.one(&txn).await?;
+    let state = existing.as_ref().map(|m| m.state.as_str());
+    let (state, expires_at, result) = update(state.filter(|s| !s.is_empty()));
+    RateLimitActiveModel {
+      id: Set(id.to_string()),
+      state: Set(state),
+      expires_at: Set(expires_at),
+    }
+    .update(&txn)
+    .await?;
+    txn.commit().await?;
+    Ok(result)
+  }
+
+  pub async fn delete_expired(&self, now: i64) -> Result<u64, DbErr> {
+    let res = RateLimitEntity::delete_many()
+      .filter(RateLimitColumn::ExpiresAt.lte(now))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected)
+  }
+}
+
+/// Errors of a transaction that lost a lock race and can simply run again
+fn is_contention(err: &DbErr) -> bool {
+  let (DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(err)))
+  | DbErr::Query(RuntimeErr::SqlxError(sqlx::Error::Database(err)))) = err
+  else {
+    return false;
+  };
+  if let Some(err) = err.try_downcast_ref::<MySqlDatabaseError>() {
+    // ER_LOCK_DEADLOCK and ER_LOCK_WAIT_TIMEOUT
+    return matches!(err.number(), 1213 | 1205);
+  }
+  // SQLITE_BUSY and SQLITE_LOCKED with their extended codes
+  err
+    .code()
+    .and_then(|code| code.parse::<i32>().ok())
+    .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use helpers::uuid::{uuid, Alphabet};
+  use sea_orm::{ConnectOptions, Database};
+
+  use super::*;
+
+  /// A fresh SQLite database file with the `rate_limit` table
+  async fn database() -> DatabaseConnection {
+    let path = std::env::temp_dir().join(format!("rate_limit_{}.db", uuid(&Alphabet::DEFAULT, 16)));
+    let url = format!("sqlite://{}?mode=rwc", path.display());
+    let db = Database::connect(ConnectOptions::new(url).max_connections(8).to_owned())
+      .await
+      .unwrap();
+    db.execute_unprepared(
+      "CREATE TABLE rate_limit (id TEXT PRIMARY KEY, state TEXT NOT NULL, expires_at BIGINT NOT NULL)",
+    )
+    .await
+    .unwrap();
+    db
+  }
+
+  #[actix_web::test]
+  async fn concurrent_updates_of_a_new_bucket_are_serialized() {
+    let db = Arc::new(database().await);
+    let tasks: Vec<_> = (0..20)
+      .map(|_| {
+        let db = db.clone();
+        actix_web::rt::spawn(async move {
+          RateLimitRepository { db: &db }
+            .update_bucket("login:ip:10.0.0.1", |state| {
+              let count = state.map_or(0, |s| s.parse::<u32>().unwrap()) + 1;
+              (count.to_string(), i64::MAX, ())
+            })
+            .await
+        })
+      })
+      .collect();
+    for task in tasks {
+      task.await.unwrap().unwrap();
+    }
+    let bucket = RateLimitEntity::find_by_id("login:ip:10.0.0.1")
+      .one(db.as_ref())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(bucket.state, "20");
+  }
+}
@@ -0,0 +1,101 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+  QueryTrait,
+};
+
+#[derive(Debug, Clone)]
+pub struct SessionRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> SessionRepository<'a> {
+  #[tracing::instrument(skip_all)]
+  pub async fn create_session(&self, session: SessionActiveModel) -> Result<SessionModel, DbErr> {
+    session.insert(self.db).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_session(&self, id: &str) -> Result<Option<SessionModel>, DbErr> {
+    SessionEntity::find_by_id(id).one(self.db).await
+  }
+
+  /// Newest first. Only sessions neither revoked nor expired at `now`, unless `all`
+  #[tracing::instrument(skip_all)]
+  pub async fn get_user_sessions(
+    &self,
+    user_id: &str,
+    now: DateTimeUtc,
+    all: bool,
+  ) -> Result<Vec<SessionModel>, DbErr> {
+    let mut query = SessionEntity::find().filter(SessionColumn::UserId.eq(user_id));
+    if !all {
+      query = query
+        .filter(SessionColumn::RevokedAt.is_null())
+        .filter(SessionColumn::ExpiresAt.gt(now));
+    }
+    query
+      .order_by_desc(SessionColumn::CreatedAt)
+      .all(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn touch_session(&self, id: &str, now: DateTimeUtc) -> Result<(), DbErr> {
+    SessionEntity::update_many()
+      .col_expr(SessionColumn::LastSeenAt, Expr::value(now))
+      .filter(SessionColumn::Id.eq(id))
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+
+  /// Revokes one session of the user, returns whether it was active
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_session(
+    &self,
+    user_id: &str,
+    id: &str,
+    now: DateTimeUtc,
+  ) -> Result<bool, DbErr> {
+    let res = SessionEntity::update_many()
+      .col_expr(SessionColumn::RevokedAt, Expr::value(now))
+      .filter(SessionColumn::Id.eq(id))
+      .filter(SessionColumn::UserId.eq(user_id))
+      .filter(SessionColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+
+  /// Revokes every active session of the user, returns how many
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_user_sessions(&self, user_id: &str, now: DateTimeUtc) -> Result<u64, DbErr> {
+    let res = SessionEntity::update_many()
+      .col_expr(SessionColumn::RevokedAt, Expr::value(now))
+      .filter(SessionColumn::UserId.eq(user_id))
+      .filter(SessionColumn::RevokedAt.is_null())
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected)
+  }
+
+  /// Revokes the active sessions of the user but `except`, returns how many
+  #[tracing::instrument(skip_all)]
+  pub async fn revoke_other_sessions(
+    &self,
+    user_id: &str,
+    except: Option<&str>,
+    now: DateTimeUtc,
+  ) -> Result<u64, DbErr> {
+    let res = SessionEntity::update_many()
+      .col_expr(SessionColumn::RevokedAt, Expr::value(now))
+      .filter(SessionColumn::UserId.eq(user_id))
+      .filter(SessionColumn::RevokedAt.is_null())
+      .apply_if(except, |query, id| query.filter(SessionColumn::Id.ne(id)))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected)
+  }
+}
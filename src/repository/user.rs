@@ -1,5 +1,7 @@
 use crate::entity::prelude::*;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use sea_orm::{
+  prelude::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+};
 
 #[derive(Debug, Clone)]
 pub struct UserRepository<'a> {
@@ -7,22 +9,34 @@ pub struct UserRepository<'a> {
 }
 
 impl<'a> UserRepository<'a> {
+  #[tracing::instrument(skip_all)]
   pub async fn get_users(&self) -> Result<Vec<UserModel>, DbErr> {
     UserEntity::find().all(self.db).await
   }
+  #[tracing::instrument(skip_all)]
   pub async fn get_user_by_id(&self, id: u32) -> Result<Option<UserModel>, DbErr> {
     UserEntity::find_by_id(id).one(self.db).await
   }
+  #[tracing::instrument(skip_all)]
+  pub async fn get_user_by_user_id(&self, user_id: &str) -> Result<Option<UserModel>, DbErr> {
+    UserEntity::find()
+      .filter(UserColumn::UserId.eq(user_id))
+      .one(self.db)
+      .await
+  }
+  #[tracing::instrument(skip_all)]
   pub async fn get_user_by_email(&self, email: &str) -> Result<Option<UserModel>, DbErr> {
     UserEntity::find()
       .filter(UserColumn::Email.eq(email))
       .one(self.db)
       .await
   }
+  #[tracing::instrument(skip_all)]
   pub async fn is_first_user(&self) -> Result<bool, DbErr> {
     let users = UserEntity::find().all(self.db).await?;
     Ok(users.is_empty())
   }
+  #[tracing::instrument(skip_all)]
   pub async fn is_admin_user(&self, email: &str) -> Result<bool, DbErr> {
     let user = UserEntity::find()
       .filter(UserColumn::Email.eq(email))
@@ -33,6 +47,7 @@ impl<'a> UserRepository<'a> {
       None => Ok(false),
     }
   }
+  #[tracing::instrument(skip_all)]
   pub async fn is_root_user(&self, user_id: u32) -> Result<bool, DbErr> {
     let user = UserEntity::find_by_id(user_id).one(self.db).await?;
     match user {
@@ -40,12 +55,49 @@ impl<'a> UserRepository<'a> {
       None => Ok(false),
     }
   }
+  #[tracing::instrument(skip_all)]
   pub async fn create_user(&self, user: UserActiveModel) -> Result<UserModel, DbErr> {
     user.insert(self.db).await
   }
+  #[tracing::instrument(skip_all)]
   pub async fn update_user(&self, user: UserActiveModel) -> Result<UserModel, DbErr> {
     user.update(self.db).await
   }
+  /// Replaces the password hash unless the password was changed since `old_hash` was read
+  #[tracing::instrument(skip_all)]
+  pub async fn rehash_password(
+    &self,
+    id: u32,
+    old_hash: &str,
+    new_hash: String,
+  ) -> Result<(), DbErr> {
+    UserEntity::update_many()
+      .col_expr(UserColumn::Password, Expr::value(new_hash))
+      .filter(UserColumn::Id.eq(id))
+      .filter(UserColumn::Password.eq(old_hash))
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+  /// Switches the email, confirmed by its new owner, unless it changed since `old_email` was
+  /// read. Fails with a unique constraint violation when the new email was taken meanwhile
+  #[tracing::instrument(skip_all)]
+  pub async fn change_email(
+    &self,
+    id: u32,
+    old_email: &str,
+    new_email: &str,
+  ) -> Result<bool, DbErr> {
+    let res = UserEntity::update_many()
+      .col_expr(UserColumn::Email, Expr::value(new_email))
+      .col_expr(UserColumn::IsEmailVerified, Expr::value(true))
+      .filter(UserColumn::Id.eq(id))
+      .filter(UserColumn::Email.eq(old_email))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected == 1)
+  }
+  #[tracing::instrument(skip_all)]
   pub async fn has_user(&self, email: &str) -> Result<Option<UserModel>, DbErr> {
     UserEntity::find()
       .filter(UserColumn::Email.eq(email))
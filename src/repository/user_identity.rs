@@ -0,0 +1,70 @@
+use crate::entity::prelude::*;
+use sea_orm::{
+  prelude::{DateTimeUtc, Expr},
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Debug, Clone)]
+pub struct UserIdentityRepository<'a> {
+  pub db: &'a DatabaseConnection,
+}
+
+impl<'a> UserIdentityRepository<'a> {
+  /// Fails on the unique (provider, subject) index when the account is already linked
+  #[tracing::instrument(skip_all)]
+  pub async fn create_identity(
+    &self,
+    identity: UserIdentityActiveModel,
+  ) -> Result<UserIdentityModel, DbErr> {
+    identity.insert(self.db).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_identity(
+    &self,
+    provider: &str,
+    subject: &str,
+  ) -> Result<Option<UserIdentityModel>, DbErr> {
+    UserIdentityEntity::find()
+      .filter(UserIdentityColumn::Provider.eq(provider))
+      .filter(UserIdentityColumn::Subject.eq(subject))
+      .one(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn get_user_identities(&self, user_id: &str) -> Result<Vec<UserIdentityModel>, DbErr> {
+    UserIdentityEntity::find()
+      .filter(UserIdentityColumn::UserId.eq(user_id))
+      .order_by_asc(UserIdentityColumn::CreatedAt)
+      .all(self.db)
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  pub async fn touch_identity(
+    &self,
+    id: u32,
+    email: Option<String>,
+    now: DateTimeUtc,
+  ) -> Result<(), DbErr> {
+    UserIdentityEntity::update_many()
+      .col_expr(UserIdentityColumn::Email, Expr::value(email))
+      .col_expr(UserIdentityColumn::LastLoginAt, Expr::value(now))
+      .filter(UserIdentityColumn::Id.eq(id))
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+
+  /// Unlinks one identity of the user, returns whether it existed
+  #[tracing::instrument(skip_all)]
+  pub async fn delete_identity(&self, user_id: &str, id: u32) -> Result<bool, DbErr> {
+    let res = UserIdentityEntity::delete_many()
+      .filter(UserIdentityColumn::Id.eq(id))
+      .filter(UserIdentityColumn::UserId.eq(user_id))
+      .exec(self.db)
+      .await?;
+    Ok(res.rows_affected > 0)
+  }
+}
@@ -3,7 +3,7 @@ use std::fmt::Display;
 use serde::Serialize;
 use utoipa::ToSchema;
 
-use crate::error::AppError;
+use crate::{error::AppError, middlewares::request_id::RequestId, telemetry::current_trace_id};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct Response<T> {
@@ -11,6 +11,12 @@ pub struct Response<T> {
   pub msg: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub data: Option<T>,
+  /// Quote it when reporting a problem, it is also in the `X-Request-Id` header
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub request_id: Option<String>,
+  /// Set on errors, to find the request in traces and logs
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub trace_id: Option<String>,
 }
 
 impl<T> Response<T> {
@@ -19,6 +25,8 @@ impl<T> Response<T> {
       data,
       code: 0,
       msg: AppError::Success.message(lang.unwrap_or("en")),
+      request_id: RequestId::current(),
+      trace_id: None,
     }
   }
 
@@ -27,6 +35,8 @@ impl<T> Response<T> {
       data: None,
       code: error.code(),
       msg: error.message(lang.unwrap_or("en")),
+      request_id: RequestId::current(),
+      trace_id: current_trace_id(),
     }
   }
 }
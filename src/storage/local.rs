@@ -0,0 +1,121 @@
+use std::{
+  fs,
+  io::ErrorKind,
+  path::{Path, PathBuf},
+};
+
+use actix_web::web;
+use async_trait::async_trait;
+use helpers::uuid::{self, Alphabet};
+
+use super::{check_key, content_type, Storage, StoredFile};
+use crate::error::AppError;
+
+/// Files under a directory, a key is a path relative to it
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+  root: PathBuf,
+}
+
+impl LocalStorage {
+  pub fn new(root: impl AsRef<Path>) -> Self {
+    Self {
+      root: root.as_ref().to_path_buf(),
+    }
+  }
+
+  fn path(&self, key: &str) -> Result<PathBuf, AppError> {
+    check_key(key)?;
+    Ok(self.root.join(key))
+  }
+}
+
+/// Runs blocking file system calls off the async workers
+async fn blocking<T: Send + 'static>(
+  f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> Result<T, AppError> {
+  web::block(f)
+    .await
+    .map_err(|err| {
+      tracing::error!("{:#?}", err);
+      AppError::Error
+    })?
+    .map_err(AppError::from)
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+  async fn put(&self, key: &str, bytes: Vec<u8>, _: &str) -> Result<(), AppError> {
+    let path = self.path(key)?;
+    blocking(move || {
+      if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+      }
+      // Written aside then renamed, so readers never see a partial file
+      let tmp = path.with_extension(format!("{}.tmp", uuid::uuid(&Alphabet::NUMBERS_LOWER, 8)));
+      fs::write(&tmp, bytes)?;
+      fs::rename(&tmp, &path)
+    })
+    .await
+  }
+
+  async fn get(&self, key: &str) -> Result<Option<StoredFile>, AppError> {
+    let path = self.path(key)?;
+    let content_type = content_type(key).to_string();
+    blocking(move || match fs::read(path) {
+      Ok(bytes) => Ok(Some(StoredFile {
+        bytes,
+        content_type,
+      })),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err),
+    })
+    .await
+  }
+
+  async fn delete(&self, key: &str) -> Result<(), AppError> {
+    let path = self.path(key)?;
+    blocking(move || match fs::remove_file(path) {
+      Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+      _ => Ok(()),
+    })
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[actix_web::test]
+  async fn puts_gets_and_deletes_files() {
+    let root = std::env::temp_dir().join(format!("storage_{}", uuid::uuid(&Alphabet::DEFAULT, 16)));
+    let storage = LocalStorage::new(&root);
+    let key = "avatars/u1/64.png";
+    assert!(storage.get(key).await.unwrap().is_none());
+    storage
+      .put(key, b"first".to_vec(), "image/png")
+      .await
+      .unwrap();
+    storage
+      .put(key, b"second".to_vec(), "image/png")
+      .await
+      .unwrap();
+    let file = storage.get(key).await.unwrap().unwrap();
+    assert_eq!(file.bytes, b"second");
+    assert_eq!(file.content_type, "image/png");
+    // No temporary file is left next to it
+    let dir = fs::read_dir(root.join("avatars/u1")).unwrap().count();
+    assert_eq!(dir, 1);
+    storage.delete(key).await.unwrap();
+    assert!(storage.get(key).await.unwrap().is_none());
+    storage.delete(key).await.unwrap();
+    // Keys outside the root are refused before touching the file system
+    assert!(storage
+      .put("../escaped.png", vec![], "image/png")
+      .await
+      .is_err());
+    assert!(!root.join("../escaped.png").exists());
+    fs::remove_dir_all(root).unwrap();
+  }
+}
@@ -0,0 +1,108 @@
+//! Where uploaded files are kept, selected by `storage`
+mod local;
+mod s3;
+
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{config::EnvConfig, error::AppError};
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+  /// Files under `storage_dir`, for a single replica or a shared volume
+  #[default]
+  Local,
+  /// A bucket of an S3 compatible service, e.g. AWS S3, MinIO or Cloudflare R2
+  S3,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+  pub bytes: Vec<u8>,
+  pub content_type: String,
+}
+
+/// A flat namespace of files. Keys are `/` separated paths checked by [`check_key`]
+#[async_trait]
+pub trait Storage: Send + Sync + Debug {
+  /// Creates or replaces the file
+  async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), AppError>;
+
+  /// `None` when there is no such file
+  async fn get(&self, key: &str) -> Result<Option<StoredFile>, AppError>;
+
+  /// Succeeds as well when there is no such file
+  async fn delete(&self, key: &str) -> Result<(), AppError>;
+}
+
+/// Keys come from requests when files are served, so they are restricted to segments of
+/// letters, digits, `-`, `_` and `.` that cannot walk up the tree
+pub fn check_key(key: &str) -> Result<(), AppError> {
+  let valid = !key.is_empty()
+    && key.split('/').all(|segment| {
+      !segment.is_empty()
+        && !segment.starts_with('.')
+        && segment
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    });
+  match valid {
+    true => Ok(()),
+    false => Err(AppError::NotFound),
+  }
+}
+
+/// Media type of a key by its extension
+pub fn content_type(key: &str) -> &'static str {
+  match key.rsplit_once('.').map(|(_, ext)| ext) {
+    Some("png") => "image/png",
+    Some("jpg" | "jpeg") => "image/jpeg",
+    Some("gif") => "image/gif",
+    Some("webp") => "image/webp",
+    Some("svg") => "image/svg+xml",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Builds the storage selected by `storage`
+pub fn storage(config: &EnvConfig) -> Result<Arc<dyn Storage>, AppError> {
+  Ok(match config.storage {
+    StorageKind::Local => Arc::new(LocalStorage::new(&config.storage_dir)),
+    StorageKind::S3 => Arc::new(S3Storage::from_config(config)?),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys_cannot_leave_the_storage() {
+    for key in ["avatars/u1/64.png", "a", "a-b_c.d/e.f.png", "x/y/z"] {
+      assert!(check_key(key).is_ok(), "{key}");
+    }
+    for key in [
+      "",
+      "..",
+      "avatars/../secret",
+      "../etc/passwd",
+      ".hidden",
+      "avatars/.hidden",
+      "avatars//64.png",
+      "avatars/",
+      "/etc/passwd",
+      "avatars\\..\\secret",
+      "C:\\file",
+      "avatars/64 png",
+      "avatars/%2e%2e",
+    ] {
+      assert!(matches!(check_key(key), Err(AppError::NotFound)), "{key}");
+    }
+  }
+}
@@ -0,0 +1,307 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{
+  header::{AUTHORIZATION, CONTENT_TYPE},
+  Client, Method, RequestBuilder, StatusCode, Url,
+};
+use ring::hmac;
+use sha2::{Digest, Sha256};
+
+use super::{check_key, Storage, StoredFile};
+use crate::{config::EnvConfig, error::AppError};
+
+/// A bucket of an S3 compatible service, addressed path-style (`<endpoint>/<bucket>/<key>`)
+/// as MinIO expects, requests are signed with AWS Signature Version 4
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+  client: Client,
+  endpoint: Url,
+  region: String,
+  bucket: String,
+  access_key: String,
+  secret_key: String,
+}
+
+/// Failures talking to the bucket are server errors, not identity provider ones
+fn storage_error(err: reqwest::Error) -> AppError {
+  tracing::error!("{:#?}", err);
+  AppError::Error
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+  let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+  hmac::sign(&key, data.as_bytes()).as_ref().to_vec()
+}
+
+/// Percent-encodes a path as SigV4 canonical URIs want it, `/` is kept
+fn uri_encode(path: &str) -> String {
+  path
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+        (byte as char).to_string()
+      }
+      _ => format!("%{byte:02X}"),
+    })
+    .collect()
+}
+
+impl S3Storage {
+  pub fn from_config(config: &EnvConfig) -> Result<Self, AppError> {
+    let (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) = (
+      &config.s3_endpoint,
+      &config.s3_bucket,
+      &config.s3_access_key,
+      &config.s3_secret_key,
+    ) else {
+      tracing::error!(
+        "STORAGE=s3 requires S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY and S3_SECRET_KEY"
+      );
+      return Err(AppError::Error);
+    };
+    let endpoint = Url::parse(endpoint).map_err(|err| {
+      tracing::error!("Invalid S3_ENDPOINT {endpoint}: {err}");
+      AppError::Error
+    })?;
+    Ok(Self {
+      client: Client::builder().build().map_err(storage_error)?,
+      endpoint,
+      region: config.s3_region.clone(),
+      bucket: bucket.clone(),
+      access_key: access_key.clone(),
+      secret_key: secret_key.clone(),
+    })
+  }
+
+  /// A request on the object, signed over its headers and `payload`
+  fn request(&self, method: Method, key: &str, payload: &[u8]) -> Result<RequestBuilder, AppError> {
+    check_key(key)?;
+    let path = format!(
+      "{}/{}/{}",
+      self.endpoint.path().trim_end_matches('/'),
+      self.bucket,
+      key
+    );
+    let path = uri_encode(&path);
+    let mut url = self.endpoint.clone();
+    url.set_path(&path);
+    let host = match self.endpoint.port() {
+      Some(port) => format!("{}:{port}", self.endpoint.host_str().unwrap_or_default()),
+      None => self.endpoint.host_str().unwrap_or_default().to_string(),
+    };
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex(&Sha256::digest(payload));
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+      "{method}\n{path}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+    );
+    let scope = format!("{date}/{}/s3/aws4_request", self.region);
+    let string_to_sign = format!(
+      "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+      hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [&date, &self.region, "s3", "aws4_request"].iter().fold(
+      format!("AWS4{}", self.secret_key).into_bytes(),
+      |key, part| hmac_sha256(&key, part),
+    );
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+    let authorization = format!(
+      "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+      self.access_key
+    );
+    Ok(
+      self
+        .client
+        .request(method, url)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", amz_date)
+        .header(AUTHORIZATION, authorization),
+    )
+  }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+  async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), AppError> {
+    self
+      .request(Method::PUT, key, &bytes)?
+      .header(CONTENT_TYPE, content_type)
+      .body(bytes)
+      .send()
+      .await
+      .and_then(|res| res.error_for_status())
+      .map_err(storage_error)?;
+    Ok(())
+  }
+
+  async fn get(&self, key: &str) -> Result<Option<StoredFile>, AppError> {
+    let res = self
+      .request(Method::GET, key, &[])?
+      .send()
+      .await
+      .map_err(storage_error)?;
+    if res.status() == StatusCode::NOT_FOUND {
+      return Ok(None);
+    }
+    let res = res.error_for_status().map_err(storage_error)?;
+    let content_type = res
+      .headers()
+      .get(CONTENT_TYPE)
+      .and_then(|value| value.to_str().ok())
+      .unwrap_or("application/octet-stream")
+      .to_string();
+    Ok(Some(StoredFile {
+      bytes: res.bytes().await.map_err(storage_error)?.to_vec(),
+      content_type,
+    }))
+  }
+
+  async fn delete(&self, key: &str) -> Result<(), AppError> {
+    let res = self
+      .request(Method::DELETE, key, &[])?
+      .send()
+      .await
+      .map_err(storage_error)?;
+    if res.status() != StatusCode::NOT_FOUND {
+      res.error_for_status().map_err(storage_error)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, sync::Mutex};
+
+  use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+  use super::*;
+
+  type Objects = Mutex<HashMap<String, (String, web::Bytes)>>;
+
+  const SECRET_KEY: &str = "minioadmin";
+
+  /// The SigV4 signature of the request as the service computes it
+  fn signature(req: &HttpRequest, body: &[u8]) -> Option<String> {
+    let header = |name| req.headers().get(name)?.to_str().ok();
+    let authorization = header("authorization")?;
+    let scope = authorization
+      .split_once("Credential=")?
+      .1
+      .split_once(',')?
+      .0
+      .split_once('/')?
+      .1;
+    let payload_hash = header("x-amz-content-sha256")?;
+    if payload_hash != hex(&Sha256::digest(body)) {
+      return None;
+    }
+    let amz_date = header("x-amz-date")?;
+    let canonical_request = format!(
+      "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+      req.method(),
+      req.uri().path(),
+      header("host")?,
+    );
+    let string_to_sign = format!(
+      "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+      hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = scope
+      .split('/')
+      .fold(format!("AWS4{SECRET_KEY}").into_bytes(), |key, part| {
+        hmac_sha256(&key, part)
+      });
+    Some(hex(&hmac_sha256(&key, &string_to_sign)))
+  }
+
+  /// A bucket in memory, refusing requests not signed with [`SECRET_KEY`]
+  async fn bucket(req: HttpRequest, body: web::Bytes, objects: web::Data<Objects>) -> HttpResponse {
+    let signed = req
+      .headers()
+      .get("authorization")
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.rsplit_once("Signature="))
+      .map(|(_, signature)| signature.to_string());
+    if signed.is_none() || signed != signature(&req, &body) {
+      return HttpResponse::Forbidden().finish();
+    }
+    let mut objects = objects.lock().unwrap();
+    let path = req.uri().path().to_string();
+    match req.method().as_str() {
+      "PUT" => {
+        let content_type = req.headers().get("content-type").unwrap();
+        let content_type = content_type.to_str().unwrap().to_string();
+        objects.insert(path, (content_type, body));
+        HttpResponse::Ok().finish()
+      }
+      "GET" => match objects.get(&path) {
+        Some((content_type, bytes)) => HttpResponse::Ok()
+          .content_type(content_type.as_str())
+          .body(bytes.clone()),
+        None => HttpResponse::NotFound().finish(),
+      },
+      "DELETE" => match objects.remove(&path) {
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => HttpResponse::NotFound().finish(),
+      },
+      _ => HttpResponse::MethodNotAllowed().finish(),
+    }
+  }
+
+  fn storage(endpoint: &str, secret_key: &str) -> S3Storage {
+    S3Storage {
+      client: Client::new(),
+      endpoint: Url::parse(endpoint).unwrap(),
+      region: "us-east-1".to_string(),
+      bucket: "avatars".to_string(),
+      access_key: "minioadmin".to_string(),
+      secret_key: secret_key.to_string(),
+    }
+  }
+
+  #[actix_web::test]
+  async fn puts_gets_and_deletes_signed_objects() {
+    let objects = web::Data::new(Objects::default());
+    let server_objects = objects.clone();
+    let server = HttpServer::new(move || {
+      App::new()
+        .app_data(server_objects.clone())
+        .default_service(web::to(bucket))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let endpoint = format!("http://{}/s3", server.addrs()[0]);
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let s3 = storage(&endpoint, SECRET_KEY);
+    let key = "avatars/abc/v1/256.png";
+    s3.put(key, b"png".to_vec(), "image/png").await.unwrap();
+    assert!(objects
+      .lock()
+      .unwrap()
+      .contains_key("/s3/avatars/avatars/abc/v1/256.png"));
+    let file = s3.get(key).await.unwrap().unwrap();
+    assert_eq!(file.bytes, b"png");
+    assert_eq!(file.content_type, "image/png");
+    s3.delete(key).await.unwrap();
+    assert!(s3.get(key).await.unwrap().is_none());
+    // Deleting a missing object succeeds, as the local storage does
+    s3.delete(key).await.unwrap();
+
+    let wrong = storage(&endpoint, "wrong secret");
+    assert!(wrong.put(key, b"png".to_vec(), "image/png").await.is_err());
+    assert!(wrong.get(key).await.is_err());
+    assert!(objects.lock().unwrap().is_empty());
+    handle.stop(true).await;
+  }
+}
@@ -0,0 +1,113 @@
+//! OpenTelemetry tracing
+use opentelemetry::{
+  global,
+  propagation::Extractor,
+  trace::{TraceContextExt, TraceId},
+};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{config::EnvConfig, error::AppError};
+
+/// Builds the tracer provider and installs the W3C `traceparent` propagator. Trace ids are
+/// always generated, spans are exported only when `otel_exporter_otlp_endpoint` is set
+pub fn tracer_provider(config: &EnvConfig) -> Result<SdkTracerProvider, AppError> {
+  global::set_text_map_propagator(TraceContextPropagator::new());
+  let mut builder = SdkTracerProvider::builder().with_resource(
+    Resource::builder()
+      .with_service_name(config.otel_service_name.clone())
+      .build(),
+  );
+  if config.otel_exporter_otlp_endpoint.is_some() {
+    // Reads `OTEL_EXPORTER_OTLP_ENDPOINT` and appends `/v1/traces`
+    let exporter = SpanExporter::builder().with_http().build()?;
+    builder = builder.with_batch_exporter(exporter);
+  }
+  let provider = builder.build();
+  global::set_tracer_provider(provider.clone());
+  Ok(provider)
+}
+
+pub struct HeaderExtractor<'a>(pub &'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).and_then(|value| value.to_str().ok())
+  }
+
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|key| key.as_str()).collect()
+  }
+}
+
+/// Trace id of the current span, `None` outside of a request
+pub fn current_trace_id() -> Option<String> {
+  let trace_id = tracing::Span::current()
+    .context()
+    .span()
+    .span_context()
+    .trace_id();
+  (trace_id != TraceId::INVALID).then(|| trace_id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{
+    test::{call_service, init_service, read_body, TestRequest},
+    web, App, HttpMessage, HttpRequest,
+  };
+  use opentelemetry::trace::TracerProvider;
+  use tracing_subscriber::layer::SubscriberExt;
+
+  use crate::middlewares::trace::{Trace, TraceId};
+
+  use super::*;
+
+  /// The trace id stored by [`Trace`] and the one of the span the handler runs in
+  async fn trace_ids(req: HttpRequest) -> String {
+    let stored = req.extensions().get::<TraceId>().unwrap().0.clone();
+    format!("{stored} {}", current_trace_id().unwrap_or_default())
+  }
+
+  async fn call(traceparent: Option<&str>) -> String {
+    let vars = [("DATABASE_URL", "sqlite::memory:"), ("JWT_TOKEN", "test")];
+    let config: EnvConfig =
+      envy::from_iter(vars.map(|(k, v)| (k.to_string(), v.to_string()))).unwrap();
+    let provider = tracer_provider(&config).unwrap();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("test"));
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+    let app = init_service(App::new().wrap(Trace).route("/", web::get().to(trace_ids))).await;
+    let mut req = TestRequest::get().uri("/");
+    if let Some(traceparent) = traceparent {
+      req = req.insert_header(("traceparent", traceparent));
+    }
+    let body = read_body(call_service(&app, req.to_request()).await).await;
+    String::from_utf8(body.to_vec()).unwrap()
+  }
+
+  #[actix_web::test]
+  async fn continues_an_incoming_trace() {
+    let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    assert_eq!(
+      call(Some(traceparent)).await,
+      "4bf92f3577b34da6a3ce929d0e0e4736 4bf92f3577b34da6a3ce929d0e0e4736"
+    );
+  }
+
+  #[actix_web::test]
+  async fn starts_a_trace_without_or_with_a_broken_traceparent() {
+    for traceparent in [None, Some("00-not-a-trace-01")] {
+      let body = call(traceparent).await;
+      let (stored, current) = body.split_once(' ').unwrap();
+      assert_eq!(stored, current);
+      assert_eq!(stored.len(), 32);
+      assert_ne!(stored, "0".repeat(32));
+    }
+  }
+
+  #[test]
+  fn no_trace_id_outside_of_a_span() {
+    assert_eq!(current_trace_id(), None);
+  }
+}
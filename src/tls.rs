@@ -0,0 +1,226 @@
+//! tls
+
+use std::{
+  fs::File,
+  io::BufReader,
+  sync::{Arc, RwLock},
+};
+
+use rustls::{
+  server::{AllowAnyAuthenticatedClient, ClientHello, ResolvesServerCert},
+  sign::{self, CertifiedKey},
+  Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+
+use crate::{config::EnvConfig, error::AppError};
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, AppError> {
+  let mut reader = BufReader::new(File::open(path)?);
+  let certs = rustls_pemfile::certs(&mut reader)?;
+  if certs.is_empty() {
+    tracing::error!("No certificate found in {path}");
+    return Err(AppError::Error);
+  }
+  Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKey, AppError> {
+  let mut reader = BufReader::new(File::open(path)?);
+  while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+    match item {
+      rustls_pemfile::Item::PKCS8Key(key)
+      | rustls_pemfile::Item::RSAKey(key)
+      | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+      _ => {}
+    }
+  }
+  tracing::error!("No private key found in {path}");
+  Err(AppError::Error)
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, AppError> {
+  let certs = load_certs(cert_path)?;
+  let key = sign::any_supported_type(&load_private_key(key_path)?).map_err(|err| {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  })?;
+  Ok(CertifiedKey::new(certs, key))
+}
+
+/// Serves the current certificate and allows swapping it while the server is running
+pub struct CertResolver {
+  cert_path: String,
+  key_path: String,
+  current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+  pub fn new(cert_path: String, key_path: String) -> Result<Self, AppError> {
+    let current = RwLock::new(Arc::new(load_certified_key(&cert_path, &key_path)?));
+    Ok(Self {
+      cert_path,
+      key_path,
+      current,
+    })
+  }
+
+  /// Reloads the certificate from disk. Existing connections keep the old one,
+  /// new handshakes pick up the new one. On failure the old certificate is kept
+  pub fn reload(&self) -> Result<(), AppError> {
+    let key = load_certified_key(&self.cert_path, &self.key_path)?;
+    *self.current.write().unwrap() = Arc::new(key);
+    Ok(())
+  }
+}
+
+impl ResolvesServerCert for CertResolver {
+  fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+    Some(self.current.read().unwrap().clone())
+  }
+}
+
+/// Builds the rustls server config, requiring client certificates when `client_ca_path` is set.
+/// The client CA is read once, unlike the certificate it is not reloaded on SIGHUP: rustls
+/// fixes the client verifier in the config, a new CA needs a restart
+pub fn server_config(
+  resolver: Arc<CertResolver>,
+  client_ca_path: Option<&str>,
+) -> Result<ServerConfig, AppError> {
+  let builder = ServerConfig::builder().with_safe_defaults();
+  let config = match client_ca_path {
+    Some(path) => {
+      let mut roots = RootCertStore::empty();
+      for cert in load_certs(path)? {
+        roots.add(&cert).map_err(|err| {
+          tracing::error!("{:#?}", err);
+          AppError::Error
+        })?;
+      }
+      builder
+        .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        .with_cert_resolver(resolver)
+    }
+    None => builder.with_no_client_auth().with_cert_resolver(resolver),
+  };
+  Ok(config)
+}
+
+/// Reloads the certificate whenever the process receives SIGHUP
+#[cfg(unix)]
+pub fn reload_on_sighup(resolver: Arc<CertResolver>) -> Result<(), AppError> {
+  use tokio::signal::unix::{signal, SignalKind};
+
+  let mut hangup = signal(SignalKind::hangup())?;
+  actix_web::rt::spawn(async move {
+    while hangup.recv().await.is_some() {
+      match resolver.reload() {
+        Ok(()) => tracing::info!("TLS certificate reloaded, the client CA needs a restart"),
+        Err(_) => tracing::error!("TLS certificate reload failed, keeping the old one"),
+      }
+    }
+  });
+  Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_resolver: Arc<CertResolver>) -> Result<(), AppError> {
+  Ok(())
+}
+
+/// Builds the TLS config when both `tls_cert_path` and `tls_key_path` are set. Only one of
+/// them is a config error rather than a silent fallback to plain HTTP
+pub fn from_config(config: &EnvConfig) -> Result<Option<ServerConfig>, AppError> {
+  let (cert_path, key_path) = match (&config.tls_cert_path, &config.tls_key_path) {
+    (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+    (None, None) => {
+      if config.tls_client_ca_path.is_some() {
+        tracing::error!("TLS_CLIENT_CA_PATH requires TLS_CERT_PATH and TLS_KEY_PATH");
+        return Err(AppError::Error);
+      }
+      return Ok(None);
+    }
+    _ => {
+      tracing::error!("TLS_CERT_PATH and TLS_KEY_PATH must be set together");
+      return Err(AppError::Error);
+    }
+  };
+  let resolver = Arc::new(CertResolver::new(cert_path.clone(), key_path.clone())?);
+  reload_on_sighup(resolver.clone())?;
+  server_config(resolver, config.tls_client_ca_path.as_deref()).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/tls/{name}", env!("CARGO_MANIFEST_DIR"))
+  }
+
+  fn config(vars: &[(&str, String)]) -> EnvConfig {
+    let mut env = vec![
+      ("DATABASE_URL".to_string(), "sqlite::memory:".to_string()),
+      ("JWT_TOKEN".to_string(), "test".to_string()),
+    ];
+    env.extend(vars.iter().map(|(k, v)| (k.to_string(), v.clone())));
+    envy::from_iter(env).unwrap()
+  }
+
+  #[actix_web::test]
+  async fn cert_and_key_must_be_set_together() {
+    let cert = ("TLS_CERT_PATH", fixture("first.cert.pem"));
+    let key = ("TLS_KEY_PATH", fixture("first.key.pem"));
+    let ca = ("TLS_CLIENT_CA_PATH", fixture("ca.pem"));
+    assert!(from_config(&config(&[])).unwrap().is_none());
+    assert!(from_config(&config(std::slice::from_ref(&cert))).is_err());
+    assert!(from_config(&config(std::slice::from_ref(&key))).is_err());
+    assert!(from_config(&config(std::slice::from_ref(&ca))).is_err());
+    assert!(from_config(&config(&[cert.clone(), key.clone()]))
+      .unwrap()
+      .is_some());
+    assert!(from_config(&config(&[cert, key, ca])).unwrap().is_some());
+  }
+
+  #[test]
+  fn unreadable_files_are_refused() {
+    let cert = fixture("first.cert.pem");
+    let key = fixture("first.key.pem");
+    assert!(CertResolver::new(cert.clone(), fixture("missing.pem")).is_err());
+    // A certificate where the key should be and the other way round
+    assert!(CertResolver::new(cert.clone(), cert).is_err());
+    assert!(CertResolver::new(key.clone(), key).is_err());
+  }
+
+  #[test]
+  fn reload_swaps_the_certificate_and_keeps_it_on_failure() {
+    let dir = std::env::temp_dir().join(format!("tls_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem").display().to_string();
+    let key_path = dir.join("key.pem").display().to_string();
+    let install = |name: &str| {
+      std::fs::copy(fixture(&format!("{name}.cert.pem")), &cert_path).unwrap();
+      std::fs::copy(fixture(&format!("{name}.key.pem")), &key_path).unwrap();
+    };
+    let served = |resolver: &CertResolver| resolver.current.read().unwrap().cert[0].clone();
+    install("first");
+    let resolver = CertResolver::new(cert_path.clone(), key_path.clone()).unwrap();
+    let first = served(&resolver);
+    install("second");
+    resolver.reload().unwrap();
+    let second = served(&resolver);
+    assert_ne!(first, second);
+    assert_eq!(second, load_certs(&fixture("second.cert.pem")).unwrap()[0]);
+    std::fs::write(&key_path, "not a key").unwrap();
+    assert!(resolver.reload().is_err());
+    assert_eq!(served(&resolver), second);
+  }
+
+  #[test]
+  fn client_ca_must_hold_a_certificate() {
+    let cert = fixture("first.cert.pem");
+    let key = fixture("first.key.pem");
+    let resolver = Arc::new(CertResolver::new(cert, key.clone()).unwrap());
+    assert!(server_config(resolver.clone(), Some(&fixture("ca.pem"))).is_ok());
+    assert!(server_config(resolver, Some(&key)).is_err());
+  }
+}